@@ -12,6 +12,8 @@ use std::cell::{Cell, OnceCell, RefCell};
 use std::ptr::NonNull;
 
 use crate::config::Config;
+use crate::ipc_host::{IpcCloseTabResult, IpcHost, IpcMergeTabResult, IpcSwapPaneResult};
+use crate::pane::{Pane, PaneId, SplitAxis, SplitDirection, Tab};
 use crate::window;
 
 pub struct AppDelegateIvars {
@@ -29,8 +31,9 @@ pub struct AppDelegateIvars {
     session_backup: Option<usize>,
     /// IPC command receiver — polled in the timer tick on the main thread.
     ipc_rx: RefCell<Option<std::sync::mpsc::Receiver<crate::ipc::IpcRequest>>>,
-    /// `wait-for-completion` requests that haven't fired yet — checked on each tick.
-    pending_waits: RefCell<Vec<PendingWait>>,
+    /// `wait-for-completion` / `wait-for-text` / `wait-for-idle` requests
+    /// that haven't fired yet, and `search-pane` scans — checked on each tick.
+    pending_waits: RefCell<Vec<crate::ipc_host::PendingWait>>,
     /// Last state published to IPC event subscribers — diffed on each tick.
     events: RefCell<crate::events::EventState>,
     /// Whether Kova is the active app. Kept by the two activation delegate
@@ -39,13 +42,6 @@ pub struct AppDelegateIvars {
     app_active: Cell<bool>,
 }
 
-/// A `wait-for-completion` request the main thread is still polling.
-struct PendingWait {
-    pane_id: u32,
-    response_tx: std::sync::mpsc::Sender<crate::ipc::IpcResponse>,
-    deadline: std::time::Instant,
-}
-
 define_class!(
     #[unsafe(super(NSObject))]
     #[thread_kind = MainThreadOnly]
@@ -106,7 +102,10 @@ define_class!(
             crate::notification::init(ProtocolObject::from_ref(self));
 
             // Start IPC server (Unix socket for external process control)
-            let ipc_rx = crate::ipc::start();
+            let ipc_rx = crate::ipc::start(
+                crate::ipc_auth::Auth::from_config(&config.ipc),
+                crate::ipc_audit::Audit::from_config(&config.ipc),
+            );
             *self.ivars().ipc_rx.borrow_mut() = Some(ipc_rx);
 
             // Start global render timer — single timer for all windows
@@ -142,6 +141,7 @@ define_class!(
         #[unsafe(method(applicationDidBecomeActive:))]
         fn did_become_active(&self, _notification: &NSNotification) {
             self.ivars().app_active.set(true);
+            crate::instances::focused();
         }
 
         #[unsafe(method(applicationDidResignActive:))]
@@ -244,15 +244,17 @@ impl AppDelegate {
 
                     // Resolve any pending `wait-for-completion` requests first,
                     // so a command that just finished is reported on this tick.
-                    poll_pending_waits(&ivars.pending_waits, &ivars.windows);
+                    crate::ipc_host::poll_waits(&ivars.ipc_host(), &mut ivars.pending_waits.borrow_mut());
 
-                    // Drain any pending search-palette worker results so the UI
-                    // updates without the user pressing a key.
+                    // Drain any pending search-palette worker results and advance
+                    // unfinished Cmd+F scans, so the UI updates without the user
+                    // pressing a key.
                     {
                         let windows = ivars.windows.borrow();
                         for win in windows.iter() {
                             if let Some(view) = kova_view(win) {
                                 view.poll_search_palette();
+                                view.poll_filter_search();
                             }
                         }
                     }
@@ -264,8 +266,8 @@ impl AppDelegate {
                             while let Ok((cmd, responder)) = rx.try_recv() {
                                 // `subscribe` is the one command that needs the
                                 // event state, so it is served here rather than in
-                                // `handle_ipc_command`.
-                                if let crate::ipc::IpcCommand::Subscribe { topics } = cmd {
+                                // `ipc_host::serve`.
+                                if let crate::ipc::IpcCommand::Subscribe { topics, .. } = cmd {
                                     let app_active = ivars.app_active.get();
                                     // Flush what is already pending first: the
                                     // subscribers that were here before this one
@@ -287,18 +289,11 @@ impl AppDelegate {
                                     });
                                     continue;
                                 }
-                                match handle_ipc_command(cmd, &ivars.windows, &ivars.config) {
-                                    Disposition::Reply(response) => {
-                                        let _ = responder.send(response);
-                                    }
-                                    Disposition::Pending(wait) => {
-                                        ivars.pending_waits.borrow_mut().push(PendingWait {
-                                            pane_id: wait.pane_id,
-                                            response_tx: responder,
-                                            deadline: wait.deadline,
-                                        });
-                                    }
-                                }
+                                crate::ipc_host::serve(
+                                    &mut ivars.ipc_host(),
+                                    (cmd, responder),
+                                    &mut ivars.pending_waits.borrow_mut(),
+                                );
                             }
                         }
                     }
@@ -307,10 +302,8 @@ impl AppDelegate {
                     // rather than in the delegate callback because this is the
                     // point where the main thread is free to borrow the windows.
                     for pane_id in crate::notification::take_pending_focus() {
-                        if let crate::ipc::IpcResponse::Error { message } =
-                            handle_ipc_focus_pane(&ivars.windows, pane_id)
-                        {
-                            log::warn!("Notifications: click ignored: {}", message);
+                        if !ivars.ipc_host().focus_pane(pane_id) {
+                            log::warn!("Notifications: click ignored: pane {} not found", pane_id);
                         }
                     }
 
@@ -322,6 +315,8 @@ impl AppDelegate {
                         fps,
                         false,
                     );
+                    // Rewrites the instance registry only when the count changed.
+                    crate::instances::set_windows(ivars.windows.borrow().len());
 
                     // Periodic session save (every ~30s) to survive crashes.
                     // Serialization + I/O is offloaded to a thread to avoid frame drops.
@@ -336,6 +331,21 @@ impl AppDelegate {
                         }
                     }
 
+                    // App-wide scrollback budget (every ~2s): when all panes
+                    // together use more, the ones idle longest are compressed,
+                    // then trimmed. Runs here so the focus it protects is current.
+                    let budget_mb = ivars.config.get().map_or(0, |c| c.terminal.scrollback_total_mb);
+                    if budget_mb > 0 && count % (fps as u64 * 2) == 0 {
+                        let watched: Vec<_> = ivars.pending_waits.borrow().iter().map(|w| w.pane_id()).collect();
+                        let mut panes = Vec::new();
+                        for win in ivars.windows.borrow().iter() {
+                            if let Some(view) = kova_view(win) {
+                                view.collect_budget_panes(&watched, &mut panes);
+                            }
+                        }
+                        crate::terminal::scrollback::enforce_budget(&panes, budget_mb << 20);
+                    }
+
                     if !dead_indices.is_empty() {
                         // Move dead windows to pending_close — they'll be deallocated
                         // at the start of the next tick.
@@ -510,523 +520,247 @@ pub fn kova_view(window: &NSWindow) -> Option<&crate::window::KovaView> {
     Some(unsafe { &*(ptr as *const crate::window::KovaView) })
 }
 
-/// What `handle_ipc_command` decided to do with the request.
-enum Disposition {
-    /// Reply to the client now with this response.
-    Reply(crate::ipc::IpcResponse),
-    /// Defer the response — the main thread keeps polling until the wait
-    /// resolves (or its deadline passes), at which point it sends back.
-    Pending(DeferredWait),
-}
-
-/// A wait that the main thread has accepted but not yet resolved.
-struct DeferredWait {
-    pane_id: u32,
-    deadline: std::time::Instant,
-}
-
-/// Dispatch an IPC command. Most commands reply synchronously; only
-/// `wait-for-completion` returns `Pending` so the main thread can defer the reply.
-fn handle_ipc_command(
-    cmd: crate::ipc::IpcCommand,
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    config_cell: &OnceCell<Config>,
-) -> Disposition {
-    use crate::ipc::IpcCommand;
-    if let IpcCommand::WaitForCompletion { pane_id, timeout_ms } = cmd {
-        return handle_ipc_wait_for_completion(windows, pane_id, timeout_ms);
+/// The event diff sees every Kova window; the key one holds the focus.
+impl crate::events::EventSource for RefCell<Vec<Retained<NSWindow>>> {
+    fn focus_key(&self) -> Option<crate::events::FocusKey> {
+        let wins = self.borrow();
+        for (idx, win) in wins.iter().enumerate() {
+            if !win.isKeyWindow() {
+                continue;
+            }
+            if let Some(view) = kova_view(win) {
+                if let Some((tab, pane, session, session_name)) = view.events_focus_key() {
+                    return Some(crate::events::FocusKey { window: idx, tab, pane, session, session_name });
+                }
+            }
+        }
+        None
     }
-    Disposition::Reply(handle_ipc_command_sync(cmd, windows, config_cell))
-}
-
-/// Synchronous handler for every command except `wait-for-completion`.
-fn handle_ipc_command_sync(
-    cmd: crate::ipc::IpcCommand,
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    config_cell: &OnceCell<Config>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcCommand;
 
-    match cmd {
-        IpcCommand::Split { direction, cmd: command, cwd } => {
-            handle_ipc_split(windows, config_cell, &direction, command, cwd)
-        }
-        IpcCommand::ListPanes => {
-            handle_ipc_list_panes(windows)
-        }
-        IpcCommand::ClosePaneById(pane_id) => {
-            handle_ipc_close_pane(windows, pane_id)
-        }
-        IpcCommand::SendKeys { pane_id, text } => {
-            handle_ipc_send_keys(windows, pane_id, &text)
-        }
-        IpcCommand::FocusPane(pane_id) => {
-            handle_ipc_focus_pane(windows, pane_id)
-        }
-        IpcCommand::NewTab { cwd, cmd } => {
-            handle_ipc_new_tab(windows, config_cell, cwd, cmd)
-        }
-        IpcCommand::SetTabTitle { pane_id, title } => {
-            handle_ipc_set_tab_title(windows, pane_id, title)
-        }
-        IpcCommand::SetTabColor { pane_id, color } => {
-            handle_ipc_set_tab_color(windows, pane_id, color)
-        }
-        IpcCommand::GetPaneContent { panes, mode, trim_trailing_blank_lines } => {
-            handle_ipc_get_pane_content(windows, panes, &mode, trim_trailing_blank_lines)
-        }
-        IpcCommand::CountPaneContent { panes, mode, trim_trailing_blank_lines } => {
-            handle_ipc_count_pane_content(windows, panes, &mode, trim_trailing_blank_lines)
-        }
-        IpcCommand::WaitForCompletion { .. } => {
-            // Routed in `handle_ipc_command` before this fn is called.
-            unreachable!("WaitForCompletion handled by handle_ipc_command, not the sync path");
-        }
-        IpcCommand::ListTabs => {
-            handle_ipc_list_tabs(windows)
-        }
-        IpcCommand::CloseTab(tab_id) => {
-            handle_ipc_close_tab(windows, tab_id)
-        }
-        IpcCommand::MergeTab { source_tab_id, target_tab_id } => {
-            handle_ipc_merge_tab(windows, source_tab_id, target_tab_id)
-        }
-        IpcCommand::SwapPane { pane_id_a, pane_id_b } => {
-            handle_ipc_swap_pane(windows, pane_id_a, pane_id_b)
-        }
-        IpcCommand::ResizePane { pane_id, axis, direction, amount_pct } => {
-            handle_ipc_resize_pane(windows, pane_id, &axis, &direction, amount_pct)
-        }
-        IpcCommand::RenamePane { pane_id, title } => {
-            handle_ipc_rename_pane(windows, pane_id, title)
-        }
-        IpcCommand::SetPaneStatus { pane_id, waiting } => {
-            handle_ipc_set_pane_status(windows, pane_id, waiting)
-        }
-        IpcCommand::DispatchAction { action, pane_id } => {
-            handle_ipc_dispatch_action(windows, &action, pane_id)
-        }
-        IpcCommand::MergeWindow { source_window, target_window } => {
-            handle_ipc_merge_window(windows, source_window, target_window)
-        }
-        IpcCommand::Notify { pane_id, title, message, sound } => {
-            handle_ipc_notify(pane_id, &title, &message, sound)
-        }
-        // Intercepted in the tick, before this dispatcher — it needs the event
-        // state, which lives on the delegate. Reaching here means that branch was
-        // lost in a refactor.
-        IpcCommand::Subscribe { .. } => {
-            log::error!("IPC: subscribe reached the generic dispatcher");
-            crate::ipc::IpcResponse::Error {
-                message: "internal: subscribe was not intercepted".to_string(),
+    fn collect_flags(&self, out: &mut std::collections::HashMap<crate::pane::PaneId, crate::events::PaneFlags>) {
+        let wins = self.borrow();
+        for (idx, win) in wins.iter().enumerate() {
+            if let Some(view) = kova_view(win) {
+                view.events_collect_flags(idx, out);
             }
         }
     }
-}
 
-/// Translate an IPC mode string into a `DumpMode`. Caller has already validated
-/// the value, but we map defensively to keep the boundary explicit.
-fn parse_dump_mode(mode: &str) -> crate::terminal::DumpMode {
-    use crate::terminal::DumpMode;
-    match mode {
-        "scrollback" => DumpMode::Scrollback,
-        "all" => DumpMode::All,
-        _ => DumpMode::Visible,
+    fn pane_json(&self, win_idx: usize, pane_id: crate::pane::PaneId) -> Option<serde_json::Value> {
+        let wins = self.borrow();
+        let win = wins.get(win_idx)?;
+        let is_key = win.isKeyWindow();
+        kova_view(win)?.events_pane_json(win_idx, pane_id, is_key)
     }
-}
 
-/// Resolve a `PaneFilter` into the concrete list of pane IDs to act on.
-/// For `All`, walks every window in order; for `Ids`, returns the list as-is.
-fn resolve_pane_filter(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    filter: crate::ipc::PaneFilter,
-) -> Vec<u32> {
-    use crate::ipc::PaneFilter;
-    match filter {
-        PaneFilter::Ids(ids) => ids,
-        PaneFilter::All => {
-            let wins = windows.borrow();
-            let mut ids = Vec::new();
-            for win in wins.iter() {
-                if let Some(view) = kova_view(win) {
-                    view.ipc_collect_pane_ids(&mut ids);
-                }
+    fn collect_panes(&self, out: &mut Vec<serde_json::Value>) {
+        let wins = self.borrow();
+        for (idx, win) in wins.iter().enumerate() {
+            if let Some(view) = kova_view(win) {
+                view.ipc_collect_panes(idx, win.isKeyWindow(), out);
             }
-            ids
         }
     }
 }
 
-/// IPC: return the rendered text of the requested panes.
-fn handle_ipc_get_pane_content(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    filter: crate::ipc::PaneFilter,
-    mode_str: &str,
-    trim: bool,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let mode = parse_dump_mode(mode_str);
-    let ids = resolve_pane_filter(windows, filter);
-    let wins = windows.borrow();
-
-    let mut entries: Vec<serde_json::Value> = Vec::with_capacity(ids.len());
-    for pane_id in ids {
-        let mut found: Option<serde_json::Value> = None;
-        for win in wins.iter() {
-            if let Some(view) = kova_view(win) {
-                if let Some(entry) = view.ipc_dump_pane_text(pane_id, mode, trim) {
-                    found = Some(entry);
-                    break;
-                }
-            }
-        }
-        match found {
-            Some(entry) => entries.push(entry),
-            None => entries.push(serde_json::json!({
-                "id": pane_id,
-                "error": "not found",
-            })),
+impl AppDelegateIvars {
+    /// The windows, as the shared IPC command handling sees them.
+    fn ipc_host(&self) -> IpcWindows<'_> {
+        IpcWindows {
+            windows: &self.windows,
+            config: self.config.get().expect("config must be set before the first tick"),
+            held: Vec::new(),
         }
     }
+}
 
-    IpcResponse::Ok {
-        data: Some(serde_json::json!({ "panes": entries })),
+/// Every Kova window, for `ipc_host`. New panes and tabs go to the key window.
+struct IpcWindows<'a> {
+    windows: &'a RefCell<Vec<Retained<NSWindow>>>,
+    config: &'a Config,
+    /// The windows a `batch` holds the layout of. A copy of the list, not a
+    /// borrow: the steps borrow it themselves, and `merge-window` closes one
+    /// of the windows.
+    held: Vec<Retained<NSWindow>>,
+}
+
+impl IpcWindows<'_> {
+    /// The key window, or the first one when Kova is in the background.
+    fn key_window(&self) -> Option<Retained<NSWindow>> {
+        let wins = self.windows.borrow();
+        wins.iter().find(|w| w.isKeyWindow()).or_else(|| wins.first()).cloned()
     }
 }
 
-/// IPC: return only the size (chars/bytes) of what `get-pane-content` would return.
-fn handle_ipc_count_pane_content(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    filter: crate::ipc::PaneFilter,
-    mode_str: &str,
-    trim: bool,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
+impl IpcHost for IpcWindows<'_> {
+    fn config(&self) -> &Config {
+        self.config
+    }
 
-    let mode = parse_dump_mode(mode_str);
-    let ids = resolve_pane_filter(windows, filter);
-    let wins = windows.borrow();
+    fn with_pane<R>(&self, pane_id: PaneId, f: impl FnOnce(&Pane) -> R) -> Option<R> {
+        let wins = self.windows.borrow();
+        let view = wins
+            .iter()
+            .filter_map(|win| kova_view(win))
+            .find(|view| view.ipc_with_pane(pane_id, |_| ()).is_some())?;
+        view.ipc_with_pane(pane_id, f)
+    }
 
-    let mut entries: Vec<serde_json::Value> = Vec::with_capacity(ids.len());
-    let mut total_chars: u64 = 0;
-    let mut total_bytes: u64 = 0;
+    fn with_tab_of<R>(&mut self, pane_id: PaneId, f: impl FnOnce(&mut Tab) -> R) -> Option<R> {
+        let wins = self.windows.borrow();
+        let view = wins
+            .iter()
+            .filter_map(|win| kova_view(win))
+            .find(|view| view.ipc_with_pane(pane_id, |_| ()).is_some())?;
+        view.ipc_with_tab_of(pane_id, f)
+    }
 
-    for pane_id in ids {
-        let mut measured: Option<(usize, usize)> = None;
-        for win in wins.iter() {
+    fn pane_ids(&self) -> Vec<PaneId> {
+        let mut ids = Vec::new();
+        for win in self.windows.borrow().iter() {
             if let Some(view) = kova_view(win) {
-                if let Some(m) = view.ipc_measure_pane_text(pane_id, mode, trim) {
-                    measured = Some(m);
-                    break;
-                }
-            }
-        }
-        match measured {
-            Some((chars, bytes)) => {
-                total_chars += chars as u64;
-                total_bytes += bytes as u64;
-                entries.push(serde_json::json!({
-                    "id": pane_id,
-                    "chars": chars,
-                    "bytes": bytes,
-                }));
+                view.ipc_collect_pane_ids(&mut ids);
             }
-            None => entries.push(serde_json::json!({
-                "id": pane_id,
-                "error": "not found",
-            })),
         }
+        ids
     }
 
-    IpcResponse::Ok {
-        data: Some(serde_json::json!({
-            "total_chars": total_chars,
-            "total_bytes": total_bytes,
-            "panes": entries,
-        })),
+    fn list_panes(&self) -> Vec<serde_json::Value> {
+        let mut panes = Vec::new();
+        crate::events::EventSource::collect_panes(self.windows, &mut panes);
+        panes
     }
-}
 
-/// IPC: wait for OSC 133;D on a pane.
-///
-/// If the flag is already set when the request arrives, reply immediately
-/// (don't make the client wait an extra tick for the obvious answer).
-/// Otherwise return `Pending` so the main thread keeps polling on each tick.
-fn handle_ipc_wait_for_completion(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    timeout_ms: u64,
-) -> Disposition {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        if let Some(view) = kova_view(win) {
-            match view.ipc_check_completion(pane_id) {
-                Some(true) => {
-                    return Disposition::Reply(IpcResponse::Ok {
-                        data: Some(serde_json::json!({
-                            "completed": true,
-                            "pane_id": pane_id,
-                            "timed_out": false,
-                        })),
-                    });
-                }
-                Some(false) => {
-                    let deadline = std::time::Instant::now()
-                        + std::time::Duration::from_millis(timeout_ms);
-                    return Disposition::Pending(DeferredWait { pane_id, deadline });
-                }
-                None => continue, // pane not in this window
+    fn list_tabs(&self) -> Vec<serde_json::Value> {
+        let mut tabs = Vec::new();
+        for (win_idx, win) in self.windows.borrow().iter().enumerate() {
+            if let Some(view) = kova_view(win) {
+                view.ipc_collect_tabs(win_idx, win.isKeyWindow(), &mut tabs);
             }
         }
+        tabs
     }
 
-    Disposition::Reply(IpcResponse::Error {
-        message: format!("pane {} not found", pane_id),
-    })
-}
-
-/// On each main-thread tick, resolve any `wait-for-completion` requests
-/// whose pane fired OSC 133;D, hit their deadline, or got closed.
-fn poll_pending_waits(
-    pending: &RefCell<Vec<PendingWait>>,
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-) {
-    let mut waits = pending.borrow_mut();
-    if waits.is_empty() {
-        return;
+    fn focused_cwd(&self) -> Option<String> {
+        let win = self.key_window()?;
+        kova_view(&win)?.ipc_focused_cwd()
     }
 
-    let now = std::time::Instant::now();
-    let wins = windows.borrow();
+    fn split_focused(
+        &mut self,
+        direction: SplitDirection,
+        make_pane: impl FnOnce(u16, u16) -> Result<Pane, String>,
+    ) -> Result<PaneId, String> {
+        let win = self.key_window().ok_or("no window")?;
+        let view = kova_view(&win).ok_or("no view")?;
+        view.ipc_split_focused(direction, make_pane)
+    }
 
-    waits.retain(|wait| {
-        let mut completion: Option<bool> = None;
-        let mut found = false;
-        for win in wins.iter() {
-            if let Some(view) = kova_view(win) {
-                if let Some(c) = view.ipc_check_completion(wait.pane_id) {
-                    completion = Some(c);
-                    found = true;
-                    break;
+    fn open_tab(&mut self, tab: Tab) {
+        match self.key_window() {
+            Some(win) => {
+                if let Some(view) = kova_view(&win) {
+                    view.ipc_open_tab(tab);
                 }
             }
+            None => log::error!("IPC: no window to open tab {} in", tab.id),
         }
+    }
 
-        if !found {
-            let _ = wait.response_tx.send(crate::ipc::IpcResponse::Error {
-                message: format!("pane {} closed during wait", wait.pane_id),
-            });
-            return false;
-        }
-
-        if completion == Some(true) {
-            let _ = wait.response_tx.send(crate::ipc::IpcResponse::Ok {
-                data: Some(serde_json::json!({
-                    "completed": true,
-                    "pane_id": wait.pane_id,
-                    "timed_out": false,
-                })),
-            });
-            return false;
-        }
-
-        if now >= wait.deadline {
-            let _ = wait.response_tx.send(crate::ipc::IpcResponse::Ok {
-                data: Some(serde_json::json!({
-                    "completed": false,
-                    "pane_id": wait.pane_id,
-                    "timed_out": true,
-                })),
-            });
-            return false;
-        }
-
-        true // still waiting
-    });
-}
-
-/// IPC: set the custom title of the tab containing `pane_id`.
-fn handle_ipc_set_tab_title(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    title: Option<String>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
+    fn close_pane(&mut self, pane_id: PaneId) -> Option<bool> {
+        let wins = self.windows.borrow();
+        wins.iter().filter_map(|win| kova_view(win)).find_map(|view| view.ipc_close_pane(pane_id))
+    }
 
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_set_tab_title(pane_id, title.clone()) {
-            return IpcResponse::Ok { data: None };
+    fn focus_pane(&mut self, pane_id: PaneId) -> bool {
+        let wins = self.windows.borrow();
+        for win in wins.iter() {
+            let Some(view) = kova_view(win) else { continue };
+            if view.ipc_focus_pane(pane_id) {
+                win.makeKeyAndOrderFront(None);
+                let app = NSApplication::sharedApplication(MainThreadMarker::new().unwrap());
+                app.activate();
+                return true;
+            }
         }
+        false
     }
 
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-/// IPC: set the color of the tab holding `pane_id`.
-fn handle_ipc_set_tab_color(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    color: Option<usize>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_set_tab_color(pane_id, color) {
-            return IpcResponse::Ok { data: None };
+    fn close_tab(&mut self, tab_id: u32) -> IpcCloseTabResult {
+        let wins = self.windows.borrow();
+        for view in wins.iter().filter_map(|win| kova_view(win)) {
+            match view.ipc_close_tab(tab_id) {
+                IpcCloseTabResult::NotFound => continue,
+                result => return result,
+            }
         }
+        IpcCloseTabResult::NotFound
     }
 
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-/// IPC: split the focused pane in the key window.
-fn handle_ipc_split(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    config_cell: &OnceCell<Config>,
-    direction: &str,
-    command: Option<String>,
-    cwd: Option<String>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let config = match config_cell.get() {
-        Some(c) => c,
-        None => return IpcResponse::Error { message: "config not loaded".to_string() },
-    };
-
-    let wins = windows.borrow();
-    // Find the key window (first one, or the one that isKeyWindow)
-    let win = wins.iter()
-        .find(|w| w.isKeyWindow())
-        .or_else(|| wins.first());
-    let win = match win {
-        Some(w) => w,
-        None => return IpcResponse::Error { message: "no window".to_string() },
-    };
-    let view = match kova_view(win) {
-        Some(v) => v,
-        None => return IpcResponse::Error { message: "no view".to_string() },
-    };
-
-    let split_dir = match direction {
-        "vertical" => crate::pane::SplitDirection::Vertical,
-        _ => crate::pane::SplitDirection::Horizontal,
-    };
-
-    // Determine CWD: explicit param > focused pane's CWD
-    let effective_cwd = cwd.or_else(|| view.ipc_focused_cwd());
-
-    let new_pane_id = view.ipc_split(config, split_dir, effective_cwd.as_deref(), command);
-    match new_pane_id {
-        Some(id) => IpcResponse::Ok {
-            data: Some(serde_json::json!({"pane_id": id})),
-        },
-        None => IpcResponse::Error { message: "split failed".to_string() },
+    fn merge_tab(&mut self, source_tab_id: u32, target_tab_id: u32) -> IpcMergeTabResult {
+        let wins = self.windows.borrow();
+        for view in wins.iter().filter_map(|win| kova_view(win)) {
+            match view.ipc_merge_tab(source_tab_id, target_tab_id) {
+                IpcMergeTabResult::SourceMissing => continue,
+                result => return result,
+            }
+        }
+        IpcMergeTabResult::SourceMissing
     }
-}
 
-/// IPC: list all panes across all windows.
-fn handle_ipc_list_panes(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    let mut panes = Vec::new();
-    for (win_idx, win) in wins.iter().enumerate() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        let is_key = win.isKeyWindow();
-        view.ipc_collect_panes(win_idx, is_key, &mut panes);
+    fn swap_pane(&mut self, pane_id_a: PaneId, pane_id_b: PaneId) -> IpcSwapPaneResult {
+        let wins = self.windows.borrow();
+        for view in wins.iter().filter_map(|win| kova_view(win)) {
+            match view.ipc_swap_pane(pane_id_a, pane_id_b) {
+                IpcSwapPaneResult::AMissing => continue,
+                result => return result,
+            }
+        }
+        IpcSwapPaneResult::AMissing
     }
 
-    IpcResponse::Ok {
-        data: Some(serde_json::Value::Array(panes)),
+    fn resize_pane(&mut self, pane_id: PaneId, axis: SplitAxis, grow: bool, amount_pct: f32) -> Option<bool> {
+        let wins = self.windows.borrow();
+        wins.iter()
+            .filter_map(|win| kova_view(win))
+            .find_map(|view| view.ipc_resize_pane(pane_id, axis, grow, amount_pct))
     }
-}
-
-/// IPC: close a pane by ID.
-fn handle_ipc_close_pane(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
 
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        match view.ipc_close_pane(pane_id) {
-            Some(true) => return IpcResponse::Ok { data: None },
-            Some(false) => return IpcResponse::Error { message: format!("pane {} is the last pane — cannot close", pane_id) },
-            None => continue,
+    fn hold_layout(&mut self) {
+        self.held = self.windows.borrow().clone();
+        for win in &self.held {
+            if let Some(view) = kova_view(win) {
+                view.ipc_hold_layout();
+            }
         }
     }
 
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-/// IPC: send keystrokes to a pane's PTY.
-fn handle_ipc_send_keys(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    text: &str,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_send_keys(pane_id, text) {
-            return IpcResponse::Ok { data: None };
+    fn release_layout(&mut self) {
+        for win in std::mem::take(&mut self.held) {
+            if let Some(view) = kova_view(&win) {
+                view.ipc_release_layout();
+            }
         }
     }
 
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-/// IPC: focus a pane by ID (switch tab/window if needed).
-fn handle_ipc_focus_pane(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_focus_pane(pane_id) {
-            win.makeKeyAndOrderFront(None);
-            let app = NSApplication::sharedApplication(MainThreadMarker::new().unwrap());
-            app.activate();
-            return IpcResponse::Ok { data: None };
+    fn on_screen(&mut self, cmd: crate::ipc::IpcCommand) -> crate::ipc::IpcResponse {
+        use crate::ipc::IpcCommand;
+        match cmd {
+            IpcCommand::DispatchAction { action, pane_id } => {
+                handle_ipc_dispatch_action(self.windows, &action, pane_id)
+            }
+            IpcCommand::MergeWindow { source_window, target_window } => {
+                handle_ipc_merge_window(self.windows, source_window, target_window)
+            }
+            IpcCommand::Notify { pane_id, title, message, sound } => {
+                handle_ipc_notify(pane_id, &title, &message, sound)
+            }
+            _ => {
+                log::error!("IPC: a pane command reached IpcWindows::on_screen");
+                crate::ipc::IpcResponse::Error { message: "internal: command was not intercepted".to_string() }
+            }
         }
     }
-
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
 }
 
 /// IPC: post a desktop notification whose click focuses `pane_id`.
@@ -1047,235 +781,6 @@ fn handle_ipc_notify(
     }
 }
 
-/// IPC: create a new tab in the key window.
-fn handle_ipc_new_tab(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    config_cell: &OnceCell<Config>,
-    cwd: Option<String>,
-    cmd: Option<String>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let config = match config_cell.get() {
-        Some(c) => c,
-        None => return IpcResponse::Error { message: "config not loaded".to_string() },
-    };
-
-    let wins = windows.borrow();
-    let win = wins.iter()
-        .find(|w| w.isKeyWindow())
-        .or_else(|| wins.first());
-    let win = match win {
-        Some(w) => w,
-        None => return IpcResponse::Error { message: "no window".to_string() },
-    };
-    let view = match kova_view(win) {
-        Some(v) => v,
-        None => return IpcResponse::Error { message: "no view".to_string() },
-    };
-
-    match view.ipc_new_tab(config, cwd.as_deref(), cmd) {
-        Some((tab_id, pane_id)) => IpcResponse::Ok {
-            data: Some(serde_json::json!({"tab_id": tab_id, "pane_id": pane_id})),
-        },
-        None => IpcResponse::Error { message: "failed to create tab".to_string() },
-    }
-}
-
-/// IPC: list all tabs across all windows.
-fn handle_ipc_list_tabs(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    let mut tabs_json = Vec::new();
-    for (win_idx, win) in wins.iter().enumerate() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        let is_key = win.isKeyWindow();
-        view.ipc_collect_tabs(win_idx, is_key, &mut tabs_json);
-    }
-
-    IpcResponse::Ok {
-        data: Some(serde_json::Value::Array(tabs_json)),
-    }
-}
-
-/// IPC: close a tab by ID. Refuses to close the last tab of its window
-/// (would terminate the app — use a dedicated shutdown path for that).
-fn handle_ipc_close_tab(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    tab_id: u32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-    use crate::window::IpcCloseTabResult;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        match view.ipc_close_tab(tab_id) {
-            IpcCloseTabResult::Closed => return IpcResponse::Ok { data: None },
-            IpcCloseTabResult::WouldTerminate => {
-                return IpcResponse::Error {
-                    message: format!("tab {} is the last tab in its window — refusing to close (would terminate app)", tab_id),
-                };
-            }
-            IpcCloseTabResult::NotFound => continue,
-        }
-    }
-
-    IpcResponse::Error { message: format!("tab {} not found", tab_id) }
-}
-
-/// IPC: merge a source tab into a target tab.
-fn handle_ipc_merge_tab(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    source_tab_id: u32,
-    target_tab_id: u32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-    use crate::window::IpcMergeTabResult;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        match view.ipc_merge_tab(source_tab_id, target_tab_id) {
-            IpcMergeTabResult::Merged => return IpcResponse::Ok { data: None },
-            IpcMergeTabResult::SourceMissing => continue,
-            IpcMergeTabResult::TargetMissing => {
-                return IpcResponse::Error {
-                    message: format!("target tab {} not found in the same window as source tab {}", target_tab_id, source_tab_id),
-                };
-            }
-        }
-    }
-
-    IpcResponse::Error { message: format!("source tab {} not found", source_tab_id) }
-}
-
-/// IPC: swap two panes.
-fn handle_ipc_swap_pane(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id_a: u32,
-    pane_id_b: u32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-    use crate::window::IpcSwapPaneResult;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        match view.ipc_swap_pane(pane_id_a, pane_id_b) {
-            IpcSwapPaneResult::Swapped => return IpcResponse::Ok { data: None },
-            IpcSwapPaneResult::AMissing => continue,
-            IpcSwapPaneResult::BMissing => {
-                return IpcResponse::Error {
-                    message: format!("pane {} not found in the same tab as pane {}", pane_id_b, pane_id_a),
-                };
-            }
-            IpcSwapPaneResult::Failed => {
-                return IpcResponse::Error {
-                    message: format!("could not swap panes {} and {}", pane_id_a, pane_id_b),
-                };
-            }
-        }
-    }
-
-    IpcResponse::Error { message: format!("pane {} not found", pane_id_a) }
-}
-
-/// IPC: resize the split containing `pane_id`.
-fn handle_ipc_resize_pane(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    axis: &str,
-    direction: &str,
-    amount_pct: f32,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-    use crate::pane::SplitAxis;
-
-    let split_axis = match axis {
-        "vertical" => SplitAxis::Vertical,
-        _ => SplitAxis::Horizontal,
-    };
-    let grow = direction == "grow";
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        match view.ipc_resize_pane(pane_id, split_axis, grow, amount_pct) {
-            Some(true) => return IpcResponse::Ok { data: None },
-            Some(false) => {
-                return IpcResponse::Error {
-                    message: format!("pane {} has no neighbor along {} axis to resize against", pane_id, axis),
-                };
-            }
-            None => continue,
-        }
-    }
-
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-/// IPC: rename a pane (set sticky custom title, like OSC 1).
-fn handle_ipc_set_pane_status(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    waiting: bool,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_set_pane_status(pane_id, waiting) {
-            return IpcResponse::Ok { data: None };
-        }
-    }
-
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
-fn handle_ipc_rename_pane(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    pane_id: u32,
-    title: Option<String>,
-) -> crate::ipc::IpcResponse {
-    use crate::ipc::IpcResponse;
-
-    let wins = windows.borrow();
-    for win in wins.iter() {
-        let view = match kova_view(win) {
-            Some(v) => v,
-            None => continue,
-        };
-        if view.ipc_rename_pane(pane_id, title.clone()) {
-            return IpcResponse::Ok { data: None };
-        }
-    }
-
-    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
-}
-
 /// IPC: trigger any keyboard action by its stable name. With `pane_id`, the
 /// owning window is focused first and the action runs there; without it, the
 /// action runs against the key window.
@@ -19,6 +19,8 @@ use std::time::{Duration, Instant};
 
 use parking_lot::Mutex;
 
+use crate::terminal::procinfo;
+
 /// How far up the process tree to look for the pane's shell (claude → shell is
 /// the normal case; the extra levels cover a wrapper script in between).
 const MAX_ANCESTRY_DEPTH: usize = 3;
@@ -51,24 +53,6 @@ fn sessions_dir() -> PathBuf {
     PathBuf::from(home).join(".claude/sessions")
 }
 
-/// Parent PID and start time (epoch seconds) of a live process.
-fn proc_info(pid: u32) -> Option<(u32, u64)> {
-    unsafe {
-        let mut info: libc::proc_bsdinfo = std::mem::zeroed();
-        let ret = libc::proc_pidinfo(
-            pid as i32,
-            libc::PROC_PIDTBSDINFO,
-            0,
-            &mut info as *mut _ as *mut libc::c_void,
-            std::mem::size_of::<libc::proc_bsdinfo>() as i32,
-        );
-        if ret <= 0 {
-            return None;
-        }
-        Some((info.pbi_ppid, info.pbi_start_tvsec))
-    }
-}
-
 /// Read one `~/.claude/sessions/<pid>.json` body into the PID that owns it, the
 /// epoch-second it started at, and the session it describes. `None` when the
 /// file is not JSON or misses a field we cannot do without.
@@ -125,7 +109,7 @@ fn scan_uncached() -> HashMap<u32, Session> {
         // process name: the executable is a version-numbered file
         // (~/.local/share/claude/versions/2.1.220), so its name is a version
         // string, not "claude".
-        let Some((_, start_secs)) = proc_info(pid) else { continue };
+        let Some((_, start_secs)) = procinfo::system().parent_and_start(pid) else { continue };
         if start_secs.abs_diff(started_at) > START_TIME_TOLERANCE_SECS {
             log::debug!("Ignoring stale Claude session file {}", path.display());
             continue;
@@ -133,7 +117,7 @@ fn scan_uncached() -> HashMap<u32, Session> {
 
         let mut current = pid;
         for _ in 0..MAX_ANCESTRY_DEPTH {
-            let Some((parent, _)) = proc_info(current) else { break };
+            let Some((parent, _)) = procinfo::system().parent_and_start(current) else { break };
             if parent <= 1 || parent == own_pid {
                 break;
             }
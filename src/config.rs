@@ -13,6 +13,9 @@ pub struct Config {
     pub splits: SplitsConfig,
     pub global_status_bar: GlobalStatusBarConfig,
     pub keys: KeysConfig,
+    pub session: SessionConfig,
+    pub recording: RecordingConfig,
+    pub ipc: IpcConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -48,11 +51,83 @@ pub struct TerminalConfig {
     pub columns: u16,
     pub rows: u16,
     pub scrollback: usize,
+    /// Per-pane cap on scrollback RAM, in MB (0 = bounded by `scrollback` lines only).
+    pub scrollback_memory_mb: usize,
+    /// Per-pane spill file for history past the in-memory limits, in MB (0 = drop it).
+    pub scrollback_disk_mb: usize,
+    /// App-wide cap on scrollback RAM across all panes, in MB (0 = none).
+    pub scrollback_total_mb: usize,
     pub fps: u32,
     pub cursor_blink_frames: u32,
     pub scroll_sensitivity: f64,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Save each pane's screen and recent scrollback with the session, and
+    /// put it back on restore.
+    pub save_scrollback: bool,
+    /// Scrollback lines saved per pane (on top of the screen).
+    pub scrollback_lines: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { save_scrollback: false, scrollback_lines: 1000 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Where pane recordings (`.cast`) are written. `~` is expanded.
+    pub dir: String,
+    /// Also record keystrokes (asciicast `"i"` events). Off by default: they
+    /// include anything typed at a password prompt.
+    pub record_input: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        RecordingConfig { dir: "~/Movies/Kova".into(), record_input: false }
+    }
+}
+
+impl RecordingConfig {
+    pub fn dir_path(&self) -> PathBuf {
+        match self.dir.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(std::env::var("HOME").unwrap_or_else(|_| ".".to_string())).join(rest),
+            None => PathBuf::from(&self.dir),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct IpcConfig {
+    /// Refuse every IPC command until the connection sends `hello` with a
+    /// token (see `ipc_auth`). Off by default: the socket is owner-only.
+    pub require_token: bool,
+    /// Tokens for clients outside Kova's panes, each with the scopes it grants.
+    pub tokens: Vec<IpcTokenConfig>,
+    /// Append every IPC command that changes something to `ipc-audit.jsonl`
+    /// in the log directory (see `ipc_audit`).
+    pub audit_log: bool,
+    /// Also keep the text of `send-keys`, `write-output` and `notify` in the
+    /// audit log. Off: only its length.
+    pub audit_payloads: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IpcTokenConfig {
+    /// Only used in the log, to tell who connected.
+    pub name: String,
+    pub token: String,
+    /// Any of `read`, `control`, `input`, `admin`.
+    pub scopes: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(default)]
 pub struct StatusBarConfig {
@@ -139,6 +214,9 @@ impl Default for Config {
             splits: SplitsConfig::default(),
             global_status_bar: GlobalStatusBarConfig::default(),
             keys: KeysConfig::default(),
+            session: SessionConfig::default(),
+            recording: RecordingConfig::default(),
+            ipc: IpcConfig::default(),
         }
     }
 }
@@ -180,6 +258,9 @@ impl Default for TerminalConfig {
             columns: 80,
             rows: 24,
             scrollback: 10_000,
+            scrollback_memory_mb: 0,
+            scrollback_disk_mb: 0,
+            scrollback_total_mb: 0,
             fps: 60,
             cursor_blink_frames: 60,
             scroll_sensitivity: 6.0,
@@ -255,6 +336,8 @@ pub struct KeysConfig {
     pub kill_window: String,
     pub copy: String,
     pub copy_raw: String,
+    /// Copy the selection with its colors and attributes (HTML + RTF).
+    pub copy_rich: String,
     pub paste: String,
     pub toggle_filter: String,
     pub prev_tab: String,
@@ -265,6 +348,8 @@ pub struct KeysConfig {
     pub break_pane: String,
     pub merge_tab: String,
     pub merge_window: String,
+    /// Start/stop recording the focused pane to an asciicast file.
+    pub toggle_recording: String,
 
     pub switch_tab_1: String,
     pub switch_tab_2: String,
@@ -322,6 +407,7 @@ impl Default for KeysConfig {
             kill_window: "cmd+option+q".into(),
             copy: "cmd+c".into(),
             copy_raw: "cmd+shift+c".into(),
+            copy_rich: "cmd+option+c".into(),
             paste: "cmd+v".into(),
             toggle_filter: "cmd+f".into(),
             prev_tab: "cmd+shift+[".into(),
@@ -332,6 +418,7 @@ impl Default for KeysConfig {
             break_pane: "cmd+ctrl+t".into(),
             merge_tab: "cmd+ctrl+m".into(),
             merge_window: "cmd+ctrl+shift+m".into(),
+            toggle_recording: "cmd+ctrl+r".into(),
 
             switch_tab_1: "cmd+1".into(),
             switch_tab_2: "cmd+2".into(),
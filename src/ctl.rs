@@ -0,0 +1,769 @@
+//! `kova ctl`: the IPC protocol from the command line.
+//!
+//! Every command of `ipc_schema::COMMANDS` is a subcommand, and each of its
+//! fields a flag typed after the table (`pane_id` → `--pane-id N`). The socket
+//! is `--socket`, else `$KOVA_SOCKET` (set in every Kova pane), else the one
+//! Kova running — `--pid` picks among several. A token (`--token`, else
+//! `$KOVA_TOKEN`) is sent in a `hello` first. Prints tables, or the response's
+//! `data` with `--json`; `subscribe` streams its events until Kova goes away.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::instances::{self, Instance};
+use crate::ipc_schema::{self, CommandSpec, FieldSpec, FieldType};
+
+pub const USAGE: &str = "\
+USAGE: kova ctl [OPTIONS] <command> [--<field> VALUE ...]
+       kova ctl <command> --help              the flags of one command
+       kova ctl commands                      every command and its flags
+       kova ctl instances                     the Kovas running, * for kova.sock's
+       kova ctl completions bash|zsh|fish     print a completion script
+
+OPTIONS:
+  --socket PATH      talk to the Kova listening on PATH
+  --pid N            talk to the Kova running as pid N
+  --token TOKEN      authenticate with TOKEN (default: $KOVA_TOKEN)
+  --json             print the response's data as JSON instead of a table
+
+Without --socket or --pid: $KOVA_SOCKET, else the Kova that kova.sock leads
+to (the one focused last), else the only Kova running.
+Each field of the command is a flag (pane_id → --pane-id). Lists are comma
+separated (--panes 3,4), a boolean takes true, false or nothing (--regex),
+and null clears an optional number or list (--color null). Text is sent as
+typed: --title null sets the title 'null'; leave --title out to clear it.
+
+EXIT STATUS: 0 done, 1 Kova refused the command, 2 usage or connection error";
+
+/// Exit status when Kova answered `ok: false`.
+const REFUSED: i32 = 1;
+/// Exit status when the command never reached Kova, or got no answer.
+const UNUSABLE: i32 = 2;
+
+/// A failure and the exit status it maps to.
+type Outcome = Result<(), (i32, String)>;
+
+enum Target {
+    Socket(PathBuf),
+    Pid(u32),
+    Discover,
+}
+
+enum Action {
+    Usage,
+    Commands,
+    Instances,
+    Help(&'static CommandSpec),
+    Completions(Shell),
+    /// A request, `cmd` included.
+    Request(Map<String, Value>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+struct CtlArgs {
+    target: Target,
+    token: Option<String>,
+    json: bool,
+    action: Action,
+}
+
+fn parse_args(args: &[String]) -> Result<CtlArgs, String> {
+    let mut target = Target::Discover;
+    let mut token = None;
+    let mut json = false;
+
+    let mut it = args.iter();
+    let command = loop {
+        let Some(arg) = it.next() else {
+            return Err("missing command".to_string());
+        };
+        let mut value = |name: &str| it.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--socket" => target = Target::Socket(PathBuf::from(value("--socket")?)),
+            "--pid" => {
+                let pid = value("--pid")?;
+                target = Target::Pid(pid.parse().map_err(|_| format!("--pid expects a number, got {:?}", pid))?);
+            }
+            "--token" => token = Some(value("--token")?.clone()),
+            "--json" => json = true,
+            "--help" | "-h" => return Ok(CtlArgs { target, token, json, action: Action::Usage }),
+            s if s.starts_with("--") => return Err(format!("unknown option: {}", s)),
+            s => break s,
+        }
+    };
+    let rest: Vec<String> = it.cloned().collect();
+
+    let action = match command {
+        "commands" => Action::Commands,
+        "instances" => Action::Instances,
+        "completions" => match rest.as_slice() {
+            [shell] => Action::Completions(match shell.as_str() {
+                "bash" => Shell::Bash,
+                "zsh" => Shell::Zsh,
+                "fish" => Shell::Fish,
+                other => return Err(format!("no completions for {:?}: bash, zsh or fish", other)),
+            }),
+            _ => return Err("completions takes one shell: bash, zsh or fish".to_string()),
+        },
+        name => {
+            let spec = ipc_schema::command(name)
+                .ok_or_else(|| format!("unknown command: {} (see kova ctl commands)", name))?;
+            parse_fields(spec, &rest, &mut json)?
+        }
+    };
+    Ok(CtlArgs { target, token, json, action })
+}
+
+/// The flags after the command name, into the fields of its request.
+fn parse_fields(spec: &'static CommandSpec, args: &[String], json: &mut bool) -> Result<Action, String> {
+    let mut request = Map::new();
+    request.insert("cmd".to_string(), spec.name.into());
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument: {}", arg));
+        };
+        let (name, inline) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+        match (name, &inline) {
+            ("help", None) => return Ok(Action::Help(spec)),
+            ("json", None) => {
+                *json = true;
+                continue;
+            }
+            _ => {}
+        }
+        let field = spec
+            .fields
+            .iter()
+            .find(|field| flag_name(field) == name)
+            .ok_or_else(|| format!("{} has no --{} (see kova ctl {} --help)", spec.name, name, spec.name))?;
+        let raw = match inline {
+            Some(value) => value,
+            // A bare boolean flag means true; `--regex false` still reads.
+            None if matches!(field.ty, FieldType::Boolean) => match args.get(i).map(String::as_str) {
+                Some(value @ ("true" | "false")) => {
+                    i += 1;
+                    value.to_string()
+                }
+                _ => "true".to_string(),
+            },
+            None => {
+                let value = args.get(i).ok_or_else(|| format!("--{} needs a value", name))?;
+                i += 1;
+                value.clone()
+            }
+        };
+        // `null` stands for "not given" where that is not a value of its own:
+        // an optional number or list, say. Text is taken as typed.
+        let value = match field.ty {
+            FieldType::String | FieldType::Enum(_) => field_value(field.ty, name, &raw)?,
+            _ if raw == "null" && !field.required => Value::Null,
+            _ => field_value(field.ty, name, &raw)?,
+        };
+        request.insert(field.name.to_string(), value);
+    }
+    Ok(Action::Request(request))
+}
+
+fn flag_name(field: &FieldSpec) -> String {
+    field.name.replace('_', "-")
+}
+
+/// The JSON for `raw`, given on the command line as `--<flag>`.
+fn field_value(ty: FieldType, flag: &str, raw: &str) -> Result<Value, String> {
+    fn integer(flag: &str, raw: &str) -> Result<Value, String> {
+        raw.trim().parse::<i64>().map(Value::from).map_err(|_| format!("--{} expects an integer, got {:?}", flag, raw))
+    }
+    fn one_of(flag: &str, values: &[&str], raw: &str) -> Result<Value, String> {
+        if values.contains(&raw) {
+            Ok(raw.into())
+        } else {
+            Err(format!("--{} must be one of {} (got {:?})", flag, values.join(", "), raw))
+        }
+    }
+    match ty {
+        FieldType::Integer => integer(flag, raw),
+        FieldType::Number => raw
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("--{} expects a number, got {:?}", flag, raw)),
+        FieldType::Boolean => match raw {
+            "true" => Ok(true.into()),
+            "false" => Ok(false.into()),
+            _ => Err(format!("--{} expects true or false, got {:?}", flag, raw)),
+        },
+        FieldType::String => Ok(raw.into()),
+        FieldType::Enum(values) => one_of(flag, values, raw),
+        FieldType::IntegerList => raw.split(',').map(|n| integer(flag, n)).collect::<Result<_, _>>().map(Value::Array),
+        FieldType::EnumList(values) => {
+            raw.split(',').map(|v| one_of(flag, values, v)).collect::<Result<_, _>>().map(Value::Array)
+        }
+        FieldType::AllOrIntegers if raw == "all" => Ok(raw.into()),
+        FieldType::AllOrIntegers => field_value(FieldType::IntegerList, flag, raw),
+        FieldType::Id => Ok(raw.parse::<i64>().map(Value::from).unwrap_or_else(|_| raw.into())),
+        FieldType::Requests => match serde_json::from_str(raw) {
+            Ok(requests @ Value::Array(_)) => Ok(requests),
+            _ => Err(format!("--{} expects a JSON array of requests, got {:?}", flag, raw)),
+        },
+    }
+}
+
+/// The socket to talk to.
+fn socket(target: &Target) -> Result<PathBuf, String> {
+    match target {
+        Target::Socket(path) => Ok(path.clone()),
+        Target::Pid(pid) => Ok(instances::socket_path_for(*pid)),
+        Target::Discover => {
+            if let Some(path) = std::env::var_os("KOVA_SOCKET").filter(|path| !path.is_empty()) {
+                return Ok(path.into());
+            }
+            let link = instances::dir().join(instances::LINK);
+            if instances::listening(&link) {
+                return Ok(link);
+            }
+            // A crashed Kova leaves its socket behind: only count the ones
+            // something still listens on.
+            let live: Vec<u32> = instances::socket_pids(instances::dir())
+                .into_iter()
+                .filter(|pid| instances::listening(&instances::socket_path_for(*pid)))
+                .collect();
+            pick(&live).map(instances::socket_path_for)
+        }
+    }
+}
+
+fn pick(live: &[u32]) -> Result<u32, String> {
+    match live {
+        [] => Err("no running Kova found (--socket PATH talks to one elsewhere)".to_string()),
+        [pid] => Ok(*pid),
+        _ => {
+            let pids: Vec<String> = live.iter().map(u32::to_string).collect();
+            Err(format!("{} Kovas are running (pids {}): pick one with --pid", live.len(), pids.join(", ")))
+        }
+    }
+}
+
+/// Send `request` to the Kova at `path`, after a `hello` with `token` if
+/// there is one, and print its answer, then the events that follow a
+/// `subscribe`.
+fn exchange(path: &Path, token: Option<&str>, request: Map<String, Value>, json: bool, out: &mut impl Write) -> Outcome {
+    let unusable = |message: String| (UNUSABLE, message);
+    let stream = UnixStream::connect(path)
+        .map_err(|e| unusable(format!("cannot connect to {}: {}", path.display(), e)))?;
+    let cmd = request["cmd"].as_str().unwrap_or_default().to_string();
+    let mut sent = String::new();
+    if let Some(token) = token {
+        sent.push_str(&serde_json::json!({ "cmd": "hello", "token": token }).to_string());
+        sent.push('\n');
+    }
+    sent.push_str(&Value::Object(request).to_string());
+    sent.push('\n');
+    (&stream).write_all(sent.as_bytes()).map_err(|e| unusable(format!("cannot send the request: {}", e)))?;
+
+    let mut lines = BufReader::new(&stream).lines();
+    let mut answer = || -> Result<Value, (i32, String)> {
+        let response = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => return Err(unusable(format!("cannot read the response: {}", e))),
+            None => return Err(unusable("Kova closed the connection without answering".to_string())),
+        };
+        let response: Value =
+            serde_json::from_str(&response).map_err(|e| unusable(format!("unreadable response: {}", e)))?;
+        if response["ok"] != true {
+            let error = response["error"].as_str().unwrap_or("the command failed");
+            return Err((REFUSED, error.to_string()));
+        }
+        Ok(response)
+    };
+    if token.is_some() {
+        answer().map_err(|(status, error)| (status, format!("hello: {}", error)))?;
+    }
+    let response = answer()?;
+
+    // Stop quietly when stdout goes away: `kova ctl subscribe | head` is done.
+    let data = response.get("data").cloned().unwrap_or(Value::Null);
+    if out.write_all(render(&cmd, &data, json).as_bytes()).is_err() {
+        return Ok(());
+    }
+    if cmd == "subscribe" {
+        for line in lines.map_while(Result::ok) {
+            let event = if json { line } else { render_event(&line) };
+            if writeln!(out, "{}", event).and_then(|()| out.flush()).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What to print for a command's `data`.
+fn render(cmd: &str, data: &Value, json: bool) -> String {
+    let text = match (cmd, data) {
+        (_, Value::Null) => return String::new(),
+        _ if json => data.to_string(),
+        ("list-panes", Value::Array(panes)) => pane_table(panes),
+        ("list-tabs", Value::Array(tabs)) => tab_table(tabs),
+        ("get-pane-content", _) if data["panes"].is_array() => pane_contents(&data["panes"]),
+        _ => serde_json::to_string_pretty(data).unwrap_or_default(),
+    };
+    if text.is_empty() || text.ends_with('\n') { text } else { text + "\n" }
+}
+
+/// Columns padded to their widest cell; the last one is not padded.
+fn table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headers = headers.iter().map(|h| h.to_string()).collect();
+    let mut text = String::new();
+    for row in std::iter::once(headers).chain(rows) {
+        let last = row.len().saturating_sub(1);
+        for (i, cell) in row.iter().enumerate() {
+            text.push_str(cell);
+            if i < last {
+                let pad = widths[i] - cell.chars().count() + 2;
+                text.extend(std::iter::repeat_n(' ', pad));
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// `*` marks the focused pane.
+fn pane_table(panes: &[Value]) -> String {
+    let state = |pane: &Value| {
+        if pane["awaiting"] == true {
+            "waiting"
+        } else if pane["working"] == true {
+            "working"
+        } else if pane["is_idle"] == true {
+            "idle"
+        } else {
+            "busy"
+        }
+    };
+    let rows = panes
+        .iter()
+        .map(|pane| {
+            let focused = if pane["focused"] == true { "*" } else { "" };
+            vec![
+                format!("{}{}", pane["id"], focused),
+                text(&pane["window"]),
+                text(&pane["tab"]),
+                state(pane).to_string(),
+                text(&pane["title"]),
+                text(&pane["cwd"]),
+            ]
+        })
+        .collect();
+    table(&["ID", "WIN", "TAB", "STATE", "TITLE", "CWD"], rows)
+}
+
+/// `*` marks the active tab.
+fn tab_table(tabs: &[Value]) -> String {
+    let rows = tabs
+        .iter()
+        .map(|tab| {
+            let active = if tab["active"] == true { "*" } else { "" };
+            vec![
+                format!("{}{}", tab["id"], active),
+                text(&tab["window"]),
+                text(&tab["tab_index"]),
+                text(&tab["pane_count"]),
+                text(&tab["title"]),
+            ]
+        })
+        .collect();
+    table(&["ID", "WIN", "INDEX", "PANES", "TITLE"], rows)
+}
+
+/// `*` marks the Kova `kova.sock` leads to.
+fn instance_table(list: &[Instance], json: bool) -> String {
+    if json {
+        return serde_json::to_string(list).unwrap_or_default() + "\n";
+    }
+    let linked = std::fs::read_link(instances::dir().join(instances::LINK)).ok();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let ago = |at: u64| {
+        let secs = now.saturating_sub(at);
+        match secs {
+            0..60 => format!("{}s ago", secs),
+            60..3600 => format!("{}m ago", secs / 60),
+            3600..86400 => format!("{}h ago", secs / 3600),
+            _ => format!("{}d ago", secs / 86400),
+        }
+    };
+    let rows = list
+        .iter()
+        .map(|instance| {
+            let current = linked.as_deref().is_some_and(|target| instance.socket.file_name() == Some(target.as_os_str()));
+            vec![
+                format!("{}{}", instance.pid, if current { "*" } else { "" }),
+                ago(instance.started_at),
+                instance.windows.to_string(),
+                instance.focused_at.map_or("never".to_string(), ago),
+                instance.socket.display().to_string(),
+            ]
+        })
+        .collect();
+    table(&["PID", "STARTED", "WINDOWS", "FOCUSED", "SOCKET"], rows)
+}
+
+/// Each pane's content as is, under a header when there are several.
+fn pane_contents(panes: &Value) -> String {
+    let panes = panes.as_array().map(Vec::as_slice).unwrap_or_default();
+    let mut dump = String::new();
+    for pane in panes {
+        if panes.len() > 1 {
+            dump.push_str(&format!("── pane {} ──\n", pane["id"]));
+        }
+        let content = match ["text", "ansi", "html"].iter().find_map(|key| pane[*key].as_str()) {
+            Some(content) => content.to_string(),
+            // One line of styled runs per line of the pane.
+            None if pane["cells"].is_array() => {
+                pane["cells"].as_array().into_iter().flatten().map(|line| line.to_string() + "\n").collect()
+            }
+            None if pane["error"].is_string() => format!("error: {}", text(&pane["error"])),
+            None => serde_json::to_string_pretty(pane).unwrap_or_default(),
+        };
+        dump.push_str(&content);
+        if !content.ends_with('\n') {
+            dump.push('\n');
+        }
+    }
+    dump
+}
+
+/// `focus pane_id=3 window=0`: the event name, then its fields.
+fn render_event(line: &str) -> String {
+    let Ok(Value::Object(event)) = serde_json::from_str::<Value>(line) else {
+        return line.to_string();
+    };
+    let mut words = vec![text(&event.get("event").cloned().unwrap_or_default())];
+    words.extend(event.iter().filter(|(key, _)| *key != "event").map(|(key, value)| format!("{}={}", key, value)));
+    words.join(" ")
+}
+
+/// How a flag's value is shown in help.
+fn metavar(ty: FieldType) -> String {
+    match ty {
+        FieldType::Integer => "N".to_string(),
+        FieldType::Number => "X".to_string(),
+        FieldType::Boolean => "[true|false]".to_string(),
+        FieldType::String => "TEXT".to_string(),
+        FieldType::Enum(values) => values.join("|"),
+        FieldType::IntegerList => "N,N,...".to_string(),
+        FieldType::EnumList(values) => format!("{},...", values.join("|")),
+        FieldType::AllOrIntegers => "all|N,N,...".to_string(),
+        FieldType::Id => "ID".to_string(),
+        FieldType::Requests => "'[{\"cmd\": ...}, ...]'".to_string(),
+    }
+}
+
+fn command_help(spec: &CommandSpec) -> String {
+    let mut text = format!("{} — {}\n", spec.name, spec.summary);
+    for field in spec.fields {
+        let mut line = format!("    --{} {}", flag_name(field), metavar(field.ty));
+        if field.required {
+            line.push_str("  (required)");
+        } else if let Some(default) = field.default {
+            line.push_str(&format!("  (default {})", default));
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+fn completions(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash_completions(),
+        // zsh runs the bash script through its compatibility layer.
+        Shell::Zsh => format!("autoload -U +X bashcompinit && bashcompinit\n{}", bash_completions()),
+        Shell::Fish => fish_completions(),
+    }
+}
+
+fn bash_completions() -> String {
+    let names: Vec<&str> = ipc_schema::COMMANDS.iter().map(|spec| spec.name).collect();
+    let mut script = String::from(
+        "# kova ctl completions for bash. Generated by `kova ctl completions bash`.\n\
+         _kova() {\n\
+         \x20 local cur=${COMP_WORDS[COMP_CWORD]} prev=${COMP_WORDS[COMP_CWORD-1]} cmd= words= i\n\
+         \x20 if ((COMP_CWORD == 1)); then\n\
+         \x20   COMPREPLY=($(compgen -W \"ctl replay\" -- \"$cur\"))\n\
+         \x20   return\n\
+         \x20 fi\n\
+         \x20 [[ ${COMP_WORDS[1]} == ctl ]] || return\n\
+         \x20 for ((i = 2; i < COMP_CWORD; i++)); do\n\
+         \x20   case ${COMP_WORDS[i]} in\n\
+         \x20     --socket|--pid|--token) ((i++)) ;;\n\
+         \x20     --*) ;;\n\
+         \x20     *) cmd=${COMP_WORDS[i]}; break ;;\n\
+         \x20   esac\n\
+         \x20 done\n\
+         \x20 case $cmd in\n",
+    );
+    script.push_str(&format!(
+        "    '') [[ $prev == --socket || $prev == --pid || $prev == --token ]] || words=\"--socket --pid --token --json commands instances completions {}\" ;;\n",
+        names.join(" ")
+    ));
+    script.push_str("    completions) words=\"bash zsh fish\" ;;\n");
+    for spec in ipc_schema::COMMANDS {
+        script.push_str(&format!("    {})\n      case $prev in\n", spec.name));
+        let mut free = Vec::new();
+        for field in spec.fields {
+            match field.ty {
+                FieldType::Enum(values) => {
+                    script.push_str(&format!("        --{}) words=\"{}\" ;;\n", flag_name(field), values.join(" ")));
+                }
+                FieldType::Boolean => {}
+                _ => free.push(format!("--{}", flag_name(field))),
+            }
+        }
+        if !free.is_empty() {
+            script.push_str(&format!("        {}) ;;\n", free.join("|")));
+        }
+        let mut flags: Vec<String> = spec.fields.iter().map(|field| format!("--{}", flag_name(field))).collect();
+        flags.extend(["--json".to_string(), "--help".to_string()]);
+        script.push_str(&format!("        *) words=\"{}\" ;;\n      esac ;;\n", flags.join(" ")));
+    }
+    script.push_str("  esac\n  COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n}\ncomplete -F _kova kova\n");
+    script
+}
+
+fn fish_completions() -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+    let names: Vec<&str> = ipc_schema::COMMANDS.iter().map(|spec| spec.name).collect();
+    let top = format!(
+        "__fish_seen_subcommand_from ctl; and not __fish_seen_subcommand_from {} commands instances completions",
+        names.join(" ")
+    );
+    let mut script = String::from("# kova ctl completions for fish. Generated by `kova ctl completions fish`.\n");
+    script.push_str("complete -c kova -n __fish_use_subcommand -xa 'ctl replay'\n");
+    script.push_str(&format!("complete -c kova -n {} -l socket -r -d 'Socket of the Kova to talk to'\n", quote(&top)));
+    script.push_str(&format!("complete -c kova -n {} -l pid -x -d 'Pid of the Kova to talk to'\n", quote(&top)));
+    script.push_str(&format!("complete -c kova -n {} -l token -x -d 'Token to authenticate with'\n", quote(&top)));
+    script.push_str("complete -c kova -n '__fish_seen_subcommand_from ctl' -l json -d 'Print the data as JSON'\n");
+    script.push_str(&format!("complete -c kova -n {} -xa commands -d 'Every command and its flags'\n", quote(&top)));
+    script.push_str(&format!("complete -c kova -n {} -xa instances -d 'The Kovas running'\n", quote(&top)));
+    script.push_str(&format!("complete -c kova -n {} -xa completions -d 'Print a completion script'\n", quote(&top)));
+    for spec in ipc_schema::COMMANDS {
+        script.push_str(&format!("complete -c kova -n {} -xa {} -d {}\n", quote(&top), spec.name, quote(spec.summary)));
+        let seen = quote(&format!("__fish_seen_subcommand_from ctl; and __fish_seen_subcommand_from {}", spec.name));
+        for field in spec.fields {
+            let takes = match field.ty {
+                FieldType::Boolean => String::new(),
+                FieldType::Enum(values) => format!(" -xa {}", quote(&values.join(" "))),
+                FieldType::String if matches!(field.name, "path" | "cwd") => " -r".to_string(),
+                _ => " -x".to_string(),
+            };
+            script.push_str(&format!("complete -c kova -n {} -l {}{}\n", seen, flag_name(field), takes));
+        }
+    }
+    script
+}
+
+/// Entry point for `kova ctl …`; returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let opts = match parse_args(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("kova ctl: {}\n\n{}", e, USAGE);
+            return UNUSABLE;
+        }
+    };
+    let printed = |text: String| {
+        print!("{}", text);
+        Ok(())
+    };
+    let result = match opts.action {
+        Action::Usage => printed(format!("{}\n", USAGE)),
+        Action::Commands => printed(ipc_schema::COMMANDS.iter().map(command_help).collect::<Vec<_>>().join("\n")),
+        Action::Instances => printed(instance_table(&instances::list(), opts.json)),
+        Action::Help(spec) => printed(command_help(spec)),
+        Action::Completions(shell) => printed(completions(shell)),
+        Action::Request(request) => {
+            let token = opts.token.or_else(|| std::env::var("KOVA_TOKEN").ok().filter(|token| !token.is_empty()));
+            socket(&opts.target)
+                .map_err(|e| (UNUSABLE, e))
+                .and_then(|path| exchange(&path, token.as_deref(), request, opts.json, &mut std::io::stdout().lock()))
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err((status, message)) => {
+            eprintln!("kova ctl: {}", message);
+            status
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(String::from).collect()
+    }
+
+    fn request(s: &str) -> Result<Value, String> {
+        match parse_args(&args(s))?.action {
+            Action::Request(request) => Ok(Value::Object(request)),
+            _ => Err("not a request".to_string()),
+        }
+    }
+
+    #[test]
+    fn flags_become_typed_fields() {
+        assert_eq!(
+            request("search-pane --pane-id 3 --pattern error --regex --case=insensitive"),
+            Ok(serde_json::json!({"cmd": "search-pane", "pane_id": 3, "pattern": "error", "regex": true, "case": "insensitive"}))
+        );
+        assert_eq!(
+            request("get-pane-content --panes 3,4 --trim-trailing-blank-lines false --json"),
+            Ok(serde_json::json!({"cmd": "get-pane-content", "panes": [3, 4], "trim_trailing_blank_lines": false}))
+        );
+        assert_eq!(request("set-tab-color --pane-id 3 --color null"), Ok(serde_json::json!({"cmd": "set-tab-color", "pane_id": 3, "color": null})));
+        assert_eq!(request("rename-pane --pane-id 3 --title null").unwrap()["title"], "null");
+        assert_eq!(request("send-keys --pane-id 3 --text null").unwrap()["text"], "null");
+        assert!(request("send-keys --pane-id null").unwrap_err().contains("expects an integer"));
+        assert_eq!(request("resize-pane --amount-pct 7.5").unwrap()["amount_pct"], 7.5);
+        assert_eq!(request("cancel --id build"), Ok(serde_json::json!({"cmd": "cancel", "id": "build"})));
+
+        assert!(request("search-pane --pane-id three").unwrap_err().contains("expects an integer"));
+        assert!(request("split --direction diagonal").unwrap_err().contains("horizontal, vertical"));
+        assert!(request("split --pane-id 3").unwrap_err().contains("split has no --pane-id"));
+        assert!(request("send-keys --pane-id").unwrap_err().contains("needs a value"));
+        assert!(request("bogus").unwrap_err().contains("unknown command: bogus"));
+
+        let opts = parse_args(&args("--pid 42 --token s3cret --json list-panes")).unwrap();
+        assert!(matches!(opts.target, Target::Pid(42)) && opts.json);
+        assert_eq!(opts.token.as_deref(), Some("s3cret"));
+        assert!(matches!(parse_args(&args("completions fish")).unwrap().action, Action::Completions(Shell::Fish)));
+        assert!(matches!(parse_args(&args("split --help")).unwrap().action, Action::Help(spec) if spec.name == "split"));
+    }
+
+    #[test]
+    fn discovery_wants_exactly_one_kova() {
+        assert_eq!(pick(&[12]), Ok(12));
+        assert!(pick(&[]).unwrap_err().contains("no running Kova"));
+        assert!(pick(&[12, 34]).unwrap_err().contains("pids 12, 34"));
+    }
+
+    /// A one-shot server answering with `lines`, handing back the request it got.
+    fn serve(name: &str, lines: &'static str) -> (PathBuf, std::thread::JoinHandle<String>) {
+        let path = std::env::temp_dir().join(format!("kova-ctl-{}-{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            stream.write_all(lines.as_bytes()).unwrap();
+            request
+        });
+        (path, server)
+    }
+
+    #[test]
+    fn a_refusal_exits_with_kova_s_error() {
+        let (path, server) = serve("refused", "{\"ok\":false,\"error\":\"pane 9 not found\"}\n");
+        let request = request("close-pane --pane-id 9").unwrap().as_object().unwrap().clone();
+        let mut out = Vec::new();
+        assert_eq!(exchange(&path, None, request, false, &mut out), Err((REFUSED, "pane 9 not found".to_string())));
+        assert_eq!(server.join().unwrap(), "{\"cmd\":\"close-pane\",\"pane_id\":9}\n");
+        assert!(out.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_token_goes_in_a_hello_first() {
+        let (path, server) = serve("token", "{\"ok\":false,\"error\":\"invalid token\"}\n");
+        let request = request("list-panes").unwrap().as_object().unwrap().clone();
+        let mut out = Vec::new();
+        let refused = exchange(&path, Some("s3cret"), request, false, &mut out);
+        assert_eq!(refused, Err((REFUSED, "hello: invalid token".to_string())));
+        assert_eq!(server.join().unwrap(), "{\"cmd\":\"hello\",\"token\":\"s3cret\"}\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn subscribe_prints_the_snapshot_then_every_event() {
+        let lines = "{\"ok\":true,\"data\":{\"focused_pane_id\":3}}\n\
+                     {\"event\":\"focus\",\"pane_id\":4}\n\
+                     {\"event\":\"pane-close\",\"pane_id\":3}\n";
+        let (path, server) = serve("subscribe", lines);
+        let request = request("subscribe --events focus,pane-close").unwrap().as_object().unwrap().clone();
+        let mut out = Vec::new();
+        assert_eq!(exchange(&path, None, request, false, &mut out), Ok(()));
+        assert!(server.join().unwrap().contains("\"events\":[\"focus\",\"pane-close\"]"));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("}\nfocus pane_id=4\npane-close pane_id=3\n"), "{}", out);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn list_panes_reads_as_a_table() {
+        let panes = serde_json::json!([
+            {"id": 3, "window": 0, "tab": 1, "focused": true, "working": true, "title": "cargo", "cwd": "/src"},
+            {"id": 12, "window": 0, "tab": 2, "is_idle": true, "title": "zsh", "cwd": "/"},
+        ]);
+        assert_eq!(
+            render("list-panes", &panes, false),
+            "ID  WIN  TAB  STATE    TITLE  CWD\n\
+             3*  0    1    working  cargo  /src\n\
+             12  0    2    idle     zsh    /\n"
+        );
+        assert_eq!(render("list-panes", &panes, true).lines().count(), 1);
+    }
+
+    #[test]
+    fn cells_print_one_line_of_runs_per_line() {
+        let run = serde_json::json!({"text": "ok", "fg": "#00ff00", "bg": null, "attrs": ["bold"]});
+        let data = serde_json::json!({"panes": [{"id": 3, "cells": [[run], []]}]});
+        assert_eq!(render("get-pane-content", &data, false), format!("{}\n[]\n", serde_json::json!([run])));
+    }
+
+    #[test]
+    fn completions_offer_every_command() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = completions(shell);
+            for spec in ipc_schema::COMMANDS {
+                assert!(script.contains(spec.name), "{:?} lacks {}", shell, spec.name);
+            }
+        }
+        assert!(completions(Shell::Bash).contains("--direction) words=\"horizontal vertical\""));
+    }
+}
@@ -14,10 +14,10 @@
 //! - the full pane sweep is throttled to ~4 Hz (`pane_poll_interval`);
 //! - the expensive JSON (a `proc_pidinfo` for the CWD, a process-table walk)
 //!   is built only when an edge actually fired.
+//!
+//! The diff reads the world through `EventSource`, so the same rules serve the
+//! AppKit windows (`app.rs`) and the headless engine (`headless.rs`).
 
-use objc2::rc::Retained;
-use objc2_app_kit::NSWindow;
-use std::cell::RefCell;
 use std::collections::HashMap;
 
 use crate::ipc::topic;
@@ -30,8 +30,15 @@ pub struct PaneFlags {
     pub working: bool,
     pub awaiting: bool,
     pub awaiting_since: Option<u64>,
+    /// Unix time (ms) of the pane's last output, `None` before the first.
+    pub last_output_ms: Option<u64>,
 }
 
+/// Silence after which a pane that printed something is reported `pane-quiet`.
+/// Long on purpose: a prompt waiting for its user, a build stuck on the
+/// network — not the pauses between two lines of a running program.
+pub const PANE_QUIET_AFTER: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Which pane holds the user's attention, and what is running in it.
 ///
 /// The Claude conversation is part of the identity on purpose. Launching `claude`
@@ -39,14 +46,27 @@ pub struct PaneFlags {
 /// focus anywhere, and a client that only hears about pane changes would learn of
 /// it only when you happen to leave and come back.
 #[derive(Clone, PartialEq, Eq, Debug)]
-struct FocusKey {
-    window: usize,
-    tab: usize,
-    pane: PaneId,
-    session: Option<String>,
+pub struct FocusKey {
+    pub window: usize,
+    pub tab: usize,
+    pub pane: PaneId,
+    pub session: Option<String>,
     /// The name `/rename` gave it. Part of the identity for the same reason as the id: renaming a
     /// conversation is how you declare it is about something else now.
-    session_name: Option<String>,
+    pub session_name: Option<String>,
+}
+
+/// Where the diff reads the world from: the AppKit window list, or the
+/// headless engine's single virtual window.
+pub trait EventSource {
+    /// The pane the keyboard would reach right now, or `None` if no window is key.
+    fn focus_key(&self) -> Option<FocusKey>;
+    /// The flags of every pane. Runs on a timer: must not cost a syscall.
+    fn collect_flags(&self, out: &mut HashMap<PaneId, PaneFlags>);
+    /// Full JSON for a pane addressed by (window index, pane id).
+    fn pane_json(&self, win_idx: usize, pane_id: PaneId) -> Option<serde_json::Value>;
+    /// Full JSON for every pane, in `list-panes` order.
+    fn collect_panes(&self, out: &mut Vec<serde_json::Value>);
 }
 
 /// The last state published to subscribers.
@@ -55,6 +75,9 @@ pub struct EventState {
     /// or it has no key window.
     focus: Option<FocusKey>,
     panes: HashMap<PaneId, PaneFlags>,
+    /// Unix time (ms) of the sweep that filled `panes`: what their `quiet`
+    /// was measured against.
+    swept_at_ms: u64,
     /// Ticks left before the next full pane sweep.
     countdown: u32,
     /// False until the first pass has filled the state. That first pass must not
@@ -73,6 +96,7 @@ impl EventState {
         Self {
             focus: None,
             panes: HashMap::new(),
+            swept_at_ms: 0,
             countdown: 0,
             seeded: false,
         }
@@ -84,7 +108,7 @@ impl EventState {
     /// anything already pending goes out before the snapshot it is about to read.
     pub fn poll(
         &mut self,
-        windows: &RefCell<Vec<Retained<NSWindow>>>,
+        source: &dyn EventSource,
         app_active: bool,
         fps: u32,
         force: bool,
@@ -102,11 +126,11 @@ impl EventState {
         let silent = !self.seeded;
         self.seeded = true;
 
-        self.poll_focus(windows, app_active, silent);
+        self.poll_focus(source, app_active, silent);
 
         if force || self.countdown == 0 {
             self.countdown = pane_poll_interval(fps);
-            self.poll_panes(windows, silent);
+            self.poll_panes(source, silent);
         } else {
             self.countdown -= 1;
         }
@@ -114,7 +138,7 @@ impl EventState {
 
     fn poll_focus(
         &mut self,
-        windows: &RefCell<Vec<Retained<NSWindow>>>,
+        source: &dyn EventSource,
         app_active: bool,
         silent: bool,
     ) {
@@ -122,7 +146,7 @@ impl EventState {
         // user's attention has left the terminal entirely. Collapsing both facts
         // into one stream is the whole point — a client should not have to join
         // our focus with the system's active app to know whether you are here.
-        let next = if app_active { current_focus(windows) } else { None };
+        let next = if app_active { source.focus_key() } else { None };
         if next == self.focus {
             return;
         }
@@ -133,7 +157,7 @@ impl EventState {
         }
         let pane = next
             .as_ref()
-            .and_then(|key| pane_json(windows, key.window, key.pane));
+            .and_then(|key| source.pane_json(key.window, key.pane));
         crate::ipc::publish(
             topic::FOCUS,
             serde_json::json!({
@@ -145,23 +169,17 @@ impl EventState {
         );
     }
 
-    fn poll_panes(&mut self, windows: &RefCell<Vec<Retained<NSWindow>>>, silent: bool) {
+    fn poll_panes(&mut self, source: &dyn EventSource, silent: bool) {
         let mut current: HashMap<PaneId, PaneFlags> = HashMap::new();
-        {
-            let wins = windows.borrow();
-            for (idx, win) in wins.iter().enumerate() {
-                if let Some(view) = crate::app::kova_view(win) {
-                    view.events_collect_flags(idx, &mut current);
-                }
-            }
-        }
+        source.collect_flags(&mut current);
+        let now_ms = crate::terminal::output::now_ms();
 
         if !silent {
             for (id, flags) in &current {
                 match self.panes.get(id) {
                     None => {
                         if crate::ipc::has_subscribers(topic::PANE_OPEN) {
-                            let pane = pane_json(windows, flags.window, *id);
+                            let pane = source.pane_json(flags.window, *id);
                             crate::ipc::publish(
                                 topic::PANE_OPEN,
                                 serde_json::json!({ "event": "pane-open", "pane": pane }),
@@ -190,6 +208,18 @@ impl EventState {
                                 }),
                             );
                         }
+                        let quiet = is_quiet(flags.last_output_ms, now_ms);
+                        if is_quiet(previous.last_output_ms, self.swept_at_ms) != quiet {
+                            crate::ipc::publish(
+                                topic::PANE_QUIET,
+                                serde_json::json!({
+                                    "event": "pane-quiet",
+                                    "pane_id": id,
+                                    "quiet": quiet,
+                                    "last_output_ms": flags.last_output_ms,
+                                }),
+                            );
+                        }
                     }
                 }
             }
@@ -211,9 +241,17 @@ impl EventState {
         }
 
         self.panes = current;
+        self.swept_at_ms = now_ms;
     }
 }
 
+/// Whether a pane whose last output was at `last_output_ms` counts as quiet
+/// at `now_ms`. A pane that never printed anything has nothing to fall silent
+/// from, and is not.
+fn is_quiet(last_output_ms: Option<u64>, now_ms: u64) -> bool {
+    last_output_ms.is_some_and(|last| now_ms.saturating_sub(last) >= PANE_QUIET_AFTER.as_millis() as u64)
+}
+
 /// How many ticks between two full pane sweeps — about 250 ms, whatever the
 /// frame rate. Fast enough that a subscriber sees a session go idle as it
 /// happens, slow enough that 30 panes never cost anything measurable.
@@ -241,57 +279,21 @@ fn focus_reason(
     }
 }
 
-/// The pane the keyboard would reach right now, or `None` if no Kova window is key.
-fn current_focus(windows: &RefCell<Vec<Retained<NSWindow>>>) -> Option<FocusKey> {
-    let wins = windows.borrow();
-    for (idx, win) in wins.iter().enumerate() {
-        if !win.isKeyWindow() {
-            continue;
-        }
-        if let Some(view) = crate::app::kova_view(win) {
-            if let Some((tab, pane, session, session_name)) = view.events_focus_key() {
-                return Some(FocusKey { window: idx, tab, pane, session, session_name });
-            }
-        }
-    }
-    None
-}
-
-/// Full JSON for a pane addressed by (window index, pane id).
-fn pane_json(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
-    win_idx: usize,
-    pane_id: PaneId,
-) -> Option<serde_json::Value> {
-    let wins = windows.borrow();
-    let win = wins.get(win_idx)?;
-    let is_key = win.isKeyWindow();
-    crate::app::kova_view(win)?.events_pane_json(win_idx, pane_id, is_key)
-}
-
 /// Snapshot handed to a client the moment it subscribes: everything it would
 /// otherwise have to reconstruct from `list-panes` plus a guess about focus.
 pub fn snapshot(
-    windows: &RefCell<Vec<Retained<NSWindow>>>,
+    source: &dyn EventSource,
     app_active: bool,
     topics: u32,
 ) -> serde_json::Value {
     let mut panes = Vec::new();
     let mut focus = serde_json::Value::Null;
+    source.collect_panes(&mut panes);
+    if app_active
+        && let Some(key) = source.focus_key()
+        && let Some(pane) = source.pane_json(key.window, key.pane)
     {
-        let wins = windows.borrow();
-        for (idx, win) in wins.iter().enumerate() {
-            if let Some(view) = crate::app::kova_view(win) {
-                view.ipc_collect_panes(idx, win.isKeyWindow(), &mut panes);
-            }
-        }
-    }
-    if app_active {
-        if let Some(key) = current_focus(windows) {
-            if let Some(pane) = pane_json(windows, key.window, key.pane) {
-                focus = pane;
-            }
-        }
+        focus = pane;
     }
     serde_json::json!({
         "events": topic::names(topics),
@@ -322,6 +324,14 @@ mod tests {
         assert_eq!(pane_poll_interval(0), 1);
     }
 
+    #[test]
+    fn a_pane_is_quiet_once_its_output_stopped_long_enough() {
+        let after = PANE_QUIET_AFTER.as_millis() as u64;
+        assert!(!is_quiet(None, 1_000_000));
+        assert!(!is_quiet(Some(50_000), 50_000 + after - 1));
+        assert!(is_quiet(Some(50_000), 50_000 + after));
+    }
+
     #[test]
     fn losing_the_app_is_reported_as_such() {
         // The distinction matters to a client that credits time: "you left Kova"
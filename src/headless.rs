@@ -0,0 +1,719 @@
+//! `kova --headless`: the pane and IPC engine, with no window.
+//!
+//! Owns one virtual window — tabs of panes laid out on a `COLS x ROWS` cell
+//! screen — and serves the IPC protocol on the usual socket, so the scripts
+//! that drive Kova can run end to end where there is no display (CI, a Linux
+//! build box). Nothing is drawn: layout is computed in cells with the same
+//! `Tab` arithmetic the GUI runs in pixels, and what the panes show is read
+//! back over IPC (`get-pane-content`, `search-pane`, the event stream).
+//!
+//! Deliberately left out:
+//! - sessions — a headless run starts with one fresh tab and saves nothing, so
+//!   it never clobbers the GUI's `session.json` (nor the recent projects);
+//! - commands that only mean something on screen (`dispatch-action`,
+//!   `notify`, `merge-window`): they answer with an error naming headless mode.
+//!
+//! The virtual window is always "key" and the app always "active": focus
+//! events report the focused pane of the active tab, as if someone sat there.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::events::{EventSource, EventState, FocusKey, PaneFlags};
+use crate::ipc::{IpcCommand, IpcRequest, IpcResponse};
+use crate::ipc_host::{IpcCloseTabResult, IpcHost, IpcMergeTabResult, IpcSwapPaneResult, PendingWait};
+use crate::pane::{pane_json, NavDirection, Pane, PaneId, PaneViewport, SplitAxis, SplitDirection, Tab};
+
+/// Set from SIGINT/SIGTERM. The loop notices on its next turn and shuts the
+/// panes down the same way the GUI does on quit.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// How long to wait for the IPC listener to bind before announcing the socket.
+const BIND_WAIT: Duration = Duration::from_secs(2);
+
+/// Command-line options of `kova --headless`.
+pub struct Options {
+    /// Screen size in cells; `None` falls back to `terminal.columns`/`rows`.
+    pub size: Option<(u16, u16)>,
+}
+
+impl Options {
+    /// Parse `--size COLSxROWS`. The other flags are the caller's business.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let size = match args.iter().position(|a| a == "--size") {
+            Some(i) => {
+                let value = args.get(i + 1).ok_or("--size needs a value, e.g. --size 120x40")?;
+                Some(parse_size(value).ok_or_else(|| format!("--size: expected COLSxROWS, got {:?}", value))?)
+            }
+            None => None,
+        };
+        Ok(Options { size })
+    }
+}
+
+/// `"120x40"` → `(120, 40)`. Zero is refused: a pane needs a cell.
+fn parse_size(s: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = s.split_once('x')?;
+    let cols: u16 = cols.parse().ok()?;
+    let rows: u16 = rows.parse().ok()?;
+    (cols > 0 && rows > 0).then_some((cols, rows))
+}
+
+/// The one virtual window: its tabs and the screen they are laid out on.
+pub(crate) struct Engine {
+    config: Config,
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    cols: u16,
+    rows: u16,
+    /// Set while a `batch` runs: `resize_all_panes` only notes in
+    /// `layout_owed` that the panes need sizing, and the batch sizes them
+    /// once at the end.
+    layout_held: bool,
+    layout_owed: Cell<bool>,
+}
+
+/// Run the headless engine until SIGINT/SIGTERM or until the last pane exits.
+/// Returns the process exit code.
+pub fn run(config: Config, opts: Options) -> i32 {
+    let (cols, rows) = opts.size.unwrap_or((config.terminal.columns, config.terminal.rows));
+    let tab = match Tab::new(&config) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("kova: failed to start the first pane: {}", e);
+            return 1;
+        }
+    };
+    let mut engine = Engine {
+        config,
+        tabs: vec![tab],
+        active_tab: 0,
+        cols,
+        rows,
+        layout_held: false,
+        layout_owed: Cell::new(false),
+    };
+    engine.resize_all_panes();
+
+    install_stop_handlers();
+    let ipc_rx = crate::ipc::start(
+        crate::ipc_auth::Auth::from_config(&engine.config.ipc),
+        crate::ipc_audit::Audit::from_config(&engine.config.ipc),
+    );
+    // The one virtual window, as the instance registry counts them.
+    crate::instances::set_windows(1);
+    let socket = crate::ipc::socket_path();
+    let bind_deadline = Instant::now() + BIND_WAIT;
+    while !socket.exists() && Instant::now() < bind_deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    // The one line a driving script needs: where to connect.
+    println!("{}", socket.display());
+    log::info!("Headless: {}x{} cells, IPC on {}", cols, rows, socket.display());
+
+    let fps = engine.config.terminal.fps.max(1);
+    let tick = Duration::from_secs(1) / fps;
+    let mut events = EventState::new();
+    let mut waits: Vec<PendingWait> = Vec::new();
+    let mut count: u64 = 0;
+    let mut next_tick = Instant::now();
+
+    while !STOP.load(Ordering::Relaxed) {
+        if Instant::now() >= next_tick {
+            next_tick = Instant::now() + tick;
+            count += 1;
+            if !engine.tick(count, fps, &waits) {
+                log::info!("Headless: last pane exited");
+                break;
+            }
+            crate::ipc_host::poll_waits(&engine, &mut waits);
+            events.poll(&engine, true, fps, false);
+        }
+        // Commands are served as they arrive, not on the next tick.
+        match ipc_rx.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+            Ok(request) => serve(&mut engine, request, &mut events, &mut waits, fps),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                log::error!("Headless: IPC listener is gone");
+                break;
+            }
+        }
+    }
+
+    log::info!("Headless: shutting down");
+    for wait in waits {
+        wait.abandon();
+    }
+    crate::terminal::pty::shutdown_all();
+    crate::ipc::cleanup();
+    log::logger().flush();
+    0
+}
+
+fn install_stop_handlers() {
+    extern "C" fn on_stop(_sig: libc::c_int) {
+        STOP.store(true, Ordering::Relaxed);
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, on_stop as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_stop as *const () as libc::sighandler_t);
+    }
+}
+
+/// Answer one IPC request, or park it when it is a wait that has not fired yet.
+fn serve(
+    engine: &mut Engine,
+    (cmd, responder): IpcRequest,
+    events: &mut EventState,
+    waits: &mut Vec<PendingWait>,
+    fps: u32,
+) {
+    match cmd {
+        IpcCommand::Subscribe { topics, .. } => {
+            // Same order as the GUI: flush what is pending to the subscribers
+            // already here before handing the newcomer its snapshot.
+            events.poll(engine, true, fps, true);
+            let data = crate::events::snapshot(engine, true, topics);
+            let _ = responder.send(IpcResponse::Ok { data: Some(data) });
+        }
+        cmd => crate::ipc_host::serve(engine, (cmd, responder), waits),
+    }
+}
+
+impl Engine {
+    /// The whole screen, in cells. No tab bar, no status bar, and no
+    /// horizontal scrolling: columns always share the screen width.
+    fn screen(&self) -> PaneViewport {
+        PaneViewport { x: 0.0, y: 0.0, width: self.cols as f32, height: self.rows as f32 }
+    }
+
+    /// Grid size of a cell-unit viewport.
+    fn viewport_to_grid(vp: &PaneViewport) -> (u16, u16) {
+        (vp.width.floor().max(1.0) as u16, vp.height.floor().max(1.0) as u16)
+    }
+
+    /// Give every pane of every tab the grid its slot holds. Unlike the GUI,
+    /// background tabs are kept at size too: a script reads them as they are.
+    fn resize_all_panes(&self) {
+        if self.layout_held {
+            self.layout_owed.set(true);
+            return;
+        }
+        let screen = self.screen();
+        for tab in &self.tabs {
+            tab.cell_h.set(1.0);
+            tab.for_each_pane_with_viewport(screen, &mut |pane, vp| {
+                if pane.minimized || pane.is_playback() {
+                    return;
+                }
+                let (cols, rows) = Self::viewport_to_grid(&vp);
+                let mut term = pane.terminal.write();
+                if cols != term.cols || rows != term.rows {
+                    term.resize(cols, rows);
+                    drop(term);
+                    pane.pty.resize(cols, rows);
+                }
+            });
+        }
+    }
+
+    /// One pass of the housekeeping the GUI does in its render tick. Returns
+    /// false once no pane is left.
+    fn tick(&mut self, count: u64, fps: u32, waits: &[PendingWait]) -> bool {
+        for tab in &self.tabs {
+            tab.for_each_pane(&mut |pane| pane.inject_pending_command());
+        }
+
+        // Foreground probe (and the Claude session lookup riding on it) every
+        // ~0.5s, like the GUI.
+        let refresh_fg = count.is_multiple_of((fps as u64 / 2).max(1));
+        for tab in &mut self.tabs {
+            tab.check_bell();
+            tab.check_running(refresh_fg);
+            tab.check_completion();
+        }
+
+        self.reap_exited_panes();
+        if self.tabs.is_empty() {
+            return false;
+        }
+
+        // App-wide scrollback budget, every ~2s.
+        let budget_mb = self.config.terminal.scrollback_total_mb;
+        if budget_mb > 0 && count.is_multiple_of(fps as u64 * 2) {
+            crate::terminal::scrollback::enforce_budget(&self.budget_panes(waits), budget_mb << 20);
+        }
+        true
+    }
+
+    /// Every pane for the scrollback budget. The focused one is protected,
+    /// and so is any pane a parked wait or `search-pane` scan is reading.
+    fn budget_panes(&self, waits: &[PendingWait]) -> Vec<crate::terminal::scrollback::BudgetPane> {
+        let mut panes = Vec::new();
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            tab.for_each_pane(&mut |pane| {
+                panes.push(crate::terminal::scrollback::BudgetPane {
+                    terminal: pane.terminal.clone(),
+                    protected: (tab_idx == self.active_tab && pane.id == tab.focused_pane)
+                        || waits.iter().any(|w| w.pane_id() == pane.id),
+                });
+            });
+        }
+        panes
+    }
+
+    /// Drop the panes whose shell exited, and the tabs left empty.
+    fn reap_exited_panes(&mut self) {
+        let mut any_removed = false;
+        self.tabs.retain_mut(|tab| {
+            let exited = tab.exited_pane_ids();
+            if exited.is_empty() {
+                return true;
+            }
+            any_removed = true;
+            log::debug!("Headless: reaping exited panes {:?}", exited);
+            for id in &exited {
+                if !tab.remove_pane(*id) {
+                    return false;
+                }
+                tab.minimized_stack.retain(|&pid| pid != *id);
+            }
+            if let Some(restored) = tab.ensure_visible_pane() {
+                tab.focused_pane = restored;
+            } else if exited.contains(&tab.focused_pane) {
+                tab.focused_pane = tab.first_visible_pane().unwrap_or_else(|| tab.first_pane().id);
+            }
+            true
+        });
+        if any_removed && !self.tabs.is_empty() {
+            self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+            self.resize_all_panes();
+        }
+    }
+
+    fn pane(&self, pane_id: PaneId) -> Option<&Pane> {
+        self.tabs.iter().find_map(|tab| tab.pane(pane_id))
+    }
+
+    fn tab_index_of(&self, pane_id: PaneId) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.contains(pane_id))
+    }
+}
+
+impl EventSource for Engine {
+    fn focus_key(&self) -> Option<FocusKey> {
+        let tab = self.tabs.get(self.active_tab)?;
+        let pane = tab.pane(tab.focused_pane);
+        Some(FocusKey {
+            window: 0,
+            tab: self.active_tab,
+            pane: tab.focused_pane,
+            session: pane.and_then(|p| p.claude_session_id()),
+            session_name: pane.and_then(|p| p.claude_session_name()),
+        })
+    }
+
+    fn collect_flags(&self, out: &mut HashMap<PaneId, PaneFlags>) {
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            tab.for_each_pane(&mut |pane| {
+                out.insert(
+                    pane.id,
+                    PaneFlags {
+                        window: 0,
+                        tab: tab_idx,
+                        working: pane.is_working(),
+                        awaiting: pane.is_awaiting(),
+                        awaiting_since: pane.awaiting_since(),
+                        last_output_ms: pane.terminal.read().last_output_ms(),
+                    },
+                );
+            });
+        }
+    }
+
+    fn pane_json(&self, _win_idx: usize, pane_id: PaneId) -> Option<serde_json::Value> {
+        let tab_idx = self.tab_index_of(pane_id)?;
+        let tab = &self.tabs[tab_idx];
+        let focused = tab_idx == self.active_tab && tab.focused_pane == pane_id;
+        Some(pane_json(tab.pane(pane_id)?, 0, tab_idx, focused))
+    }
+
+    fn collect_panes(&self, out: &mut Vec<serde_json::Value>) {
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            tab.for_each_pane(&mut |pane| {
+                let focused = tab_idx == self.active_tab && pane.id == tab.focused_pane;
+                out.push(pane_json(pane, 0, tab_idx, focused));
+            });
+        }
+    }
+}
+
+impl IpcHost for Engine {
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn with_pane<R>(&self, pane_id: PaneId, f: impl FnOnce(&Pane) -> R) -> Option<R> {
+        self.pane(pane_id).map(f)
+    }
+
+    fn with_tab_of<R>(&mut self, pane_id: PaneId, f: impl FnOnce(&mut Tab) -> R) -> Option<R> {
+        self.tabs.iter_mut().find(|tab| tab.contains(pane_id)).map(f)
+    }
+
+    fn pane_ids(&self) -> Vec<PaneId> {
+        let mut ids = Vec::new();
+        for tab in &self.tabs {
+            tab.for_each_pane(&mut |p| ids.push(p.id));
+        }
+        ids
+    }
+
+    fn list_panes(&self) -> Vec<serde_json::Value> {
+        let mut panes = Vec::new();
+        self.collect_panes(&mut panes);
+        panes
+    }
+
+    fn list_tabs(&self) -> Vec<serde_json::Value> {
+        self.tabs
+            .iter()
+            .enumerate()
+            .map(|(tab_idx, tab)| {
+                let mut pane_count = 0;
+                tab.for_each_pane(&mut |_| pane_count += 1);
+                serde_json::json!({
+                    "id": tab.id,
+                    "window": 0,
+                    "tab_index": tab_idx,
+                    "title": tab.title(),
+                    "pane_count": pane_count,
+                    "focused_pane_id": tab.focused_pane,
+                    "active": tab_idx == self.active_tab,
+                    "has_bell": tab.has_bell,
+                    "has_completion": tab.has_completion,
+                    "has_running": tab.has_running,
+                })
+            })
+            .collect()
+    }
+
+    fn focused_cwd(&self) -> Option<String> {
+        let tab = self.tabs.get(self.active_tab)?;
+        tab.pane(tab.focused_pane).and_then(|p| p.cwd())
+    }
+
+    fn split_focused(
+        &mut self,
+        direction: SplitDirection,
+        make_pane: impl FnOnce(u16, u16) -> Result<Pane, String>,
+    ) -> Result<PaneId, String> {
+        let screen = self.screen();
+        let tab = self.tabs.get_mut(self.active_tab).ok_or("no active tab")?;
+        let focused = tab.focused_pane;
+        let vp = tab.viewport_for_pane(focused, screen).ok_or("no focused pane")?;
+        let (cols, rows) = match direction {
+            SplitDirection::Horizontal => Self::viewport_to_grid(&PaneViewport { width: vp.width / 2.0, ..vp }),
+            SplitDirection::Vertical => Self::viewport_to_grid(&PaneViewport { height: vp.height / 2.0, ..vp }),
+        };
+        let pane = make_pane(cols, rows)?;
+        let new_id = pane.id;
+        match direction {
+            SplitDirection::Horizontal => {
+                tab.insert_column_after_focused(pane);
+            }
+            SplitDirection::Vertical => tab.vsplit_at_pane(focused, pane),
+        }
+        tab.focused_pane = new_id;
+        self.resize_all_panes();
+        Ok(new_id)
+    }
+
+    fn open_tab(&mut self, tab: Tab) {
+        self.active_tab += 1;
+        self.tabs.insert(self.active_tab, tab);
+        self.resize_all_panes();
+    }
+
+    /// The very last pane is refused, as in the GUI: it would end the engine.
+    fn close_pane(&mut self, pane_id: PaneId) -> Option<bool> {
+        let idx = self.tab_index_of(pane_id)?;
+        if self.tabs[idx].is_single_pane() {
+            if self.tabs.len() == 1 {
+                return Some(false);
+            }
+            self.tabs.remove(idx);
+            self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+            self.resize_all_panes();
+            return Some(true);
+        }
+
+        let screen = self.screen();
+        let tab = &mut self.tabs[idx];
+        let next_focus = [NavDirection::Right, NavDirection::Left, NavDirection::Down, NavDirection::Up]
+            .into_iter()
+            .find_map(|dir| tab.neighbor(pane_id, dir, screen));
+        if !tab.remove_pane(pane_id) {
+            return Some(false);
+        }
+        tab.minimized_stack.retain(|&pid| pid != pane_id);
+        let restored = tab.ensure_visible_pane();
+        let new_focus = restored
+            .or(next_focus.filter(|id| tab.contains(*id)))
+            .or_else(|| tab.first_visible_pane())
+            .unwrap_or_else(|| tab.first_pane().id);
+        tab.focused_pane = new_focus;
+        self.resize_all_panes();
+        Some(true)
+    }
+
+    fn focus_pane(&mut self, pane_id: PaneId) -> bool {
+        let Some(idx) = self.tab_index_of(pane_id) else {
+            return false;
+        };
+        let screen_w = self.screen().width;
+        let tab = &mut self.tabs[idx];
+        // A minimized pane has no slot: focus alone would land on nothing.
+        if tab.pane(pane_id).is_some_and(|p| p.minimized) {
+            tab.restore_pane_adjust_virtual(pane_id, screen_w, 0.0);
+        }
+        tab.focused_pane = pane_id;
+        self.active_tab = idx;
+        self.resize_all_panes();
+        true
+    }
+
+    fn close_tab(&mut self, tab_id: u32) -> IpcCloseTabResult {
+        let Some(idx) = self.tabs.iter().position(|t| t.id == tab_id) else {
+            return IpcCloseTabResult::NotFound;
+        };
+        if self.tabs.len() == 1 {
+            return IpcCloseTabResult::WouldTerminate;
+        }
+        self.tabs.remove(idx);
+        self.active_tab = self.active_tab.min(self.tabs.len() - 1);
+        self.resize_all_panes();
+        IpcCloseTabResult::Closed
+    }
+
+    fn merge_tab(&mut self, source_tab_id: u32, target_tab_id: u32) -> IpcMergeTabResult {
+        let Some(source) = self.tabs.iter().position(|t| t.id == source_tab_id) else {
+            return IpcMergeTabResult::SourceMissing;
+        };
+        let target = match self.tabs.iter().position(|t| t.id == target_tab_id) {
+            Some(t) if t != source => t,
+            _ => return IpcMergeTabResult::TargetMissing,
+        };
+        let source_tab = self.tabs.remove(source);
+        let target = if target > source { target - 1 } else { target };
+        self.tabs[target].merge_from(source_tab);
+        self.active_tab = target;
+        self.resize_all_panes();
+        IpcMergeTabResult::Merged
+    }
+
+    fn swap_pane(&mut self, pane_id_a: PaneId, pane_id_b: PaneId) -> IpcSwapPaneResult {
+        let Some(idx) = self.tab_index_of(pane_id_a) else {
+            return IpcSwapPaneResult::AMissing;
+        };
+        if !self.tabs[idx].contains(pane_id_b) {
+            return IpcSwapPaneResult::BMissing;
+        }
+        if pane_id_a == pane_id_b || !self.tabs[idx].swap_panes_auto(pane_id_a, pane_id_b) {
+            return IpcSwapPaneResult::Failed;
+        }
+        self.resize_all_panes();
+        IpcSwapPaneResult::Swapped
+    }
+
+    fn resize_pane(&mut self, pane_id: PaneId, axis: SplitAxis, grow: bool, amount_pct: f32) -> Option<bool> {
+        let idx = self.tab_index_of(pane_id)?;
+        if !self.tabs[idx].resize_split(pane_id, axis, grow, amount_pct) {
+            return Some(false);
+        }
+        self.resize_all_panes();
+        Some(true)
+    }
+
+    fn hold_layout(&mut self) {
+        self.layout_held = true;
+    }
+
+    fn release_layout(&mut self) {
+        self.layout_held = false;
+        if self.layout_owed.take() {
+            self.resize_all_panes();
+        }
+    }
+
+    fn on_screen(&mut self, cmd: IpcCommand) -> IpcResponse {
+        let name = match cmd {
+            IpcCommand::DispatchAction { .. } => "dispatch-action",
+            IpcCommand::MergeWindow { .. } => "merge-window",
+            _ => "notify",
+        };
+        IpcResponse::Error { message: format!("{} is not available in headless mode", name) }
+    }
+}
+
+/// A headless engine over one loopback pane, sized the way `run` sizes it:
+/// the host the IPC tests drive.
+#[cfg(test)]
+pub(crate) struct Fixture {
+    pub engine: Engine,
+    pub program: crate::terminal::loopback::LoopbackHandle,
+    /// The loopback pane.
+    pub id: PaneId,
+    pub waits: Vec<PendingWait>,
+    events: EventState,
+}
+
+#[cfg(test)]
+impl Fixture {
+    pub fn new() -> Self {
+        let config = Config::default();
+        let (pane, program) = Pane::loopback(80, 24, &config);
+        let engine = Engine {
+            config,
+            tabs: vec![Tab::with_pane(pane)],
+            active_tab: 0,
+            cols: 80,
+            rows: 24,
+            layout_held: false,
+            layout_owed: Cell::new(false),
+        };
+        engine.resize_all_panes();
+        let id = engine.tabs[0].focused_pane;
+        Fixture { engine, program, id, waits: Vec::new(), events: EventState::new() }
+    }
+
+    /// Serve `cmd` as the loop does; a parked wait answers on the receiver
+    /// once `poll_waits` resolves it.
+    pub fn serve(&mut self, cmd: IpcCommand) -> mpsc::Receiver<IpcResponse> {
+        let (tx, rx) = mpsc::channel();
+        serve(&mut self.engine, (cmd, tx), &mut self.events, &mut self.waits, 60);
+        rx
+    }
+
+    /// One tick's turn for the parked waits.
+    pub fn poll_waits(&mut self) {
+        crate::ipc_host::poll_waits(&self.engine, &mut self.waits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::PaneFilter;
+    use crate::terminal::export::ExportFormat;
+
+    #[test]
+    fn size_flag_parses_cols_by_rows() {
+        assert_eq!(parse_size("120x40"), Some((120, 40)));
+        assert_eq!(parse_size("120X40"), None);
+        assert_eq!(parse_size("0x40"), None);
+        assert_eq!(parse_size("120"), None);
+        let args: Vec<String> = ["kova", "--headless", "--size", "90x30"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(Options::from_args(&args).unwrap().size, Some((90, 30)));
+        assert!(Options::from_args(&args[..3]).is_err());
+    }
+
+    #[test]
+    fn cell_viewports_map_to_whole_cells() {
+        let vp = PaneViewport { x: 0.0, y: 0.0, width: 40.5, height: 0.2 };
+        assert_eq!(Engine::viewport_to_grid(&vp), (40, 1));
+    }
+
+    #[test]
+    fn send_keys_reach_the_program_and_its_output_reads_back() {
+        let mut f = Fixture::new();
+        let sent = crate::ipc_host::handle(&mut f.engine, IpcCommand::SendKeys { pane_id: f.id, text: "ls\r".into() });
+        assert!(matches!(sent, IpcResponse::Ok { .. }));
+        assert_eq!(f.program.take_input(), b"ls\r");
+
+        f.program.output(b"Cargo.toml  src\r\n");
+        let IpcResponse::Ok { data: Some(data) } = crate::ipc_host::handle(&mut f.engine, IpcCommand::GetPaneContent {
+            panes: PaneFilter::Ids(vec![f.id]),
+            mode: "visible".into(),
+            trim_trailing_blank_lines: true,
+            format: ExportFormat::Text,
+        }) else {
+            panic!("get-pane-content failed");
+        };
+        assert_eq!(data["panes"][0]["text"].as_str().unwrap().lines().next(), Some("Cargo.toml  src"));
+    }
+
+    #[test]
+    fn a_batch_lays_out_its_panes_once_and_an_atomic_one_rolls_back() {
+        let Fixture { mut engine, program, .. } = Fixture::new();
+        let batch = |atomic: bool, last: serde_json::Value| {
+            crate::ipc::parse_value(serde_json::json!({"cmd": "batch", "atomic": atomic, "commands": [
+                {"cmd": "new-virtual-pane", "split": "horizontal"},
+                {"cmd": "new-virtual-pane", "split": "vertical", "title": "logs"},
+                last,
+            ]}))
+            .unwrap()
+        };
+
+        let failing = batch(true, serde_json::json!({"cmd": "rename-pane", "pane_id": 999, "title": "x"}));
+        let IpcResponse::Error { message } = crate::ipc_host::handle(&mut engine, failing) else {
+            panic!("the batch should have failed");
+        };
+        assert!(message.starts_with("step 2 (rename-pane): pane 999 not found"), "{}", message);
+        assert!(engine.tabs[0].is_single_pane());
+        assert_eq!(program.size(), (80, 24));
+
+        let IpcResponse::Ok { data: Some(data) } =
+            crate::ipc_host::handle(&mut engine, batch(true, serde_json::json!({"cmd": "focus-pane", "pane_id": "$1.pane_id"})))
+        else {
+            panic!("batch failed");
+        };
+        let logs = data["results"][1]["data"]["pane_id"].as_u64().unwrap() as PaneId;
+        assert_eq!(engine.tabs[0].focused_pane, logs);
+        assert_eq!(program.size(), (40, 24));
+        assert!(!engine.layout_held && !engine.layout_owed.get());
+    }
+
+    #[test]
+    fn exited_panes_are_reaped_and_the_last_one_stops_the_engine() {
+        let Fixture { mut engine, program: first, .. } = Fixture::new();
+        let (pane, second) = Pane::loopback(40, 24, &engine.config);
+        let second_id = engine.tabs[0].insert_column_after_focused(pane);
+        engine.tabs[0].focused_pane = second_id;
+        engine.resize_all_panes();
+
+        second.exit();
+        assert!(engine.tick(1, 60, &[]));
+        assert!(!engine.tabs[0].contains(second_id));
+        assert_ne!(engine.tabs[0].focused_pane, second_id);
+        // The survivor takes the whole screen again.
+        assert_eq!(first.size(), (80, 24));
+
+        first.exit();
+        assert!(!engine.tick(2, 60, &[]));
+    }
+
+    #[test]
+    fn the_budget_spares_panes_with_a_parked_wait() {
+        let mut fx = Fixture::new();
+        let (pane, _second) = Pane::loopback(40, 24, &fx.engine.config);
+        let watched = fx.engine.tabs[0].insert_column_after_focused(pane);
+        let (pane, _third) = Pane::loopback(40, 24, &fx.engine.config);
+        let idle = fx.engine.tabs[0].insert_column_after_focused(pane);
+        fx.engine.tabs[0].focused_pane = fx.id;
+        let _rx = fx.serve(IpcCommand::WaitForCompletion { pane_id: watched, timeout_ms: 60_000 });
+        assert_eq!(fx.waits.len(), 1);
+
+        let protected = |fx: &Fixture, id: PaneId| {
+            let term = fx.engine.with_pane(id, |p| p.terminal.clone()).unwrap();
+            fx.engine.budget_panes(&fx.waits).iter().any(|b| b.protected && std::sync::Arc::ptr_eq(&b.terminal, &term))
+        };
+        assert!(protected(&fx, fx.id), "focused");
+        assert!(protected(&fx, watched), "waited on");
+        assert!(!protected(&fx, idle));
+    }
+}
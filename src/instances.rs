@@ -0,0 +1,326 @@
+//! The running Kovas: the directory their sockets live in, the registry that
+//! lists them, and the `kova.sock` link to the one focused last.
+//!
+//! The directory is `$XDG_RUNTIME_DIR/kova`, else `$TMPDIR/kova-{uid}` (`/tmp`
+//! without a `TMPDIR`), and is kept owner-only: a socket takes keystrokes for
+//! every pane, so nobody else gets to even list them. The registry,
+//! `instances.json`, is rewritten under an exclusive `flock` by whichever Kova
+//! changes it. Each rewrite drops the Kovas that died without unregistering
+//! and points `kova.sock` at the one focused last; a Kova starting up also
+//! removes the sockets they left behind.
+
+use std::fs;
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+const REGISTRY: &str = "instances.json";
+/// Always a link to the socket of the Kova focused last.
+pub const LINK: &str = "kova.sock";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Instance {
+    pub pid: u32,
+    /// Epoch seconds.
+    pub started_at: u64,
+    pub windows: usize,
+    pub socket: PathBuf,
+    /// Epoch seconds at which it last became the active app. `None` for one
+    /// that never did, like `kova --headless`.
+    pub focused_at: Option<u64>,
+}
+
+/// This process's window count and last focus, as last reported. Kept here so
+/// that `register`, which runs once the socket is bound, starts from them
+/// whatever the order the two happened in.
+static WINDOWS: AtomicUsize = AtomicUsize::new(0);
+static FOCUSED_AT: AtomicU64 = AtomicU64::new(0);
+
+/// Where the sockets, the registry and the link live.
+pub fn dir() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("kova"),
+        None => std::env::temp_dir().join(format!("kova-{}", unsafe { libc::geteuid() })),
+    })
+}
+
+/// The socket of the Kova running as `pid`.
+pub fn socket_path_for(pid: u32) -> PathBuf {
+    dir().join(socket_name(pid))
+}
+
+fn socket_name(pid: u32) -> String {
+    format!("kova-{}.sock", pid)
+}
+
+/// The pids of every Kova that left a socket in `dir`, running or not, in
+/// ascending order.
+pub fn socket_pids(dir: &Path) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut pids: Vec<u32> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?.strip_prefix("kova-")?.strip_suffix(".sock")?.parse().ok()
+        })
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// Whether a socket has a Kova behind it: one that crashed left the file.
+pub fn listening(socket: &Path) -> bool {
+    UnixStream::connect(socket).is_ok()
+}
+
+pub fn alive(pid: u32) -> bool {
+    // kill() reads a negative pid as a process group.
+    if pid == 0 || pid > i32::MAX as u32 {
+        return false;
+    }
+    // EPERM: alive, and someone else's.
+    let signaled = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    signaled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Make the directory ready for this Kova's socket: create it owner-only, or
+/// tighten it, and clear what dead Kovas left there. Refuses a directory that
+/// belongs to someone else.
+pub fn prepare() -> Result<(), String> {
+    let dir = dir();
+    prepare_dir(dir)?;
+    sweep(dir, std::process::id());
+    Ok(())
+}
+
+fn prepare_dir(dir: &Path) -> Result<(), String> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    // Not `metadata`: a link planted in our place must not be followed.
+    let meta = fs::symlink_metadata(dir).map_err(|e| format!("cannot stat {}: {}", dir.display(), e))?;
+    if !meta.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    let uid = unsafe { libc::geteuid() };
+    if meta.uid() != uid {
+        return Err(format!("{} belongs to uid {}, not {}", dir.display(), meta.uid(), uid));
+    }
+    if meta.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("cannot make {} owner-only: {}", dir.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Remove the sockets nothing listens on any more, but `own`'s, then the
+/// registry entries of the Kovas that left them.
+fn sweep(dir: &Path, own: u32) {
+    for pid in socket_pids(dir) {
+        let socket = dir.join(socket_name(pid));
+        // A live pid is not enough: pids get reused.
+        if pid != own && !(alive(pid) && listening(&socket)) {
+            log::info!("Removing the stale socket {}", socket.display());
+            let _ = fs::remove_file(&socket);
+        }
+    }
+    change(dir, |_| {});
+}
+
+/// Add this Kova to the registry, once its socket is bound.
+pub fn register() {
+    let pid = std::process::id();
+    change(dir(), |instances| {
+        instances.retain(|instance| instance.pid != pid);
+        instances.push(Instance {
+            pid,
+            started_at: now_secs(),
+            windows: WINDOWS.load(Ordering::Relaxed),
+            socket: socket_path_for(pid),
+            focused_at: Some(FOCUSED_AT.load(Ordering::Relaxed)).filter(|&at| at > 0),
+        });
+    });
+}
+
+/// Record how many windows this Kova has. Cheap to call every tick: the
+/// registry is only rewritten when the count changed.
+pub fn set_windows(count: usize) {
+    if WINDOWS.swap(count, Ordering::Relaxed) != count {
+        let pid = std::process::id();
+        change(dir(), |instances| {
+            if let Some(own) = instances.iter_mut().find(|instance| instance.pid == pid) {
+                own.windows = count;
+            }
+        });
+    }
+}
+
+/// This Kova just became the active app: `kova.sock` now leads to it.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn focused() {
+    let now = now_secs();
+    FOCUSED_AT.store(now, Ordering::Relaxed);
+    let pid = std::process::id();
+    change(dir(), |instances| {
+        if let Some(own) = instances.iter_mut().find(|instance| instance.pid == pid) {
+            own.focused_at = Some(now);
+        }
+    });
+}
+
+/// Take this Kova out of the registry, handing `kova.sock` to the one focused
+/// before it. Harmless when done twice.
+pub fn unregister() {
+    let pid = std::process::id();
+    change(dir(), |instances| instances.retain(|instance| instance.pid != pid));
+}
+
+/// The Kovas in the registry.
+pub fn list() -> Vec<Instance> {
+    let Ok(file) = fs::File::open(dir().join(REGISTRY)) else { return Vec::new() };
+    // Shared: waits out a rewrite in progress.
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) };
+    let mut text = String::new();
+    let _ = (&file).read_to_string(&mut text);
+    serde_json::from_str(&text).unwrap_or_default()
+}
+
+/// Rewrite the registry in `dir` through `apply`, under its lock.
+fn change(dir: &Path, apply: impl FnOnce(&mut Vec<Instance>)) {
+    match rewrite(dir, apply) {
+        // Before `prepare`: there is no registry to be in yet.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Cannot update {}: {}", dir.join(REGISTRY).display(), e),
+        Ok(()) => {}
+    }
+}
+
+fn rewrite(dir: &Path, apply: impl FnOnce(&mut Vec<Instance>)) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o600).open(dir.join(REGISTRY))?;
+    // Released when `file` is closed.
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    // A registry that does not parse is rebuilt from whoever registers next.
+    let mut instances: Vec<Instance> = serde_json::from_str(&text).unwrap_or_default();
+    apply(&mut instances);
+    instances.retain(|instance| alive(instance.pid) && instance.socket.exists());
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(serde_json::to_string_pretty(&instances)?.as_bytes())?;
+    relink(dir, &instances)
+}
+
+/// Point `kova.sock` at the Kova focused last (started last if none ever
+/// was), or remove it when there is none.
+fn relink(dir: &Path, instances: &[Instance]) -> std::io::Result<()> {
+    let link = dir.join(LINK);
+    let target = instances.iter().max_by_key(|instance| (instance.focused_at, instance.started_at));
+    let Some(target) = target else {
+        return match fs::remove_file(&link) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    };
+    // Relative, so the link survives the directory being reached another way.
+    let name = PathBuf::from(socket_name(target.pid));
+    if fs::read_link(&link).is_ok_and(|current| current == name) {
+        return Ok(());
+    }
+    // Built aside and renamed over: a client never finds the link missing.
+    let staging = dir.join(format!("{}.{}", LINK, std::process::id()));
+    let _ = fs::remove_file(&staging);
+    std::os::unix::fs::symlink(&name, &staging)?;
+    fs::rename(&staging, &link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kova-instances-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn instance(dir: &Path, pid: u32, started_at: u64, focused_at: Option<u64>) -> Instance {
+        Instance { pid, started_at, windows: 1, socket: dir.join(socket_name(pid)), focused_at }
+    }
+
+    #[test]
+    fn the_directory_is_made_owner_only() {
+        let dir = scratch("dir");
+        prepare_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        prepare_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_sweep_removes_the_sockets_nobody_listens_on() {
+        let dir = scratch("sweep");
+        prepare_dir(&dir).unwrap();
+        // Our parent is alive: a socket of its pid that is listened on stays,
+        // while one of a pid that cannot exist goes.
+        let parent = std::os::unix::process::parent_id();
+        let _listener = UnixListener::bind(dir.join(socket_name(parent))).unwrap();
+        drop(UnixListener::bind(dir.join(socket_name(i32::MAX as u32))).unwrap());
+        // Ours stays, listened on or not: it is about to be bound.
+        drop(UnixListener::bind(dir.join(socket_name(std::process::id()))).unwrap());
+
+        sweep(&dir, std::process::id());
+        assert_eq!(socket_pids(&dir), {
+            let mut pids = vec![parent, std::process::id()];
+            pids.sort_unstable();
+            pids
+        });
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_link_follows_the_last_focus_and_outlives_who_held_it() {
+        let dir = scratch("link");
+        prepare_dir(&dir).unwrap();
+        let (ours, parent) = (std::process::id(), std::os::unix::process::parent_id());
+        let _a = UnixListener::bind(dir.join(socket_name(ours))).unwrap();
+        let _b = UnixListener::bind(dir.join(socket_name(parent))).unwrap();
+        let link = || fs::read_link(dir.join(LINK)).ok();
+
+        // Nobody focused yet: the one started last.
+        change(&dir, |all| all.extend([instance(&dir, ours, 10, None), instance(&dir, parent, 20, None)]));
+        assert_eq!(link(), Some(PathBuf::from(socket_name(parent))));
+        change(&dir, |all| all[0].focused_at = Some(30));
+        assert_eq!(link(), Some(PathBuf::from(socket_name(ours))));
+        // A dead Kova is dropped on the next rewrite.
+        change(&dir, |all| all.push(instance(&dir, i32::MAX as u32, 40, Some(50))));
+        assert_eq!(link(), Some(PathBuf::from(socket_name(ours))));
+
+        change(&dir, |all| all.retain(|instance| instance.pid != ours));
+        assert_eq!(link(), Some(PathBuf::from(socket_name(parent))));
+        let registry: Vec<Instance> =
+            serde_json::from_str(&fs::read_to_string(dir.join(REGISTRY)).unwrap()).unwrap();
+        assert_eq!(registry, vec![instance(&dir, parent, 20, None)]);
+
+        change(&dir, |all| all.clear());
+        assert_eq!(link(), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,10 +1,17 @@
 //! Unix socket IPC server for external process control.
 //!
-//! Listens on `/tmp/kova-{pid}.sock` and accepts JSON commands from clients.
+//! Listens on `kova-{pid}.sock` in the owner-only directory of `instances::dir()`
+//! and accepts JSON commands from clients.
 //! A connection is a stream of newline-delimited JSON requests, each answered by
 //! one response line. All window/pane mutations are forwarded to the main thread
 //! via mpsc channel.
 //!
+//! Answers come back in request order, except for the waits sent with an `id`:
+//! those resolve on a worker thread of the connection, in whatever order they
+//! resolve, and `cancel` can give up on them. `hello` switches a connection to
+//! JSON-RPC 2.0 framing; the commands stay the same, and `describe` lists them
+//! (see `ipc_schema`).
+//!
 //! One command breaks that shape: `subscribe` turns the connection into a
 //! one-way event stream (see the "Event subscriptions" section at the bottom).
 //! After its response — a snapshot of the current state — Kova pushes one JSON
@@ -12,16 +19,21 @@
 
 use std::io::{BufRead, BufReader, Read, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::{mpsc, Mutex};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::ipc_audit::Audit;
+use crate::ipc_auth::Auth;
+use crate::ipc_schema;
 
 /// Maximum length of a single JSON line from a client (64 KB).
 const MAX_LINE_LEN: usize = 65536;
 
-/// How many colors the tab palette holds. Mirrors `renderer::TAB_COLORS`, which is what
-/// the right-click menu offers; an index outside it would be silently wrapped at draw time.
-const TAB_COLOR_COUNT: usize = crate::renderer::TAB_COLORS.len();
+/// How many colors the tab palette holds — the size of `renderer::TAB_COLORS`, which is
+/// what the right-click menu offers; an index outside it would be silently wrapped at draw time.
+const TAB_COLOR_COUNT: usize = crate::pane::TAB_COLOR_COUNT;
 
 /// Filter for commands that act on a set of panes.
 pub enum PaneFilter {
@@ -31,6 +43,42 @@ pub enum PaneFilter {
     Ids(Vec<u32>),
 }
 
+/// Where a command puts the pane it creates.
+pub enum Placement {
+    /// Split off the focused pane, `"horizontal"` or `"vertical"` as `split` takes it.
+    Split(String),
+    /// Alone in a new tab, after the active one.
+    Tab,
+}
+
+/// How a connection frames its requests and responses, picked by `hello`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Protocol {
+    /// `{"cmd": ...}` in, `{"ok": ...}` out. What a connection starts with.
+    Kova,
+    /// JSON-RPC 2.0: `method` / `params` in, `result` / `error` out.
+    JsonRpc,
+}
+
+impl Protocol {
+    pub const NAMES: [&'static str; 2] = ["kova", "jsonrpc"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Kova => "kova",
+            Protocol::JsonRpc => "jsonrpc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "kova" => Some(Protocol::Kova),
+            "jsonrpc" => Some(Protocol::JsonRpc),
+            _ => None,
+        }
+    }
+}
+
 /// A command received from an IPC client.
 pub enum IpcCommand {
     /// Create a new split in the focused pane of the active window.
@@ -64,11 +112,13 @@ pub enum IpcCommand {
         pane_id: u32,
         color: Option<usize>,
     },
-    /// Return the rendered text of the requested panes.
+    /// Return the rendered content of the requested panes, as plain text or
+    /// a styled export (`format`).
     GetPaneContent {
         panes: PaneFilter,
         mode: String,
         trim_trailing_blank_lines: bool,
+        format: crate::terminal::export::ExportFormat,
     },
     /// Return the size (chars + bytes) the equivalent `GetPaneContent` would produce.
     /// Lets the caller decide whether the payload is worth fetching — no cap is enforced.
@@ -76,6 +126,14 @@ pub enum IpcCommand {
         panes: PaneFilter,
         mode: String,
         trim_trailing_blank_lines: bool,
+        format: crate::terminal::export::ExportFormat,
+    },
+    /// Search a pane's scrollback and screen. The pattern is compiled by the
+    /// connection thread, so a bad regex is rejected before reaching the main thread.
+    SearchPane {
+        pane_id: u32,
+        matcher: crate::terminal::search::Matcher,
+        max_results: usize,
     },
     /// Block until a shell command in `pane_id` reports completion via OSC 133;D,
     /// or until `timeout_ms` elapses. Returns immediately if the flag is already set.
@@ -83,6 +141,21 @@ pub enum IpcCommand {
         pane_id: u32,
         timeout_ms: u64,
     },
+    /// Block until `matcher` finds a line in `pane_id` (on screen, or in what
+    /// it prints from now on), or until `timeout_ms` elapses.
+    WaitForText {
+        pane_id: u32,
+        matcher: crate::terminal::search::Matcher,
+        scope: crate::terminal::search::WaitScope,
+        timeout_ms: u64,
+    },
+    /// Block until `pane_id` has printed nothing for `quiet_ms`, or until
+    /// `timeout_ms` elapses.
+    WaitForIdle {
+        pane_id: u32,
+        quiet_ms: u64,
+        timeout_ms: u64,
+    },
     /// List all tabs across all windows.
     ListTabs,
     /// Close a tab by ID. Refuses if it would close the last tab (would terminate the app).
@@ -125,6 +198,7 @@ pub enum IpcCommand {
     /// Trigger any keyboard action by its stable name (see `action_from_ipc_name`).
     /// `pane_id` optionally targets (and focuses) a specific pane's window first;
     /// without it, the action runs against the key window.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     DispatchAction {
         action: String,
         pane_id: Option<u32>,
@@ -132,6 +206,7 @@ pub enum IpcCommand {
     /// Merge every tab of `source_window` into `target_window`, then close the
     /// now-empty source window. Windows are addressed by the index reported in
     /// `list-tabs` / `list-panes` (`"window"` field).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     MergeWindow {
         source_window: usize,
         target_window: usize,
@@ -139,26 +214,118 @@ pub enum IpcCommand {
     /// Post a desktop notification. Clicking it focuses `pane_id`.
     /// Kova posts it itself because it is the only process that can act on the
     /// click — see `crate::notification`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     Notify {
         pane_id: Option<u32>,
         title: String,
         message: String,
         sound: bool,
     },
+    /// Start recording a pane to an asciicast v2 file. `path: None` picks a
+    /// fresh file in `recording.dir`; `record_input: None` follows
+    /// `recording.record_input`.
+    StartRecording {
+        pane_id: u32,
+        path: Option<std::path::PathBuf>,
+        record_input: Option<bool>,
+    },
+    /// Stop recording a pane and close its file.
+    StopRecording(u32),
+    /// Open a read-only pane that replays a `.cast` file, split off the
+    /// focused pane like `split`.
+    PlayRecording {
+        path: std::path::PathBuf,
+        speed: f32,
+        direction: String,
+    },
+    /// Change the speed and/or pause state of a playback pane.
+    SetPlayback {
+        pane_id: u32,
+        speed: Option<f32>,
+        paused: Option<bool>,
+    },
+    /// Turn the op inspector of a pane on or off. `capacity: None` keeps the
+    /// current ring size (or `inspect::DEFAULT_CAPACITY` for a new ring).
+    InspectPane {
+        pane_id: u32,
+        on: bool,
+        capacity: Option<usize>,
+    },
+    /// Dump a pane's op ring (newest `limit` records) and its unhandled-sequence
+    /// counters. `clear` empties both after reading.
+    GetPaneOps {
+        pane_id: u32,
+        limit: Option<usize>,
+        clear: bool,
+    },
+    /// Open a pane with no process behind it. The client writes its output
+    /// (`WriteOutput`) and hears its input as `pane-input` events.
+    NewVirtualPane {
+        title: Option<String>,
+        placement: Placement,
+    },
+    /// Feed bytes to a virtual pane's terminal, escape sequences included.
+    WriteOutput {
+        pane_id: u32,
+        data: String,
+    },
+    /// Stream a pane's output to a command or a file; `target: None` stops it.
+    PipePane {
+        pane_id: u32,
+        target: Option<crate::terminal::pipe::PipeTarget>,
+        format: crate::terminal::pipe::PipeFormat,
+    },
     /// Turn this connection into an event stream for the given topics.
     /// The main thread answers with a snapshot of the current state; every
     /// change after that is pushed as its own line. See `topic`.
+    /// `panes: Some` limits the per-pane streams (`pane-output`, `pane-input`)
+    /// to those panes. `own` claims virtual panes: their `pane-input` goes to
+    /// this subscriber alone, and they close when it goes away.
     Subscribe {
         topics: u32,
+        panes: Option<Vec<u32>>,
+        own: Vec<u32>,
+    },
+    /// Pick the framing for the rest of the connection (`None`: keep it), and
+    /// authenticate with `token` (see `ipc_auth`). Answered by the connection
+    /// thread itself, like `Cancel`: neither reaches the main thread.
+    Hello {
+        protocol: Option<Protocol>,
+        token: Option<String>,
+    },
+    /// Give up on a request still in flight on this connection, named by the
+    /// `id` it was sent with.
+    Cancel {
+        id: serde_json::Value,
+    },
+    /// Every command with its fields, from `ipc_schema`. Answered by the
+    /// connection thread.
+    Describe,
+    /// The last `limit` entries of the audit log, those aimed at `pane_id`
+    /// only when given (see `ipc_audit`). Answered by the connection thread.
+    GetAuditLog {
+        limit: usize,
+        pane_id: Option<u32>,
+    },
+    /// Run these requests in one turn of the main thread, with the layout
+    /// held until the last (see `ipc_batch`). Each is kept as JSON: its
+    /// references to earlier steps are filled in as they run.
+    Batch {
+        steps: Vec<serde_json::Value>,
+        atomic: bool,
+        /// Set by the connection when the audit log wants the steps.
+        log: Option<crate::ipc_batch::StepLog>,
     },
 }
 
 /// How long the IPC connection thread should wait for the main thread's response.
-/// Most commands reply within microseconds; `wait-for-completion` may legitimately
-/// take up to its requested timeout, so we extend the deadline accordingly.
+/// Most commands reply within microseconds; the waits may legitimately take
+/// up to their requested timeout, so we extend the deadline accordingly.
 pub fn command_recv_timeout(cmd: &IpcCommand) -> std::time::Duration {
     match cmd {
-        IpcCommand::WaitForCompletion { timeout_ms, .. } => {
+        IpcCommand::WaitForCompletion { timeout_ms, .. }
+        | IpcCommand::WaitForText { timeout_ms, .. }
+        | IpcCommand::WaitForIdle { timeout_ms, .. } => {
             // Add a 2s buffer so the main thread always has time to send back
             // the timeout response itself before the connection gives up.
             std::time::Duration::from_millis(timeout_ms.saturating_add(2_000))
@@ -173,23 +340,6 @@ pub enum IpcResponse {
     Error { message: String },
 }
 
-impl IpcResponse {
-    fn to_json(&self) -> serde_json::Value {
-        match self {
-            IpcResponse::Ok { data } => {
-                let mut obj = serde_json::json!({"ok": true});
-                if let Some(d) = data {
-                    obj["data"] = d.clone();
-                }
-                obj
-            }
-            IpcResponse::Error { message } => {
-                serde_json::json!({"ok": false, "error": message})
-            }
-        }
-    }
-}
-
 /// A pending IPC request: the command plus a channel to send the response back.
 pub type IpcRequest = (IpcCommand, mpsc::Sender<IpcResponse>);
 
@@ -202,23 +352,31 @@ impl Drop for SocketCleanup {
     fn drop(&mut self) {
         let _ = std::fs::remove_file(&self.path);
         log::debug!("IPC socket removed: {}", self.path.display());
+        crate::instances::unregister();
     }
 }
 
-/// Start the IPC server on a background thread.
+/// Start the IPC server on a background thread. `auth` decides what each
+/// connection may do, and `audit` keeps a record of what it did.
 ///
 /// Returns the receiver end of the channel — the main thread polls this
 /// in its timer tick to process commands.
-pub fn start(
-) -> mpsc::Receiver<IpcRequest> {
+pub fn start(auth: Auth, audit: Audit) -> mpsc::Receiver<IpcRequest> {
     let (tx, rx) = mpsc::channel::<IpcRequest>();
+    let auth = Arc::new(auth);
+    let audit = Arc::new(audit);
 
     std::thread::Builder::new()
         .name("ipc-listener".into())
         .spawn(move || {
+            // Also clears the sockets of the Kovas that crashed.
+            if let Err(e) = crate::instances::prepare() {
+                log::error!("IPC: {}", e);
+                return;
+            }
             let path = socket_path();
 
-            // Remove stale socket from a previous crash
+            // Our own pid may be a dead Kova's from before a reboot.
             if path.exists() {
                 let _ = std::fs::remove_file(&path);
             }
@@ -251,6 +409,7 @@ pub fn start(
 
             // Guard ensures cleanup even on panic
             let _cleanup = SocketCleanup { path: path.clone() };
+            crate::instances::register();
 
             log::info!("IPC: listening on {}", path.display());
 
@@ -264,10 +423,11 @@ pub fn start(
                 };
 
                 let tx = tx.clone();
+                let (auth, audit) = (Arc::clone(&auth), Arc::clone(&audit));
                 std::thread::Builder::new()
                     .name("ipc-conn".into())
                     .spawn(move || {
-                        handle_connection(stream, tx);
+                        handle_connection(stream, tx, &auth, &audit);
                     })
                     .ok();
             }
@@ -277,89 +437,455 @@ pub fn start(
     rx
 }
 
-/// Handle a single client connection: read one JSON line, dispatch, respond.
+/// Requests one connection may have in flight at once. Each holds a thread.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Whether a command may be answered out of order, when its request has an
+/// `id`: the ones that can take up to their `timeout_ms`.
+fn is_long_running(cmd: &IpcCommand) -> bool {
+    matches!(
+        cmd,
+        IpcCommand::WaitForCompletion { .. } | IpcCommand::WaitForText { .. } | IpcCommand::WaitForIdle { .. }
+    )
+}
+
+/// JSON-RPC 2.0 error codes. Whatever the main thread refuses is a
+/// `SERVER_ERROR`; the message says why, the same message as without JSON-RPC.
+pub mod rpc_code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const SERVER_ERROR: i64 = -32000;
+    /// The connection's token does not cover the command (see `ipc_auth`).
+    pub const FORBIDDEN: i64 = -32001;
+    /// Not in the spec; the code LSP uses for a cancelled request.
+    pub const CANCELLED: i64 = -32800;
+}
+
+/// A request that came to nothing, with its JSON-RPC code.
+#[derive(Debug)]
+struct Failure {
+    code: i64,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Failure { code, message: message.into() }
+    }
+}
+
+/// What a request came to: its `data`, or why it failed.
+type Outcome = Result<Option<serde_json::Value>, Failure>;
+
+fn outcome(response: IpcResponse) -> Outcome {
+    match response {
+        IpcResponse::Ok { data } => Ok(data),
+        IpcResponse::Error { message } => Err(Failure::new(rpc_code::SERVER_ERROR, message)),
+    }
+}
+
+/// A request line, taken out of its framing.
+struct Envelope {
+    /// The framing to answer in: the connection's, except for a `hello`
+    /// sent as JSON-RPC to a connection that does not speak it yet.
+    framing: Protocol,
+    /// Echoed in the reply. `None`: none given — or, in JSON-RPC, a
+    /// notification, which gets no reply at all.
+    id: Option<serde_json::Value>,
+    /// The scopes the request needs (see `ipc_schema::scopes_needed`).
+    needs: u8,
+    /// The request, when it is one the audit log records (see `ipc_audit`).
+    audited: Option<serde_json::Value>,
+    command: Result<IpcCommand, Failure>,
+}
+
+impl Envelope {
+    /// A line too broken to tell its id. JSON-RPC still answers it, with a null id.
+    fn broken(framing: Protocol, code: i64, message: impl Into<String>) -> Self {
+        let id = (framing == Protocol::JsonRpc).then_some(serde_json::Value::Null);
+        Envelope { framing, id, needs: 0, audited: None, command: Err(Failure::new(code, message)) }
+    }
+}
+
+/// Unwrap one request line on a connection currently speaking `framing`.
+fn unwrap_request(line: &str, framing: Protocol) -> Envelope {
+    let mut v: serde_json::Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => return Envelope::broken(framing, rpc_code::PARSE_ERROR, format!("invalid JSON: {}", e)),
+    };
+    if v.get("jsonrpc").is_some() {
+        return unwrap_jsonrpc(v, framing);
+    }
+    if framing == Protocol::JsonRpc {
+        return Envelope::broken(
+            framing,
+            rpc_code::INVALID_REQUEST,
+            "this connection speaks JSON-RPC 2.0 (see \"hello\"): missing \"jsonrpc\"",
+        );
+    }
+    // `cancel` takes the id of the request it cancels, not one of its own.
+    let id = match v.as_object_mut() {
+        Some(obj) if obj.get("cmd").and_then(|c| c.as_str()) != Some("cancel") => obj.remove("id"),
+        _ => None,
+    };
+    if id.as_ref().is_some_and(|id| !id.is_string() && !id.is_number()) {
+        return Envelope::broken(framing, rpc_code::INVALID_REQUEST, "\"id\" must be a string or a number");
+    }
+    let needs = ipc_schema::scopes_needed(&v);
+    let audited = audited(&v);
+    let command = parse_value(v).map_err(|message| Failure::new(rpc_code::INVALID_PARAMS, message));
+    Envelope { framing, id, needs, audited, command }
+}
+
+/// Unwrap a JSON-RPC 2.0 request into the `{"cmd": ...}` object the parser
+/// takes: `method` becomes `cmd`, `params` its fields.
+fn unwrap_jsonrpc(v: serde_json::Value, framing: Protocol) -> Envelope {
+    use serde_json::Value;
+    let id = match v.get("id") {
+        None => None,
+        Some(id @ (Value::String(_) | Value::Number(_) | Value::Null)) => Some(id.clone()),
+        Some(_) => {
+            return Envelope::broken(Protocol::JsonRpc, rpc_code::INVALID_REQUEST, "\"id\" must be a string, a number or null")
+        }
+    };
+    let fail = |code, message: String| Envelope {
+        framing: Protocol::JsonRpc,
+        id: Some(id.clone().unwrap_or(Value::Null)),
+        needs: 0,
+        audited: None,
+        command: Err(Failure::new(code, message)),
+    };
+    if let Some(key) = v
+        .as_object()
+        .and_then(|obj| obj.keys().find(|k| !["jsonrpc", "method", "params", "id"].contains(&k.as_str())))
+    {
+        return fail(rpc_code::INVALID_REQUEST, format!("unknown field \"{}\" in a JSON-RPC request", key));
+    }
+    if v["jsonrpc"] != "2.0" {
+        return fail(rpc_code::INVALID_REQUEST, "\"jsonrpc\" must be \"2.0\"".to_string());
+    }
+    let Some(method) = v.get("method").and_then(|m| m.as_str()) else {
+        return fail(rpc_code::INVALID_REQUEST, "missing \"method\" field".to_string());
+    };
+    if framing == Protocol::Kova && method != "hello" {
+        return fail(
+            rpc_code::INVALID_REQUEST,
+            "send \"hello\" with {\"protocol\": \"jsonrpc\"} before any other JSON-RPC request".to_string(),
+        );
+    }
+    let mut params = match v.get("params") {
+        None | Some(Value::Null) => serde_json::Map::new(),
+        Some(Value::Object(params)) => params.clone(),
+        Some(_) => return fail(rpc_code::INVALID_PARAMS, "\"params\" must be an object".to_string()),
+    };
+    if params.contains_key("cmd") {
+        return fail(rpc_code::INVALID_PARAMS, format!("unknown field \"cmd\" for command \"{}\"", method));
+    }
+    params.insert("cmd".to_string(), Value::String(method.to_string()));
+    let code = if ipc_schema::command(method).is_some() { rpc_code::INVALID_PARAMS } else { rpc_code::METHOD_NOT_FOUND };
+    let params = Value::Object(params);
+    let needs = ipc_schema::scopes_needed(&params);
+    let audited = audited(&params);
+    let command = parse_value(params).map_err(|message| Failure::new(code, message));
+    Envelope { framing: Protocol::JsonRpc, id, needs, audited, command }
+}
+
+/// A copy of `request` for the audit log, if it is for a command that changes
+/// something.
+fn audited(request: &serde_json::Value) -> Option<serde_json::Value> {
+    let spec = request.get("cmd").and_then(|cmd| cmd.as_str()).and_then(ipc_schema::command)?;
+    let mutates = match spec.name {
+        "batch" => crate::ipc_batch::mutates(request),
+        _ => spec.mutates(),
+    };
+    mutates.then(|| request.clone())
+}
+
+/// The line answering a request, `None` for a JSON-RPC notification.
+fn frame(framing: Protocol, id: Option<&serde_json::Value>, outcome: Outcome) -> Option<String> {
+    let reply = match framing {
+        Protocol::Kova => {
+            let mut reply = match outcome {
+                Ok(data) => {
+                    let mut reply = serde_json::json!({"ok": true});
+                    if let Some(data) = data {
+                        reply["data"] = data;
+                    }
+                    reply
+                }
+                Err(failure) => serde_json::json!({"ok": false, "error": failure.message}),
+            };
+            if let Some(id) = id {
+                reply["id"] = id.clone();
+            }
+            reply
+        }
+        Protocol::JsonRpc => {
+            let mut reply = serde_json::json!({"jsonrpc": "2.0", "id": id?});
+            match outcome {
+                Ok(data) => reply["result"] = data.unwrap_or(serde_json::Value::Null),
+                Err(failure) => {
+                    reply["error"] = serde_json::json!({"code": failure.code, "message": failure.message})
+                }
+            }
+            reply
+        }
+    };
+    Some(reply.to_string())
+}
+
+/// The socket's write half. Workers answering out of order share it, so
+/// each line goes out whole under the lock.
+type Outbox<'a> = Mutex<&'a UnixStream>;
+
+fn send_line(out: &Outbox, line: &str) -> bool {
+    let mut writer = out.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(writer, "{}", line).is_ok() && writer.flush().is_ok()
+}
+
+/// Answer a request; false once the client is gone.
+fn reply(out: &Outbox, framing: Protocol, id: Option<&serde_json::Value>, outcome: Outcome) -> bool {
+    match frame(framing, id, outcome) {
+        Some(line) => send_line(out, &line),
+        None => true,
+    }
+}
+
+/// The requests of one connection still waiting on the main thread, by id
+/// (as JSON, so `1` and `"1"` stay apart). The sender is a clone of the one
+/// the main thread answers on: `cancel` wakes the worker through it.
+type InFlight = Mutex<HashMap<String, mpsc::Sender<IpcResponse>>>;
+
+fn in_flight(requests: &InFlight) -> std::sync::MutexGuard<'_, HashMap<String, mpsc::Sender<IpcResponse>>> {
+    requests.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Why a connection holding `scopes` may not send a request that `needs` more.
+fn forbidden(needs: u8, scopes: u8) -> Failure {
+    let message = if scopes == 0 {
+        "this connection has no token: send \"hello\" with {\"token\": ...} first".to_string()
+    } else {
+        let missing = ipc_schema::scope::names(needs & !scopes);
+        let plural = if missing.len() > 1 { "s" } else { "" };
+        format!("this request needs the \"{}\" scope{}", missing.join("\", \""), plural)
+    };
+    Failure::new(rpc_code::FORBIDDEN, message)
+}
+
+/// Handle a single client connection: read JSON lines, dispatch, respond.
+///
+/// Requests are answered in order, except the long-running ones that carry an
+/// `id`: those are handed to a worker thread and answered whenever they
+/// resolve, so one slow wait does not hold up the commands behind it.
 fn handle_connection(
     stream: std::os::unix::net::UnixStream,
     tx: mpsc::Sender<IpcRequest>,
+    auth: &Auth,
+    audit: &Audit,
 ) {
     // Set a read timeout so a misbehaving client doesn't block the thread forever
     let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(5)));
 
-    let mut reader = BufReader::new(&stream);
-    let mut writer = &stream;
-
-    let mut buf: Vec<u8> = Vec::new();
-    loop {
-        buf.clear();
-        // Bound each read so an unterminated line can't grow memory without
-        // limit — the length check must happen BEFORE the full line is buffered.
-        match std::io::Read::by_ref(&mut reader)
-            .take((MAX_LINE_LEN + 2) as u64)
-            .read_until(b'\n', &mut buf)
-        {
-            Ok(0) => break,
-            Ok(_) => {}
-            Err(e) => {
-                log::debug!("IPC: read error: {}", e);
+    let peer = crate::ipc_audit::peer_pid(&stream);
+    let out: Outbox = Mutex::new(&stream);
+    let requests: InFlight = Mutex::new(HashMap::new());
+
+    std::thread::scope(|scope| {
+        let mut reader = BufReader::new(&stream);
+        let mut framing = Protocol::Kova;
+        let mut scopes = auth.initial_scopes();
+
+        let mut buf: Vec<u8> = Vec::new();
+        loop {
+            // Bound each read so an unterminated line can't grow memory without
+            // limit — the length check must happen BEFORE the full line is buffered.
+            match std::io::Read::by_ref(&mut reader)
+                .take((MAX_LINE_LEN + 2) as u64)
+                .read_until(b'\n', &mut buf)
+            {
+                Ok(0) => break,
+                Ok(_) => {}
+                // The timeout is for idle connections, and one still owed an
+                // answer is not idle: its client is waiting on us. What was read
+                // of a line stays in `buf`.
+                Err(e)
+                    if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+                        && !in_flight(&requests).is_empty() =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    log::debug!("IPC: read error: {}", e);
+                    break;
+                }
+            }
+            while matches!(buf.last(), Some(b'\n') | Some(b'\r')) {
+                buf.pop();
+            }
+            if buf.len() > MAX_LINE_LEN {
+                let id = (framing == Protocol::JsonRpc).then_some(serde_json::Value::Null);
+                let failure = Failure::new(rpc_code::INVALID_REQUEST, "request too large");
+                reply(&out, framing, id.as_ref(), Err(failure));
                 break;
             }
-        }
-        while matches!(buf.last(), Some(b'\n') | Some(b'\r')) {
-            buf.pop();
-        }
-        if buf.len() > MAX_LINE_LEN {
-            let resp = IpcResponse::Error { message: "request too large".to_string() };
-            let _ = writeln!(writer, "{}", resp.to_json());
-            break;
-        }
 
-        let line = String::from_utf8_lossy(&buf).trim().to_string();
-        if line.is_empty() {
-            continue;
-        }
-
-        let response = match parse_command(&line) {
-            Ok(IpcCommand::Subscribe { topics }) => {
-                // Register BEFORE asking the main thread for the snapshot. An event
-                // that fires in between then lands in this subscriber's queue and is
-                // delivered just after the snapshot: the client may see an edge it
-                // already knows about (harmless — every event carries absolute state,
-                // so applying it twice changes nothing), but it can never miss one.
-                // The reverse order would open a real gap.
-                let (sub_id, events) = register_subscriber(topics);
-                let response = dispatch(&tx, IpcCommand::Subscribe { topics });
-                // Only stream behind a snapshot the client actually got. If the
-                // main thread refused (shutting down, timed out), streaming would
-                // park this thread on a subscription no tick will ever feed.
-                let live = matches!(response, IpcResponse::Ok { .. });
-                let sent = writeln!(writer, "{}", response.to_json()).is_ok()
-                    && writer.flush().is_ok();
-                if live && sent {
-                    // From here the connection is one-way: we never read from it
-                    // again. A client that also needs to issue commands opens a
-                    // second connection.
-                    stream_events(&stream, events);
+            let line = String::from_utf8_lossy(&buf).trim().to_string();
+            buf.clear();
+            if line.is_empty() {
+                continue;
+            }
+
+            let Envelope { framing: answer_in, id, needs, audited, command } = unwrap_request(&line, framing);
+            let command = match command {
+                Ok(_) if needs & !scopes != 0 => Err(forbidden(needs, scopes)),
+                command => command,
+            };
+            let sent = match command {
+                Err(failure) => {
+                    audit.record(audited, peer, Err(&failure.message));
+                    reply(&out, answer_in, id.as_ref(), Err(failure))
                 }
-                unregister_subscriber(sub_id);
-                return;
+                Ok(IpcCommand::Hello { protocol, token }) => match auth.hello(token.as_deref(), scopes) {
+                    Ok(granted) => {
+                        scopes = granted;
+                        if let Some(protocol) = protocol {
+                            framing = protocol;
+                        }
+                        let mut data = ipc_schema::versions();
+                        data["protocol"] = framing.name().into();
+                        data["scopes"] = serde_json::json!(ipc_schema::scope::names(scopes));
+                        reply(&out, answer_in, id.as_ref(), Ok(Some(data)))
+                    }
+                    Err(message) => reply(&out, answer_in, id.as_ref(), Err(Failure::new(rpc_code::FORBIDDEN, message))),
+                },
+                Ok(IpcCommand::Describe) => reply(&out, answer_in, id.as_ref(), Ok(Some(ipc_schema::describe()))),
+                Ok(IpcCommand::GetAuditLog { limit, pane_id }) => {
+                    let result = audit.recent(limit, pane_id).map(Some).map_err(|m| Failure::new(rpc_code::SERVER_ERROR, m));
+                    reply(&out, answer_in, id.as_ref(), result)
+                }
+                Ok(IpcCommand::Cancel { id: target }) => {
+                    let key = target.to_string();
+                    let result = match in_flight(&requests).remove(&key) {
+                        Some(waiter) => {
+                            // Wakes the worker, which finds its entry gone and
+                            // answers the request as cancelled.
+                            let _ = waiter.send(IpcResponse::Error { message: "cancelled".to_string() });
+                            Ok(Some(serde_json::json!({ "cancelled": target })))
+                        }
+                        None => Err(Failure::new(rpc_code::SERVER_ERROR, format!("no request {} in flight", key))),
+                    };
+                    reply(&out, answer_in, id.as_ref(), result)
+                }
+                Ok(IpcCommand::Subscribe { topics, panes, own }) => {
+                    // Register BEFORE asking the main thread for the snapshot. An event
+                    // that fires in between then lands in this subscriber's queue and is
+                    // delivered just after the snapshot: the client may see an edge it
+                    // already knows about (harmless — every event carries absolute state,
+                    // so applying it twice changes nothing), but it can never miss one.
+                    // The reverse order would open a real gap.
+                    let (sub_id, events) = register_subscriber(topics, panes.clone());
+                    if let Err(message) = claim_virtual_panes(sub_id, &own) {
+                        unregister_subscriber(sub_id);
+                        let sent = reply(&out, answer_in, id.as_ref(), outcome(IpcResponse::Error { message }));
+                        if !sent {
+                            break;
+                        }
+                        continue;
+                    }
+                    let response = dispatch(&tx, IpcCommand::Subscribe { topics, panes, own });
+                    // Only stream behind a snapshot the client actually got. If the
+                    // main thread refused (shutting down, timed out), streaming would
+                    // park this thread on a subscription no tick will ever feed.
+                    let live = matches!(response, IpcResponse::Ok { .. });
+                    let sent = reply(&out, answer_in, id.as_ref(), outcome(response));
+                    if live && sent {
+                        // From here the connection is one-way: we never read from it
+                        // again. A client that also needs to issue commands opens a
+                        // second connection. Requests already in flight still answer.
+                        stream_events(&stream, &out, framing, events);
+                    }
+                    unregister_subscriber(sub_id);
+                    // Nobody is left to hear what is typed into its panes.
+                    for pane_id in release_virtual_panes(sub_id) {
+                        dispatch(&tx, IpcCommand::ClosePaneById(pane_id));
+                    }
+                    break;
+                }
+                Ok(cmd) if id.is_some() && is_long_running(&cmd) => {
+                    let key = id.as_ref().map(|id| id.to_string()).unwrap_or_default();
+                    let mut pending = in_flight(&requests);
+                    if pending.contains_key(&key) {
+                        drop(pending);
+                        let failure = Failure::new(rpc_code::INVALID_REQUEST, format!("request {} is already in flight", key));
+                        reply(&out, answer_in, id.as_ref(), Err(failure))
+                    } else if pending.len() >= MAX_IN_FLIGHT {
+                        drop(pending);
+                        let failure = Failure::new(
+                            rpc_code::SERVER_ERROR,
+                            format!("too many requests in flight on this connection (max {})", MAX_IN_FLIGHT),
+                        );
+                        reply(&out, answer_in, id.as_ref(), Err(failure))
+                    } else {
+                        let timeout = command_recv_timeout(&cmd);
+                        let (resp_tx, resp_rx) = mpsc::channel::<IpcResponse>();
+                        if tx.send((cmd, resp_tx.clone())).is_err() {
+                            drop(pending);
+                            let failure = Failure::new(rpc_code::SERVER_ERROR, "app shutting down");
+                            reply(&out, answer_in, id.as_ref(), Err(failure))
+                        } else {
+                            pending.insert(key.clone(), resp_tx);
+                            drop(pending);
+                            let (out, requests) = (&out, &requests);
+                            scope.spawn(move || {
+                                let response = await_response(&resp_rx, timeout);
+                                // Whoever takes the entry out answers for the request. A
+                                // cancel that got there first has already said so.
+                                let result = match in_flight(requests).remove(&key) {
+                                    Some(_) => outcome(response),
+                                    None => Err(Failure::new(rpc_code::CANCELLED, "cancelled")),
+                                };
+                                reply(out, answer_in, id.as_ref(), result);
+                            });
+                            true
+                        }
+                    }
+                }
+                Ok(IpcCommand::Batch { steps, atomic, .. }) if audited.is_some() => {
+                    // One entry per step that ran, references filled in, rather
+                    // than one for the batch as it was sent.
+                    let (log, ran) = mpsc::channel();
+                    let result = outcome(dispatch(&tx, IpcCommand::Batch { steps, atomic, log: Some(log) }));
+                    for (request, step) in ran.try_iter() {
+                        audit.record(self::audited(&request), peer, step.as_ref().map(|_| ()).map_err(String::as_str));
+                    }
+                    reply(&out, answer_in, id.as_ref(), result)
+                }
+                Ok(cmd) => {
+                    let result = outcome(dispatch(&tx, cmd));
+                    audit.record(audited, peer, result.as_ref().map(|_| ()).map_err(|f| f.message.as_str()));
+                    reply(&out, answer_in, id.as_ref(), result)
+                }
+            };
+            if !sent {
+                break;
             }
-            Ok(cmd) => dispatch(&tx, cmd),
-            Err(msg) => IpcResponse::Error { message: msg },
-        };
+        }
 
-        let json = response.to_json().to_string();
-        if writeln!(writer, "{}", json).is_err() {
-            break;
+        // The client is gone: nobody is left to answer. Wake the workers
+        // rather than keep them until their timeouts.
+        for (_, waiter) in in_flight(&requests).drain() {
+            let _ = waiter.send(IpcResponse::Error { message: "cancelled".to_string() });
         }
-        let _ = writer.flush();
-    }
+    });
 }
 
 /// Hand one command to the main thread and block on its answer.
 ///
 /// The main thread drains these in its render tick, so the wait is normally
-/// microseconds; `command_recv_timeout` gives `wait-for-completion` the longer
-/// deadline it legitimately needs.
+/// microseconds; `command_recv_timeout` gives the waits the longer deadline
+/// they legitimately need.
 fn dispatch(tx: &mpsc::Sender<IpcRequest>, cmd: IpcCommand) -> IpcResponse {
     let timeout = command_recv_timeout(&cmd);
     let (resp_tx, resp_rx) = mpsc::channel::<IpcResponse>();
@@ -368,6 +894,10 @@ fn dispatch(tx: &mpsc::Sender<IpcRequest>, cmd: IpcCommand) -> IpcResponse {
             message: "app shutting down".to_string(),
         };
     }
+    await_response(&resp_rx, timeout)
+}
+
+fn await_response(resp_rx: &mpsc::Receiver<IpcResponse>, timeout: std::time::Duration) -> IpcResponse {
     match resp_rx.recv_timeout(timeout) {
         Ok(r) => r,
         Err(_) => IpcResponse::Error {
@@ -376,46 +906,16 @@ fn dispatch(tx: &mpsc::Sender<IpcRequest>, cmd: IpcCommand) -> IpcResponse {
     }
 }
 
-/// The set of accepted top-level fields for a command (besides `cmd`, which is
-/// always legitimate). Returns `None` for an unknown command, so the dispatcher's
-/// `unknown command` arm handles it instead of an `unknown field` error.
-///
-/// Must stay in sync with the per-command parsing in `parse_command` (and with
-/// `parse_pane_content_args` for the two pane-content commands).
-fn allowed_fields(cmd: &str) -> Option<&'static [&'static str]> {
-    Some(match cmd {
-        "split" => &["direction", "command", "cwd"],
-        "list-panes" => &[],
-        "close-pane" => &["pane_id"],
-        "send-keys" => &["pane_id", "text"],
-        "focus-pane" => &["pane_id"],
-        "new-tab" => &["cwd", "command"],
-        "set-tab-title" => &["pane_id", "title"],
-        "set-tab-color" => &["pane_id", "color"],
-        "get-pane-content" | "count-pane-content" => {
-            &["panes", "mode", "trim_trailing_blank_lines"]
-        }
-        "wait-for-completion" => &["pane_id", "timeout_ms"],
-        "list-tabs" => &[],
-        "close-tab" => &["tab_id"],
-        "merge-tab" => &["source_tab_id", "target_tab_id"],
-        "swap-pane" => &["pane_id_a", "pane_id_b"],
-        "resize-pane" => &["pane_id", "axis", "direction", "amount_pct"],
-        "rename-pane" => &["pane_id", "title"],
-        "set-pane-status" => &["pane_id", "status"],
-        "dispatch-action" => &["action", "pane_id"],
-        "merge-window" => &["source_window", "target_window"],
-        "notify" => &["pane_id", "title", "message", "sound"],
-        "subscribe" => &["events"],
-        _ => return None,
-    })
-}
-
 /// Parse a JSON line into an IpcCommand.
+#[cfg(test)]
 fn parse_command(line: &str) -> Result<IpcCommand, String> {
     let v: serde_json::Value =
         serde_json::from_str(line).map_err(|e| format!("invalid JSON: {}", e))?;
+    parse_value(v)
+}
 
+/// Parse a request, out of its framing, into an IpcCommand.
+pub fn parse_value(v: serde_json::Value) -> Result<IpcCommand, String> {
     let cmd = v
         .get("cmd")
         .and_then(|c| c.as_str())
@@ -424,16 +924,19 @@ fn parse_command(line: &str) -> Result<IpcCommand, String> {
     // Reject unknown fields BEFORE the per-command parsing below. Without this,
     // a stray key (e.g. `pane_id` on a command that expects `panes`) is silently
     // ignored and the command does something other than asked — a muted failure.
-    // Unknown commands are left to the match's `unknown command` arm.
-    if let Some(allowed) = allowed_fields(cmd) {
-        if let Some(obj) = v.as_object() {
-            for key in obj.keys() {
-                if key != "cmd" && !allowed.contains(&key.as_str()) {
-                    return Err(format!("unknown field \"{}\" for command \"{}\"", key, cmd));
-                }
+    // The fields come from `ipc_schema::COMMANDS`, whose tests hold it to the
+    // per-command parsing below.
+    let Some(spec) = ipc_schema::command(cmd) else {
+        return Err(format!("unknown command: {}", cmd));
+    };
+    if let Some(obj) = v.as_object() {
+        for key in obj.keys() {
+            if key != "cmd" && !spec.fields.iter().any(|field| field.name == key) {
+                return Err(format!("unknown field \"{}\" for command \"{}\"", key, cmd));
             }
         }
     }
+    spec.check(&v)?;
 
     match cmd {
         "split" => {
@@ -535,12 +1038,35 @@ fn parse_command(line: &str) -> Result<IpcCommand, String> {
             Ok(IpcCommand::SetTabColor { pane_id, color })
         }
         "get-pane-content" => {
-            let (panes, mode, trim) = parse_pane_content_args(&v)?;
-            Ok(IpcCommand::GetPaneContent { panes, mode, trim_trailing_blank_lines: trim })
+            let (panes, mode, trim, format) = parse_pane_content_args(&v)?;
+            Ok(IpcCommand::GetPaneContent { panes, mode, trim_trailing_blank_lines: trim, format })
         }
         "count-pane-content" => {
-            let (panes, mode, trim) = parse_pane_content_args(&v)?;
-            Ok(IpcCommand::CountPaneContent { panes, mode, trim_trailing_blank_lines: trim })
+            let (panes, mode, trim, format) = parse_pane_content_args(&v)?;
+            Ok(IpcCommand::CountPaneContent { panes, mode, trim_trailing_blank_lines: trim, format })
+        }
+        "search-pane" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            const MAX_RESULTS: u64 = 100_000;
+            let max_results = match v.get("max_results") {
+                None | Some(serde_json::Value::Null) => 1_000,
+                Some(m) => {
+                    let n = m
+                        .as_u64()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| "\"max_results\" must be a positive integer".to_string())?;
+                    if n > MAX_RESULTS {
+                        return Err(format!("\"max_results\" too large ({}) — max is {}", n, MAX_RESULTS));
+                    }
+                    n as usize
+                }
+            };
+            let matcher = parse_search_query(&v)?.compile()?;
+            Ok(IpcCommand::SearchPane { pane_id, matcher, max_results })
         }
         "wait-for-completion" => {
             let pane_id = v
@@ -548,22 +1074,50 @@ fn parse_command(line: &str) -> Result<IpcCommand, String> {
                 .and_then(|p| p.as_u64())
                 .ok_or_else(|| "missing \"pane_id\" field".to_string())?
                 as u32;
-            // Default 30s, capped at 5 min — keeps the connection thread from
-            // sitting on a half-dead client indefinitely.
-            let timeout_ms = match v.get("timeout_ms") {
-                None | Some(serde_json::Value::Null) => 30_000,
-                Some(t) => t
+            let timeout_ms = parse_timeout_ms(&v)?;
+            Ok(IpcCommand::WaitForCompletion { pane_id, timeout_ms })
+        }
+        "wait-for-text" => {
+            use crate::terminal::search::WaitScope;
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let matcher = parse_search_query(&v)?.compile()?;
+            let timeout_ms = parse_timeout_ms(&v)?;
+            let scope = match v.get("scope") {
+                None | Some(serde_json::Value::Null) => WaitScope::Screen,
+                Some(serde_json::Value::String(name)) => WaitScope::from_name(name).ok_or_else(|| {
+                    format!("\"scope\" must be one of \"{}\" (got \"{}\")", WaitScope::NAMES.join("\", \""), name)
+                })?,
+                Some(_) => return Err("\"scope\" must be a string".to_string()),
+            };
+            Ok(IpcCommand::WaitForText { pane_id, matcher, scope, timeout_ms })
+        }
+        "wait-for-idle" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let timeout_ms = parse_timeout_ms(&v)?;
+            let quiet_ms = match v.get("quiet_ms") {
+                None | Some(serde_json::Value::Null) => 1_000,
+                Some(q) => q
                     .as_u64()
-                    .ok_or_else(|| "\"timeout_ms\" must be a non-negative integer".to_string())?,
+                    .filter(|q| *q > 0)
+                    .ok_or_else(|| "\"quiet_ms\" must be a positive integer".to_string())?,
             };
-            const MAX_TIMEOUT_MS: u64 = 300_000;
-            if timeout_ms > MAX_TIMEOUT_MS {
+            // Silence is counted from the request at the earliest: a longer
+            // quiet period than the timeout could only ever time out.
+            if quiet_ms > timeout_ms {
                 return Err(format!(
-                    "\"timeout_ms\" too large ({}ms) — max is {}ms",
-                    timeout_ms, MAX_TIMEOUT_MS
+                    "\"quiet_ms\" ({}ms) is longer than \"timeout_ms\" ({}ms) — the wait could never succeed",
+                    quiet_ms, timeout_ms
                 ));
             }
-            Ok(IpcCommand::WaitForCompletion { pane_id, timeout_ms })
+            Ok(IpcCommand::WaitForIdle { pane_id, quiet_ms, timeout_ms })
         }
         "list-tabs" => Ok(IpcCommand::ListTabs),
         "close-tab" => {
@@ -732,63 +1286,369 @@ fn parse_command(line: &str) -> Result<IpcCommand, String> {
             };
             Ok(IpcCommand::Notify { pane_id, title, message, sound })
         }
-        "subscribe" => {
-            // Omitted / null = every topic. An explicit list is validated name by
-            // name: a typo must fail loudly, exactly like an unknown field, rather
-            // than leave the client waiting forever for events it will never get.
-            let topics = match v.get("events") {
-                None | Some(serde_json::Value::Null) => topic::ALL,
-                Some(serde_json::Value::Array(items)) => {
-                    if items.is_empty() {
-                        return Err("\"events\" must not be empty (omit it to get every topic)".to_string());
-                    }
-                    let mut mask = 0u32;
-                    for item in items {
-                        let name = item
-                            .as_str()
-                            .ok_or_else(|| "\"events\" must be an array of strings".to_string())?;
-                        match topic::from_name(name) {
-                            Some(bit) => mask |= bit,
-                            None => {
-                                return Err(format!(
-                                    "unknown event \"{}\" — known events: {}",
-                                    name,
-                                    topic::ALL_NAMES.join(", ")
-                                ))
-                            }
-                        }
-                    }
-                    mask
-                }
-                Some(_) => return Err("\"events\" must be an array of strings".to_string()),
+        "start-recording" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let path = match v.get("path") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(p)) => Some(absolute_path(p)?),
+                Some(_) => return Err("\"path\" must be a string".to_string()),
+            };
+            let record_input = match v.get("input") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::Bool(b)) => Some(*b),
+                Some(_) => return Err("\"input\" must be a boolean".to_string()),
             };
-            Ok(IpcCommand::Subscribe { topics })
+            Ok(IpcCommand::StartRecording { pane_id, path, record_input })
         }
-        other => Err(format!("unknown command: {}", other)),
-    }
-}
-
-/// Shared parser for `get-pane-content` and `count-pane-content` arguments.
-///
-/// Returns `(panes, mode, trim_trailing_blank_lines)`. Defaults:
-/// - `panes`: omitted / null → `All`; `"all"` → `All`; array of integers → `Ids`.
-/// - `mode`: `"visible"` (must be one of `visible|scrollback|all`).
-/// - `trim_trailing_blank_lines`: `true`.
-fn parse_pane_content_args(
-    v: &serde_json::Value,
-) -> Result<(PaneFilter, String, bool), String> {
-    let panes = match v.get("panes") {
-        None | Some(serde_json::Value::Null) => PaneFilter::All,
-        Some(serde_json::Value::String(s)) if s == "all" => PaneFilter::All,
-        Some(serde_json::Value::String(s)) => {
-            return Err(format!("\"panes\" string must be \"all\", got \"{}\"", s));
+        "stop-recording" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            Ok(IpcCommand::StopRecording(pane_id))
         }
-        Some(serde_json::Value::Array(arr)) => {
-            let mut ids = Vec::with_capacity(arr.len());
-            for (i, item) in arr.iter().enumerate() {
-                let id = item.as_u64().ok_or_else(|| {
-                    format!("\"panes\"[{}] must be a non-negative integer", i)
-                })?;
+        "play-recording" => {
+            let path = v
+                .get("path")
+                .and_then(|p| p.as_str())
+                .ok_or_else(|| "missing \"path\" field".to_string())?;
+            let path = absolute_path(path)?;
+            let speed = parse_playback_speed(&v)?.unwrap_or(1.0);
+            let direction = v
+                .get("direction")
+                .and_then(|d| d.as_str())
+                .unwrap_or("horizontal")
+                .to_string();
+            if direction != "horizontal" && direction != "vertical" {
+                return Err(format!("invalid direction: {}", direction));
+            }
+            Ok(IpcCommand::PlayRecording { path, speed, direction })
+        }
+        "set-playback" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let speed = parse_playback_speed(&v)?;
+            let paused = match v.get("paused") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::Bool(b)) => Some(*b),
+                Some(_) => return Err("\"paused\" must be a boolean".to_string()),
+            };
+            Ok(IpcCommand::SetPlayback { pane_id, speed, paused })
+        }
+        "inspect-pane" => {
+            use crate::terminal::inspect::MAX_CAPACITY;
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let on = match v.get("on") {
+                Some(serde_json::Value::Bool(b)) => *b,
+                None | Some(serde_json::Value::Null) => return Err("missing \"on\" field".to_string()),
+                Some(_) => return Err("\"on\" must be a boolean".to_string()),
+            };
+            let capacity = match v.get("capacity") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(c) => match c.as_u64() {
+                    Some(n) if (1..=MAX_CAPACITY as u64).contains(&n) => Some(n as usize),
+                    _ => {
+                        return Err(format!(
+                            "\"capacity\" must be an integer in [1, {}]",
+                            MAX_CAPACITY
+                        ))
+                    }
+                },
+            };
+            Ok(IpcCommand::InspectPane { pane_id, on, capacity })
+        }
+        "get-pane-ops" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let limit = match v.get("limit") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(l) => Some(
+                    l.as_u64()
+                        .ok_or_else(|| "\"limit\" must be a non-negative integer".to_string())?
+                        as usize,
+                ),
+            };
+            let clear = match v.get("clear") {
+                None | Some(serde_json::Value::Null) => false,
+                Some(serde_json::Value::Bool(b)) => *b,
+                Some(_) => return Err("\"clear\" must be a boolean".to_string()),
+            };
+            Ok(IpcCommand::GetPaneOps { pane_id, limit, clear })
+        }
+        "new-virtual-pane" => {
+            let title = match v.get("title") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(s)) => Some(s.clone()),
+                Some(_) => return Err("\"title\" must be a string or null".to_string()),
+            };
+            let tab = match v.get("tab") {
+                None | Some(serde_json::Value::Null) => false,
+                Some(serde_json::Value::Bool(b)) => *b,
+                Some(_) => return Err("\"tab\" must be a boolean".to_string()),
+            };
+            let placement = match (tab, v.get("split")) {
+                (true, None | Some(serde_json::Value::Null)) => Placement::Tab,
+                (true, Some(_)) => return Err("\"split\" and \"tab\" are exclusive".to_string()),
+                (false, None | Some(serde_json::Value::Null)) => Placement::Split("horizontal".to_string()),
+                (false, Some(serde_json::Value::String(d))) if d == "horizontal" || d == "vertical" => {
+                    Placement::Split(d.clone())
+                }
+                (false, Some(other)) => return Err(format!("invalid split direction: {}", other)),
+            };
+            Ok(IpcCommand::NewVirtualPane { title, placement })
+        }
+        "write-output" => {
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let data = v
+                .get("data")
+                .and_then(|d| d.as_str())
+                .ok_or_else(|| "missing \"data\" field".to_string())?
+                .to_string();
+            Ok(IpcCommand::WriteOutput { pane_id, data })
+        }
+        "pipe-pane" => {
+            use crate::terminal::pipe::{PipeFormat, PipeTarget};
+            let pane_id = v
+                .get("pane_id")
+                .and_then(|p| p.as_u64())
+                .ok_or_else(|| "missing \"pane_id\" field".to_string())?
+                as u32;
+            let command = v.get("command").and_then(|c| c.as_str());
+            let path = v.get("path").and_then(|p| p.as_str());
+            let target = match (command, path) {
+                (Some(_), Some(_)) => return Err("\"command\" and \"path\" are exclusive".to_string()),
+                (Some(command), None) => Some(PipeTarget::Command(command.to_string())),
+                (None, Some(path)) => Some(PipeTarget::Path(absolute_path(path)?)),
+                (None, None) => None,
+            };
+            let format = match v.get("format") {
+                None | Some(serde_json::Value::Null) => PipeFormat::Raw,
+                Some(f) => f
+                    .as_str()
+                    .and_then(PipeFormat::from_name)
+                    .ok_or_else(|| "\"format\" must be \"raw\" or \"text\"".to_string())?,
+            };
+            Ok(IpcCommand::PipePane { pane_id, target, format })
+        }
+        "subscribe" => {
+            // Omitted / null = every topic but `pane-output`, which is a firehose
+            // and has to be asked for. An explicit list is validated name by
+            // name: a typo must fail loudly, exactly like an unknown field, rather
+            // than leave the client waiting forever for events it will never get.
+            let mut topics = match v.get("events") {
+                None | Some(serde_json::Value::Null) => topic::DEFAULT,
+                Some(serde_json::Value::Array(items)) => {
+                    if items.is_empty() {
+                        return Err("\"events\" must not be empty (omit it to get every topic)".to_string());
+                    }
+                    let mut mask = 0u32;
+                    for item in items {
+                        let name = item
+                            .as_str()
+                            .ok_or_else(|| "\"events\" must be an array of strings".to_string())?;
+                        match topic::from_name(name) {
+                            Some(bit) => mask |= bit,
+                            None => {
+                                return Err(format!(
+                                    "unknown event \"{}\" — known events: {}",
+                                    name,
+                                    topic::ALL_NAMES.join(", ")
+                                ))
+                            }
+                        }
+                    }
+                    mask
+                }
+                Some(_) => return Err("\"events\" must be an array of strings".to_string()),
+            };
+            match v.get("output") {
+                None | Some(serde_json::Value::Null) => {}
+                Some(output) => {
+                    let raw = match output.as_str() {
+                        Some("lines") => false,
+                        Some("raw") => true,
+                        _ => return Err("\"output\" must be \"lines\" or \"raw\"".to_string()),
+                    };
+                    if topics & topic::PANE_OUTPUT == 0 {
+                        return Err("\"output\" needs \"pane-output\" in \"events\"".to_string());
+                    }
+                    if raw {
+                        topics = (topics & !topic::PANE_OUTPUT) | topic::PANE_OUTPUT_RAW;
+                    }
+                }
+            }
+            let panes = match v.get("panes") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::Array(items)) => Some(
+                    items
+                        .iter()
+                        .map(|item| item.as_u64().and_then(|n| u32::try_from(n).ok()))
+                        .collect::<Option<Vec<u32>>>()
+                        .ok_or_else(|| "\"panes\" must be an array of pane ids".to_string())?,
+                ),
+                Some(_) => return Err("\"panes\" must be an array of pane ids".to_string()),
+            };
+            let own = match v.get("own") {
+                None | Some(serde_json::Value::Null) => Vec::new(),
+                Some(serde_json::Value::Array(items)) => items
+                    .iter()
+                    .map(|id| id.as_u64().map(|id| id as u32))
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or_else(|| "\"own\" must be an array of pane ids".to_string())?,
+                Some(_) => return Err("\"own\" must be an array of pane ids".to_string()),
+            };
+            if !own.is_empty() {
+                topics |= topic::PANE_INPUT;
+            }
+            Ok(IpcCommand::Subscribe { topics, panes, own })
+        }
+        "hello" => {
+            let protocol = match v.get("protocol") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(name)) => Some(Protocol::from_name(name).ok_or_else(|| {
+                    format!("\"protocol\" must be one of \"{}\" (got \"{}\")", Protocol::NAMES.join("\", \""), name)
+                })?),
+                Some(_) => return Err("\"protocol\" must be a string".to_string()),
+            };
+            let token = match v.get("token") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(token)) => Some(token.clone()),
+                Some(_) => return Err("\"token\" must be a string".to_string()),
+            };
+            Ok(IpcCommand::Hello { protocol, token })
+        }
+        "cancel" => {
+            let id = v
+                .get("id")
+                .filter(|id| id.is_string() || id.is_number())
+                .cloned()
+                .ok_or_else(|| "\"id\" must be the id of the request to cancel, a string or a number".to_string())?;
+            Ok(IpcCommand::Cancel { id })
+        }
+        "describe" => Ok(IpcCommand::Describe),
+        "get-audit-log" => {
+            let limit = match v.get("limit") {
+                None | Some(serde_json::Value::Null) => 100,
+                Some(l) => l
+                    .as_u64()
+                    .map(|l| l as usize)
+                    .filter(|l| (1..=crate::ipc_audit::MAX_LIMIT).contains(l))
+                    .ok_or_else(|| format!("\"limit\" must be an integer from 1 to {}", crate::ipc_audit::MAX_LIMIT))?,
+            };
+            let pane_id = match v.get("pane_id") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(p) => Some(p.as_u64().ok_or_else(|| "\"pane_id\" must be a non-negative integer".to_string())? as u32),
+            };
+            Ok(IpcCommand::GetAuditLog { limit, pane_id })
+        }
+        "batch" => {
+            let steps = crate::ipc_batch::parse_steps(&v)?;
+            let atomic = match v.get("atomic") {
+                None | Some(serde_json::Value::Null) => false,
+                Some(serde_json::Value::Bool(b)) => *b,
+                Some(_) => return Err("\"atomic\" must be a boolean".to_string()),
+            };
+            Ok(IpcCommand::Batch { steps, atomic, log: None })
+        }
+        other => Err(format!("unknown command: {}", other)),
+    }
+}
+
+/// The pattern fields shared by `search-pane` and `wait-for-text`: `pattern`
+/// (required), `regex` (false), `case` and `accents` (`"smart"`).
+fn parse_search_query(v: &serde_json::Value) -> Result<crate::terminal::search::SearchQuery, String> {
+    use crate::terminal::search::{AccentMode, CaseMode, SearchQuery};
+    let pattern = v
+        .get("pattern")
+        .and_then(|p| p.as_str())
+        .ok_or_else(|| "missing \"pattern\" field".to_string())?
+        .to_string();
+    if pattern.is_empty() {
+        return Err("\"pattern\" must not be empty".to_string());
+    }
+    let regex = match v.get("regex") {
+        None | Some(serde_json::Value::Null) => false,
+        Some(serde_json::Value::Bool(b)) => *b,
+        Some(_) => return Err("\"regex\" must be a boolean".to_string()),
+    };
+    let case = match v.get("case") {
+        None | Some(serde_json::Value::Null) => CaseMode::Smart,
+        Some(serde_json::Value::String(name)) => CaseMode::from_name(name).ok_or_else(|| {
+            format!("\"case\" must be one of \"{}\" (got \"{}\")", CaseMode::NAMES.join("\", \""), name)
+        })?,
+        Some(_) => return Err("\"case\" must be a string".to_string()),
+    };
+    let accents = match v.get("accents") {
+        None | Some(serde_json::Value::Null) => AccentMode::Smart,
+        Some(serde_json::Value::String(name)) => AccentMode::from_name(name).ok_or_else(|| {
+            format!("\"accents\" must be one of \"{}\" (got \"{}\")", AccentMode::NAMES.join("\", \""), name)
+        })?,
+        Some(_) => return Err("\"accents\" must be a string".to_string()),
+    };
+    Ok(SearchQuery { pattern, regex, case, accents })
+}
+
+/// `timeout_ms` of the two wait commands.
+fn parse_timeout_ms(v: &serde_json::Value) -> Result<u64, String> {
+    // Default 30s, capped at 5 min — keeps the connection thread from
+    // sitting on a half-dead client indefinitely.
+    let timeout_ms = match v.get("timeout_ms") {
+        None | Some(serde_json::Value::Null) => 30_000,
+        Some(t) => t
+            .as_u64()
+            .ok_or_else(|| "\"timeout_ms\" must be a non-negative integer".to_string())?,
+    };
+    const MAX_TIMEOUT_MS: u64 = 300_000;
+    if timeout_ms > MAX_TIMEOUT_MS {
+        return Err(format!(
+            "\"timeout_ms\" too large ({}ms) — max is {}ms",
+            timeout_ms, MAX_TIMEOUT_MS
+        ));
+    }
+    Ok(timeout_ms)
+}
+
+/// Shared parser for `get-pane-content` and `count-pane-content` arguments.
+///
+/// Returns `(panes, mode, trim_trailing_blank_lines, format)`. Defaults:
+/// - `panes`: omitted / null → `All`; `"all"` → `All`; array of integers → `Ids`.
+/// - `mode`: `"visible"` (must be one of `visible|scrollback|all`).
+/// - `trim_trailing_blank_lines`: `true`.
+/// - `format`: `"text"` (must be one of `text|ansi|html|cells`).
+fn parse_pane_content_args(
+    v: &serde_json::Value,
+) -> Result<(PaneFilter, String, bool, crate::terminal::export::ExportFormat), String> {
+    let panes = match v.get("panes") {
+        None | Some(serde_json::Value::Null) => PaneFilter::All,
+        Some(serde_json::Value::String(s)) if s == "all" => PaneFilter::All,
+        Some(serde_json::Value::String(s)) => {
+            return Err(format!("\"panes\" string must be \"all\", got \"{}\"", s));
+        }
+        Some(serde_json::Value::Array(arr)) => {
+            let mut ids = Vec::with_capacity(arr.len());
+            for (i, item) in arr.iter().enumerate() {
+                let id = item.as_u64().ok_or_else(|| {
+                    format!("\"panes\"[{}] must be a non-negative integer", i)
+                })?;
                 ids.push(id as u32);
             }
             PaneFilter::Ids(ids)
@@ -820,12 +1680,49 @@ fn parse_pane_content_args(
         }
     };
 
-    Ok((panes, mode, trim))
+    let format_str = match v.get("format") {
+        None | Some(serde_json::Value::Null) => "text",
+        Some(serde_json::Value::String(f)) => f.as_str(),
+        Some(_) => return Err("\"format\" must be a string".to_string()),
+    };
+    let format = crate::terminal::export::ExportFormat::parse(format_str).ok_or_else(|| {
+        format!(
+            "\"format\" must be one of \"text\", \"ansi\", \"html\", \"cells\" (got \"{}\")",
+            format_str
+        )
+    })?;
+
+    Ok((panes, mode, trim, format))
+}
+
+/// A file path from a client, which must be absolute: the connection has no
+/// working directory a relative path could mean.
+fn absolute_path(p: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(p);
+    if !path.is_absolute() {
+        return Err(format!("path must be absolute: {}", p));
+    }
+    Ok(path)
+}
+
+/// Optional `speed` of the playback commands: a multiplier of recorded time.
+fn parse_playback_speed(v: &serde_json::Value) -> Result<Option<f32>, String> {
+    use crate::terminal::recording::{MAX_SPEED, MIN_SPEED};
+    match v.get("speed") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(s) => {
+            let f = s.as_f64().ok_or_else(|| "\"speed\" must be a number".to_string())? as f32;
+            if !(MIN_SPEED..=MAX_SPEED).contains(&f) {
+                return Err(format!("\"speed\" must be in [{}, {}] (got {})", MIN_SPEED, MAX_SPEED, f));
+            }
+            Ok(Some(f))
+        }
+    }
 }
 
 /// The canonical socket path for this process.
 pub fn socket_path() -> PathBuf {
-    Path::new("/tmp").join(format!("kova-{}.sock", std::process::id()))
+    crate::instances::socket_path_for(std::process::id())
 }
 
 /// Remove the socket file (called from will_terminate for explicit cleanup).
@@ -835,6 +1732,7 @@ pub fn cleanup() {
         let _ = std::fs::remove_file(&path);
         log::debug!("IPC: socket cleaned up at {}", path.display());
     }
+    crate::instances::unregister();
 }
 
 // ─── Event subscriptions ────────────────────────────────────────────────────
@@ -856,16 +1754,35 @@ pub mod topic {
     pub const PANE_OPEN: u32 = 1 << 3;
     /// A pane went away.
     pub const PANE_CLOSE: u32 = 1 << 4;
-
-    pub const ALL: u32 = FOCUS | PANE_STATUS | PANE_WORKING | PANE_OPEN | PANE_CLOSE;
+    /// Input for a virtual pane: what the user typed into it (or `send-keys`
+    /// sent it), for the client that writes its output.
+    pub const PANE_INPUT: u32 = 1 << 5;
+    /// A pane's output, one logical line at a time (`terminal::output`).
+    pub const PANE_OUTPUT: u32 = 1 << 6;
+    /// A pane fell silent for `events::PANE_QUIET_AFTER`, or spoke again.
+    pub const PANE_QUIET: u32 = 1 << 7;
+    /// `pane-output` with `"output": "raw"`: the PTY chunks themselves. Not a
+    /// wire name of its own; a subscriber holds this bit or `PANE_OUTPUT`.
+    /// Kept clear of the named bits, which follow `ALL_NAMES`.
+    pub const PANE_OUTPUT_RAW: u32 = 1 << 31;
+
+    pub const ALL: u32 =
+        FOCUS | PANE_STATUS | PANE_WORKING | PANE_OPEN | PANE_CLOSE | PANE_INPUT | PANE_OUTPUT | PANE_QUIET;
+    /// What a `subscribe` without `events` gets: everything but the output
+    /// stream, which would bury the state changes of one pane under the
+    /// chatter of all the others.
+    pub const DEFAULT: u32 = ALL & !PANE_OUTPUT;
 
     /// Wire names, in bit order — `names()` relies on that ordering.
-    pub const ALL_NAMES: [&str; 5] = [
+    pub const ALL_NAMES: [&str; 8] = [
         "focus",
         "pane-status",
         "pane-working",
         "pane-open",
         "pane-close",
+        "pane-input",
+        "pane-output",
+        "pane-quiet",
     ];
 
     pub fn from_name(name: &str) -> Option<u32> {
@@ -876,6 +1793,7 @@ pub mod topic {
     /// The names covered by `mask`, in bit order (echoed back in the subscribe
     /// snapshot so a client can see what it actually got).
     pub fn names(mask: u32) -> Vec<&'static str> {
+        let mask = if mask & PANE_OUTPUT_RAW != 0 { mask | PANE_OUTPUT } else { mask };
         ALL_NAMES
             .iter()
             .enumerate()
@@ -898,6 +1816,8 @@ const EVENT_WRITE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(
 struct Subscriber {
     id: u64,
     topics: u32,
+    /// Panes whose `publish_pane` events this client gets; `None` = all.
+    panes: Option<Vec<u32>>,
     /// Bounded on purpose: `publish` runs on the main thread and must never
     /// block, so it `try_send`s and drops the client when the queue is full.
     tx: mpsc::SyncSender<String>,
@@ -919,11 +1839,11 @@ fn refresh_topic_mask(subs: &[Subscriber]) {
     SUBSCRIBED_TOPICS.store(mask, Ordering::Relaxed);
 }
 
-fn register_subscriber(topics: u32) -> (u64, mpsc::Receiver<String>) {
+fn register_subscriber(topics: u32, panes: Option<Vec<u32>>) -> (u64, mpsc::Receiver<String>) {
     let (tx, rx) = mpsc::sync_channel::<String>(EVENT_QUEUE_CAP);
     let id = NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed);
     let mut subs = subscribers();
-    subs.push(Subscriber { id, topics, tx });
+    subs.push(Subscriber { id, topics, panes, tx });
     refresh_topic_mask(&subs);
     log::info!("IPC: subscriber {} listening on {:?}", id, topic::names(topics));
     (id, rx)
@@ -936,6 +1856,94 @@ fn unregister_subscriber(id: u64) {
     log::info!("IPC: subscriber {} gone", id);
 }
 
+/// A virtual pane's input: who hears it, and what came while nobody did.
+#[derive(Default)]
+struct VirtualInput {
+    /// The subscriber that claimed the pane (`subscribe` with `own`).
+    owner: Option<u64>,
+    /// `pane-input` events from before the claim, handed to the owner first.
+    backlog: std::collections::VecDeque<String>,
+}
+
+/// Every open virtual pane, by id. Locked before `SUBSCRIBERS` when both are.
+static VIRTUAL_INPUT: Mutex<std::collections::BTreeMap<u32, VirtualInput>> =
+    Mutex::new(std::collections::BTreeMap::new());
+/// Input kept per unclaimed pane; the oldest goes first.
+const VIRTUAL_INPUT_BACKLOG: usize = 64;
+
+fn virtual_input() -> std::sync::MutexGuard<'static, std::collections::BTreeMap<u32, VirtualInput>> {
+    VIRTUAL_INPUT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A virtual pane opened: its input is kept until a subscriber claims it.
+pub fn virtual_pane_opened(pane_id: u32) {
+    virtual_input().insert(pane_id, VirtualInput::default());
+}
+
+pub fn virtual_pane_closed(pane_id: u32) {
+    virtual_input().remove(&pane_id);
+}
+
+/// Send a virtual pane's input to the subscriber that owns it, or keep it
+/// for the one that will. Never to anyone else: it is what the user typed.
+pub fn publish_pane_input(pane_id: u32, event: serde_json::Value) {
+    let mut panes = virtual_input();
+    let Some(pane) = panes.get_mut(&pane_id) else { return };
+    let line = event.to_string();
+    let Some(owner) = pane.owner else {
+        if pane.backlog.len() == VIRTUAL_INPUT_BACKLOG {
+            pane.backlog.pop_front();
+        }
+        pane.backlog.push_back(line);
+        return;
+    };
+    let mut subs = subscribers();
+    let Some(sub) = subs.iter().find(|sub| sub.id == owner) else { return };
+    if let Err(mpsc::TrySendError::Full(_)) = sub.tx.try_send(line) {
+        // As in `send_event`. Its connection then closes the pane.
+        log::warn!("IPC: subscriber {} fell {} events behind — dropping it", owner, EVENT_QUEUE_CAP);
+        subs.retain(|sub| sub.id != owner);
+        refresh_topic_mask(&subs);
+    }
+}
+
+/// Make subscriber `sub_id` the owner of the virtual panes `own`, and hand it
+/// what they got so far. All or none: a pane that is not virtual, or that
+/// another subscriber owns, fails the claim.
+fn claim_virtual_panes(sub_id: u64, own: &[u32]) -> Result<(), String> {
+    let mut panes = virtual_input();
+    for pane_id in own {
+        match panes.get(pane_id) {
+            None => return Err(format!("pane {} is not a virtual pane", pane_id)),
+            Some(pane) if pane.owner.is_some() => return Err(format!("pane {} already has an owner", pane_id)),
+            Some(_) => {}
+        }
+    }
+    let subs = subscribers();
+    let Some(sub) = subs.iter().find(|sub| sub.id == sub_id) else { return Ok(()) };
+    for pane_id in own {
+        let pane = panes.get_mut(pane_id).expect("checked above");
+        pane.owner = Some(sub_id);
+        for line in pane.backlog.drain(..) {
+            let _ = sub.tx.try_send(line);
+        }
+    }
+    Ok(())
+}
+
+/// Forget what subscriber `sub_id` owned. Returns those panes, to close.
+fn release_virtual_panes(sub_id: u64) -> Vec<u32> {
+    let mut panes = virtual_input();
+    let mut released = Vec::new();
+    for (&pane_id, pane) in panes.iter_mut() {
+        if pane.owner == Some(sub_id) {
+            pane.owner = None;
+            released.push(pane_id);
+        }
+    }
+    released
+}
+
 /// True if at least one client wants any of `topics`. Lets the main thread skip
 /// the whole state-diffing pass when nobody is listening.
 pub fn has_subscribers(topics: u32) -> bool {
@@ -949,6 +1957,16 @@ pub fn has_subscribers(topics: u32) -> bool {
 /// served a hole — it must reconnect, and a fresh `subscribe` hands it a
 /// snapshot, so it resyncs instead of carrying a silently wrong view of the world.
 pub fn publish(topic: u32, event: serde_json::Value) {
+    send_event(topic, None, event);
+}
+
+/// `publish` for an event about one pane, which subscribers that named other
+/// panes in `panes` do not get. Also called from PTY reader threads.
+pub fn publish_pane(topic: u32, pane_id: u32, event: serde_json::Value) {
+    send_event(topic, Some(pane_id), event);
+}
+
+fn send_event(topic: u32, pane_id: Option<u32>, event: serde_json::Value) {
     if !has_subscribers(topic) {
         return;
     }
@@ -959,6 +1977,13 @@ pub fn publish(topic: u32, event: serde_json::Value) {
         if sub.topics & topic == 0 {
             return true;
         }
+        let wanted = match (pane_id, &sub.panes) {
+            (Some(id), Some(panes)) => panes.contains(&id),
+            _ => true,
+        };
+        if !wanted {
+            return true;
+        }
         match sub.tx.try_send(line.clone()) {
             Ok(()) => true,
             Err(mpsc::TrySendError::Full(_)) => {
@@ -980,9 +2005,9 @@ pub fn publish(topic: u32, event: serde_json::Value) {
 /// Pump queued events to a subscribed client until its connection dies.
 ///
 /// Runs on that connection's own thread, which is the whole point: the socket
-/// write happens here, never on the main thread that produced the event.
-fn stream_events(stream: &UnixStream, events: mpsc::Receiver<String>) {
-    let mut writer = stream;
+/// write happens here, never on the main thread that produced the event. On a
+/// JSON-RPC connection each event is an `event` notification.
+fn stream_events(stream: &UnixStream, out: &Outbox, framing: Protocol, events: mpsc::Receiver<String>) {
     let _ = stream.set_write_timeout(Some(EVENT_WRITE_TIMEOUT));
     loop {
         let line = match events.recv_timeout(EVENT_HEARTBEAT) {
@@ -993,7 +2018,11 @@ fn stream_events(stream: &UnixStream, events: mpsc::Receiver<String>) {
             Err(mpsc::RecvTimeoutError::Timeout) => r#"{"event":"ping"}"#.to_string(),
             Err(mpsc::RecvTimeoutError::Disconnected) => break,
         };
-        if writeln!(writer, "{}", line).is_err() || writer.flush().is_err() {
+        let line = match framing {
+            Protocol::Kova => line,
+            Protocol::JsonRpc => format!(r#"{{"jsonrpc":"2.0","method":"event","params":{}}}"#, line),
+        };
+        if !send_line(out, &line) {
             break;
         }
     }
@@ -1038,6 +2067,8 @@ mod tests {
             r#"{"cmd":"get-pane-content","panes":[1,2],"mode":"all","trim_trailing_blank_lines":false}"#
         )
         .is_ok());
+        assert!(parse_command(r#"{"cmd":"get-pane-content","format":"html"}"#).is_ok());
+        assert!(parse_command(r#"{"cmd":"count-pane-content","format":"cells"}"#).is_ok());
         // `split`/`new-tab` use `command`, not `cmd`, for the shell command.
         assert!(parse_command(
             r#"{"cmd":"split","direction":"vertical","command":"ls"}"#
@@ -1046,6 +2077,109 @@ mod tests {
         assert!(parse_command(r#"{"cmd":"list-panes"}"#).is_ok());
     }
 
+    #[test]
+    fn pane_content_format_defaults_to_text_and_rejects_unknown_names() {
+        use crate::terminal::export::ExportFormat;
+        assert!(matches!(
+            parse_command(r#"{"cmd":"get-pane-content"}"#),
+            Ok(IpcCommand::GetPaneContent { format: ExportFormat::Text, .. })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"get-pane-content","format":"ansi"}"#),
+            Ok(IpcCommand::GetPaneContent { format: ExportFormat::Ansi, .. })
+        ));
+        match parse_command(r#"{"cmd":"get-pane-content","format":"rtf"}"#) {
+            Err(e) => assert!(e.contains("\"format\" must be one of"), "{e}"),
+            Ok(_) => panic!("rtf is not an IPC format"),
+        }
+        for wrong in [r#"5"#, r#"["html"]"#] {
+            let line = format!(r#"{{"cmd":"count-pane-content","format":{}}}"#, wrong);
+            assert_eq!(err(&line), "\"format\" must be a string");
+        }
+    }
+
+    #[test]
+    fn recording_commands_require_absolute_paths_and_bounded_speeds() {
+        assert!(matches!(
+            parse_command(r#"{"cmd":"start-recording","pane_id":3}"#),
+            Ok(IpcCommand::StartRecording { pane_id: 3, path: None, record_input: None })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"start-recording","pane_id":3,"path":"/tmp/a.cast","input":true}"#),
+            Ok(IpcCommand::StartRecording { path: Some(_), record_input: Some(true), .. })
+        ));
+        assert!(parse_command(r#"{"cmd":"start-recording","pane_id":3,"path":"a.cast"}"#).is_err());
+        match parse_command(r#"{"cmd":"play-recording","path":"/tmp/a.cast"}"#) {
+            Ok(IpcCommand::PlayRecording { speed, direction, .. }) => {
+                assert_eq!(speed, 1.0);
+                assert_eq!(direction, "horizontal");
+            }
+            _ => panic!("play-recording with defaults should parse"),
+        }
+        assert!(parse_command(r#"{"cmd":"play-recording","path":"/tmp/a.cast","speed":100}"#).is_err());
+        assert!(matches!(
+            parse_command(r#"{"cmd":"set-playback","pane_id":4,"paused":true}"#),
+            Ok(IpcCommand::SetPlayback { pane_id: 4, speed: None, paused: Some(true) })
+        ));
+    }
+
+    #[test]
+    fn virtual_pane_goes_in_a_split_or_a_tab_but_not_both() {
+        match parse_command(r#"{"cmd":"new-virtual-pane","title":"CI"}"#) {
+            Ok(IpcCommand::NewVirtualPane { title: Some(title), placement: Placement::Split(direction) }) => {
+                assert_eq!(title, "CI");
+                assert_eq!(direction, "horizontal");
+            }
+            _ => panic!("new-virtual-pane with defaults should parse"),
+        }
+        assert!(matches!(
+            parse_command(r#"{"cmd":"new-virtual-pane","tab":true}"#),
+            Ok(IpcCommand::NewVirtualPane { title: None, placement: Placement::Tab })
+        ));
+        assert!(parse_command(r#"{"cmd":"new-virtual-pane","tab":true,"split":"vertical"}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"new-virtual-pane","split":"diagonal"}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"write-output","pane_id":7}"#).is_err());
+        assert!(matches!(
+            parse_command(r#"{"cmd":"write-output","pane_id":7,"data":"\u001b[1mok\r\n"}"#),
+            Ok(IpcCommand::WriteOutput { pane_id: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn pipe_pane_takes_a_command_or_an_absolute_path_and_nothing_stops_it() {
+        use crate::terminal::pipe::{PipeFormat, PipeTarget};
+        assert!(matches!(
+            parse_command(r#"{"cmd":"pipe-pane","pane_id":3,"command":"cat >> /tmp/log"}"#),
+            Ok(IpcCommand::PipePane { pane_id: 3, target: Some(PipeTarget::Command(_)), format: PipeFormat::Raw })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"pipe-pane","pane_id":3,"path":"/tmp/log","format":"text"}"#),
+            Ok(IpcCommand::PipePane { target: Some(PipeTarget::Path(_)), format: PipeFormat::Text, .. })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"pipe-pane","pane_id":3}"#),
+            Ok(IpcCommand::PipePane { target: None, .. })
+        ));
+        assert!(parse_command(r#"{"cmd":"pipe-pane","pane_id":3,"path":"log"}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"pipe-pane","pane_id":3,"path":"/tmp/a","command":"cat"}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"pipe-pane","pane_id":3,"command":"cat","format":"html"}"#).is_err());
+    }
+
+    #[test]
+    fn inspector_commands_require_on_and_bound_capacity() {
+        assert!(matches!(
+            parse_command(r#"{"cmd":"inspect-pane","pane_id":2,"on":true}"#),
+            Ok(IpcCommand::InspectPane { pane_id: 2, on: true, capacity: None })
+        ));
+        assert!(parse_command(r#"{"cmd":"inspect-pane","pane_id":2}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"inspect-pane","pane_id":2,"on":true,"capacity":0}"#).is_err());
+        assert!(parse_command(r#"{"cmd":"inspect-pane","pane_id":2,"on":true,"capacity":1000000}"#).is_err());
+        assert!(matches!(
+            parse_command(r#"{"cmd":"get-pane-ops","pane_id":2,"limit":50,"clear":true}"#),
+            Ok(IpcCommand::GetPaneOps { pane_id: 2, limit: Some(50), clear: true })
+        ));
+    }
+
     #[test]
     fn set_pane_status_parses_both_states() {
         assert!(matches!(
@@ -1114,26 +2248,95 @@ mod tests {
             err(r#"{"cmd":"set-pane-status","pane_id":7,"status":"busy"}"#),
             "\"status\" must be \"waiting\" or \"none\""
         );
-        assert_eq!(
-            err(r#"{"cmd":"set-pane-status","pane_id":7}"#),
-            "\"status\" must be \"waiting\" or \"none\""
-        );
+        assert_eq!(err(r#"{"cmd":"set-pane-status","pane_id":7}"#), "missing \"status\" field");
         assert_eq!(
             err(r#"{"cmd":"set-pane-status","status":"waiting"}"#),
             "missing \"pane_id\" field"
         );
     }
 
+    #[test]
+    fn search_pane_defaults_and_validation() {
+        match parse_command(r#"{"cmd":"search-pane","pane_id":3,"pattern":"err"}"#) {
+            Ok(IpcCommand::SearchPane { pane_id, matcher, max_results }) => {
+                assert_eq!(pane_id, 3);
+                assert_eq!(max_results, 1_000);
+                // Smart case and accents by default: a plain lowercase
+                // pattern ignores both.
+                assert!(matcher.is_match("ÉRR"));
+            }
+            _ => panic!("search-pane should parse with pane_id and pattern"),
+        }
+        assert_eq!(
+            err(r#"{"cmd":"search-pane","pane_id":3,"pattern":"(","regex":true}"#),
+            "invalid regex: unclosed group"
+        );
+        assert_eq!(
+            err(r#"{"cmd":"search-pane","pane_id":3,"pattern":"x","case":"upper"}"#),
+            "\"case\" must be one of \"sensitive\", \"insensitive\", \"smart\" (got \"upper\")"
+        );
+        assert_eq!(
+            err(r#"{"cmd":"search-pane","pane_id":3,"pattern":"x","accents":true}"#),
+            "\"accents\" must be a string"
+        );
+        assert_eq!(
+            err(r#"{"cmd":"search-pane","pane_id":3,"pattern":""}"#),
+            "\"pattern\" must not be empty"
+        );
+        assert_eq!(
+            err(r#"{"cmd":"search-pane","pane_id":3,"pattern":"x","max_results":0}"#),
+            "\"max_results\" must be a positive integer"
+        );
+    }
+
     #[test]
     fn unknown_command_takes_precedence_over_field_check() {
         assert_eq!(err(r#"{"cmd":"bogus","whatever":1}"#), "unknown command: bogus");
     }
 
     #[test]
-    fn subscribe_without_events_takes_every_topic() {
+    fn wait_for_text_takes_the_search_fields_and_a_scope() {
+        use crate::terminal::search::WaitScope;
+        assert!(matches!(
+            parse_command(r#"{"cmd":"wait-for-text","pane_id":3,"pattern":"\\$ $","regex":true}"#),
+            Ok(IpcCommand::WaitForText { pane_id: 3, scope: WaitScope::Screen, timeout_ms: 30_000, .. })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"wait-for-text","pane_id":3,"pattern":"ok","scope":"new-output","timeout_ms":500}"#),
+            Ok(IpcCommand::WaitForText { scope: WaitScope::NewOutput, timeout_ms: 500, .. })
+        ));
+        assert_eq!(
+            err(r#"{"cmd":"wait-for-text","pane_id":3,"pattern":"ok","scope":"scrollback"}"#),
+            "\"scope\" must be one of \"screen\", \"new-output\" (got \"scrollback\")"
+        );
+        assert_eq!(err(r#"{"cmd":"wait-for-text","pane_id":3,"pattern":"(","regex":true}"#), "invalid regex: unclosed group");
+    }
+
+    #[test]
+    fn wait_for_idle_needs_a_quiet_period_that_fits_in_the_timeout() {
+        assert!(matches!(
+            parse_command(r#"{"cmd":"wait-for-idle","pane_id":3}"#),
+            Ok(IpcCommand::WaitForIdle { pane_id: 3, quiet_ms: 1_000, timeout_ms: 30_000 })
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"wait-for-idle","pane_id":3,"quiet_ms":200,"timeout_ms":5000}"#),
+            Ok(IpcCommand::WaitForIdle { quiet_ms: 200, timeout_ms: 5_000, .. })
+        ));
+        assert_eq!(
+            err(r#"{"cmd":"wait-for-idle","pane_id":3,"quiet_ms":0}"#),
+            "\"quiet_ms\" must be a positive integer"
+        );
+        assert_eq!(
+            err(r#"{"cmd":"wait-for-idle","pane_id":3,"quiet_ms":2000,"timeout_ms":1000}"#),
+            "\"quiet_ms\" (2000ms) is longer than \"timeout_ms\" (1000ms) — the wait could never succeed"
+        );
+    }
+
+    #[test]
+    fn subscribe_without_events_takes_every_topic_but_the_output_stream() {
         assert!(matches!(
             parse_command(r#"{"cmd":"subscribe"}"#),
-            Ok(IpcCommand::Subscribe { topics }) if topics == topic::ALL
+            Ok(IpcCommand::Subscribe { topics, panes: None, .. }) if topics == topic::ALL & !topic::PANE_OUTPUT
         ));
     }
 
@@ -1141,17 +2344,42 @@ mod tests {
     fn subscribe_builds_the_mask_from_the_named_events() {
         assert!(matches!(
             parse_command(r#"{"cmd":"subscribe","events":["focus","pane-close"]}"#),
-            Ok(IpcCommand::Subscribe { topics }) if topics == topic::FOCUS | topic::PANE_CLOSE
+            Ok(IpcCommand::Subscribe { topics, .. }) if topics == topic::FOCUS | topic::PANE_CLOSE
         ));
     }
 
+    #[test]
+    fn subscribe_to_output_picks_lines_or_raw_and_the_panes() {
+        assert!(matches!(
+            parse_command(r#"{"cmd":"subscribe","events":["pane-output"],"panes":[3,7]}"#),
+            Ok(IpcCommand::Subscribe { topics, panes: Some(p), .. }) if topics == topic::PANE_OUTPUT && p == [3, 7]
+        ));
+        assert!(matches!(
+            parse_command(r#"{"cmd":"subscribe","events":["focus","pane-output"],"output":"raw"}"#),
+            Ok(IpcCommand::Subscribe { topics, .. }) if topics == topic::FOCUS | topic::PANE_OUTPUT_RAW
+        ));
+        assert_eq!(topic::names(topic::PANE_OUTPUT_RAW), vec!["pane-output"]);
+        assert_eq!(
+            err(r#"{"cmd":"subscribe","output":"raw"}"#),
+            "\"output\" needs \"pane-output\" in \"events\""
+        );
+        assert_eq!(
+            err(r#"{"cmd":"subscribe","events":["pane-output"],"output":"html"}"#),
+            "\"output\" must be \"lines\" or \"raw\""
+        );
+        assert_eq!(
+            err(r#"{"cmd":"subscribe","events":["pane-output"],"panes":["3"]}"#),
+            "\"panes\" must be an array of pane ids"
+        );
+    }
+
     #[test]
     fn subscribe_rejects_an_unknown_event() {
         // A typo here would otherwise leave the client waiting forever for events
         // that will never come — the failure has to be loud and immediate.
         assert_eq!(
             err(r#"{"cmd":"subscribe","events":["focous"]}"#),
-            "unknown event \"focous\" — known events: focus, pane-status, pane-working, pane-open, pane-close"
+            "unknown event \"focous\" — known events: focus, pane-status, pane-working, pane-open, pane-close, pane-input, pane-output, pane-quiet"
         );
         assert_eq!(
             err(r#"{"cmd":"subscribe","events":[]}"#),
@@ -1172,4 +2400,314 @@ mod tests {
         assert_eq!(topic::names(topic::ALL), topic::ALL_NAMES.to_vec());
         assert_eq!(topic::from_name("nope"), None);
     }
+
+    #[test]
+    fn what_is_typed_into_a_virtual_pane_is_published_as_pane_input() {
+        let pane = crate::pane::Pane::virtual_pane(20, 5, None, &crate::config::Config::default());
+        let (sub_id, events) = register_subscriber(topic::PANE_INPUT, Some(vec![pane.id]));
+        claim_virtual_panes(sub_id, &[pane.id]).unwrap();
+        pane.pty.write(b"q");
+        // The parser's replies go the same way: the client is the program.
+        pane.pty.write_output(b"\x1b[6n").unwrap();
+        let mine: Vec<serde_json::Value> = events
+            .try_iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(&line).unwrap())
+            .collect();
+        unregister_subscriber(sub_id);
+        assert_eq!(mine.len(), 2);
+        assert_eq!(mine[0]["event"], "pane-input");
+        assert_eq!(mine[0]["data"], "q");
+        assert_eq!(mine[1]["data"], "\x1b[1;1R");
+    }
+
+    #[test]
+    fn a_virtual_panes_input_waits_for_its_owner_and_reaches_nobody_else() {
+        let pane = crate::pane::Pane::virtual_pane(20, 5, None, &crate::config::Config::default());
+        let (other_id, other) = register_subscriber(topic::PANE_INPUT, None);
+        // Typed before anyone claimed the pane: kept for its owner.
+        pane.pty.write(b"early");
+        let (owner_id, owner) = register_subscriber(topic::PANE_INPUT, None);
+        claim_virtual_panes(owner_id, &[pane.id]).unwrap();
+        pane.pty.write(b"late");
+        let data = |events: &mpsc::Receiver<String>| -> Vec<String> {
+            events
+                .try_iter()
+                .map(|line| serde_json::from_str::<serde_json::Value>(&line).unwrap()["data"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let (mine, theirs) = (data(&owner), data(&other));
+        let claimed_twice = claim_virtual_panes(other_id, &[pane.id]);
+        unregister_subscriber(owner_id);
+        let released = release_virtual_panes(owner_id);
+        unregister_subscriber(other_id);
+        assert_eq!(mine, ["early", "late"]);
+        assert!(theirs.is_empty());
+        assert_eq!(claimed_twice, Err(format!("pane {} already has an owner", pane.id)));
+        // Its connection closes what it owned.
+        assert_eq!(released, [pane.id]);
+    }
+
+    #[test]
+    fn only_open_virtual_panes_can_be_owned() {
+        let config = crate::config::Config::default();
+        let (shell, _program) = crate::pane::Pane::loopback(10, 3, &config);
+        let gone = crate::pane::Pane::virtual_pane(20, 5, None, &config);
+        let gone_id = gone.id;
+        drop(gone);
+        let (sub_id, _events) = register_subscriber(topic::PANE_INPUT, None);
+        let results = [claim_virtual_panes(sub_id, &[shell.id]), claim_virtual_panes(sub_id, &[gone_id])];
+        unregister_subscriber(sub_id);
+        assert_eq!(results[0], Err(format!("pane {} is not a virtual pane", shell.id)));
+        assert_eq!(results[1], Err(format!("pane {} is not a virtual pane", gone_id)));
+    }
+
+    #[test]
+    fn owning_panes_subscribes_to_their_input() {
+        match parse_command(r#"{"cmd":"subscribe","events":["focus"],"own":[5,6]}"#) {
+            Ok(IpcCommand::Subscribe { topics, own, .. }) => {
+                assert_eq!(topics, topic::FOCUS | topic::PANE_INPUT);
+                assert_eq!(own, [5, 6]);
+            }
+            _ => panic!("subscribe with own should parse"),
+        }
+        assert!(parse_command(r#"{"cmd":"subscribe","own":[-1]}"#).is_err());
+    }
+
+    #[test]
+    fn pane_output_streams_the_lines_and_chunks_of_the_panes_asked_for() {
+        let config = crate::config::Config::default();
+        let (pane, program) = crate::pane::Pane::loopback(10, 3, &config);
+        let (_other, other_program) = crate::pane::Pane::loopback(10, 3, &config);
+        let (lines_id, lines) = register_subscriber(topic::PANE_OUTPUT, Some(vec![pane.id]));
+        let (raw_id, raw) = register_subscriber(topic::PANE_OUTPUT_RAW, Some(vec![pane.id]));
+        let first = b"\x1b[1mone\x1b[0m\r\nwrapped across two\r\nprompt \xc3";
+        program.output(first);
+        other_program.output(b"elsewhere\r\n");
+        // The cursor is still on the prompt: that line is not finished.
+        program.output(b"\xa9");
+        let read = |events: &mpsc::Receiver<String>| -> Vec<serde_json::Value> {
+            events.try_iter().map(|line| serde_json::from_str(&line).unwrap()).collect()
+        };
+        let (lines, raw) = (read(&lines), read(&raw));
+        unregister_subscriber(lines_id);
+        unregister_subscriber(raw_id);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "pane-output");
+        assert_eq!(lines[0]["pane_id"], pane.id);
+        assert_eq!((lines[0]["seq"].as_u64(), lines[0]["text"].as_str()), (Some(0), Some("one")));
+        assert_eq!(
+            (lines[1]["seq"].as_u64(), lines[1]["text"].as_str()),
+            (Some(1), Some("wrapped across two"))
+        );
+
+        // The `é` cut between two reads goes out whole, with the second.
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw[0]["offset"], 0);
+        assert_eq!(raw[0]["data"].as_str().unwrap().as_bytes(), &first[..first.len() - 1]);
+        assert_eq!(raw[1]["offset"], first.len() - 1);
+        assert_eq!(raw[1]["data"], "é");
+    }
+
+    #[test]
+    fn a_request_id_comes_back_with_the_reply() {
+        let envelope = unwrap_request(r#"{"cmd":"list-panes","id":"a1"}"#, Protocol::Kova);
+        assert_eq!(envelope.id, Some(serde_json::json!("a1")));
+        assert!(matches!(envelope.command, Ok(IpcCommand::ListPanes)));
+        let line = frame(Protocol::Kova, envelope.id.as_ref(), Ok(None)).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&line).unwrap(), serde_json::json!({"ok": true, "id": "a1"}));
+        // `cancel`'s id is its argument, not an id of its own.
+        let envelope = unwrap_request(r#"{"cmd":"cancel","id":7}"#, Protocol::Kova);
+        assert_eq!(envelope.id, None);
+        assert!(matches!(envelope.command, Ok(IpcCommand::Cancel { id }) if id == 7));
+        let envelope = unwrap_request(r#"{"cmd":"list-panes","id":[1]}"#, Protocol::Kova);
+        assert_eq!(envelope.command.err().unwrap().message, "\"id\" must be a string or a number");
+    }
+
+    #[test]
+    fn jsonrpc_is_spoken_after_a_hello_only() {
+        let request = r#"{"jsonrpc":"2.0","method":"list-panes","id":1}"#;
+        let refused = unwrap_request(request, Protocol::Kova);
+        assert_eq!(refused.framing, Protocol::JsonRpc);
+        assert_eq!(refused.command.err().unwrap().code, rpc_code::INVALID_REQUEST);
+        let hello = unwrap_request(r#"{"jsonrpc":"2.0","method":"hello","params":{"protocol":"jsonrpc"},"id":0}"#, Protocol::Kova);
+        assert!(matches!(hello.command, Ok(IpcCommand::Hello { protocol: Some(Protocol::JsonRpc), .. })));
+        assert!(matches!(unwrap_request(request, Protocol::JsonRpc).command, Ok(IpcCommand::ListPanes)));
+        // Once negotiated, the Kova framing is refused in turn.
+        let native = unwrap_request(r#"{"cmd":"list-panes"}"#, Protocol::JsonRpc);
+        assert_eq!(native.id, Some(serde_json::Value::Null));
+        assert_eq!(native.command.err().unwrap().code, rpc_code::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn jsonrpc_errors_carry_the_spec_codes() {
+        let code = |line: &str| unwrap_request(line, Protocol::JsonRpc).command.err().unwrap().code;
+        assert_eq!(code("{"), rpc_code::PARSE_ERROR);
+        assert_eq!(code(r#"{"jsonrpc":"1.0","method":"list-panes","id":1}"#), rpc_code::INVALID_REQUEST);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"list-pains","id":1}"#), rpc_code::METHOD_NOT_FOUND);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"close-pane","params":[3],"id":1}"#), rpc_code::INVALID_PARAMS);
+        assert_eq!(code(r#"{"jsonrpc":"2.0","method":"close-pane","params":{"pane":3},"id":1}"#), rpc_code::INVALID_PARAMS);
+
+        let id = serde_json::json!(4);
+        let error = frame(Protocol::JsonRpc, Some(&id), Err(Failure::new(rpc_code::SERVER_ERROR, "pane 3 not found"))).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&error).unwrap(),
+            serde_json::json!({"jsonrpc": "2.0", "id": 4, "error": {"code": -32000, "message": "pane 3 not found"}})
+        );
+        let result = frame(Protocol::JsonRpc, Some(&id), Ok(None)).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&result).unwrap()["result"], serde_json::Value::Null);
+        // A notification is never answered.
+        assert_eq!(frame(Protocol::JsonRpc, None, Ok(None)), None);
+    }
+
+    /// A connection served by `handle_connection`, with a main thread that
+    /// answers every command at once except the waits, which it keeps.
+    fn connection() -> (UnixStream, std::io::Lines<BufReader<UnixStream>>) {
+        connection_with(Auth::open(), Audit::off())
+    }
+
+    fn connection_with(auth: Auth, audit: Audit) -> (UnixStream, std::io::Lines<BufReader<UnixStream>>) {
+        let (client, server) = UnixStream::pair().unwrap();
+        client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let (tx, rx) = mpsc::channel::<IpcRequest>();
+        std::thread::spawn(move || handle_connection(server, tx, &auth, &audit));
+        std::thread::spawn(move || {
+            let mut parked = Vec::new();
+            for (cmd, responder) in rx {
+                if is_long_running(&cmd) {
+                    parked.push(responder);
+                } else if let IpcCommand::Batch { steps, atomic, log } = cmd {
+                    let exec = |_| IpcResponse::Ok { data: Some(serde_json::json!({ "pane_id": 7 })) };
+                    let _ = responder.send(crate::ipc_batch::run(steps, atomic, log.as_ref(), exec));
+                } else {
+                    let _ = responder.send(IpcResponse::Ok { data: Some(serde_json::json!("done")) });
+                }
+            }
+        });
+        let lines = BufReader::new(client.try_clone().unwrap()).lines();
+        (client, lines)
+    }
+
+    fn next_reply(lines: &mut std::io::Lines<BufReader<UnixStream>>) -> serde_json::Value {
+        serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn a_slow_wait_with_an_id_lets_later_commands_through_until_cancelled() {
+        let (mut client, mut lines) = connection();
+        writeln!(client, r#"{{"cmd":"wait-for-completion","pane_id":1,"id":"build"}}"#).unwrap();
+        writeln!(client, r#"{{"cmd":"list-panes","id":2}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines), serde_json::json!({"ok": true, "data": "done", "id": 2}));
+
+        writeln!(client, r#"{{"cmd":"wait-for-completion","pane_id":1,"id":"build"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["error"], "request \"build\" is already in flight");
+
+        writeln!(client, r#"{{"cmd":"cancel","id":"build"}}"#).unwrap();
+        let mut replies = [next_reply(&mut lines), next_reply(&mut lines)];
+        replies.sort_by_key(|reply| reply.get("id").is_some());
+        assert_eq!(replies[0], serde_json::json!({"ok": true, "data": {"cancelled": "build"}}));
+        assert_eq!(replies[1], serde_json::json!({"ok": false, "error": "cancelled", "id": "build"}));
+
+        writeln!(client, r#"{{"cmd":"cancel","id":"build"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["error"], "no request \"build\" in flight");
+    }
+
+    #[test]
+    fn a_connection_switched_to_jsonrpc_answers_in_it() {
+        let (mut client, mut lines) = connection();
+        writeln!(client, r#"{{"cmd":"hello","protocol":"jsonrpc"}}"#).unwrap();
+        let hello = next_reply(&mut lines);
+        assert_eq!(hello["data"]["protocol"], "jsonrpc");
+
+        // A notification runs but is not answered: the next line answers id 5.
+        writeln!(client, r#"{{"jsonrpc":"2.0","method":"list-panes"}}"#).unwrap();
+        writeln!(client, r#"{{"jsonrpc":"2.0","method":"wait-for-idle","params":{{"pane_id":1}},"id":4}}"#).unwrap();
+        writeln!(client, r#"{{"jsonrpc":"2.0","method":"list-panes","id":5}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines), serde_json::json!({"jsonrpc": "2.0", "id": 5, "result": "done"}));
+        writeln!(client, r#"{{"jsonrpc":"2.0","method":"cancel","params":{{"id":4}},"id":6}}"#).unwrap();
+        let mut replies = [next_reply(&mut lines), next_reply(&mut lines)];
+        replies.sort_by_key(|reply| reply["id"].as_u64());
+        assert_eq!(replies[0]["error"], serde_json::json!({"code": rpc_code::CANCELLED, "message": "cancelled"}));
+        assert_eq!(replies[1]["result"], serde_json::json!({"cancelled": 4}));
+    }
+
+    #[test]
+    fn with_require_token_a_connection_gets_only_its_scopes() {
+        let config = crate::config::IpcConfig {
+            require_token: true,
+            tokens: vec![crate::config::IpcTokenConfig {
+                name: "dashboard".to_string(),
+                token: "0123456789abcdef-read".to_string(),
+                scopes: vec!["read".to_string()],
+            }],
+            ..Default::default()
+        };
+        let (mut client, mut lines) = connection_with(Auth::from_config(&config), Audit::off());
+        writeln!(client, r#"{{"cmd":"list-panes"}}"#).unwrap();
+        assert!(next_reply(&mut lines)["error"].as_str().unwrap().contains("no token"));
+        // Describing the protocol needs no scope.
+        writeln!(client, r#"{{"cmd":"describe"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["ok"], true);
+
+        writeln!(client, r#"{{"cmd":"hello","token":"0123456789abcdef-wrong"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines), serde_json::json!({"ok": false, "error": "invalid token"}));
+        writeln!(client, r#"{{"cmd":"hello","token":"0123456789abcdef-read"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["data"]["scopes"], serde_json::json!(["read"]));
+
+        writeln!(client, r#"{{"cmd":"list-panes"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines), serde_json::json!({"ok": true, "data": "done"}));
+        writeln!(client, r#"{{"cmd":"send-keys","pane_id":1,"text":"ls"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["error"], "this request needs the \"input\" scope");
+        // A split is layout, but one that runs a command is input too.
+        writeln!(client, r#"{{"cmd":"split","command":"htop"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["error"], "this request needs the \"control\", \"input\" scopes");
+        // The audit log shows what every client sent.
+        writeln!(client, r#"{{"cmd":"get-audit-log"}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines)["error"], "this request needs the \"admin\" scope");
+
+        writeln!(client, r#"{{"cmd":"hello","token":"{}"}}"#, crate::ipc_auth::launch_token()).unwrap();
+        assert_eq!(next_reply(&mut lines)["data"]["scopes"], serde_json::json!(ipc_schema::scope::ALL_NAMES));
+        writeln!(client, r#"{{"cmd":"close-pane","pane_id":1}}"#).unwrap();
+        assert_eq!(next_reply(&mut lines), serde_json::json!({"ok": true, "data": "done"}));
+    }
+
+    #[test]
+    fn the_audit_log_keeps_what_changed_something_and_who_asked() {
+        let dir = std::env::temp_dir().join(format!("kova-ipc-audit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (mut client, mut lines) = connection_with(Auth::open(), Audit::at(dir.join("ipc-audit.jsonl"), false));
+        writeln!(client, r#"{{"cmd":"send-keys","pane_id":3,"text":"ls\n"}}"#).unwrap();
+        next_reply(&mut lines);
+        writeln!(client, r#"{{"cmd":"list-panes"}}"#).unwrap();
+        next_reply(&mut lines);
+        writeln!(client, r#"{{"cmd":"close-pane","pane_id":"three"}}"#).unwrap();
+        next_reply(&mut lines);
+
+        writeln!(client, r#"{{"cmd":"get-audit-log"}}"#).unwrap();
+        let entries = next_reply(&mut lines)["data"]["entries"].clone();
+        assert_eq!(entries.as_array().unwrap().len(), 2, "{}", entries);
+        assert_eq!(entries[0]["cmd"], "send-keys");
+        assert_eq!(entries[0]["args"], serde_json::json!({"text": "[3 bytes]"}));
+        assert_eq!(entries[0]["peer_pid"], std::process::id());
+        assert_eq!(entries[1]["cmd"], "close-pane");
+        assert_eq!(entries[1]["ok"], false);
+
+        // A batch is logged step by step, as each step ran.
+        writeln!(
+            client,
+            r#"{{"cmd":"batch","commands":[{{"cmd":"split"}},{{"cmd":"list-panes"}},{{"cmd":"send-keys","pane_id":"$0.pane_id","text":"make\n"}}]}}"#
+        )
+        .unwrap();
+        assert_eq!(next_reply(&mut lines)["data"]["failed"], 0);
+        writeln!(client, r#"{{"cmd":"get-audit-log","limit":2}}"#).unwrap();
+        let entries = next_reply(&mut lines)["data"]["entries"].clone();
+        assert_eq!(entries[0]["cmd"], "split");
+        assert_eq!(entries[1]["cmd"], "send-keys");
+        assert_eq!(entries[1]["pane_id"], 7);
+        assert_eq!(entries[1]["args"], serde_json::json!({"text": "[5 bytes]"}));
+        assert_eq!(entries[1]["ok"], true);
+        writeln!(client, r#"{{"cmd":"get-audit-log","limit":0}}"#).unwrap();
+        assert!(next_reply(&mut lines)["error"].as_str().unwrap().contains("from 1 to"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
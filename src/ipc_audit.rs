@@ -0,0 +1,297 @@
+//! A record of what IPC clients did.
+//!
+//! Off by default. With `ipc.audit_log`, every request for a command that
+//! changes something (see `ipc_schema::CommandSpec::mutates`) appends one JSON
+//! line to `ipc-audit.jsonl`, refused ones included. It lives in Kova's log
+//! directory on macOS, in `$XDG_STATE_HOME/kova` (default `~/.local/state`)
+//! elsewhere:
+//!
+//! ```text
+//! {"args":{"text":"[9 bytes]"},"cmd":"send-keys","ok":true,"pane_id":3,"peer_pid":4242,"time_ms":1760000000000}
+//! ```
+//!
+//! What a command carries to the pane (`send-keys` text, …) is replaced by its
+//! length unless `ipc.audit_payloads` is set. A batch gets one entry per step
+//! that ran, as `ipc_batch` reports them. The file is owner-only, and
+//! rotated to `ipc-audit.1.jsonl` … once over `MAX_BYTES`. `get-audit-log`
+//! reads it back.
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+use crate::config::IpcConfig;
+use crate::ipc_schema;
+
+/// Size past which the log is rotated.
+const MAX_BYTES: u64 = 1024 * 1024;
+/// Rotated logs kept next to the current one.
+const HISTORY_COUNT: usize = 4;
+/// Most entries one `get-audit-log` returns.
+pub const MAX_LIMIT: usize = 10_000;
+
+pub struct Audit {
+    /// `None`: the audit log is off.
+    path: Option<PathBuf>,
+    payloads: bool,
+    /// Connections write from their own threads; one line at a time, and
+    /// never while another rotates.
+    lock: Mutex<()>,
+}
+
+impl Audit {
+    pub fn off() -> Self {
+        Audit { path: None, payloads: false, lock: Mutex::new(()) }
+    }
+
+    pub fn from_config(config: &IpcConfig) -> Self {
+        if !config.audit_log {
+            return Audit::off();
+        }
+        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+        let state = std::env::var_os("XDG_STATE_HOME");
+        let path = default_path(Path::new(&home), state.as_deref().map(Path::new));
+        log::info!("IPC: audit log at {}", path.display());
+        Audit::at(path, config.audit_payloads)
+    }
+
+    pub fn at(path: PathBuf, payloads: bool) -> Self {
+        Audit { path: Some(path), payloads, lock: Mutex::new(()) }
+    }
+
+    /// Append the entry for `request` (as `ipc::Envelope::audited` keeps it),
+    /// sent by `peer`, which came to `result`.
+    pub fn record(&self, request: Option<Value>, peer: Option<u32>, result: Result<(), &str>) {
+        let (Some(path), Some(request)) = (&self.path, request) else { return };
+        let mut line = entry(request, self.payloads, peer, result).to_string();
+        line.push('\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if std::fs::metadata(path).is_ok_and(|m| m.len() >= MAX_BYTES) {
+            rotate(path);
+        }
+        if let Err(e) = append(path, line.as_bytes()) {
+            log::warn!("IPC: cannot write the audit log {}: {}", path.display(), e);
+        }
+    }
+
+    /// The last `limit` entries, oldest first; only those aimed at
+    /// `pane_id` when given.
+    pub fn recent(&self, limit: usize, pane_id: Option<u32>) -> Result<Value, String> {
+        let Some(path) = &self.path else {
+            return Err("the audit log is off (set ipc.audit_log = true in the config)".to_string());
+        };
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries: Vec<Value> = Vec::new();
+        // Newest file first, each read backwards, until there are enough.
+        for n in 0..=HISTORY_COUNT {
+            if entries.len() == limit {
+                break;
+            }
+            let Ok(text) = std::fs::read_to_string(backup(path, n)) else { continue };
+            for line in text.lines().rev() {
+                if entries.len() == limit {
+                    break;
+                }
+                let Ok(entry) = serde_json::from_str::<Value>(line) else { continue };
+                if pane_id.is_none_or(|pane| entry["pane_id"] == pane) {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries.reverse();
+        Ok(serde_json::json!({ "path": path.display().to_string(), "entries": entries }))
+    }
+}
+
+fn default_path(home: &Path, xdg_state: Option<&Path>) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        return home.join("Library/Logs/Kova/ipc-audit.jsonl");
+    }
+    // The spec says to ignore a relative path.
+    match xdg_state.filter(|d| d.is_absolute()) {
+        Some(state) => state.join("kova/ipc-audit.jsonl"),
+        None => home.join(".local/state/kova/ipc-audit.jsonl"),
+    }
+}
+
+/// The line for `request`: who, what, on which pane, and how it went.
+fn entry(mut request: Value, payloads: bool, peer: Option<u32>, result: Result<(), &str>) -> Value {
+    if !payloads {
+        request = redact(request);
+    }
+    let args = request.as_object_mut().map(std::mem::take).unwrap_or_default();
+    let mut entry = serde_json::json!({
+        "time_ms": now_ms(),
+        "peer_pid": peer,
+        "cmd": args.get("cmd").cloned().unwrap_or(Value::Null),
+        "pane_id": args.get("pane_id").cloned().unwrap_or(Value::Null),
+        "ok": result.is_ok(),
+    });
+    if let Err(message) = result {
+        entry["error"] = message.into();
+    }
+    let args: serde_json::Map<String, Value> =
+        args.into_iter().filter(|(key, _)| !matches!(key.as_str(), "cmd" | "pane_id")).collect();
+    if !args.is_empty() {
+        entry["args"] = Value::Object(args);
+    }
+    entry
+}
+
+/// `request` with what it carries to the pane replaced by its length, in each
+/// step of a batch too.
+fn redact(mut request: Value) -> Value {
+    let spec = request.get("cmd").and_then(|c| c.as_str()).and_then(ipc_schema::command);
+    let (Some(spec), Some(fields)) = (spec, request.as_object_mut()) else { return request };
+    for (key, value) in fields.iter_mut() {
+        match value {
+            Value::String(text) if spec.fields.iter().any(|f| f.name == key.as_str() && f.payload) => {
+                let len = text.len();
+                *value = format!("[{} bytes]", len).into();
+            }
+            Value::Array(steps) if spec.name == "batch" && key == "commands" => {
+                for step in steps.iter_mut() {
+                    *step = redact(std::mem::take(step));
+                }
+            }
+            _ => {}
+        }
+    }
+    request
+}
+
+/// The path of the `n`th rotated log, `path` itself for 0.
+fn backup(path: &Path, n: usize) -> PathBuf {
+    if n == 0 { path.to_path_buf() } else { path.with_file_name(format!("ipc-audit.{}.jsonl", n)) }
+}
+
+/// ipc-audit.jsonl -> ipc-audit.1.jsonl -> ipc-audit.2.jsonl -> ...
+fn rotate(path: &Path) {
+    let _ = std::fs::remove_file(backup(path, HISTORY_COUNT));
+    for i in (1..HISTORY_COUNT).rev() {
+        let _ = std::fs::rename(backup(path, i), backup(path, i + 1));
+    }
+    let _ = std::fs::rename(path, backup(path, 1));
+}
+
+fn append(path: &Path, line: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).mode(0o600).open(path)?;
+    file.write_all(line)
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// The pid of the process on the other end of `stream`, when the OS tells.
+pub fn peer_pid(stream: &UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+    let fd = stream.as_raw_fd();
+    #[cfg(target_os = "linux")]
+    {
+        let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(fd, libc::SOL_SOCKET, libc::SO_PEERCRED, &mut cred as *mut _ as *mut libc::c_void, &mut len)
+        };
+        (rc == 0).then_some(cred.pid as u32)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut pid: libc::pid_t = 0;
+        let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(fd, libc::SOL_LOCAL, libc::LOCAL_PEERPID, &mut pid as *mut _ as *mut libc::c_void, &mut len)
+        };
+        (rc == 0).then_some(pid as u32)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = fd;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kova-audit-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("ipc-audit.jsonl")
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn the_log_goes_to_the_xdg_state_dir() {
+        let home = Path::new("/home/u");
+        assert_eq!(default_path(home, Some(Path::new("/s"))), Path::new("/s/kova/ipc-audit.jsonl"));
+        assert_eq!(default_path(home, None), Path::new("/home/u/.local/state/kova/ipc-audit.jsonl"));
+    }
+
+    #[test]
+    fn payloads_are_redacted_unless_asked_for() {
+        let request = serde_json::json!({"cmd": "send-keys", "pane_id": 3, "text": "hunter2\n"});
+        let entry = entry(request.clone(), false, Some(42), Ok(()));
+        assert_eq!(entry["args"], serde_json::json!({"text": "[8 bytes]"}));
+        assert_eq!((entry["cmd"].as_str(), entry["pane_id"].as_u64(), entry["peer_pid"].as_u64()), (Some("send-keys"), Some(3), Some(42)));
+        assert_eq!(entry["ok"], true);
+        assert_eq!(entry.get("error"), None);
+
+        let entry = super::entry(request, true, None, Err("pane 3 not found"));
+        assert_eq!(entry["args"]["text"], "hunter2\n");
+        assert_eq!((entry["ok"].as_bool(), entry["error"].as_str()), (Some(false), Some("pane 3 not found")));
+        // Only the payload is redacted: what is run stays readable.
+        let entry = super::entry(serde_json::json!({"cmd": "split", "command": "htop"}), false, None, Ok(()));
+        assert_eq!(entry["args"], serde_json::json!({"command": "htop"}));
+        assert_eq!(entry["pane_id"], Value::Null);
+        // So are the payloads of the steps of a batch.
+        let batch = serde_json::json!({"cmd": "batch", "commands": [
+            {"cmd": "split"},
+            {"cmd": "send-keys", "pane_id": "$0.pane_id", "text": "hunter2\n"},
+        ]});
+        let entry = super::entry(batch, false, None, Ok(()));
+        assert_eq!(entry["args"]["commands"][1]["text"], "[8 bytes]");
+        assert_eq!(entry["args"]["commands"][1]["pane_id"], "$0.pane_id");
+    }
+
+    #[test]
+    fn the_log_is_owner_only_rotates_and_reads_back_newest_last() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_log("rotate");
+        let audit = Audit::at(path.clone(), false);
+        let close = |pane: u32| Some(serde_json::json!({"cmd": "close-pane", "pane_id": pane}));
+        audit.record(close(1), None, Ok(()));
+        audit.record(close(2), None, Err("pane 2 not found"));
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        // Not a request the log keeps.
+        audit.record(None, None, Ok(()));
+
+        rotate(&path);
+        audit.record(close(1), None, Ok(()));
+        assert!(backup(&path, 1).exists());
+
+        let recent = audit.recent(10, None).unwrap();
+        let panes: Vec<u64> = recent["entries"].as_array().unwrap().iter().map(|e| e["pane_id"].as_u64().unwrap()).collect();
+        assert_eq!(panes, [1, 2, 1]);
+        assert_eq!(audit.recent(2, None).unwrap()["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(audit.recent(10, Some(2)).unwrap()["entries"][0]["error"], "pane 2 not found");
+        assert!(Audit::off().recent(10, None).unwrap_err().contains("audit log is off"));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn the_peer_is_this_process() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        assert_eq!(peer_pid(&a), Some(std::process::id()));
+    }
+}
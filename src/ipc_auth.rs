@@ -0,0 +1,178 @@
+//! Who may do what over the IPC socket.
+//!
+//! Off by default: the socket is owner-only, and every process of the user
+//! gets every command. With `ipc.require_token`, a connection starts with no
+//! scope at all and gets some with `hello {"token": ...}`:
+//!
+//! - the launch token, made up at startup and handed to Kova's own panes as
+//!   `$KOVA_TOKEN`, gets every scope;
+//! - a token from `[[ipc.tokens]]` in the config gets the scopes listed there.
+//!
+//! Which scope a command needs is in `ipc_schema::COMMANDS`; the connection
+//! thread refuses what its scopes do not cover.
+
+use std::io::Read;
+use std::sync::OnceLock;
+
+use crate::config::IpcConfig;
+use crate::ipc_schema::scope;
+
+/// Shorter tokens are refused: guessing one must not be an option.
+const MIN_TOKEN_LEN: usize = 16;
+
+pub struct Auth {
+    required: bool,
+    tokens: Vec<NamedToken>,
+}
+
+struct NamedToken {
+    name: String,
+    secret: String,
+    scopes: u8,
+}
+
+impl Auth {
+    /// Every connection gets every scope, as without `ipc.require_token`.
+    #[cfg(test)]
+    pub fn open() -> Self {
+        Auth { required: false, tokens: Vec::new() }
+    }
+
+    pub fn from_config(config: &IpcConfig) -> Self {
+        let tokens = config
+            .tokens
+            .iter()
+            .filter_map(|token| {
+                if token.token.len() < MIN_TOKEN_LEN {
+                    log::warn!(
+                        "config: ipc token {:?} is shorter than {} characters, ignored",
+                        token.name,
+                        MIN_TOKEN_LEN
+                    );
+                    return None;
+                }
+                let mut scopes = 0;
+                for name in &token.scopes {
+                    match scope::from_name(name) {
+                        Some(bit) => scopes |= bit,
+                        None => log::warn!(
+                            "config: ipc token {:?}: unknown scope {:?} (one of {})",
+                            token.name,
+                            name,
+                            scope::ALL_NAMES.join(", ")
+                        ),
+                    }
+                }
+                Some(NamedToken { name: token.name.clone(), secret: token.token.clone(), scopes })
+            })
+            .collect();
+        Auth { required: config.require_token, tokens }
+    }
+
+    /// The scopes of a connection before any `hello`.
+    pub fn initial_scopes(&self) -> u8 {
+        if self.required { 0 } else { scope::ALL }
+    }
+
+    /// The scopes a connection holding `scopes` has after a `hello` with
+    /// `token`. Without `require_token` a token changes nothing, so a client
+    /// can send its token whatever Kova's config says.
+    pub fn hello(&self, token: Option<&str>, scopes: u8) -> Result<u8, String> {
+        let Some(token) = token else { return Ok(scopes) };
+        if !self.required {
+            return Ok(scopes);
+        }
+        let launch = launch_token();
+        if !launch.is_empty() && same(token, launch) {
+            log::info!("IPC: a connection authenticated with the launch token");
+            return Ok(scope::ALL);
+        }
+        // Every token is compared, so the time taken says nothing of which
+        // one came close.
+        let found = self.tokens.iter().fold(None, |found, named| {
+            if same(token, &named.secret) { Some(named) } else { found }
+        });
+        match found {
+            Some(named) => {
+                log::info!("IPC: a connection authenticated as {:?}", named.name);
+                Ok(named.scopes)
+            }
+            None => {
+                log::warn!("IPC: a connection sent an invalid token");
+                Err("invalid token".to_string())
+            }
+        }
+    }
+}
+
+/// Compare in a time that depends on the lengths only.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// This launch's token, exported to the panes as `$KOVA_TOKEN`. Empty when no
+/// randomness could be had: then there is no launch token at all, rather than
+/// a guessable one.
+pub fn launch_token() -> &'static str {
+    static TOKEN: OnceLock<String> = OnceLock::new();
+    TOKEN.get_or_init(|| {
+        let mut bytes = [0u8; 16];
+        match std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes)) {
+            Ok(()) => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            Err(e) => {
+                log::error!("IPC: no launch token, /dev/urandom is unreadable: {}", e);
+                String::new()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IpcTokenConfig;
+
+    fn auth(required: bool) -> Auth {
+        let token = |name: &str, token: &str, scopes: &[&str]| IpcTokenConfig {
+            name: name.to_string(),
+            token: token.to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        };
+        Auth::from_config(&IpcConfig {
+            require_token: required,
+            tokens: vec![
+                token("dashboard", "0123456789abcdef-read", &["read", "bogus"]),
+                token("agent", "0123456789abcdef-agent", &["read", "input"]),
+                token("short", "tiny", &["admin"]),
+            ],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn a_token_grants_its_scopes_and_nothing_else() {
+        let auth = auth(true);
+        assert_eq!(auth.initial_scopes(), 0);
+        assert_eq!(auth.hello(Some("0123456789abcdef-read"), 0), Ok(scope::READ));
+        assert_eq!(auth.hello(Some("0123456789abcdef-agent"), scope::READ), Ok(scope::READ | scope::INPUT));
+        assert_eq!(auth.hello(Some(launch_token()), 0), Ok(scope::ALL));
+        assert_eq!(auth.hello(Some("tiny"), 0), Err("invalid token".to_string()));
+        assert_eq!(auth.hello(Some("0123456789abcdef-rea"), 0), Err("invalid token".to_string()));
+        // A hello that only picks the framing keeps what the connection had.
+        assert_eq!(auth.hello(None, scope::READ), Ok(scope::READ));
+    }
+
+    #[test]
+    fn without_require_token_everyone_gets_everything() {
+        let auth = auth(false);
+        assert_eq!(auth.initial_scopes(), scope::ALL);
+        assert_eq!(auth.hello(Some("0123456789abcdef-read"), scope::ALL), Ok(scope::ALL));
+        assert_eq!(auth.hello(Some("wrong"), scope::ALL), Ok(scope::ALL));
+    }
+
+    #[test]
+    fn the_launch_token_is_random_hex() {
+        assert_eq!(launch_token().len(), 32);
+        assert!(launch_token().bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+}
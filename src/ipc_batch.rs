@@ -0,0 +1,407 @@
+//! `batch`: a list of commands run in one turn of the main thread.
+//!
+//! Building a layout step by step over IPC costs a round trip per step, and
+//! every intermediate layout is applied: each `split` reflows its neighbors,
+//! which the next `resize-pane` reflows again. A batch runs its steps back to
+//! back, with the layout held until the last one (see `run`'s caller), so the
+//! panes are sized once, for the layout the client asked for.
+//!
+//! A step can use what an earlier one returned: a string `"$<n>.<field>"`, in
+//! a field that takes ids (an integer or a list of them, per `ipc_schema`),
+//! stands for `<field>` of step `<n>`'s data (`"$0.pane_id"`,
+//! `"$1.panes.0.id"`). Text fields are left alone, so `send-keys` types
+//! `"$1.name"` as it is. With `atomic`, the first step that
+//! fails stops the batch and closes the panes it created; what the steps
+//! changed in panes that were already there stays changed.
+
+use std::sync::mpsc;
+
+use serde_json::Value;
+
+use crate::ipc::{IpcCommand, IpcResponse};
+use crate::ipc_schema::FieldType;
+
+/// Most steps in one batch. The whole of it holds the main thread.
+pub const MAX_STEPS: usize = 64;
+
+/// What cannot be a step: the commands that wait or scan across ticks,
+/// stream, or belong to the connection rather than to the main thread.
+const NOT_IN_BATCH: &[&str] = &[
+    "batch",
+    "subscribe",
+    "wait-for-completion",
+    "wait-for-text",
+    "wait-for-idle",
+    "search-pane",
+    "hello",
+    "cancel",
+    "describe",
+    "get-audit-log",
+];
+
+/// Where a batch reports each step it ran: the request with its references
+/// filled in, and how it went. The connection keeps them in the audit log,
+/// one entry per step.
+pub type StepLog = mpsc::Sender<(Value, Result<(), String>)>;
+
+/// The commands that create a pane, reported as `pane_id` in their data.
+/// Rolling an atomic batch back closes those panes.
+const CREATES_PANE: &[&str] = &["split", "new-tab", "new-virtual-pane", "play-recording"];
+
+/// The steps of a `batch` request, checked as far as they can be before any
+/// runs: each must be a command allowed in a batch, refer only to the steps
+/// before it, and parse once its references are filled in.
+pub fn parse_steps(v: &Value) -> Result<Vec<Value>, String> {
+    let steps = match v.get("commands") {
+        Some(Value::Array(steps)) => steps,
+        Some(_) => return Err("\"commands\" must be an array of requests".to_string()),
+        None => return Err("missing \"commands\" field".to_string()),
+    };
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(format!("\"commands\" must hold from 1 to {} requests", MAX_STEPS));
+    }
+    for (n, step) in steps.iter().enumerate() {
+        let name = step
+            .get("cmd")
+            .and_then(|cmd| cmd.as_str())
+            .ok_or_else(|| format!("step {}: missing \"cmd\" field", n))?;
+        if NOT_IN_BATCH.contains(&name) {
+            return Err(format!("step {}: {} cannot be part of a batch", n, name));
+        }
+        // The ids an earlier step will return are not known yet: parse with
+        // stand-ins, so a malformed step fails before the batch starts. They
+        // differ, for `merge-tab` and `swap-pane` refuse the same id twice.
+        let mut stand_in = u32::MAX;
+        let parsed = fill(step.clone(), &mut |s| match reference(s) {
+            Some((target, _)) if target >= n => Err(format!("step {}: \"{}\" must refer to an earlier step", n, s)),
+            _ => {
+                stand_in -= 1;
+                Ok(stand_in.into())
+            }
+        })?;
+        crate::ipc::parse_value(parsed).map_err(|e| format!("step {}: {}", n, e))?;
+    }
+    Ok(steps.clone())
+}
+
+/// The scopes a batch needs: those of every step.
+pub fn scopes_needed(v: &Value) -> u8 {
+    steps(v).fold(0, |needed, step| needed | crate::ipc_schema::scopes_needed(step))
+}
+
+/// Whether a step of the batch changes something (see `CommandSpec::mutates`).
+pub fn mutates(v: &Value) -> bool {
+    steps(v).any(|step| {
+        let spec = step.get("cmd").and_then(|cmd| cmd.as_str()).and_then(crate::ipc_schema::command);
+        spec.is_some_and(|spec| spec.mutates())
+    })
+}
+
+fn steps(v: &Value) -> impl Iterator<Item = &Value> {
+    v.get("commands").and_then(|steps| steps.as_array()).into_iter().flatten()
+}
+
+/// Run `steps` through `exec`, in order. Without `atomic`, every step runs and
+/// gets its own entry in `results`; a step referring to one that failed fails
+/// too. With `atomic`, the first failure closes the panes created so far and
+/// fails the batch. Each step that runs is reported to `log`.
+pub fn run(
+    steps: Vec<Value>,
+    atomic: bool,
+    log: Option<&StepLog>,
+    mut exec: impl FnMut(IpcCommand) -> IpcResponse,
+) -> IpcResponse {
+    let mut done: Vec<Result<Value, String>> = Vec::with_capacity(steps.len());
+    let mut created: Vec<u32> = Vec::new();
+    for (n, step) in steps.into_iter().enumerate() {
+        let name = step.get("cmd").and_then(|cmd| cmd.as_str()).unwrap_or_default().to_string();
+        let result = fill(step, &mut |reference| resolve(reference, &done)).and_then(|request| {
+            let filled = log.map(|_| request.clone());
+            let cmd = crate::ipc::parse_value(request)?;
+            let result = match exec(cmd) {
+                IpcResponse::Ok { data } => Ok(data.unwrap_or(Value::Null)),
+                IpcResponse::Error { message } => Err(message),
+            };
+            if let (Some(log), Some(filled)) = (log, filled) {
+                let _ = log.send((filled, result.as_ref().map(|_| ()).map_err(Clone::clone)));
+            }
+            result
+        });
+        match &result {
+            Ok(data) if CREATES_PANE.contains(&name.as_str()) => {
+                created.extend(data.get("pane_id").and_then(|id| id.as_u64()).map(|id| id as u32));
+            }
+            Err(message) if atomic => {
+                let rolled_back = roll_back(&created, &mut exec);
+                return IpcResponse::Error { message: format!("step {} ({}): {}{}", n, name, message, rolled_back) };
+            }
+            _ => {}
+        }
+        done.push(result);
+    }
+    let failed = done.iter().filter(|result| result.is_err()).count();
+    let results: Vec<Value> = done
+        .into_iter()
+        .map(|result| match result {
+            Ok(data) => serde_json::json!({ "ok": true, "data": data }),
+            Err(message) => serde_json::json!({ "ok": false, "error": message }),
+        })
+        .collect();
+    IpcResponse::Ok { data: Some(serde_json::json!({ "results": results, "failed": failed })) }
+}
+
+/// Close the panes an atomic batch created, newest first. Returns what to
+/// append to the batch's error.
+fn roll_back(created: &[u32], exec: &mut impl FnMut(IpcCommand) -> IpcResponse) -> String {
+    if created.is_empty() {
+        return String::new();
+    }
+    for &pane_id in created.iter().rev() {
+        if let IpcResponse::Error { message } = exec(IpcCommand::ClosePaneById(pane_id)) {
+            log::warn!("IPC batch: cannot roll back pane {}: {}", pane_id, message);
+        }
+    }
+    let ids: Vec<String> = created.iter().map(|id| id.to_string()).collect();
+    format!(" (rolled back: closed pane {})", ids.join(", "))
+}
+
+/// A reference, `"$<step>.<field>[.<field>...]"`: the step and the path. The
+/// first field must start with a letter or `_`, so `"$5.00"` stays text.
+fn reference(s: &str) -> Option<(usize, Vec<&str>)> {
+    let (step, path) = s.strip_prefix('$')?.split_once('.')?;
+    if step.is_empty() || !step.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let path: Vec<&str> = path.split('.').collect();
+    let word = |field: &str| !field.is_empty() && field.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    let named = path[0].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if !named || !path.iter().all(|field| word(field)) {
+        return None;
+    }
+    Some((step.parse().ok()?, path))
+}
+
+/// Whether a field of this type takes ids, and so references to them.
+fn takes_references(ty: FieldType) -> bool {
+    matches!(ty, FieldType::Integer | FieldType::IntegerList | FieldType::AllOrIntegers)
+}
+
+/// `step` with each reference in its id fields replaced by what `lookup`
+/// makes of it.
+fn fill(step: Value, lookup: &mut impl FnMut(&str) -> Result<Value, String>) -> Result<Value, String> {
+    let Value::Object(mut fields) = step else { return Ok(step) };
+    let spec = fields.get("cmd").and_then(|cmd| cmd.as_str()).and_then(crate::ipc_schema::command);
+    for field in spec.map_or(&[][..], |spec| spec.fields) {
+        if takes_references(field.ty)
+            && let Some(value) = fields.get_mut(field.name)
+        {
+            *value = fill_value(value.take(), lookup)?;
+        }
+    }
+    Ok(Value::Object(fields))
+}
+
+fn fill_value(value: Value, lookup: &mut impl FnMut(&str) -> Result<Value, String>) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(s) if reference(&s).is_some() => lookup(&s)?,
+        Value::Array(items) => {
+            Value::Array(items.into_iter().map(|item| fill_value(item, lookup)).collect::<Result<_, _>>()?)
+        }
+        other => other,
+    })
+}
+
+/// The value `reference` stands for, from the results of the steps `done`.
+fn resolve(reference_text: &str, done: &[Result<Value, String>]) -> Result<Value, String> {
+    let (step, path) = reference(reference_text).expect("only references are resolved");
+    let data = match done.get(step) {
+        Some(Ok(data)) => data,
+        Some(Err(_)) => return Err(format!("\"{}\": step {} failed", reference_text, step)),
+        None => return Err(format!("\"{}\": no step {} before this one", reference_text, step)),
+    };
+    path.iter()
+        .try_fold(data, |value, field| match value {
+            Value::Array(items) => field.parse::<usize>().ok().and_then(|i| items.get(i)),
+            value => value.get(field),
+        })
+        .cloned()
+        .ok_or_else(|| format!("\"{}\": step {} returned no {}", reference_text, step, path.join(".")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn steps(v: Value) -> Result<Vec<Value>, String> {
+        parse_steps(&json!({ "cmd": "batch", "commands": v }))
+    }
+
+    /// A main thread that opens panes 10, 11, ... and knows no other pane.
+    struct Fake {
+        next: u32,
+        open: Vec<u32>,
+        ran: Vec<String>,
+    }
+
+    impl Fake {
+        fn new() -> Self {
+            Fake { next: 10, open: vec![1], ran: Vec::new() }
+        }
+
+        fn exec(&mut self, cmd: IpcCommand) -> IpcResponse {
+            match cmd {
+                IpcCommand::Split { .. } | IpcCommand::NewTab { .. } => {
+                    let id = self.next;
+                    self.next += 1;
+                    self.open.push(id);
+                    self.ran.push(format!("open {}", id));
+                    IpcResponse::Ok { data: Some(json!({ "pane_id": id })) }
+                }
+                IpcCommand::RenamePane { pane_id, .. } if self.open.contains(&pane_id) => {
+                    self.ran.push(format!("rename {}", pane_id));
+                    IpcResponse::Ok { data: None }
+                }
+                IpcCommand::ClosePaneById(pane_id) if self.open.contains(&pane_id) => {
+                    self.open.retain(|&id| id != pane_id);
+                    self.ran.push(format!("close {}", pane_id));
+                    IpcResponse::Ok { data: None }
+                }
+                IpcCommand::RenamePane { pane_id, .. } | IpcCommand::ClosePaneById(pane_id) => {
+                    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
+                }
+                _ => IpcResponse::Ok { data: None },
+            }
+        }
+    }
+
+    #[test]
+    fn references_need_a_field_and_an_earlier_step() {
+        assert_eq!(reference("$0.pane_id"), Some((0, vec!["pane_id"])));
+        assert_eq!(reference("$12.panes.0.id"), Some((12, vec!["panes", "0", "id"])));
+        assert_eq!(reference("$5.00"), None);
+        assert_eq!(reference("$0"), None);
+        assert_eq!(reference("$x.pane_id"), None);
+        assert_eq!(reference("$0.pane_id."), None);
+
+        let forward = steps(json!([{"cmd": "rename-pane", "pane_id": "$0.pane_id", "title": "x"}]));
+        assert_eq!(forward.unwrap_err(), "step 0: \"$0.pane_id\" must refer to an earlier step");
+        let backward = steps(json!([{"cmd": "split"}, {"cmd": "rename-pane", "pane_id": "$0.pane_id", "title": "x"}]));
+        assert_eq!(backward.map(|s| s.len()), Ok(2));
+        let merge = steps(json!([
+            {"cmd": "new-tab"},
+            {"cmd": "new-tab"},
+            {"cmd": "merge-tab", "source_tab_id": "$1.tab_id", "target_tab_id": "$0.tab_id"},
+        ]));
+        assert!(merge.is_ok(), "{:?}", merge);
+    }
+
+    #[test]
+    fn steps_are_checked_before_the_batch_runs() {
+        assert!(steps(json!([])).is_err());
+        assert!(steps(json!("list-panes")).is_err());
+        assert_eq!(steps(json!([{"pane_id": 1}])).unwrap_err(), "step 0: missing \"cmd\" field");
+        assert_eq!(
+            steps(json!([{"cmd": "wait-for-idle", "pane_id": 1}])).unwrap_err(),
+            "step 0: wait-for-idle cannot be part of a batch"
+        );
+        assert_eq!(
+            steps(json!([{"cmd": "split"}, {"cmd": "split", "direction": "diagonal"}])).unwrap_err(),
+            "step 1: invalid direction: diagonal"
+        );
+        // Ids of the connection do not go into a step.
+        assert!(steps(json!([{"cmd": "list-panes", "id": 1}])).is_err());
+        let too_many = vec![json!({"cmd": "list-panes"}); MAX_STEPS + 1];
+        assert!(steps(Value::Array(too_many)).is_err());
+    }
+
+    #[test]
+    fn a_batch_needs_the_scopes_of_its_steps() {
+        use crate::ipc_schema::scope;
+        let batch = json!({"cmd": "batch", "commands": [
+            {"cmd": "split"},
+            {"cmd": "send-keys", "pane_id": "$0.pane_id", "text": "ls\r"},
+        ]});
+        assert_eq!(crate::ipc_schema::scopes_needed(&batch), scope::CONTROL | scope::INPUT);
+        assert!(mutates(&batch));
+        assert!(!mutates(&json!({"cmd": "batch", "commands": [{"cmd": "list-panes"}]})));
+    }
+
+    #[test]
+    fn later_steps_use_the_ids_earlier_ones_returned() {
+        let mut fake = Fake::new();
+        let steps = steps(json!([
+            {"cmd": "new-tab"},
+            {"cmd": "split", "direction": "vertical"},
+            {"cmd": "rename-pane", "pane_id": "$1.pane_id", "title": "logs"},
+        ]))
+        .unwrap();
+        let IpcResponse::Ok { data: Some(data) } = run(steps, true, None, |cmd| fake.exec(cmd)) else {
+            panic!("batch failed");
+        };
+        assert_eq!(data["failed"], 0);
+        assert_eq!(data["results"][1], json!({"ok": true, "data": {"pane_id": 11}}));
+        assert_eq!(data["results"][2], json!({"ok": true, "data": null}));
+        assert_eq!(fake.ran, ["open 10", "open 11", "rename 11"]);
+    }
+
+    #[test]
+    fn an_atomic_batch_closes_what_it_opened_when_a_step_fails() {
+        let mut fake = Fake::new();
+        let steps = steps(json!([
+            {"cmd": "new-tab"},
+            {"cmd": "split"},
+            {"cmd": "rename-pane", "pane_id": 99, "title": "nope"},
+            {"cmd": "split"},
+        ]))
+        .unwrap();
+        let IpcResponse::Error { message } = run(steps, true, None, |cmd| fake.exec(cmd)) else {
+            panic!("batch should fail");
+        };
+        assert_eq!(message, "step 2 (rename-pane): pane 99 not found (rolled back: closed pane 10, 11)");
+        assert_eq!(fake.ran, ["open 10", "open 11", "close 11", "close 10"]);
+        assert_eq!(fake.open, [1]);
+    }
+
+    #[test]
+    fn without_atomic_every_step_runs_and_reports() {
+        let mut fake = Fake::new();
+        let steps = steps(json!([
+            {"cmd": "rename-pane", "pane_id": 99, "title": "nope"},
+            {"cmd": "split"},
+            {"cmd": "rename-pane", "pane_id": "$0.pane_id", "title": "x"},
+            {"cmd": "rename-pane", "pane_id": "$1.tab_id", "title": "x"},
+        ]))
+        .unwrap();
+        let IpcResponse::Ok { data: Some(data) } = run(steps, false, None, |cmd| fake.exec(cmd)) else {
+            panic!("a batch without atomic answers every step");
+        };
+        assert_eq!(data["failed"], 3);
+        assert_eq!(data["results"][0]["error"], "pane 99 not found");
+        assert_eq!(data["results"][1]["ok"], true);
+        assert_eq!(data["results"][2]["error"], "\"$0.pane_id\": step 0 failed");
+        assert_eq!(data["results"][3]["error"], "\"$1.tab_id\": step 1 returned no tab_id");
+        assert_eq!(fake.open, [1, 10]);
+    }
+
+    #[test]
+    fn text_fields_are_sent_as_given() {
+        let steps = steps(json!([
+            {"cmd": "split"},
+            {"cmd": "send-keys", "pane_id": "$0.pane_id", "text": "$1.name"},
+        ]))
+        .unwrap();
+        let mut sent = Vec::new();
+        let IpcResponse::Ok { data: Some(data) } = run(steps, true, None, |cmd| match cmd {
+            IpcCommand::SendKeys { pane_id, text } => {
+                sent.push((pane_id, text));
+                IpcResponse::Ok { data: None }
+            }
+            _ => IpcResponse::Ok { data: Some(json!({ "pane_id": 10 })) },
+        }) else {
+            panic!("batch failed");
+        };
+        assert_eq!(data["failed"], 0);
+        assert_eq!(sent, [(10, "$1.name".to_string())]);
+    }
+}
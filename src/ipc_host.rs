@@ -0,0 +1,751 @@
+//! The IPC commands that act on tabs and panes, for both front ends.
+//!
+//! The AppKit windows (`app.rs`) and `kova --headless` (`headless.rs`) answer
+//! the same protocol over the same `Tab`s and `Pane`s; what differs is where
+//! the tabs live and what a layout change costs (pixels and a redraw, or a
+//! cell grid). `IpcHost` is that difference and nothing more. Everything a
+//! command does once the pane is found — the checks, the error messages, the
+//! reply's shape, parking and resolving the waits — is written once, here.
+//!
+//! `subscribe` is not served here: it needs the event state, which each
+//! front end keeps next to its tick.
+
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::ipc::{IpcCommand, IpcRequest, IpcResponse, PaneFilter, Placement};
+use crate::pane::{playback_json, start_pane_recording, Pane, PaneId, SplitAxis, SplitDirection, Tab};
+use crate::terminal::output::IdleWait;
+use crate::terminal::search::{OutputSignal, SearchJob, TextWait, ROWS_PER_LOCK};
+
+/// Longest a tick spends on one parked `search-pane` — the same slice as the
+/// Cmd+F overlay, so a huge scrollback is scanned over many ticks instead of
+/// freezing the screen.
+const SEARCH_STEP_BUDGET: Duration = Duration::from_millis(4);
+
+/// Outcome of `IpcHost::close_tab`.
+pub enum IpcCloseTabResult {
+    Closed,
+    /// It is the last tab of its window: closing it would end the app.
+    WouldTerminate,
+    NotFound,
+}
+
+/// Outcome of `IpcHost::merge_tab`. `TargetMissing` also covers a target in
+/// another window than the source.
+pub enum IpcMergeTabResult {
+    Merged,
+    SourceMissing,
+    TargetMissing,
+}
+
+/// Outcome of `IpcHost::swap_pane`. `BMissing` also covers a pane in another
+/// tab than the first.
+pub enum IpcSwapPaneResult {
+    Swapped,
+    AMissing,
+    BMissing,
+    Failed,
+}
+
+/// What a front end provides for the shared command handling: finding panes,
+/// and the layout changes, which it applies to its own screen.
+pub trait IpcHost {
+    fn config(&self) -> &Config;
+
+    /// Run `f` on pane `pane_id`. `None` if no tab holds it.
+    fn with_pane<R>(&self, pane_id: PaneId, f: impl FnOnce(&Pane) -> R) -> Option<R>;
+
+    /// Run `f` on the tab holding `pane_id`, for a change that shows (a title,
+    /// a color, a pane's waiting flag). `None` if no tab holds it.
+    fn with_tab_of<R>(&mut self, pane_id: PaneId, f: impl FnOnce(&mut Tab) -> R) -> Option<R>;
+
+    /// Every pane, in window, tab and layout order.
+    fn pane_ids(&self) -> Vec<PaneId>;
+
+    /// `list-panes` entries.
+    fn list_panes(&self) -> Vec<serde_json::Value>;
+
+    /// `list-tabs` entries.
+    fn list_tabs(&self) -> Vec<serde_json::Value>;
+
+    /// CWD of the focused pane, the default for a new split or tab.
+    fn focused_cwd(&self) -> Option<String>;
+
+    /// Split the focused pane of the active tab; `make_pane` gets the grid
+    /// size of the new slot. The new pane is focused.
+    fn split_focused(
+        &mut self,
+        direction: SplitDirection,
+        make_pane: impl FnOnce(u16, u16) -> Result<Pane, String>,
+    ) -> Result<PaneId, String>;
+
+    /// Insert `tab` after the active tab and make it active.
+    fn open_tab(&mut self, tab: Tab);
+
+    /// `Some(true)` closed (with its tab when it was the tab's last pane),
+    /// `Some(false)` refused (the very last pane), `None` not found.
+    fn close_pane(&mut self, pane_id: PaneId) -> Option<bool>;
+
+    /// Focus a pane, switching tab (and window) as needed. False if not found.
+    fn focus_pane(&mut self, pane_id: PaneId) -> bool;
+
+    fn close_tab(&mut self, tab_id: u32) -> IpcCloseTabResult;
+
+    fn merge_tab(&mut self, source_tab_id: u32, target_tab_id: u32) -> IpcMergeTabResult;
+
+    fn swap_pane(&mut self, pane_id_a: PaneId, pane_id_b: PaneId) -> IpcSwapPaneResult;
+
+    /// Grow or shrink the split holding `pane_id`: `Some(false)` when it has
+    /// no neighbor on that axis, `None` when the pane is not found.
+    fn resize_pane(&mut self, pane_id: PaneId, axis: SplitAxis, grow: bool, amount_pct: f32) -> Option<bool>;
+
+    /// Hold pane resizes until `release_layout`, which sizes them once.
+    fn hold_layout(&mut self);
+
+    fn release_layout(&mut self);
+
+    /// The commands that only mean something on screen: `dispatch-action`,
+    /// `merge-window`, `notify`.
+    fn on_screen(&mut self, cmd: IpcCommand) -> IpcResponse;
+}
+
+/// A `wait-for-completion`, `wait-for-text` or `wait-for-idle` request that
+/// has not resolved yet, or a `search-pane` still scanning. The front end
+/// polls it on every tick (`poll_waits`).
+pub struct PendingWait {
+    pane_id: PaneId,
+    kind: WaitKind,
+    response_tx: mpsc::Sender<IpcResponse>,
+    deadline: Instant,
+}
+
+impl PendingWait {
+    /// The pane the wait or scan is watching. The scrollback budget leaves it
+    /// alone until the wait resolves.
+    pub fn pane_id(&self) -> PaneId {
+        self.pane_id
+    }
+
+    /// Answer a wait that will never resolve because Kova is quitting.
+    pub fn abandon(self) {
+        let _ = self.response_tx.send(IpcResponse::Error {
+            message: format!("kova is shutting down; the wait on pane {} did not resolve", self.pane_id),
+        });
+    }
+}
+
+/// What a parked wait is waiting for.
+enum WaitKind {
+    /// `wait-for-completion`: OSC 133;D.
+    Completion,
+    /// `wait-for-text`: the pattern, looked for again once the pane's output
+    /// signal moved past `seen`.
+    Text { text: TextWait, signal: Arc<OutputSignal>, seen: u64 },
+    /// `wait-for-idle`: the pane's silence, measured on every tick.
+    Idle(IdleWait),
+    /// `search-pane`: the scan, advanced a slice per tick. No deadline: it
+    /// ends with the buffer or `max_results`.
+    Search(SearchJob),
+}
+
+/// Answer one request, or park it in `waits` when it is a wait that has not
+/// fired yet.
+pub fn serve(host: &mut impl IpcHost, (cmd, responder): IpcRequest, waits: &mut Vec<PendingWait>) {
+    let (pane_id, kind, timeout_ms) = match cmd {
+        IpcCommand::WaitForCompletion { pane_id, timeout_ms } => {
+            // Already done: don't make the client wait a tick for the obvious answer.
+            match host.with_pane(pane_id, |pane| pane.terminal.read().completion_reply(pane_id, false)) {
+                None => {
+                    let _ = responder.send(not_found(pane_id));
+                    return;
+                }
+                Some(Some(data)) => {
+                    let _ = responder.send(IpcResponse::Ok { data: Some(data) });
+                    return;
+                }
+                Some(None) => (pane_id, WaitKind::Completion, timeout_ms),
+            }
+        }
+        IpcCommand::WaitForText { pane_id, matcher, scope, timeout_ms } => {
+            // Checked once right away: the text may already be on screen.
+            let checked = host.with_pane(pane_id, |pane| {
+                let term = pane.terminal.read();
+                let seen = term.output_signal.rung_count();
+                let mut text = TextWait::new(matcher, scope, &term);
+                let hit = text.check(&term);
+                (text, hit, term.output_signal.clone(), seen)
+            });
+            match checked {
+                None => {
+                    let _ = responder.send(not_found(pane_id));
+                    return;
+                }
+                Some((_, Some(hit), ..)) => {
+                    let _ = responder.send(IpcResponse::Ok { data: Some(TextWait::reply(pane_id, Some(hit))) });
+                    return;
+                }
+                Some((text, None, signal, seen)) => (pane_id, WaitKind::Text { text, signal, seen }, timeout_ms),
+            }
+        }
+        IpcCommand::WaitForIdle { pane_id, quiet_ms, timeout_ms } => {
+            // Never idle on arrival: the silence is counted from now.
+            if host.with_pane(pane_id, |_| ()).is_none() {
+                let _ = responder.send(not_found(pane_id));
+                return;
+            }
+            (pane_id, WaitKind::Idle(IdleWait::new(quiet_ms)), timeout_ms)
+        }
+        IpcCommand::SearchPane { pane_id, matcher, max_results } => {
+            // The first slice runs right away: a short buffer is answered on
+            // the spot, like the Cmd+F overlay's first keystroke.
+            let first = host.with_pane(pane_id, |pane| {
+                let mut job = SearchJob::new(matcher, max_results);
+                let done = job.step(&pane.terminal.read(), ROWS_PER_LOCK);
+                (job, done)
+            });
+            match first {
+                None => {
+                    let _ = responder.send(not_found(pane_id));
+                    return;
+                }
+                Some((job, true)) => {
+                    let _ = responder.send(IpcResponse::Ok { data: Some(job.ipc_json(pane_id)) });
+                    return;
+                }
+                Some((job, false)) => (pane_id, WaitKind::Search(job), 0),
+            }
+        }
+        cmd => {
+            let _ = responder.send(handle(host, cmd));
+            return;
+        }
+    };
+    waits.push(PendingWait {
+        pane_id,
+        kind,
+        response_tx: responder,
+        deadline: Instant::now() + Duration::from_millis(timeout_ms),
+    });
+}
+
+/// Resolve the parked waits whose pane completed, showed the text or went
+/// quiet, timed out, or went away.
+pub fn poll_waits(host: &impl IpcHost, waits: &mut Vec<PendingWait>) {
+    if waits.is_empty() {
+        return;
+    }
+    let now = Instant::now();
+    waits.retain_mut(|wait| {
+        let pane_id = wait.pane_id;
+        let give_up = now >= wait.deadline;
+        let resolved = host.with_pane(pane_id, |pane| {
+            // Nothing printed since the last look: the text is not read.
+            if let WaitKind::Text { signal, seen, .. } = &wait.kind
+                && !give_up
+                && signal.rung_count() == *seen
+            {
+                return None;
+            }
+            let term = pane.terminal.read();
+            match &mut wait.kind {
+                WaitKind::Completion => term.completion_reply(pane_id, give_up),
+                WaitKind::Text { text, signal, seen } => {
+                    *seen = signal.rung_count();
+                    match text.check(&term) {
+                        None if !give_up => None,
+                        hit => Some(TextWait::reply(pane_id, hit)),
+                    }
+                }
+                WaitKind::Idle(idle) => idle.check(pane_id, &term, give_up),
+                WaitKind::Search(job) => {
+                    // The read lock is let go between slices, so the PTY
+                    // reader applies output in between.
+                    drop(term);
+                    let slice_end = Instant::now() + SEARCH_STEP_BUDGET;
+                    loop {
+                        if job.step(&pane.terminal.read(), ROWS_PER_LOCK) {
+                            break Some(job.ipc_json(pane_id));
+                        }
+                        if Instant::now() >= slice_end {
+                            break None;
+                        }
+                    }
+                }
+            }
+        });
+        let response = match resolved {
+            None if matches!(wait.kind, WaitKind::Search(_)) => {
+                IpcResponse::Error { message: format!("pane {} closed during search", pane_id) }
+            }
+            None => IpcResponse::Error { message: format!("pane {} closed during wait", pane_id) },
+            Some(Some(data)) => IpcResponse::Ok { data: Some(data) },
+            Some(None) => return true, // still waiting
+        };
+        let _ = wait.response_tx.send(response);
+        false
+    });
+}
+
+/// Every command but the waits and `search-pane` (see `serve`), and
+/// `subscribe`.
+pub fn handle(host: &mut impl IpcHost, cmd: IpcCommand) -> IpcResponse {
+    match cmd {
+        IpcCommand::Split { direction, cmd, cwd } => {
+            let cwd = cwd.or_else(|| host.focused_cwd());
+            let config = host.config().clone();
+            let spawned = host.split_focused(split_direction(&direction), |cols, rows| {
+                let pane = Pane::spawn(cols, rows, &config, cwd.as_deref()).map_err(|e| e.to_string())?;
+                // Injected once the shell is ready.
+                if let Some(cmd) = cmd {
+                    pane.pending_command.set(Some(cmd));
+                }
+                Ok(pane)
+            });
+            match spawned {
+                Ok(id) => {
+                    log::info!("IPC: split created pane {}", id);
+                    IpcResponse::Ok { data: Some(serde_json::json!({ "pane_id": id })) }
+                }
+                Err(e) => {
+                    log::error!("IPC split: failed to spawn pane: {}", e);
+                    IpcResponse::Error { message: "split failed".to_string() }
+                }
+            }
+        }
+        IpcCommand::ListPanes => IpcResponse::Ok { data: Some(serde_json::Value::Array(host.list_panes())) },
+        IpcCommand::ClosePaneById(pane_id) => match host.close_pane(pane_id) {
+            Some(true) => IpcResponse::Ok { data: None },
+            Some(false) => IpcResponse::Error { message: format!("pane {} is the last pane — cannot close", pane_id) },
+            None => not_found(pane_id),
+        },
+        IpcCommand::SendKeys { pane_id, text } => reply_with(pane_id, host.with_pane(pane_id, |pane| {
+            // Same rule as a keystroke: someone answered this pane.
+            pane.clear_awaiting();
+            pane.pty.write(text.as_bytes());
+            Ok(None)
+        })),
+        IpcCommand::FocusPane(pane_id) => match host.focus_pane(pane_id) {
+            true => IpcResponse::Ok { data: None },
+            false => not_found(pane_id),
+        },
+        IpcCommand::NewTab { cwd, cmd } => {
+            let cwd = cwd.or_else(|| host.focused_cwd());
+            let tab = match Tab::new_with_cwd(host.config(), cwd.as_deref()) {
+                Ok(t) => t,
+                Err(e) => {
+                    log::error!("IPC new-tab: failed to create tab: {}", e);
+                    return IpcResponse::Error { message: "failed to create tab".to_string() };
+                }
+            };
+            let (tab_id, pane_id) = (tab.id, tab.first_pane().id);
+            if let Some(cmd) = cmd {
+                tab.first_pane().pending_command.set(Some(cmd));
+            }
+            host.open_tab(tab);
+            log::info!("IPC: new tab created: tab_id={}, pane_id={}", tab_id, pane_id);
+            IpcResponse::Ok { data: Some(serde_json::json!({ "tab_id": tab_id, "pane_id": pane_id })) }
+        }
+        IpcCommand::SetTabTitle { pane_id, title } => reply_with(pane_id, host.with_tab_of(pane_id, |tab| {
+            // `None` falls back to the title derived from the panes.
+            tab.custom_title = title;
+            Ok(None)
+        })),
+        IpcCommand::SetTabColor { pane_id, color } => reply_with(pane_id, host.with_tab_of(pane_id, |tab| {
+            tab.color = color;
+            Ok(None)
+        })),
+        IpcCommand::GetPaneContent { panes, mode, trim_trailing_blank_lines, format } => {
+            let mode = crate::terminal::DumpMode::from_ipc(&mode);
+            let entries: Vec<serde_json::Value> = resolve_pane_filter(host, panes)
+                .into_iter()
+                .map(|id| {
+                    host.with_pane(id, |pane| pane.content_json(mode, trim_trailing_blank_lines, format))
+                        .unwrap_or_else(|| serde_json::json!({ "id": id, "error": "not found" }))
+                })
+                .collect();
+            IpcResponse::Ok { data: Some(serde_json::json!({ "panes": entries })) }
+        }
+        IpcCommand::CountPaneContent { panes, mode, trim_trailing_blank_lines, format } => {
+            let mode = crate::terminal::DumpMode::from_ipc(&mode);
+            let (mut total_chars, mut total_bytes) = (0u64, 0u64);
+            let mut entries = Vec::new();
+            for id in resolve_pane_filter(host, panes) {
+                let measured = host.with_pane(id, |pane| {
+                    pane.terminal.read().measure_export(mode, trim_trailing_blank_lines, format)
+                });
+                match measured {
+                    Some((chars, bytes)) => {
+                        total_chars += chars as u64;
+                        total_bytes += bytes as u64;
+                        entries.push(serde_json::json!({ "id": id, "chars": chars, "bytes": bytes }));
+                    }
+                    None => entries.push(serde_json::json!({ "id": id, "error": "not found" })),
+                }
+            }
+            IpcResponse::Ok {
+                data: Some(serde_json::json!({
+                    "total_chars": total_chars,
+                    "total_bytes": total_bytes,
+                    "panes": entries,
+                })),
+            }
+        }
+        IpcCommand::ListTabs => IpcResponse::Ok { data: Some(serde_json::Value::Array(host.list_tabs())) },
+        IpcCommand::CloseTab(tab_id) => match host.close_tab(tab_id) {
+            IpcCloseTabResult::Closed => IpcResponse::Ok { data: None },
+            IpcCloseTabResult::WouldTerminate => IpcResponse::Error {
+                message: format!("tab {} is the last tab in its window — refusing to close (would terminate app)", tab_id),
+            },
+            IpcCloseTabResult::NotFound => IpcResponse::Error { message: format!("tab {} not found", tab_id) },
+        },
+        IpcCommand::MergeTab { source_tab_id, target_tab_id } => match host.merge_tab(source_tab_id, target_tab_id) {
+            IpcMergeTabResult::Merged => IpcResponse::Ok { data: None },
+            IpcMergeTabResult::SourceMissing => {
+                IpcResponse::Error { message: format!("source tab {} not found", source_tab_id) }
+            }
+            IpcMergeTabResult::TargetMissing => IpcResponse::Error {
+                message: format!("target tab {} not found in the same window as source tab {}", target_tab_id, source_tab_id),
+            },
+        },
+        IpcCommand::SwapPane { pane_id_a, pane_id_b } => match host.swap_pane(pane_id_a, pane_id_b) {
+            IpcSwapPaneResult::Swapped => IpcResponse::Ok { data: None },
+            IpcSwapPaneResult::AMissing => not_found(pane_id_a),
+            IpcSwapPaneResult::BMissing => IpcResponse::Error {
+                message: format!("pane {} not found in the same tab as pane {}", pane_id_b, pane_id_a),
+            },
+            IpcSwapPaneResult::Failed => IpcResponse::Error {
+                message: format!("could not swap panes {} and {}", pane_id_a, pane_id_b),
+            },
+        },
+        IpcCommand::ResizePane { pane_id, axis, direction, amount_pct } => {
+            let split_axis = match axis.as_str() {
+                "vertical" => SplitAxis::Vertical,
+                _ => SplitAxis::Horizontal,
+            };
+            match host.resize_pane(pane_id, split_axis, direction == "grow", amount_pct) {
+                Some(true) => IpcResponse::Ok { data: None },
+                Some(false) => IpcResponse::Error {
+                    message: format!("pane {} has no neighbor along {} axis to resize against", pane_id, axis),
+                },
+                None => not_found(pane_id),
+            }
+        }
+        IpcCommand::RenamePane { pane_id, title } => reply_with(pane_id, host.with_tab_of(pane_id, |tab| {
+            let Some(pane) = tab.pane_mut(pane_id) else {
+                return Err(format!("pane {} not found", pane_id));
+            };
+            pane.custom_title = title;
+            // A stale OSC 1 must not overwrite the IPC title on the next frame.
+            let mut term = pane.terminal.write();
+            term.osc1_title = None;
+            term.dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(None)
+        })),
+        IpcCommand::SetPaneStatus { pane_id, waiting } => reply_with(pane_id, host.with_tab_of(pane_id, |tab| {
+            // Stored as-is; whether it is *shown* on the pane the user sits on
+            // is decided where focus is known.
+            let pane = tab.pane(pane_id).ok_or_else(|| format!("pane {} not found", pane_id))?;
+            if waiting {
+                pane.set_awaiting();
+            } else {
+                pane.clear_awaiting();
+            }
+            Ok(None)
+        })),
+        cmd @ (IpcCommand::DispatchAction { .. } | IpcCommand::MergeWindow { .. } | IpcCommand::Notify { .. }) => {
+            host.on_screen(cmd)
+        }
+        IpcCommand::StartRecording { pane_id, path, record_input } => {
+            let recording = host.config().recording.clone();
+            reply_with(pane_id, host.with_pane(pane_id, |pane| {
+                let path = start_pane_recording(pane, &recording, path, record_input)?;
+                Ok(Some(serde_json::json!({ "path": path.display().to_string() })))
+            }))
+        }
+        IpcCommand::StopRecording(pane_id) => reply_with(pane_id, host.with_pane(pane_id, |pane| {
+            match pane.pty.stop_recording() {
+                Some(path) => Ok(Some(serde_json::json!({ "path": path.display().to_string() }))),
+                None => Err(format!("pane {} is not recording", pane_id)),
+            }
+        })),
+        IpcCommand::PlayRecording { path, speed, direction } => {
+            let cast = match crate::terminal::recording::Cast::load(&path) {
+                Ok(c) => c,
+                Err(message) => return IpcResponse::Error { message },
+            };
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let config = host.config().clone();
+            let spawned = host.split_focused(split_direction(&direction), move |_, _| {
+                Pane::playback(cast, format!("▶ {}", name), speed, &config).map_err(|e| e.to_string())
+            });
+            match spawned {
+                Ok(id) => {
+                    log::info!("IPC: playback of {} in pane {}", path.display(), id);
+                    IpcResponse::Ok { data: Some(serde_json::json!({ "pane_id": id })) }
+                }
+                Err(message) => IpcResponse::Error { message },
+            }
+        }
+        IpcCommand::SetPlayback { pane_id, speed, paused } => reply_with(pane_id, host.with_pane(pane_id, |pane| {
+            let control = pane.pty.playback_control().ok_or_else(|| format!("pane {} is not a playback pane", pane_id))?;
+            if let Some(speed) = speed {
+                control.set_speed(speed);
+            }
+            if let Some(paused) = paused {
+                control.set_paused(paused);
+            }
+            Ok(Some(playback_json(control)))
+        })),
+        IpcCommand::InspectPane { pane_id, on, capacity } => {
+            reply_with(pane_id, host.with_pane(pane_id, |pane| Ok(Some(pane.set_inspecting(on, capacity)))))
+        }
+        IpcCommand::GetPaneOps { pane_id, limit, clear } => {
+            reply_with(pane_id, host.with_pane(pane_id, |pane| Ok(Some(pane.ops_json(limit, clear)))))
+        }
+        IpcCommand::NewVirtualPane { title, placement } => match placement {
+            Placement::Split(direction) => {
+                let config = host.config().clone();
+                let spawned = host.split_focused(split_direction(&direction), |cols, rows| {
+                    Ok(Pane::virtual_pane(cols, rows, title, &config))
+                });
+                match spawned {
+                    Ok(id) => {
+                        log::info!("IPC: virtual pane {} created", id);
+                        IpcResponse::Ok { data: Some(serde_json::json!({ "pane_id": id })) }
+                    }
+                    Err(message) => IpcResponse::Error { message },
+                }
+            }
+            Placement::Tab => {
+                // Sized for the whole screen once the tab is laid out.
+                let config = host.config();
+                let pane = Pane::virtual_pane(config.terminal.columns, config.terminal.rows, title, config);
+                let tab = Tab::with_pane(pane);
+                let (tab_id, pane_id) = (tab.id, tab.first_pane().id);
+                host.open_tab(tab);
+                log::info!("IPC: virtual pane {} created in tab {}", pane_id, tab_id);
+                IpcResponse::Ok { data: Some(serde_json::json!({ "tab_id": tab_id, "pane_id": pane_id })) }
+            }
+        },
+        IpcCommand::WriteOutput { pane_id, data } => reply_with(pane_id, host.with_pane(pane_id, |pane| {
+            pane.pty.write_output(data.as_bytes()).map(|()| None).map_err(|e| format!("pane {}: {}", pane_id, e))
+        })),
+        IpcCommand::PipePane { pane_id, target, format } => reply_with(pane_id, host.with_pane(pane_id, |pane| {
+            match target {
+                Some(target) => pane.start_pipe(target, format).map(Some),
+                None => pane.stop_pipe().map(Some).ok_or_else(|| format!("pane {} is not piped", pane_id)),
+            }
+        })),
+        IpcCommand::Batch { steps, atomic, log } => {
+            // Sized once, for the layout the last step leaves.
+            host.hold_layout();
+            let response = crate::ipc_batch::run(steps, atomic, log.as_ref(), |cmd| handle(host, cmd));
+            host.release_layout();
+            response
+        }
+        IpcCommand::WaitForCompletion { .. }
+        | IpcCommand::WaitForText { .. }
+        | IpcCommand::WaitForIdle { .. }
+        | IpcCommand::SearchPane { .. }
+        | IpcCommand::Subscribe { .. } => {
+            // Waits and searches are parked by `serve`; `subscribe` is served by the front
+            // end, which owns the event state. Reaching here means that branch
+            // was lost in a refactor.
+            log::error!("IPC: a loop-level command reached ipc_host::handle");
+            IpcResponse::Error { message: "internal: command was not intercepted".to_string() }
+        }
+        // Answered by the connection thread, which owns the framing and the
+        // requests in flight. Never sent to the main thread.
+        IpcCommand::Hello { .. } | IpcCommand::Cancel { .. } | IpcCommand::Describe | IpcCommand::GetAuditLog { .. } => {
+            log::error!("IPC: a connection-level command reached the main thread");
+            IpcResponse::Error { message: "internal: command was not intercepted".to_string() }
+        }
+    }
+}
+
+/// The reply to a command run on one pane: `None` is a pane not found.
+fn reply_with(pane_id: PaneId, outcome: Option<Result<Option<serde_json::Value>, String>>) -> IpcResponse {
+    match outcome {
+        Some(Ok(data)) => IpcResponse::Ok { data },
+        Some(Err(message)) => IpcResponse::Error { message },
+        None => not_found(pane_id),
+    }
+}
+
+/// The panes a `PaneFilter` names; `All` in window, tab and layout order.
+fn resolve_pane_filter(host: &impl IpcHost, filter: PaneFilter) -> Vec<PaneId> {
+    match filter {
+        PaneFilter::Ids(ids) => ids,
+        PaneFilter::All => host.pane_ids(),
+    }
+}
+
+pub fn not_found(pane_id: PaneId) -> IpcResponse {
+    IpcResponse::Error { message: format!("pane {} not found", pane_id) }
+}
+
+fn split_direction(direction: &str) -> SplitDirection {
+    match direction {
+        "vertical" => SplitDirection::Vertical,
+        _ => SplitDirection::Horizontal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::Fixture;
+    use crate::terminal::search::{CaseMode, SearchQuery, WaitScope};
+
+    #[test]
+    fn a_parked_wait_resolves_when_the_command_completes() {
+        let mut f = Fixture::new();
+        f.program.command_started("cargo test");
+        let rx = f.serve(IpcCommand::WaitForCompletion { pane_id: f.id, timeout_ms: 60_000 });
+        f.poll_waits();
+        assert_eq!(f.waits.len(), 1);
+        assert!(rx.try_recv().is_err());
+
+        f.program.output(b"test result: FAILED\r\n");
+        f.program.command_finished(101);
+        f.poll_waits();
+        assert!(f.waits.is_empty());
+        let Ok(IpcResponse::Ok { data: Some(data) }) = rx.try_recv() else {
+            panic!("wait did not resolve");
+        };
+        assert_eq!(data["completed"], true);
+        assert_eq!(data["exit_code"], 101);
+    }
+
+    #[test]
+    fn a_parked_text_wait_resolves_when_the_program_prints_it() {
+        let mut f = Fixture::new();
+        f.program.output(b"$ npm start\r\n");
+        let query = SearchQuery { pattern: r"listening on port (\d+)".into(), regex: true, ..Default::default() };
+        let rx = f.serve(IpcCommand::WaitForText {
+            pane_id: f.id,
+            matcher: query.compile().unwrap(),
+            scope: WaitScope::NewOutput,
+            timeout_ms: 60_000,
+        });
+        assert_eq!(f.waits.len(), 1);
+        // Nothing printed yet: polling does not even read the pane.
+        f.poll_waits();
+        assert!(rx.try_recv().is_err());
+
+        f.program.output(b"compiling...\r\n");
+        f.poll_waits();
+        assert!(rx.try_recv().is_err());
+
+        f.program.output(b"Listening on port \x1b[1m3000\x1b[0m\r\n");
+        f.poll_waits();
+        assert!(f.waits.is_empty());
+        let Ok(IpcResponse::Ok { data: Some(data) }) = rx.try_recv() else {
+            panic!("wait did not resolve");
+        };
+        assert_eq!(data["found"], true);
+        assert_eq!(data["text"], "Listening on port 3000");
+        assert_eq!(data["groups"][0], "3000");
+    }
+
+    #[test]
+    fn a_parked_text_wait_fails_when_its_pane_closes() {
+        let mut f = Fixture::new();
+        let IpcResponse::Ok { data: Some(created) } =
+            handle(&mut f.engine, IpcCommand::NewVirtualPane { title: None, placement: Placement::Tab })
+        else {
+            panic!("new-virtual-pane failed");
+        };
+        let id = created["pane_id"].as_u64().unwrap() as PaneId;
+        let query = SearchQuery { pattern: "never".into(), ..Default::default() };
+        let rx = f.serve(IpcCommand::WaitForText {
+            pane_id: id,
+            matcher: query.compile().unwrap(),
+            scope: WaitScope::Screen,
+            timeout_ms: 60_000,
+        });
+        assert_eq!(f.engine.close_pane(id), Some(true));
+        f.poll_waits();
+        assert!(f.waits.is_empty());
+        let Ok(IpcResponse::Error { message }) = rx.try_recv() else {
+            panic!("wait outlived its pane");
+        };
+        assert_eq!(message, format!("pane {} closed during wait", id));
+    }
+
+    #[test]
+    fn an_idle_wait_resolves_once_the_output_stops() {
+        let mut f = Fixture::new();
+        f.program.output(b"$ make\r\n");
+        let rx = f.serve(IpcCommand::WaitForIdle { pane_id: f.id, quiet_ms: 50, timeout_ms: 60_000 });
+        f.poll_waits();
+        assert!(rx.try_recv().is_err(), "idle before the quiet period even ran");
+
+        f.program.output(b"cc -o kova main.c\r\n");
+        std::thread::sleep(Duration::from_millis(60));
+        f.poll_waits();
+        assert!(f.waits.is_empty());
+        let Ok(IpcResponse::Ok { data: Some(data) }) = rx.try_recv() else {
+            panic!("wait did not resolve");
+        };
+        assert_eq!(data["idle"], true);
+        assert!(data["silent_ms"].as_u64().unwrap() >= 50);
+        assert!(data["last_output_ms"].is_u64());
+    }
+
+    #[test]
+    fn a_long_search_is_parked_and_scanned_across_ticks() {
+        let mut f = Fixture::new();
+        let lines = 2 * ROWS_PER_LOCK;
+        let output: String = (0..lines).map(|n| format!("line {}\r\n", n)).collect();
+        f.program.output(output.as_bytes());
+        let rx = f.serve(IpcCommand::SearchPane {
+            pane_id: f.id,
+            matcher: SearchQuery::literal("line 1", CaseMode::Smart).compile().unwrap(),
+            max_results: 100_000,
+        });
+        assert_eq!(f.waits.len(), 1, "a scan longer than one slice is parked");
+        assert!(rx.try_recv().is_err());
+
+        while !f.waits.is_empty() {
+            f.poll_waits();
+        }
+        let Ok(IpcResponse::Ok { data: Some(data) }) = rx.try_recv() else {
+            panic!("search did not resolve");
+        };
+        let expected = (0..lines).filter(|n| n.to_string().starts_with('1')).count();
+        assert_eq!(data["matches"].as_array().unwrap().len(), expected);
+        assert_eq!(data["truncated"], false);
+    }
+
+    #[test]
+    fn a_virtual_pane_shows_what_its_client_writes() {
+        let mut f = Fixture::new();
+        let shell_id = f.id;
+        let IpcResponse::Ok { data: Some(created) } =
+            handle(&mut f.engine, IpcCommand::NewVirtualPane { title: Some("CI".into()), placement: Placement::Tab })
+        else {
+            panic!("new-virtual-pane failed");
+        };
+        let id = created["pane_id"].as_u64().unwrap() as PaneId;
+        let tabs = f.engine.list_tabs();
+        assert_eq!(tabs.len(), 2);
+        assert_eq!(tabs[1]["active"], true);
+
+        let written = handle(&mut f.engine, IpcCommand::WriteOutput { pane_id: id, data: "\x1b[32mbuild ok\x1b[0m\r\n".into() });
+        assert!(matches!(written, IpcResponse::Ok { .. }));
+        let text = f.engine.with_pane(id, |p| p.terminal.read().dump_text(crate::terminal::DumpMode::Visible, true).text).unwrap();
+        assert_eq!(text.lines().next(), Some("build ok"));
+
+        let listed: Vec<(u64, bool)> =
+            f.engine.list_panes().iter().map(|p| (p["id"].as_u64().unwrap(), p["virtual"] == true)).collect();
+        assert!(listed.contains(&(id as u64, true)));
+        assert!(listed.contains(&(shell_id as u64, false)));
+
+        // A pane with a program behind it keeps its output to itself.
+        let refused = handle(&mut f.engine, IpcCommand::WriteOutput { pane_id: shell_id, data: "x".into() });
+        assert!(matches!(refused, IpcResponse::Error { .. }));
+    }
+}
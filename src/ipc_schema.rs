@@ -0,0 +1,653 @@
+//! The IPC protocol as data: every command, its fields, their types and
+//! defaults.
+//!
+//! `ipc::parse_value` rejects an unknown command or field, a missing required
+//! field and a value of the wrong type from this table, so it cannot drift
+//! from what the parser accepts without a test failing (see the tests below,
+//! which hold each command's fields, types and defaults to the parser).
+//! `describe` hands it to clients, which is how one learns about a command
+//! without reading `docs/ipc.md`. It also says which scope each
+//! command needs, for the connections that authenticate (see `ipc_auth`).
+
+use crate::ipc::{topic, Protocol};
+use crate::terminal::search::{AccentMode, CaseMode, WaitScope};
+
+/// Bumped when a change could break a client written against the previous
+/// one. Additions do not bump it: they show up in `describe` and, for what is
+/// not a command, in `CAPABILITIES`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Protocol features a client can test for, beyond the command list.
+pub const CAPABILITIES: &[&str] = &[
+    // `id` on any request, echoed in its response.
+    "request-ids",
+    // Waits sent with an `id` are answered when they resolve.
+    "out-of-order",
+    "cancel",
+    "jsonrpc",
+    "describe",
+    // `hello` takes a `token`, and commands can be refused for a scope.
+    "tokens",
+];
+
+/// What a token lets a connection do, as a bitmask. The scopes do not nest:
+/// `admin` alone cannot list panes.
+pub mod scope {
+    /// Look: lists, pane content, searches, waits, events.
+    pub const READ: u8 = 1 << 0;
+    /// Move things around: focus, layout, titles, new panes.
+    pub const CONTROL: u8 = 1 << 1;
+    /// Type into panes, and run commands.
+    pub const INPUT: u8 = 1 << 2;
+    /// Destroy, merge, write files, trigger any key binding.
+    pub const ADMIN: u8 = 1 << 3;
+    pub const ALL: u8 = READ | CONTROL | INPUT | ADMIN;
+
+    pub const ALL_NAMES: [&str; 4] = ["read", "control", "input", "admin"];
+
+    pub fn from_name(name: &str) -> Option<u8> {
+        ALL_NAMES.iter().position(|n| *n == name).map(|i| 1 << i)
+    }
+
+    pub fn names(mask: u8) -> Vec<&'static str> {
+        ALL_NAMES.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0).map(|(_, n)| *n).collect()
+    }
+}
+
+/// What a field holds. `describe` renders it as a JSON Schema fragment.
+#[derive(Clone, Copy)]
+pub enum FieldType {
+    Integer,
+    Number,
+    Boolean,
+    String,
+    /// One of these strings.
+    Enum(&'static [&'static str]),
+    IntegerList,
+    /// A list of these strings.
+    EnumList(&'static [&'static str]),
+    /// `"all"`, or a list of integers.
+    AllOrIntegers,
+    /// A request id: a string or a number.
+    Id,
+    /// A list of requests, each an object with its `cmd`.
+    Requests,
+}
+
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub ty: FieldType,
+    pub required: bool,
+    /// The value an absent field stands for, as JSON. `None`: nothing to
+    /// tell beyond "not given", or a default that depends on the pane.
+    pub default: Option<&'static str>,
+    /// The scope needed, on top of the command's, to give this field a value.
+    pub scope: u8,
+    /// What the command carries to the pane (text typed, printed, shown):
+    /// the audit log keeps only its length (see `ipc_audit`).
+    pub payload: bool,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// The scope a connection needs for it; 0 for those of the connection
+    /// itself, always allowed.
+    pub scope: u8,
+    pub summary: &'static str,
+    pub fields: &'static [FieldSpec],
+}
+
+const fn req(name: &'static str, ty: FieldType) -> FieldSpec {
+    FieldSpec { name, ty, required: true, default: None, scope: 0, payload: false }
+}
+
+const fn opt(name: &'static str, ty: FieldType, default: Option<&'static str>) -> FieldSpec {
+    FieldSpec { name, ty, required: false, default, scope: 0, payload: false }
+}
+
+impl FieldSpec {
+    /// Giving this field a value also needs `scope`.
+    const fn needs(self, scope: u8) -> FieldSpec {
+        FieldSpec { scope, ..self }
+    }
+
+    const fn payload(self) -> FieldSpec {
+        FieldSpec { payload: true, ..self }
+    }
+}
+
+impl CommandSpec {
+    /// Whether it changes something: the audit log records these. Not the
+    /// reads, nor the commands of the connection itself.
+    pub fn mutates(&self) -> bool {
+        self.scope != 0 && self.scope != scope::READ && self.name != "get-audit-log"
+    }
+}
+
+const PANE_ID: FieldSpec = req("pane_id", FieldType::Integer);
+const DIRECTION: FieldType = FieldType::Enum(&["horizontal", "vertical"]);
+const TIMEOUT_MS: FieldSpec = opt("timeout_ms", FieldType::Integer, Some("30000"));
+const PATTERN: FieldSpec = req("pattern", FieldType::String);
+const REGEX: FieldSpec = opt("regex", FieldType::Boolean, Some("false"));
+const CASE: FieldSpec = opt("case", FieldType::Enum(&CaseMode::NAMES), Some("\"smart\""));
+const ACCENTS: FieldSpec = opt("accents", FieldType::Enum(&AccentMode::NAMES), Some("\"smart\""));
+const PANE_CONTENT: &[FieldSpec] = &[
+    opt("panes", FieldType::AllOrIntegers, Some("\"all\"")),
+    opt("mode", FieldType::Enum(&["visible", "scrollback", "all"]), Some("\"visible\"")),
+    opt("trim_trailing_blank_lines", FieldType::Boolean, Some("true")),
+    opt("format", FieldType::Enum(&["text", "ansi", "html", "cells"]), Some("\"text\"")),
+];
+
+/// Every command, in the order `docs/ipc.md` does not care about.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "split",
+        scope: scope::CONTROL,
+        summary: "Split the focused pane of the active window.",
+        fields: &[
+            opt("direction", DIRECTION, Some("\"horizontal\"")),
+            opt("command", FieldType::String, None).needs(scope::INPUT),
+            opt("cwd", FieldType::String, None),
+        ],
+    },
+    CommandSpec { name: "list-panes", scope: scope::READ, summary: "Every pane across every window.", fields: &[] },
+    CommandSpec { name: "close-pane", scope: scope::ADMIN, summary: "Close a pane.", fields: &[PANE_ID] },
+    CommandSpec {
+        name: "send-keys",
+        scope: scope::INPUT,
+        summary: "Write text to a pane's PTY.",
+        fields: &[PANE_ID, req("text", FieldType::String).payload()],
+    },
+    CommandSpec { name: "focus-pane", scope: scope::CONTROL, summary: "Bring a pane into focus.", fields: &[PANE_ID] },
+    CommandSpec {
+        name: "new-tab",
+        scope: scope::CONTROL,
+        summary: "Open a tab in the active window.",
+        fields: &[opt("cwd", FieldType::String, None), opt("command", FieldType::String, None).needs(scope::INPUT)],
+    },
+    CommandSpec {
+        name: "set-tab-title",
+        scope: scope::CONTROL,
+        summary: "Override the title of the tab holding a pane; null restores it.",
+        fields: &[PANE_ID, opt("title", FieldType::String, Some("null"))],
+    },
+    CommandSpec {
+        name: "set-tab-color",
+        scope: scope::CONTROL,
+        summary: "Color the tab holding a pane with a palette index; null clears it.",
+        fields: &[PANE_ID, opt("color", FieldType::Integer, Some("null"))],
+    },
+    CommandSpec {
+        name: "get-pane-content",
+        scope: scope::READ,
+        summary: "The rendered text of one or more panes.",
+        fields: PANE_CONTENT,
+    },
+    CommandSpec {
+        name: "count-pane-content",
+        scope: scope::READ,
+        summary: "The size of what get-pane-content would return.",
+        fields: PANE_CONTENT,
+    },
+    CommandSpec {
+        name: "search-pane",
+        scope: scope::READ,
+        summary: "Find a pattern in a pane's scrollback and screen.",
+        fields: &[PANE_ID, PATTERN, REGEX, CASE, ACCENTS, opt("max_results", FieldType::Integer, Some("1000"))],
+    },
+    CommandSpec {
+        name: "wait-for-completion",
+        scope: scope::READ,
+        summary: "Block until a shell command finishes (OSC 133;D).",
+        fields: &[PANE_ID, TIMEOUT_MS],
+    },
+    CommandSpec {
+        name: "wait-for-text",
+        scope: scope::READ,
+        summary: "Block until a pattern shows up in a pane.",
+        fields: &[
+            PANE_ID,
+            PATTERN,
+            REGEX,
+            CASE,
+            ACCENTS,
+            TIMEOUT_MS,
+            opt("scope", FieldType::Enum(&WaitScope::NAMES), Some("\"screen\"")),
+        ],
+    },
+    CommandSpec {
+        name: "wait-for-idle",
+        scope: scope::READ,
+        summary: "Block until a pane prints nothing for quiet_ms.",
+        fields: &[PANE_ID, opt("quiet_ms", FieldType::Integer, Some("1000")), TIMEOUT_MS],
+    },
+    CommandSpec { name: "list-tabs", scope: scope::READ, summary: "Every tab across every window.", fields: &[] },
+    CommandSpec {
+        name: "close-tab",
+        scope: scope::ADMIN,
+        summary: "Close a tab.",
+        fields: &[req("tab_id", FieldType::Integer)],
+    },
+    CommandSpec {
+        name: "merge-tab",
+        scope: scope::ADMIN,
+        summary: "Move a tab's columns into another tab of the same window.",
+        fields: &[req("source_tab_id", FieldType::Integer), req("target_tab_id", FieldType::Integer)],
+    },
+    CommandSpec {
+        name: "swap-pane",
+        scope: scope::CONTROL,
+        summary: "Swap two panes of the same tab.",
+        fields: &[req("pane_id_a", FieldType::Integer), req("pane_id_b", FieldType::Integer)],
+    },
+    CommandSpec {
+        name: "resize-pane",
+        scope: scope::CONTROL,
+        summary: "Grow or shrink a pane's split.",
+        fields: &[
+            PANE_ID,
+            opt("axis", DIRECTION, Some("\"horizontal\"")),
+            req("direction", FieldType::Enum(&["grow", "shrink"])),
+            opt("amount_pct", FieldType::Number, Some("5.0")),
+        ],
+    },
+    CommandSpec {
+        name: "rename-pane",
+        scope: scope::CONTROL,
+        summary: "Set a pane's sticky title; null clears it.",
+        fields: &[PANE_ID, opt("title", FieldType::String, Some("null"))],
+    },
+    CommandSpec {
+        name: "set-pane-status",
+        scope: scope::CONTROL,
+        summary: "Declare that a pane is waiting for the user, or no longer.",
+        fields: &[PANE_ID, req("status", FieldType::Enum(&["waiting", "none"]))],
+    },
+    CommandSpec {
+        name: "dispatch-action",
+        scope: scope::ADMIN,
+        summary: "Trigger a keyboard action by name.",
+        fields: &[req("action", FieldType::String), opt("pane_id", FieldType::Integer, Some("null"))],
+    },
+    CommandSpec {
+        name: "merge-window",
+        scope: scope::ADMIN,
+        summary: "Move every tab of a window into another.",
+        fields: &[req("source_window", FieldType::Integer), req("target_window", FieldType::Integer)],
+    },
+    CommandSpec {
+        name: "notify",
+        scope: scope::CONTROL,
+        summary: "Post a desktop notification that focuses a pane when clicked.",
+        fields: &[
+            opt("pane_id", FieldType::Integer, Some("null")),
+            opt("title", FieldType::String, Some("\"Kova\"")),
+            req("message", FieldType::String).payload(),
+            opt("sound", FieldType::Boolean, Some("false")),
+        ],
+    },
+    CommandSpec {
+        name: "subscribe",
+        scope: scope::READ,
+        summary: "Turn the connection into an event stream.",
+        fields: &[
+            opt(
+                "events",
+                FieldType::EnumList(&topic::ALL_NAMES),
+                Some(r#"["focus","pane-status","pane-working","pane-open","pane-close","pane-input","pane-quiet"]"#),
+            ),
+            opt("panes", FieldType::IntegerList, Some("null")),
+            opt("own", FieldType::IntegerList, Some("[]")),
+            opt("output", FieldType::Enum(&["lines", "raw"]), Some("\"lines\"")),
+        ],
+    },
+    CommandSpec {
+        name: "start-recording",
+        scope: scope::CONTROL,
+        summary: "Record a pane as an asciicast.",
+        fields: &[
+            PANE_ID,
+            opt("path", FieldType::String, None).needs(scope::ADMIN),
+            opt("input", FieldType::Boolean, None),
+        ],
+    },
+    CommandSpec {
+        name: "stop-recording",
+        scope: scope::CONTROL,
+        summary: "Stop recording a pane.",
+        fields: &[PANE_ID],
+    },
+    CommandSpec {
+        name: "play-recording",
+        scope: scope::CONTROL,
+        summary: "Replay a .cast file in a new pane.",
+        fields: &[
+            req("path", FieldType::String),
+            opt("speed", FieldType::Number, Some("1.0")),
+            opt("direction", DIRECTION, Some("\"horizontal\"")),
+        ],
+    },
+    CommandSpec {
+        name: "set-playback",
+        scope: scope::CONTROL,
+        summary: "Pause or change the speed of a playback pane.",
+        fields: &[PANE_ID, opt("speed", FieldType::Number, None), opt("paused", FieldType::Boolean, None)],
+    },
+    CommandSpec {
+        name: "inspect-pane",
+        scope: scope::CONTROL,
+        summary: "Start or stop keeping the parser's last ops for a pane.",
+        fields: &[PANE_ID, req("on", FieldType::Boolean), opt("capacity", FieldType::Integer, None)],
+    },
+    CommandSpec {
+        name: "get-pane-ops",
+        scope: scope::READ,
+        summary: "The ops kept by inspect-pane.",
+        fields: &[PANE_ID, opt("limit", FieldType::Integer, None), opt("clear", FieldType::Boolean, Some("false"))],
+    },
+    CommandSpec {
+        name: "new-virtual-pane",
+        scope: scope::CONTROL,
+        summary: "Open a pane fed by write-output instead of a shell.",
+        fields: &[
+            opt("title", FieldType::String, Some("null")),
+            opt("split", DIRECTION, Some("\"horizontal\"")),
+            opt("tab", FieldType::Boolean, Some("false")),
+        ],
+    },
+    CommandSpec {
+        name: "write-output",
+        scope: scope::INPUT,
+        summary: "Print to a virtual pane.",
+        fields: &[PANE_ID, req("data", FieldType::String).payload()],
+    },
+    CommandSpec {
+        name: "pipe-pane",
+        scope: scope::CONTROL,
+        summary: "Stream a pane's output to a command or a file; neither stops it.",
+        fields: &[
+            PANE_ID,
+            opt("command", FieldType::String, None).needs(scope::INPUT),
+            opt("path", FieldType::String, None).needs(scope::ADMIN),
+            opt("format", FieldType::Enum(&["raw", "text"]), Some("\"raw\"")),
+        ],
+    },
+    CommandSpec {
+        name: "hello",
+        scope: 0,
+        summary: "Pick the connection's framing, or authenticate; tells the protocol version and capabilities.",
+        fields: &[opt("protocol", FieldType::Enum(&Protocol::NAMES), None), opt("token", FieldType::String, None)],
+    },
+    CommandSpec {
+        name: "cancel",
+        scope: 0,
+        summary: "Give up on a request of this connection still in flight.",
+        fields: &[req("id", FieldType::Id)],
+    },
+    CommandSpec { name: "describe", scope: 0, summary: "This table.", fields: &[] },
+    CommandSpec {
+        name: "get-audit-log",
+        // It shows what every client sent, not just this one.
+        scope: scope::ADMIN,
+        summary: "The last entries of the IPC audit log, oldest first.",
+        fields: &[opt("limit", FieldType::Integer, Some("100")), opt("pane_id", FieldType::Integer, Some("null"))],
+    },
+    CommandSpec {
+        name: "batch",
+        // Those of its commands (see `scopes_needed`).
+        scope: 0,
+        summary: "Run several commands in one go; \"$0.pane_id\" stands for what an earlier one returned.",
+        fields: &[req("commands", FieldType::Requests), opt("atomic", FieldType::Boolean, Some("false"))],
+    },
+];
+
+/// The spec of the command named `name`.
+pub fn command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name == name)
+}
+
+impl CommandSpec {
+    /// Refuse a request missing a required field, or giving one a value of
+    /// the wrong type. `null` is an absent field. What a value means (one of
+    /// an enum's names, a pane that exists, a range) is the parser's to check,
+    /// with a message that says what it takes.
+    pub fn check(&self, request: &serde_json::Value) -> Result<(), String> {
+        for field in self.fields {
+            match request.get(field.name) {
+                None | Some(serde_json::Value::Null) if field.required => {
+                    return Err(format!("missing \"{}\" field", field.name));
+                }
+                None | Some(serde_json::Value::Null) => {}
+                Some(value) => field.ty.check(field.name, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FieldType {
+    fn check(self, name: &str, value: &serde_json::Value) -> Result<(), String> {
+        use serde_json::Value;
+        let integers = |v: &Value| v.as_array().is_some_and(|items| items.iter().all(|i| i.is_u64()));
+        let (ok, expected) = match self {
+            FieldType::Integer => (value.is_u64(), "a non-negative integer"),
+            FieldType::Number => (value.is_number(), "a number"),
+            FieldType::Boolean => (value.is_boolean(), "a boolean"),
+            FieldType::String | FieldType::Enum(_) => (value.is_string(), "a string"),
+            FieldType::IntegerList => (integers(value), "an array of pane ids"),
+            FieldType::EnumList(_) => {
+                (value.as_array().is_some_and(|items| items.iter().all(|i| i.is_string())), "an array of strings")
+            }
+            FieldType::AllOrIntegers => (value.is_string() || integers(value), "\"all\" or an array of pane ids"),
+            FieldType::Id => (value.is_string() || value.is_number(), "a string or a number"),
+            FieldType::Requests => (value.is_array(), "an array of requests"),
+        };
+        if ok { Ok(()) } else { Err(format!("\"{}\" must be {}", name, expected)) }
+    }
+
+
+    fn schema(self) -> serde_json::Value {
+        use serde_json::json;
+        match self {
+            FieldType::Integer => json!({"type": "integer"}),
+            FieldType::Number => json!({"type": "number"}),
+            FieldType::Boolean => json!({"type": "boolean"}),
+            FieldType::String => json!({"type": "string"}),
+            FieldType::Enum(values) => json!({"type": "string", "enum": values}),
+            FieldType::IntegerList => json!({"type": "array", "items": {"type": "integer"}}),
+            FieldType::EnumList(values) => json!({"type": "array", "items": {"type": "string", "enum": values}}),
+            FieldType::AllOrIntegers => json!({"oneOf": [
+                {"const": "all"},
+                {"type": "array", "items": {"type": "integer"}},
+            ]}),
+            FieldType::Id => json!({"type": ["string", "number"]}),
+            FieldType::Requests => json!({
+                "type": "array",
+                "items": {"type": "object", "required": ["cmd"]},
+            }),
+        }
+    }
+}
+
+/// The scopes `request` needs: its command's, and those of the fields it gives
+/// a value; for a batch, those of its commands. 0 for an unknown command, which
+/// is refused anyway.
+pub fn scopes_needed(request: &serde_json::Value) -> u8 {
+    let Some(spec) = request.get("cmd").and_then(|cmd| cmd.as_str()).and_then(command) else {
+        return 0;
+    };
+    if spec.name == "batch" {
+        return crate::ipc_batch::scopes_needed(request);
+    }
+    spec.fields
+        .iter()
+        .filter(|field| request.get(field.name).is_some_and(|value| !value.is_null()))
+        .fold(spec.scope, |needed, field| needed | field.scope)
+}
+
+/// What `hello` and `describe` both tell about the protocol.
+pub fn versions() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol_version": PROTOCOL_VERSION,
+        "capabilities": CAPABILITIES,
+    })
+}
+
+/// The reply to `describe`.
+pub fn describe() -> serde_json::Value {
+    let commands: Vec<serde_json::Value> = COMMANDS
+        .iter()
+        .map(|spec| {
+            let fields: Vec<serde_json::Value> = spec
+                .fields
+                .iter()
+                .map(|field| {
+                    let mut json = serde_json::json!({
+                        "name": field.name,
+                        "required": field.required,
+                        "schema": field.ty.schema(),
+                    });
+                    if let Some(default) = field.default {
+                        json["default"] = serde_json::from_str(default).expect("defaults are JSON");
+                    }
+                    if field.scope != 0 {
+                        json["scope"] = scope::names(field.scope).into();
+                    }
+                    json
+                })
+                .collect();
+            serde_json::json!({
+                "name": spec.name,
+                "summary": spec.summary,
+                "scope": scope::names(spec.scope),
+                "fields": fields,
+            })
+        })
+        .collect();
+    let mut data = versions();
+    data["commands"] = commands.into();
+    data["events"] = topic::ALL_NAMES.to_vec().into();
+    data["scopes"] = scope::ALL_NAMES.to_vec().into();
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A value of `ty` the parser takes; `n` keeps integer fields apart
+    /// (`merge-tab` refuses a tab merged into itself).
+    fn sample(ty: FieldType, n: u64) -> serde_json::Value {
+        match ty {
+            FieldType::Integer => n.into(),
+            FieldType::Number => 1.0.into(),
+            FieldType::Boolean => true.into(),
+            FieldType::String => "/tmp".into(),
+            FieldType::Enum(values) => values[0].into(),
+            FieldType::IntegerList => serde_json::json!([n]),
+            FieldType::EnumList(values) => serde_json::json!([values[0]]),
+            FieldType::AllOrIntegers => "all".into(),
+            FieldType::Id => "req-1".into(),
+            FieldType::Requests => serde_json::json!([{"cmd": "list-panes"}]),
+        }
+    }
+
+    /// `spec` with only its required fields, from `sample`.
+    fn minimal(spec: &CommandSpec) -> serde_json::Map<String, serde_json::Value> {
+        let mut request = serde_json::Map::new();
+        request.insert("cmd".into(), spec.name.into());
+        for (n, field) in spec.fields.iter().enumerate().filter(|(_, f)| f.required) {
+            request.insert(field.name.into(), sample(field.ty, n as u64 + 1));
+        }
+        request
+    }
+
+    fn parses(request: serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+        crate::ipc::parse_value(serde_json::Value::Object(request)).map(|_| ())
+    }
+
+    #[test]
+    fn every_command_parses_from_its_required_fields_and_none_less() {
+        for spec in COMMANDS {
+            assert_eq!(parses(minimal(spec)), Ok(()), "{}", spec.name);
+            for field in spec.fields.iter().filter(|f| f.required) {
+                let mut request = minimal(spec);
+                request.remove(field.name);
+                assert!(parses(request).is_err(), "{} without {}", spec.name, field.name);
+            }
+        }
+    }
+
+    #[test]
+    fn every_default_is_a_value_the_parser_takes() {
+        for spec in COMMANDS {
+            for field in spec.fields {
+                let Some(default) = field.default else { continue };
+                let mut request = minimal(spec);
+                request.insert(field.name.into(), serde_json::from_str(default).unwrap());
+                if (spec.name, field.name) == ("subscribe", "output") {
+                    // Only meaningful with the stream it shapes.
+                    request.insert("events".into(), serde_json::json!(["pane-output"]));
+                }
+                assert_eq!(parses(request), Ok(()), "{}.{} = {}", spec.name, field.name, default);
+            }
+        }
+    }
+
+    /// A value no field of `ty` takes.
+    fn wrong(ty: FieldType) -> serde_json::Value {
+        match ty {
+            FieldType::String | FieldType::Enum(_) | FieldType::EnumList(_) | FieldType::Requests => 7.into(),
+            FieldType::Id => true.into(),
+            _ => "seven".into(),
+        }
+    }
+
+    #[test]
+    fn every_field_refuses_a_value_of_the_wrong_type() {
+        for spec in COMMANDS {
+            for field in spec.fields {
+                let mut request = minimal(spec);
+                request.insert(field.name.into(), wrong(field.ty));
+                let error = parses(request).expect_err(&format!("{}.{}", spec.name, field.name));
+                assert!(error.contains(field.name), "{}.{}: {}", spec.name, field.name, error);
+                if let FieldType::Enum(_) = field.ty {
+                    let mut request = minimal(spec);
+                    request.insert(field.name.into(), "bogus".into());
+                    assert!(parses(request).is_err(), "{}.{} = \"bogus\"", spec.name, field.name);
+                }
+                if field.required {
+                    let mut request = minimal(spec);
+                    request.insert(field.name.into(), serde_json::Value::Null);
+                    assert_eq!(parses(request), Err(format!("missing \"{}\" field", field.name)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn subscribe_defaults_to_the_topics_it_subscribes_to() {
+        let events = command("subscribe").unwrap().fields[0].default.unwrap();
+        let names: Vec<String> = serde_json::from_str(events).unwrap();
+        assert_eq!(names, topic::names(topic::DEFAULT));
+    }
+
+    #[test]
+    fn command_names_are_unique() {
+        for (i, spec) in COMMANDS.iter().enumerate() {
+            assert!(COMMANDS[..i].iter().all(|other| other.name != spec.name), "{}", spec.name);
+        }
+    }
+
+    #[test]
+    fn describe_lists_the_commands_and_the_events() {
+        let data = describe();
+        assert_eq!(data["protocol_version"], PROTOCOL_VERSION);
+        let search = data["commands"].as_array().unwrap().iter().find(|c| c["name"] == "search-pane").unwrap();
+        assert_eq!(search["fields"][0], serde_json::json!({"name": "pane_id", "required": true, "schema": {"type": "integer"}}));
+        assert_eq!(search["fields"][3]["default"], "smart");
+        assert_eq!(search["fields"][3]["schema"]["enum"], serde_json::json!(["sensitive", "insensitive", "smart"]));
+        assert!(data["events"].as_array().unwrap().contains(&"pane-quiet".into()));
+    }
+}
@@ -1,3 +1,4 @@
+#[cfg(target_os = "macos")]
 use objc2_app_kit::{NSEvent, NSEventModifierFlags};
 use std::collections::HashMap;
 
@@ -5,6 +6,7 @@ use crate::pane::{NavDirection, SplitAxis};
 
 /// A hashable key combination (modifiers + key).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub struct KeyCombo {
     pub cmd: bool,
     pub ctrl: bool,
@@ -26,6 +28,7 @@ pub enum Key {
 
 /// Window/tab/split actions dispatched from performKeyEquivalent.
 #[derive(Debug, Clone)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub enum Action {
     NewTab,
     ClosePaneOrTab,
@@ -38,6 +41,7 @@ pub enum Action {
     KillWindow,
     Copy,
     CopyRaw,
+    CopyRich,
     Paste,
     ToggleFilter,
     PrevTab,
@@ -48,6 +52,7 @@ pub enum Action {
     BreakPane,
     MergeTab,
     MergeWindow,
+    ToggleRecording,
 
     SwitchTab(usize),
     Navigate(NavDirection),
@@ -72,6 +77,7 @@ pub enum Action {
 
 /// Terminal-level actions dispatched from handle_key_event.
 #[derive(Debug, Clone)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub enum TerminalAction {
     KillLine,
     Home,
@@ -81,11 +87,13 @@ pub enum TerminalAction {
     ShiftEnter,
 }
 
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub struct Keybindings {
     pub window_map: HashMap<KeyCombo, Action>,
     pub terminal_map: HashMap<KeyCombo, TerminalAction>,
 }
 
+#[cfg(target_os = "macos")]
 impl KeyCombo {
     pub fn from_event(event: &NSEvent) -> Self {
         let modifiers = event.modifierFlags();
@@ -113,6 +121,7 @@ impl KeyCombo {
 /// Map macOS virtual keycodes to Key for special keys only (arrows, enter,
 /// backspace). Character keys are resolved via charactersIgnoringModifiers
 /// to respect the active keyboard layout.
+#[cfg(target_os = "macos")]
 fn keycode_to_special(code: u16) -> Option<Key> {
     match code {
         0x24 => Some(Key::Enter),
@@ -191,6 +200,7 @@ fn parse_key_combo(s: &str) -> KeyCombo {
 use crate::config::KeysConfig;
 
 impl Keybindings {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn from_config(keys: &KeysConfig) -> Self {
         let mut window_map = HashMap::new();
         let mut terminal_map = HashMap::new();
@@ -211,6 +221,7 @@ impl Keybindings {
         bind(&keys.kill_window, Action::KillWindow);
         bind(&keys.copy, Action::Copy);
         bind(&keys.copy_raw, Action::CopyRaw);
+        bind(&keys.copy_rich, Action::CopyRich);
         bind(&keys.paste, Action::Paste);
         bind(&keys.toggle_filter, Action::ToggleFilter);
         bind(&keys.prev_tab, Action::PrevTab);
@@ -221,6 +232,7 @@ impl Keybindings {
         bind(&keys.break_pane, Action::BreakPane);
         bind(&keys.merge_tab, Action::MergeTab);
         bind(&keys.merge_window, Action::MergeWindow);
+        bind(&keys.toggle_recording, Action::ToggleRecording);
 
 
         for (i, s) in [
@@ -293,6 +305,7 @@ impl Keybindings {
 /// Resize/edge-grow deltas mirror the keyboard bindings in
 /// [`Keybindings::from_config`] so a dispatched action behaves identically to
 /// its keystroke.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn action_from_ipc_name(name: &str) -> Option<Action> {
     let action = match name {
         "new-tab" => Action::NewTab,
@@ -306,6 +319,7 @@ pub fn action_from_ipc_name(name: &str) -> Option<Action> {
         "kill-window" => Action::KillWindow,
         "copy" => Action::Copy,
         "copy-raw" => Action::CopyRaw,
+        "copy-rich" => Action::CopyRich,
         "paste" => Action::Paste,
         "toggle-filter" => Action::ToggleFilter,
         "prev-tab" => Action::PrevTab,
@@ -316,6 +330,7 @@ pub fn action_from_ipc_name(name: &str) -> Option<Action> {
         "break-pane" => Action::BreakPane,
         "merge-tab" => Action::MergeTab,
         "merge-window" => Action::MergeWindow,
+        "toggle-recording" => Action::ToggleRecording,
 
         "switch-tab-1" => Action::SwitchTab(0),
         "switch-tab-2" => Action::SwitchTab(1),
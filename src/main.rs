@@ -1,21 +1,40 @@
+// The AppKit front end. Everything else builds on any Unix, which is what
+// `kova --headless` runs on.
+#[cfg(target_os = "macos")]
 mod app;
 mod claude_session;
 mod config;
+mod ctl;
 mod events;
+mod headless;
+#[cfg(target_os = "macos")]
 mod input;
+mod instances;
 mod ipc;
+mod ipc_audit;
+mod ipc_auth;
+mod ipc_batch;
+mod ipc_host;
+mod ipc_schema;
 mod keybindings;
+#[cfg(target_os = "macos")]
 mod notification;
 mod pane;
 mod pane_history;
 mod recent_projects;
+#[cfg(target_os = "macos")]
 mod renderer;
+mod replay;
 mod session;
 mod terminal;
+#[cfg(target_os = "macos")]
 mod window;
 
+#[cfg(target_os = "macos")]
 use objc2::{AnyThread, runtime::ProtocolObject};
+#[cfg(target_os = "macos")]
 use objc2_app_kit::{NSApplication, NSApplicationActivationPolicy, NSImage};
+#[cfg(target_os = "macos")]
 use objc2_foundation::{MainThreadMarker, NSData};
 
 use log::LevelFilter;
@@ -81,9 +100,11 @@ fn install_crash_signal_handlers() {
     }
 }
 
+#[cfg(target_os = "macos")]
 static ICON_DATA: &[u8] = include_bytes!("../assets/kova.icns");
 
 /// Process RSS (Resident Set Size) in MB via mach API.
+#[cfg(target_os = "macos")]
 pub(crate) fn get_rss_mb() -> f64 {
     unsafe {
         let mut info: libc::mach_task_basic_info_data_t = std::mem::zeroed();
@@ -227,6 +248,12 @@ fn main() {
     // Informational flags must exit BEFORE any app machinery runs: launching
     // the full app here restores (and deletes) session.json and truncates the
     // PTY capture files of a concurrently running instance.
+    if args.get(1).is_some_and(|a| a == "replay") {
+        std::process::exit(replay::run(&args[2..]));
+    }
+    if args.get(1).is_some_and(|a| a == "ctl") {
+        std::process::exit(ctl::run(&args[2..]));
+    }
     if args.iter().any(|a| a == "--version" || a == "-V") {
         println!("kova {}", env!("CARGO_PKG_VERSION"));
         return;
@@ -235,10 +262,14 @@ fn main() {
         println!("kova {} — macOS terminal (Rust + Metal)", env!("CARGO_PKG_VERSION"));
         println!();
         println!("USAGE: kova [OPTIONS]");
+        println!("       kova replay <file> --cols N --rows N [OPTIONS]   (see kova replay --help)");
+        println!("       kova ctl <command> [--<field> VALUE ...]         (see kova ctl --help)");
         println!("  --version, -V      print version and exit");
         println!("  --help, -h         print this help and exit");
         println!("  --list-sessions    list session backups and exit");
         println!("  --session N        restore session.N.json instead of session.json");
+        println!("  --headless         serve the IPC socket with no window (see docs/ipc.md)");
+        println!("  --size COLSxROWS   headless screen size (default: terminal.columns x terminal.rows)");
         return;
     }
 
@@ -252,6 +283,19 @@ fn main() {
         .find(|w| w[0] == "--session")
         .and_then(|w| w[1].parse::<usize>().ok());
 
+    // Parsed before any setup so a bad flag fails fast, with nothing to undo.
+    let headless = if args.iter().any(|a| a == "--headless") {
+        match headless::Options::from_args(&args) {
+            Ok(opts) => Some(opts),
+            Err(e) => {
+                eprintln!("kova: {}", e);
+                std::process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+
     setup_logging();
     log::info!("========== Kova starting ==========");
     prune_old_captures();
@@ -288,6 +332,14 @@ fn main() {
 
     let config = config::Config::load();
 
+    if let Some(opts) = headless {
+        std::process::exit(headless::run(config, opts));
+    }
+    run_app(config, session_backup);
+}
+
+#[cfg(target_os = "macos")]
+fn run_app(config: config::Config, session_backup: Option<usize>) {
     let mtm = MainThreadMarker::new().expect("must run on main thread");
     let app = NSApplication::sharedApplication(mtm);
     app.setActivationPolicy(NSApplicationActivationPolicy::Regular);
@@ -305,6 +357,12 @@ fn main() {
     app.run();
 }
 
+#[cfg(not(target_os = "macos"))]
+fn run_app(_config: config::Config, _session_backup: Option<usize>) {
+    eprintln!("kova: the windowed app needs macOS — run `kova --headless` here");
+    std::process::exit(2);
+}
+
 #[cfg(test)]
 mod tests {
     use super::is_capture_file;
@@ -4,7 +4,6 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use crate::config::Config;
-use crate::renderer::PaneViewport;
 use crate::terminal::pty::{ProcessInfo, Pty};
 use crate::terminal::TerminalState;
 
@@ -346,6 +345,7 @@ impl PaneOpenTimer {
     /// Tree mutation done: the new pane now exists in the layout (main thread).
     /// Logged only for interactive splits (the split handlers call this);
     /// restore/initial panes never reach it, so its absence flags a restore.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mark_inserted(&self, pane_id: PaneId) {
         if self.inserted_logged.swap(true, Ordering::Relaxed) {
             return;
@@ -355,6 +355,7 @@ impl PaneOpenTimer {
 
     /// First frame the pane is submitted to the renderer = pane becomes visible
     /// (its loading overlay paints). This is the "time to rectangle".
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mark_first_paint(&self, pane_id: PaneId) {
         if self.paint_logged.swap(true, Ordering::Relaxed) {
             return;
@@ -392,6 +393,7 @@ pub enum SplitAxis {
 
 /// Info about a separator line, used for mouse hit-testing and dragging.
 #[derive(Clone, Copy)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub struct SeparatorInfo {
     /// Pixel position of the separator line (x for column sep, y for row sep).
     pub pos: f32,
@@ -422,6 +424,21 @@ pub enum NavDirection {
 
 pub type TabId = u32;
 
+/// Number of entries in the tab color palette (`renderer::TAB_COLORS`). Lives
+/// here, with `Tab::color`, so code that validates a color index does not need
+/// the renderer.
+pub const TAB_COLOR_COUNT: usize = 6;
+
+/// Sub-region of the drawable where a pane is rendered (in pixels). Headless
+/// mode lays panes out in cells instead, with the same arithmetic.
+#[derive(Clone, Copy)]
+pub struct PaneViewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
 static NEXT_PANE_ID: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
 static NEXT_TAB_ID: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(1);
 
@@ -517,11 +534,10 @@ fn grow_virtual_for_restored_column(w_col: f32, w_others: f32, old_virtual: f32,
 }
 
 impl Tab {
-    /// Create a new tab with a single pane.
-    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
-        let pane = Pane::spawn(config.terminal.columns, config.terminal.rows, config, None)?;
+    /// A tab holding `pane` alone.
+    pub fn with_pane(pane: Pane) -> Self {
         let focused = pane.id;
-        Ok(Tab {
+        Tab {
             id: alloc_tab_id(),
             columns: vec![Column::new(pane)],
             column_weights: vec![1.0],
@@ -537,54 +553,26 @@ impl Tab {
             scroll_offset_x: 0.0,
             virtual_width_override: 0.0,
             cell_h: Cell::new(0.0),
-        })
+        }
+    }
+
+    /// Create a new tab with a single pane.
+    pub fn new(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let pane = Pane::spawn(config.terminal.columns, config.terminal.rows, config, None)?;
+        Ok(Self::with_pane(pane))
     }
 
     /// Create a placeholder tab with a dummy pane (no shell process).
     /// Used for deferred tab restore to avoid shell contention at startup.
     pub fn placeholder(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
         let pane = Pane::placeholder(config.terminal.columns, config.terminal.rows, config)?;
-        let focused = pane.id;
-        Ok(Tab {
-            id: alloc_tab_id(),
-            columns: vec![Column::new(pane)],
-            column_weights: vec![1.0],
-            custom_weights: vec![false],
-            focused_pane: focused,
-            custom_title: None,
-            color: None,
-            has_bell: false,
-            has_completion: false,
-            has_running: false,
-            fg_running_cache: false,
-            minimized_stack: Vec::new(),
-            scroll_offset_x: 0.0,
-            virtual_width_override: 0.0,
-            cell_h: Cell::new(0.0),
-        })
+        Ok(Self::with_pane(pane))
     }
 
     /// Create a new tab inheriting the CWD from another pane.
     pub fn new_with_cwd(config: &Config, cwd: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
         let pane = Pane::spawn(config.terminal.columns, config.terminal.rows, config, cwd)?;
-        let focused = pane.id;
-        Ok(Tab {
-            id: alloc_tab_id(),
-            columns: vec![Column::new(pane)],
-            column_weights: vec![1.0],
-            custom_weights: vec![false],
-            focused_pane: focused,
-            custom_title: None,
-            color: None,
-            has_bell: false,
-            has_completion: false,
-            has_running: false,
-            fg_running_cache: false,
-            minimized_stack: Vec::new(),
-            scroll_offset_x: 0.0,
-            virtual_width_override: 0.0,
-            cell_h: Cell::new(0.0),
-        })
+        Ok(Self::with_pane(pane))
     }
 
     /// Compute the virtual width for this tab's split layout.
@@ -600,6 +588,7 @@ impl Tab {
     }
 
     /// Scale virtual_width_override proportionally when column count changes (e.g. pane close).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scale_virtual_width(&mut self, old_columns: usize, new_columns: usize) {
         if self.virtual_width_override > 0.0 && old_columns > 0 {
             self.virtual_width_override *= new_columns as f32 / old_columns as f32;
@@ -607,6 +596,7 @@ impl Tab {
     }
 
     /// Clamp scroll_offset_x after a tree change.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clamp_scroll(&mut self, screen_width: f32, min_split_width: f32) {
         let vw = self.virtual_width(screen_width, min_split_width);
         let max_scroll = (vw - screen_width).max(0.0);
@@ -615,6 +605,7 @@ impl Tab {
 
     /// Adjust scroll_offset_x so that the given pane viewport is fully visible.
     /// `pane_vp` is in virtual-space coordinates (from panes_viewport_for_tab).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scroll_to_reveal(&mut self, pane_vp: &PaneViewport, screen_width: f32) {
         let pane_left = pane_vp.x + self.scroll_offset_x;
         let pane_right = pane_left + pane_vp.width;
@@ -656,6 +647,7 @@ impl Tab {
     }
 
     /// Clear the bell/attention flag (call when switching to this tab).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clear_bell(&mut self) {
         self.has_bell = false;
     }
@@ -731,6 +723,7 @@ impl Tab {
     }
 
     /// Minimize the pane with given id. Refuses if it's the last non-minimized pane.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn minimize_pane(&mut self, id: PaneId) -> bool {
         // Count non-minimized panes
         let mut non_minimized = 0;
@@ -773,6 +766,7 @@ impl Tab {
     }
 
     /// Restore the last minimized pane (FILO), adjusting the virtual space.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn restore_last_minimized(&mut self, screen_width: f32, min_split_width: f32) -> bool {
         if let Some(id) = self.minimized_stack.last().copied() {
             self.restore_pane_adjust_virtual(id, screen_width, min_split_width);
@@ -787,6 +781,7 @@ impl Tab {
     /// gives its pixel width back to the virtual space, so the remaining
     /// panes keep their exact sizes — never shrinking below the screen width.
     /// When not scrolling, the visible panes simply reshare the screen.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn minimize_pane_adjust_virtual(&mut self, id: PaneId, screen_width: f32, min_split_width: f32) -> bool {
         let old_vw = self.virtual_width(screen_width, min_split_width);
         let col_px = self
@@ -885,6 +880,7 @@ impl Tab {
     /// every pane of the tab becomes visible at once, so all of them are "seen".
     /// Only the attention state is acked — `command_completed` itself stays set
     /// for IPC `wait-for-completion`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clear_completion(&mut self) {
         self.has_completion = false;
         self.for_each_pane(&mut |pane| {
@@ -927,6 +923,7 @@ impl Tab {
     }
 
     /// Return the last (rightmost/bottommost) pane.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn last_pane(&self) -> &Pane {
         self.columns.last().unwrap().last_pane()
     }
@@ -939,6 +936,7 @@ impl Tab {
     }
 
     /// Mark all panes as dirty (needs redraw).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mark_all_dirty(&self) {
         self.for_each_pane(&mut |p| {
             p.terminal.read().dirty.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -984,6 +982,7 @@ impl Tab {
     }
 
     /// 1-based index of the pane's column among visible columns (for status bar).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn visible_column_index(&self, id: PaneId) -> Option<usize> {
         let idx = self.column_index_of(id)?;
         Some(
@@ -996,6 +995,7 @@ impl Tab {
     }
 
     /// Count minimized panes across all columns.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn count_minimized(&self) -> usize {
         let mut n = 0;
         self.for_each_pane(&mut |p| if p.minimized { n += 1 });
@@ -1030,6 +1030,7 @@ impl Tab {
     }
 
     /// Hit-test: find which pane contains the pixel coordinate (x, y).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn hit_test(&self, x: f32, y: f32, vp: PaneViewport) -> Option<(&Pane, PaneViewport)> {
         let widths = self.column_widths(vp.width);
         let ch = self.cell_h.get();
@@ -1051,6 +1052,7 @@ impl Tab {
     /// Collect separator lines between splits as (x1, y1, x2, y2) segments.
     /// Fully-minimized columns are zero-width: no separator is drawn for them
     /// (a visible column draws one only when a visible column precedes it).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn collect_separators(&self, vp: PaneViewport, out: &mut Vec<(f32, f32, f32, f32)>) {
         let widths = self.column_widths(vp.width);
         let minimized = self.minimized_columns();
@@ -1077,6 +1079,7 @@ impl Tab {
     /// looks draggable without being so: `column_sep_index` is the visible
     /// column on the left of the separator, and its partner is the next visible
     /// one (a minimized column in between changes nothing).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn collect_separator_info(&self, vp: PaneViewport, out: &mut Vec<SeparatorInfo>) {
         let widths = self.column_widths(vp.width);
         let minimized = self.minimized_columns();
@@ -1192,6 +1195,7 @@ impl Tab {
 
     /// Append a new column at the end.
     /// Returns the new pane's id.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn append_column(&mut self, new_pane: Pane) -> PaneId {
         let new_id = new_pane.id;
         let avg_weight = new_entry_weight(&self.column_weights, &self.minimized_columns());
@@ -1210,6 +1214,7 @@ impl Tab {
 
     /// Split at the bottom of the column containing the focused pane.
     /// Appends the new pane at the bottom of the column.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn vsplit_root_at_column(&mut self, new_pane: Pane) {
         let focused_id = self.focused_pane;
         if let Some(idx) = self.column_index_of(focused_id) {
@@ -1252,6 +1257,7 @@ impl Tab {
     }
 
     /// Extract a pane by id, returning it separately. The tab retains the remainder.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn extract_pane(&mut self, id: PaneId) -> Option<Pane> {
         let col_idx = self.column_index_of(id)?;
 
@@ -1298,7 +1304,33 @@ impl Tab {
         }
     }
 
+    /// Grow (`grow`) or shrink the split holding `id` along `axis` by
+    /// `amount_pct` percent. False when there is no neighbor on that axis to
+    /// resize against, or nothing moved.
+    pub fn resize_split(&mut self, id: PaneId, axis: SplitAxis, grow: bool, amount_pct: f32) -> bool {
+        // Translate grow/shrink into the signed delta the internal API expects.
+        // For non-last position, delta>0 = grow. For last position, delta<0 = grow.
+        // (Mirrors adjust_column_weight_directional / adjust_row_weight_directional.)
+        let is_last = match axis {
+            SplitAxis::Horizontal => {
+                if self.columns.len() < 2 { return false; }
+                self.column_index_of(id).is_some_and(|i| i == self.columns.len() - 1)
+            }
+            SplitAxis::Vertical => {
+                let Some(col_idx) = self.column_index_of(id) else { return false };
+                let col = &self.columns[col_idx];
+                if col.panes.len() < 2 { return false; }
+                col.panes.iter().position(|p| p.id == id)
+                    .is_some_and(|i| i == col.panes.len() - 1)
+            }
+        };
+        let mag = amount_pct / 100.0;
+        let delta = if grow != is_last { mag } else { -mag };
+        self.adjust_ratio_directional(id, delta, axis)
+    }
+
     /// Fallback: adjust nearest separator. Directional already handles all cases for flat columns.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn adjust_ratio_nearest(&mut self, id: PaneId, _delta: f32, axis: SplitAxis) -> bool {
         match axis {
             SplitAxis::Horizontal => false,
@@ -1330,17 +1362,20 @@ impl Tab {
     }
 
     /// Returns the maximum leaf width as a fraction of total width (0.0–1.0).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn max_leaf_width_fraction(&self) -> f32 {
         max_visible_fraction(&self.column_weights, &self.minimized_columns())
     }
 
     /// Post-validation: adjust weights so no leaf exceeds `max_w` pixels.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clamp_pane_widths(&mut self, total: f32, max_w: f32) {
         let minimized = self.minimized_columns();
         clamp_weights_to_max(&mut self.column_weights, &minimized, total, max_w);
     }
 
     /// Scale ratios so that only `target_id` absorbs the size change (edge grow).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scale_ratios_for_edge_grow(&mut self, target_id: PaneId, old_total: f32, new_total: f32) {
         let col_idx = match self.column_index_of(target_id) {
             Some(i) => i,
@@ -1360,6 +1395,7 @@ impl Tab {
     /// pixel units here (column_widths normalizes by their sum), so after this
     /// the new override equals the sum of the desired pixel widths and the layout
     /// reproduces them exactly.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn grow_virtual_for_scrolled_split(
         &mut self,
         new_col_idx: usize,
@@ -1384,6 +1420,7 @@ impl Tab {
     /// delta directly and becomes pinned. The freed/consumed space is redistributed among all
     /// non-pinned columns on the opposite side. If all opposite columns are pinned, only the
     /// adjacent one absorbs (fallback).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn set_column_weights_by_drag(&mut self, col_idx: usize, delta_px: f32, total_width: f32) {
         let minimized = self.minimized_columns();
         apply_separator_drag(
@@ -1421,7 +1458,59 @@ impl Tab {
         }
     }
 
+    /// Swap two panes of this tab wherever they sit: within their column when
+    /// they share one, whole columns otherwise. Both are marked dirty so they
+    /// redraw in their new positions.
+    pub fn swap_panes_auto(&mut self, id1: PaneId, id2: PaneId) -> bool {
+        // Pick a synthetic direction based on layout: same column → Up (in-column),
+        // different columns → Right (swap whole columns). This reuses the existing
+        // direction-aware logic without forcing the caller to know layout details.
+        let dir = match (self.column_index_of(id1), self.column_index_of(id2)) {
+            (Some(a), Some(b)) if a == b => NavDirection::Up,
+            (Some(_), Some(_)) => NavDirection::Right,
+            _ => return false,
+        };
+        if !self.swap_panes(id1, id2, dir) {
+            return false;
+        }
+        for id in [id1, id2] {
+            if let Some(p) = self.pane(id) {
+                p.terminal.read().dirty.store(true, Ordering::Relaxed);
+            }
+        }
+        true
+    }
+
+    /// Append every column of `source` to this tab, its weights rescaled to
+    /// this tab's average, and focus the leftmost pane that came with it.
+    pub fn merge_from(&mut self, source: Tab) {
+        // The leftmost pane in the source becomes the new focus
+        let new_focus = source.columns.first()
+            .and_then(|col| col.panes.first())
+            .map(|p| p.id)
+            .unwrap_or(source.focused_pane);
+
+        // Append source columns, normalizing weights
+        let target_avg: f32 = self.column_weights.iter().sum::<f32>()
+            / self.columns.len() as f32;
+        let source_avg: f32 = source.column_weights.iter().sum::<f32>()
+            / source.columns.len().max(1) as f32;
+        let scale = if source_avg > 0.0 { target_avg / source_avg } else { 1.0 };
+        for (i, (col, weight)) in source.columns.into_iter().zip(source.column_weights).enumerate() {
+            self.columns.push(col);
+            self.column_weights.push(weight * scale);
+            self.custom_weights.push(
+                source.custom_weights.get(i).copied().unwrap_or(false)
+            );
+        }
+
+        // Merge minimized stacks
+        self.minimized_stack.extend(source.minimized_stack);
+        self.focused_pane = new_focus;
+    }
+
     /// Reparent pane: move to adjacent column (Left/Right) or swap within column (Up/Down).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn reparent_pane(&mut self, focused_id: PaneId, dir: NavDirection) -> bool {
         match dir {
             NavDirection::Left | NavDirection::Right => {
@@ -1467,6 +1556,7 @@ impl Tab {
     }
 
     /// Equalize: reset all column weights to 1.0 and all VSplit ratios proportionally (by leaf count).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn equalize(&mut self) {
         for w in &mut self.column_weights {
             *w = 1.0;
@@ -1528,6 +1618,7 @@ pub struct Pane {
     pub pty: Pty,
     pub shell_exited: Arc<AtomicBool>,
     pub shell_ready: Arc<AtomicBool>,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub scroll_accumulator: Cell<f64>,
     /// Command to inject into PTY once shell is ready (for session restore).
     pub pending_command: Cell<Option<String>>,
@@ -1538,6 +1629,7 @@ pub struct Pane {
     /// Whether this pane is minimized (collapsed to a thin bar).
     pub minimized: bool,
     /// Open-latency instrumentation (time-to-rectangle / time-to-prompt).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub open_timer: Arc<PaneOpenTimer>,
     /// The app in this pane told us it is waiting for the user (Claude Code
     /// pushes this from its `Stop` / permission-prompt hooks over IPC), plus
@@ -1561,6 +1653,10 @@ pub struct Pane {
     /// every frame while resolving it costs two syscalls: it is refreshed on the
     /// same ~0.5s throttle as the running-state probe, in `Tab::check_running`.
     fg_process: RefCell<Option<ProcessInfo>>,
+    /// `TerminalState::content_generation` and line limit of the last
+    /// scrollback snapshot the session save took, so an unchanged pane is
+    /// not serialized and written again on every autosave.
+    pub saved_generation: Cell<Option<((u64, u64), usize)>>,
 }
 
 /// Resolve the label to show for a pane, in priority order: user-set custom
@@ -1666,19 +1762,27 @@ fn derive_display_title(
     fallback.to_string()
 }
 
+/// Fresh terminal state for pane `id`, with the scrollback limits from the config.
+fn new_terminal_state(id: PaneId, cols: u16, rows: u16, config: &Config) -> TerminalState {
+    let mut state = TerminalState::new(
+        cols,
+        rows,
+        config.terminal.scrollback,
+        crate::terminal::color_to_u8(config.colors.foreground),
+        crate::terminal::color_to_u8(config.colors.background),
+    );
+    state.set_scrollback_storage(config.terminal.scrollback_memory_mb, config.terminal.scrollback_disk_mb);
+    state.line_tap = Some(crate::terminal::output::LineTap::new(id));
+    state
+}
+
 impl Pane {
     pub fn spawn(cols: u16, rows: u16, config: &Config, working_dir: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
         // Reference instant for open-latency instrumentation — captured first so
         // it covers the whole spawn (TerminalState alloc + fork/exec + dups).
         let open_timer = Arc::new(PaneOpenTimer::new());
         let id = alloc_pane_id();
-        let terminal = Arc::new(RwLock::new(TerminalState::new(
-            cols,
-            rows,
-            config.terminal.scrollback,
-            crate::terminal::color_to_u8(config.colors.foreground),
-            crate::terminal::color_to_u8(config.colors.background),
-        )));
+        let terminal = Arc::new(RwLock::new(new_terminal_state(id, cols, rows, config)));
         let shell_exited = Arc::new(AtomicBool::new(false));
         let shell_ready = Arc::new(AtomicBool::new(false));
         let pty = Pty::spawn(
@@ -1706,6 +1810,7 @@ impl Pane {
             claude_session: RefCell::new(None),
             idle_claude_seen: Cell::new(false),
             fg_process: RefCell::new(None),
+            saved_generation: Cell::new(None),
         })
     }
 
@@ -1714,14 +1819,8 @@ impl Pane {
     /// compete with the active tab's shells for zshrc loading time.
     pub fn placeholder(cols: u16, rows: u16, config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
         let id = alloc_pane_id();
-        let terminal = Arc::new(RwLock::new(TerminalState::new(
-            cols,
-            rows,
-            config.terminal.scrollback,
-            crate::terminal::color_to_u8(config.colors.foreground),
-            crate::terminal::color_to_u8(config.colors.background),
-        )));
-        let pty = Pty::dummy()?;
+        let terminal = Arc::new(RwLock::new(new_terminal_state(id, cols, rows, config)));
+        let pty = Pty::dummy();
         Ok(Pane {
             id,
             terminal,
@@ -1737,13 +1836,139 @@ impl Pane {
             claude_session: RefCell::new(None),
             idle_claude_seen: Cell::new(false),
             fg_process: RefCell::new(None),
+            saved_generation: Cell::new(None),
+        })
+    }
+
+    /// Create a virtual pane: no process, its output written over IPC
+    /// (`write-output`) and its input published as `pane-input` events.
+    pub fn virtual_pane(cols: u16, rows: u16, title: Option<String>, config: &Config) -> Self {
+        let id = alloc_pane_id();
+        let terminal = Arc::new(RwLock::new(new_terminal_state(id, cols, rows, config)));
+        let pty = Pty::virtual_pane(id, terminal.clone());
+        Pane {
+            id,
+            terminal,
+            pty,
+            shell_exited: Arc::new(AtomicBool::new(false)),
+            shell_ready: Arc::new(AtomicBool::new(true)),
+            scroll_accumulator: Cell::new(0.0),
+            pending_command: Cell::new(None),
+            custom_title: title,
+            minimized: false,
+            open_timer: Arc::new(PaneOpenTimer::new()),
+            awaiting: Cell::new(AwaitingFlag::default()),
+            claude_session: RefCell::new(None),
+            idle_claude_seen: Cell::new(false),
+            fg_process: RefCell::new(None),
+            saved_generation: Cell::new(None),
+        }
+    }
+
+    /// A pane over an in-memory PTY, for tests: the handle plays the program
+    /// (see `terminal::loopback`).
+    #[cfg(test)]
+    pub fn loopback(cols: u16, rows: u16, config: &Config) -> (Self, crate::terminal::loopback::LoopbackHandle) {
+        let id = alloc_pane_id();
+        let terminal = Arc::new(RwLock::new(new_terminal_state(id, cols, rows, config)));
+        let shell_exited = Arc::new(AtomicBool::new(false));
+        let shell_ready = Arc::new(AtomicBool::new(false));
+        let (pty, program) = Pty::loopback(id, cols, rows, terminal.clone(), shell_ready.clone(), shell_exited.clone());
+        let pane = Pane {
+            id,
+            terminal,
+            pty,
+            shell_exited,
+            shell_ready,
+            scroll_accumulator: Cell::new(0.0),
+            pending_command: Cell::new(None),
+            custom_title: None,
+            minimized: false,
+            open_timer: Arc::new(PaneOpenTimer::new()),
+            awaiting: Cell::new(AwaitingFlag::default()),
+            claude_session: RefCell::new(None),
+            idle_claude_seen: Cell::new(false),
+            fg_process: RefCell::new(None),
+            saved_generation: Cell::new(None),
+        };
+        (pane, program)
+    }
+
+    /// Create a read-only pane that replays an asciicast recording at the
+    /// recorded size. Keys typed into it control the player (see
+    /// `PlaybackControl::handle_input`).
+    pub fn playback(
+        cast: crate::terminal::recording::Cast,
+        title: String,
+        speed: f32,
+        config: &Config,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let id = alloc_pane_id();
+        let terminal = Arc::new(RwLock::new(new_terminal_state(id, cast.cols, cast.rows, config)));
+        let pty = Pty::playback(id, cast, terminal.clone(), speed)?;
+        Ok(Pane {
+            id,
+            terminal,
+            pty,
+            shell_exited: Arc::new(AtomicBool::new(false)),
+            shell_ready: Arc::new(AtomicBool::new(true)),
+            scroll_accumulator: Cell::new(0.0),
+            pending_command: Cell::new(None),
+            custom_title: Some(title),
+            minimized: false,
+            open_timer: Arc::new(PaneOpenTimer::new()),
+            awaiting: Cell::new(AwaitingFlag::default()),
+            claude_session: RefCell::new(None),
+            idle_claude_seen: Cell::new(false),
+            fg_process: RefCell::new(None),
+            saved_generation: Cell::new(None),
         })
     }
 
+    /// Playback panes keep the geometry of their recording: the layout does
+    /// not resize them, only the recording's own resize events do.
+    pub fn is_playback(&self) -> bool {
+        self.pty.playback_control().is_some()
+    }
+
+    /// Start piping this pane's output (`pipe-pane`). Returns the pipe's state.
+    pub fn start_pipe(
+        &self,
+        target: crate::terminal::pipe::PipeTarget,
+        format: crate::terminal::pipe::PipeFormat,
+    ) -> Result<serde_json::Value, String> {
+        use crate::terminal::pipe::{PaneSink, PipeFormat};
+        let slot = self.pty.pipe_slot();
+        let mut current = slot.lock();
+        if let Some(sink) = current.as_ref() {
+            return Err(format!("pane {} is already piped: {}", self.id, sink.json()));
+        }
+        let sink = PaneSink::start(target, format, self.id)?;
+        let state = sink.json();
+        *current = Some(sink);
+        drop(current);
+        // A text pipe is fed from the terminal, as rows scroll off.
+        self.terminal.write().pipe = (format == PipeFormat::Text).then(|| slot.clone());
+        Ok(state)
+    }
+
+    /// Stop piping. Returns the final state of the pipe, `None` if there was none.
+    pub fn stop_pipe(&self) -> Option<serde_json::Value> {
+        self.terminal.write().pipe = None;
+        let sink = self.pty.pipe_slot().lock().take()?;
+        Some(sink.json())
+    }
+
+    /// Virtual panes show what an IPC client writes; nothing runs in them.
+    pub fn is_virtual(&self) -> bool {
+        self.pty.is_virtual()
+    }
+
     pub fn cwd(&self) -> Option<String> {
         self.pty.cwd()
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn foreground_process_name(&self) -> Option<String> {
         self.pty.foreground_process_name()
     }
@@ -1765,6 +1990,7 @@ impl Pane {
     /// Cached name and version of the foreground binary, `None` at a bare shell
     /// prompt. Up to ~0.5s stale (see `Tab::check_running`), which is what makes
     /// it free to read on every frame.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn fg_process(&self) -> Option<ProcessInfo> {
         self.fg_process.borrow().clone()
     }
@@ -1874,6 +2100,7 @@ impl Pane {
 
     /// Record that the pane has been looked at while waiting (called by the
     /// frame loop on the focused pane of the key window).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mark_awaiting_seen(&self) {
         self.awaiting.set(self.awaiting.get().read());
     }
@@ -1888,6 +2115,7 @@ impl Pane {
     /// A Claude Code session lives in this pane, working or not. What Cmd+J's
     /// non-draining loop walks: an open session is an open loop whether it is
     /// chewing or waiting to be closed.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn has_claude_session(&self) -> bool {
         self.claude_session.borrow().is_some()
     }
@@ -1895,6 +2123,7 @@ impl Pane {
     /// The mirror of `is_idle_claude`: a Claude session that is actively
     /// working. Never a landing spot for the draining tiers — the loop walks it,
     /// but it is never announced as something asking for an answer.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn is_working_claude(&self) -> bool {
         self.claude_session.borrow().is_some() && self.is_working()
     }
@@ -1914,12 +2143,14 @@ impl Pane {
     /// re-armed in `Tab::check_running` as soon as the session works again or
     /// goes away, so a walk that covered everything hands straight over to the
     /// non-draining loop.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn is_idle_claude_unseen(&self) -> bool {
         !self.idle_claude_seen.get() && self.is_idle_claude()
     }
 
     /// Record that the idle Claude session here has been looked at (called by the
     /// frame loop on the focused pane, alongside `mark_awaiting_seen`).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mark_idle_claude_seen(&self) {
         self.idle_claude_seen.set(true);
     }
@@ -1929,6 +2160,68 @@ impl Pane {
         self.idle_claude_seen.set(false);
     }
 
+    /// `get-pane-content` entry for this pane: geometry, cursor, and the
+    /// requested part of the buffer in `format`.
+    pub fn content_json(
+        &self,
+        mode: crate::terminal::DumpMode,
+        trim: bool,
+        format: crate::terminal::export::ExportFormat,
+    ) -> serde_json::Value {
+        let term = self.terminal.read();
+        let mut entry = serde_json::json!({
+            "id": self.id,
+            "cols": term.cols,
+            "rows": term.rows,
+            "cursor": { "row": term.cursor_y, "col": term.cursor_x },
+        });
+        entry[format.name()] = term.export(mode, trim, format);
+        entry
+    }
+
+    /// Turn the op inspector on or off. Returns the resulting inspector state,
+    /// as `inspect-pane` reports it.
+    pub fn set_inspecting(&self, on: bool, capacity: Option<usize>) -> serde_json::Value {
+        use crate::terminal::inspect::{OpRing, DEFAULT_CAPACITY};
+        let mut term = self.terminal.write();
+        match (on, term.op_ring.as_mut()) {
+            (true, Some(ring)) => {
+                if let Some(capacity) = capacity {
+                    ring.set_capacity(capacity);
+                }
+            }
+            (true, None) => term.op_ring = Some(OpRing::new(capacity.unwrap_or(DEFAULT_CAPACITY))),
+            // Off frees the ring: inspection is a debugging session, not a mode.
+            (false, _) => term.op_ring = None,
+        }
+        serde_json::json!({
+            "pane_id": self.id,
+            "inspecting": term.op_ring.is_some(),
+            "capacity": term.op_ring.as_ref().map(|r| r.capacity()),
+        })
+    }
+
+    /// Recent ops and unhandled-sequence counters, as `get-pane-ops` reports
+    /// them. `clear` empties both once read.
+    pub fn ops_json(&self, limit: Option<usize>, clear: bool) -> serde_json::Value {
+        let mut term = self.terminal.write();
+        let data = serde_json::json!({
+            "pane_id": self.id,
+            "inspecting": term.op_ring.is_some(),
+            "capacity": term.op_ring.as_ref().map(|r| r.capacity()),
+            "evicted": term.op_ring.as_ref().map_or(0, |r| r.evicted()),
+            "ops": term.op_ring.as_ref().map_or(serde_json::json!([]), |r| r.to_json(limit)),
+            "unhandled": term.unhandled.to_json(),
+        });
+        if clear {
+            if let Some(ring) = term.op_ring.as_mut() {
+                ring.clear();
+            }
+            term.unhandled.clear();
+        }
+        data
+    }
+
     /// If the shell is ready and there's a pending command, write it to the PTY
     /// (without \r so the user can review before pressing Enter).
     pub fn inject_pending_command(&self) {
@@ -1942,6 +2235,103 @@ impl Pane {
     }
 }
 
+/// The JSON shape of one pane, shared by `list-panes`, the `subscribe` snapshot
+/// and the event payloads — a client parses the same object everywhere.
+///
+/// Costs a `proc_pidinfo` (the CWD) and a process-table walk (the children), so
+/// callers on a polling path must build it only for panes they actually report.
+pub fn pane_json(
+    pane: &Pane,
+    win_idx: usize,
+    tab_idx: usize,
+    focused: bool,
+) -> serde_json::Value {
+    let pid = pane.pty.pid();
+    let children = pane.pty.child_processes();
+    let is_idle = children.is_empty();
+    let child_json: Vec<serde_json::Value> = children
+        .into_iter()
+        .map(|(cpid, info)| {
+            serde_json::json!({
+                "pid": cpid,
+                "name": info.name,
+                "version": info.version,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "id": pane.id,
+        "window": win_idx,
+        "tab": tab_idx,
+        "cwd": pane.cwd().unwrap_or_default(),
+        "title": pane.display_title("shell"),
+        "focused": focused,
+        "pid": pid,
+        "child_processes": child_json,
+        "is_idle": is_idle,
+        "working": pane.is_working(),
+        "awaiting": pane.is_awaiting(),
+        "awaiting_since": pane.awaiting_since(),
+        // Whether the waiting pane has been looked at here since it started waiting. This is
+        // the halt Cmd+J obeys and the status bar ignores: the `?` marker stays up until the
+        // question is answered, while a pane that has been read stops pulling the jump back to
+        // itself. A remote client walking waiting panes needs the same halt, or it hands back
+        // panes already read on this Mac. False on a pane that is not waiting at all, where the
+        // bit means nothing.
+        "awaiting_seen": pane.is_awaiting() && !pane.is_awaiting_unseen(),
+        "minimized": pane.minimized,
+        "scrollback_lost_lines": pane.terminal.read().budget_lines_dropped(),
+        "last_output_ms": pane.terminal.read().last_output_ms(),
+        "claude_session_id": pane.claude_session_id(),
+        "claude_session_name": pane.claude_session_name(),
+        "recording": pane.pty.recording_path().map(|p| p.display().to_string()),
+        "playback": pane.pty.playback_control().map(|c| playback_json(c)),
+        "virtual": pane.is_virtual(),
+        "pipe": pane.pty.pipe_slot().lock().as_ref().map(|sink| sink.json()),
+    })
+}
+
+/// Player state of a playback pane, as `list-panes` and `set-playback` report it.
+pub fn playback_json(control: &crate::terminal::recording::PlaybackControl) -> serde_json::Value {
+    serde_json::json!({
+        "speed": control.speed(),
+        "paused": control.is_paused(),
+        "finished": control.is_finished(),
+    })
+}
+
+/// Start recording `pane` to `path`, or to a fresh file in the configured
+/// recording directory. `record_input` overrides `recording.record_input`.
+pub fn start_pane_recording(
+    pane: &Pane,
+    recording: &crate::config::RecordingConfig,
+    path: Option<std::path::PathBuf>,
+    record_input: Option<bool>,
+) -> Result<std::path::PathBuf, String> {
+    let path = path.unwrap_or_else(|| {
+        let millis = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        // A stop and restart within the same millisecond (or a clock step
+        // back) must not truncate the recording just written.
+        let dir = recording.dir_path();
+        let mut path = dir.join(format!("kova-{}-{}.cast", pane.id, millis));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = dir.join(format!("kova-{}-{}-{}.cast", pane.id, millis, n));
+        }
+        path
+    });
+    let (cols, rows) = {
+        let term = pane.terminal.read();
+        (term.cols, term.rows)
+    };
+    pane.pty.start_recording(&path, cols, rows, record_input.unwrap_or(recording.record_input))?;
+    Ok(path)
+}
+
 // (split_sizes removed — replaced by Column::row_heights)
 
 /// A column: flat list of panes stacked vertically with proportional weights.
@@ -1974,6 +2364,7 @@ impl Column {
         self.panes.first().unwrap()
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn last_pane(&self) -> &Pane {
         self.panes.last().unwrap()
     }
@@ -2047,6 +2438,7 @@ impl Column {
         None
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn hit_test(&self, x: f32, y: f32, vp: PaneViewport, cell_h: f32) -> Option<(&Pane, PaneViewport)> {
         if x < vp.x || x >= vp.x + vp.width || y < vp.y || y >= vp.y + vp.height {
             return None;
@@ -2072,6 +2464,7 @@ impl Column {
             })
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn collect_separators(&self, vp: PaneViewport, cell_h: f32, out: &mut Vec<(f32, f32, f32, f32)>) {
         let heights = self.row_heights(vp.height, cell_h);
         // Minimized panes are zero-height: one separator per boundary between
@@ -2082,6 +2475,7 @@ impl Column {
         }
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn collect_separator_info(&self, col_index: usize, vp: PaneViewport, cell_h: f32, out: &mut Vec<SeparatorInfo>) {
         let heights = self.row_heights(vp.height, cell_h);
         for (top, _) in adjacent_visible_pairs(&self.minimized_rows()) {
@@ -2136,6 +2530,7 @@ impl Column {
     }
 
     /// Extract a pane by id, returning it. Returns None if not found or sole pane.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn extract_pane(&mut self, id: PaneId) -> Option<Pane> {
         let idx = self.pane_index_of(id)?;
         if self.panes.len() < 2 { return None; }
@@ -2150,6 +2545,7 @@ impl Column {
         Some(pane)
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn equalize(&mut self) {
         for w in &mut self.row_weights { *w = 1.0; }
         for cw in &mut self.custom_row_weights { *cw = false; }
@@ -2175,6 +2571,7 @@ impl Column {
     /// Set row weights by dragging a row separator.
     /// `row_idx` is the index of the visible pane above the separator (the pane
     /// below is the next visible one, skipping minimized ones).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn set_row_weights_by_drag(&mut self, row_idx: usize, delta_px: f32, total_height: f32) {
         let minimized = self.minimized_rows();
         apply_separator_drag(
@@ -2199,6 +2596,7 @@ impl Column {
     }
 
     /// Reparent pane within column (Up/Down swap with neighbor).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn reparent_pane(&mut self, focused_id: PaneId, dir: NavDirection) -> bool {
         let idx = match self.pane_index_of(focused_id) { Some(i) => i, None => return false };
         match dir {
@@ -14,6 +14,7 @@ const MAX_ENTRIES: usize = 128;
 
 /// What the rest of the app says about a pane the trail refers to.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub enum PaneState {
     /// Visible and focusable — a valid landing spot.
     Focusable,
@@ -35,11 +36,13 @@ pub struct PaneHistory {
 }
 
 impl PaneHistory {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub const fn new() -> Self {
         PaneHistory { entries: Vec::new(), pos: 0 }
     }
 
     /// The pane the cursor sits on, if any.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn current(&self) -> Option<PaneId> {
         self.entries.get(self.pos).copied()
     }
@@ -59,6 +62,7 @@ impl PaneHistory {
     /// instead of adding a copy. Without that, going back and forth between two
     /// panes would fill the trail with `A B A B` and walking back would bounce
     /// between the same two instead of reaching what came before them.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn record(&mut self, id: PaneId, state: &dyn Fn(PaneId) -> PaneState) {
         if self.current() == Some(id) {
             return;
@@ -100,12 +104,14 @@ impl PaneHistory {
 
     /// Move the cursor one step toward the older end and return the pane to
     /// focus. `None` when the trail is exhausted.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn back(&mut self, state: &dyn Fn(PaneId) -> PaneState) -> Option<PaneId> {
         self.step(false, state)
     }
 
     /// Move the cursor one step toward the newer end and return the pane to
     /// focus. `None` when there is nothing to redo.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn forward(&mut self, state: &dyn Fn(PaneId) -> PaneState) -> Option<PaneId> {
         self.step(true, state)
     }
@@ -115,6 +121,7 @@ impl PaneHistory {
     /// Closed panes and duplicates of the pane already focused are dropped on
     /// the way — the trail self-heals. Minimized panes are stepped over but
     /// kept, since restoring one puts it back in reach.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn step(&mut self, forward: bool, state: &dyn Fn(PaneId) -> PaneState) -> Option<PaneId> {
         let current = self.current();
         // Cursor for the walk. `self.pos` only moves once we commit to a
@@ -165,6 +172,7 @@ impl PaneHistory {
 static HISTORY: Mutex<PaneHistory> = Mutex::new(PaneHistory::new());
 
 /// Record a visit in the global trail.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn record(id: PaneId, state: &dyn Fn(PaneId) -> PaneState) {
     if let Ok(mut h) = HISTORY.lock() {
         h.record(id, state);
@@ -172,11 +180,13 @@ pub fn record(id: PaneId, state: &dyn Fn(PaneId) -> PaneState) {
 }
 
 /// Step back in the global trail; returns the pane to focus.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn back(state: &dyn Fn(PaneId) -> PaneState) -> Option<PaneId> {
     HISTORY.lock().ok().and_then(|mut h| h.back(state))
 }
 
 /// Step forward in the global trail; returns the pane to focus.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn forward(state: &dyn Fn(PaneId) -> PaneState) -> Option<PaneId> {
     HISTORY.lock().ok().and_then(|mut h| h.forward(state))
 }
@@ -8,6 +8,7 @@ use crate::session::SavedTab;
 const MAX_RECENT_PROJECTS: usize = 50;
 
 #[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub struct RecentProject {
     pub path: String,
     pub last_opened: u64, // seconds since UNIX epoch
@@ -24,6 +25,7 @@ fn recent_projects_path() -> PathBuf {
     PathBuf::from(home).join(".config/kova/recent_projects.json")
 }
 
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn load() -> RecentProjects {
     let path = recent_projects_path();
     let data = match std::fs::read_to_string(&path) {
@@ -44,6 +46,7 @@ pub fn load() -> RecentProjects {
     }
 }
 
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 fn save(projects: &RecentProjects) {
     let path = recent_projects_path();
     if let Some(parent) = path.parent() {
@@ -61,11 +64,13 @@ fn save(projects: &RecentProjects) {
 
 /// Add a tab snapshot to recent projects, keyed by its primary CWD.
 /// Replaces any existing entry with the same path.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn add(tab: &crate::pane::Tab) {
     add_batch(std::slice::from_ref(tab));
 }
 
 /// Add multiple tabs at once (single load/save cycle).
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn add_batch(tabs: &[crate::pane::Tab]) {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -105,6 +110,7 @@ pub fn add_batch(tabs: &[crate::pane::Tab]) {
 }
 
 /// Remove a recent project by path.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn remove(path: &str) {
     let mut projects = load();
     projects.projects.retain(|p| p.path != path);
@@ -135,6 +141,7 @@ fn primary_cwd(tab: &crate::pane::Tab) -> String {
 }
 
 /// Tildify a path for display: /Users/foo/bar → ~/bar
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn tildify(path: &str) -> String {
     if let Ok(home) = std::env::var("HOME") {
         if let Some(rest) = path.strip_prefix(&home) {
@@ -145,6 +152,7 @@ pub fn tildify(path: &str) -> String {
 }
 
 /// Format a duration as relative time: "2s", "3m", "1h", "2d", "1w", "3mo"
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn time_ago(epoch_secs: u64) -> String {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -167,6 +175,7 @@ pub fn time_ago(epoch_secs: u64) -> String {
 }
 
 /// Count the number of panes (leaves) in a saved tab.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn pane_count_tab(tab: &crate::session::SavedTab) -> usize {
     if let Some(ref flat) = tab.flat_columns {
         flat.iter().map(|c| c.panes.len()).sum()
@@ -37,7 +37,7 @@ pub struct ActiveTooltip {
 
 /// Predefined tab color palette (macOS Finder-style tags).
 /// Each entry is [R, G, B] in 0.0–1.0.
-pub const TAB_COLORS: [[f32; 3]; 6] = [
+pub const TAB_COLORS: [[f32; 3]; crate::pane::TAB_COLOR_COUNT] = [
     [0.82, 0.22, 0.22], // Red
     [0.90, 0.55, 0.15], // Orange
     [0.85, 0.75, 0.15], // Yellow
@@ -102,6 +102,7 @@ use vertex::Vertex;
 use crate::config::{Config, KeysConfig};
 use crate::terminal::paste_block::RowPaint;
 use crate::pane::PaneId;
+pub use crate::pane::PaneViewport;
 
 /// Color of the minimized-pane marker (status-bar counter and switcher ⊟ icon).
 /// Violet: distinct from the bell (orange) and completion (green) dots.
@@ -145,12 +146,22 @@ impl PaneAttention {
         }
     }
 }
+use crate::terminal::search::CaseMode;
 use crate::terminal::{CellAttrs, CursorShape, FilterMatch, TerminalState};
 
 /// Data passed to the renderer for drawing filter overlay.
 pub struct FilterRenderData {
     pub query: String,
+    pub regex: bool,
+    pub case: CaseMode,
+    /// The first matches, in buffer order (only what can fit on screen).
     pub matches: Vec<FilterMatch>,
+    /// Total matches found so far.
+    pub total: usize,
+    /// The scan is still running — the count may grow.
+    pub searching: bool,
+    /// The query can't run (invalid regex).
+    pub error: Option<String>,
 }
 
 /// A single entry in the recent projects overlay.
@@ -399,15 +410,6 @@ struct PaneVertexEntry {
     ready: bool,
 }
 
-/// Sub-region of the drawable where a pane is rendered (in pixels).
-#[derive(Clone, Copy)]
-pub struct PaneViewport {
-    pub x: f32,
-    pub y: f32,
-    pub width: f32,
-    pub height: f32,
-}
-
 const INITIAL_VERTEX_BYTES: usize = 8 * 1024 * 1024; // 8MB
 
 pub struct Renderer {
@@ -2100,9 +2102,22 @@ impl Renderer {
         let bar_fg = [1.0, 0.8, 0.2, 1.0]; // accent yellow
         self.render_status_text(vertices, &bar_text, vp.x + PANE_H_PADDING, vp.y, vp.x + vp.width - cell_w, bar_fg, no_bg);
 
-        // Match count
-        let count_text = format!("{} matches", filter.matches.len());
-        let count_fg = [0.6, 0.6, 0.6, 1.0];
+        // Match count (or the regex error), then the active search modes
+        let modes = format!(
+            "{}{}",
+            if filter.regex { ".* " } else { "" },
+            match filter.case {
+                CaseMode::Smart => "",
+                CaseMode::Sensitive => "Aa ",
+                CaseMode::Insensitive => "aa ",
+            },
+        );
+        let count_text = match filter.error {
+            Some(ref e) => format!("{}{}", modes, e),
+            None if filter.searching => format!("{}{} matches…", modes, filter.total),
+            None => format!("{}{} matches", modes, filter.total),
+        };
+        let count_fg = if filter.error.is_some() { [1.0, 0.45, 0.4, 1.0] } else { [0.6, 0.6, 0.6, 1.0] };
         let count_w = count_text.chars().count() as f32 * cell_w;
         self.render_status_text(vertices, &count_text, vp.x + vp.width - count_w - PANE_H_PADDING, vp.y, vp.x + vp.width, count_fg, no_bg);
 
@@ -2110,7 +2125,6 @@ impl Renderer {
         let max_visible = ((vp.height / cell_h).floor() as usize).saturating_sub(1);
         let match_fg = [0.85, 0.85, 0.85, 1.0];
         let highlight_fg = [1.0, 0.8, 0.2, 1.0];
-        let query_lower = filter.query.to_lowercase();
         let max_chars = ((vp.width - 2.0 * PANE_H_PADDING) / cell_w) as usize;
 
         for (i, m) in filter.matches.iter().take(max_visible).enumerate() {
@@ -2125,52 +2139,27 @@ impl Renderer {
             // Truncate line text to what fits on screen
             let prefix_chars = prefix.chars().count();
             let text_limit = max_chars.saturating_sub(prefix_chars);
-            let display_text: String = m.text.chars().take(text_limit).collect();
-
-            if query_lower.is_empty() {
-                self.render_status_text(vertices, &display_text, after_prefix, y, max_x, match_fg, no_bg);
-            } else {
-                // Split text into spans: alternating normal/highlighted.
-                // to_lowercase() is not byte-length-preserving ('İ' is 2
-                // bytes, lowercases to 3), so byte offsets found in the
-                // lowercased copy can't slice display_text directly — build
-                // a map from each text_lower byte back to the byte offset of
-                // its source char in display_text.
-                let mut text_lower = String::with_capacity(display_text.len());
-                let mut lower_to_orig: Vec<usize> = Vec::with_capacity(display_text.len() + 1);
-                for (orig_idx, ch) in display_text.char_indices() {
-                    for lc in ch.to_lowercase() {
-                        text_lower.push(lc);
-                        lower_to_orig.resize(text_lower.len(), orig_idx);
-                    }
+            let display_len = m.text.char_indices().nth(text_limit).map_or(m.text.len(), |(i, _)| i);
+            let display_text = &m.text[..display_len];
+
+            // Alternate normal/highlighted spans from the engine's byte
+            // ranges. Both the ranges and the truncation point sit on char
+            // boundaries, so clamping a range to the prefix keeps it valid.
+            let mut x = after_prefix;
+            let mut pos = 0;
+            for r in &m.ranges {
+                let start = r.start.min(display_len);
+                let end = r.end.min(display_len);
+                if start > pos {
+                    x = self.render_status_text(vertices, &display_text[pos..start], x, y, max_x, match_fg, no_bg);
                 }
-                lower_to_orig.push(display_text.len());
-
-                let mut spans: Vec<(&str, bool)> = Vec::new();
-                let mut pos = 0; // byte position in text_lower
-                while pos < text_lower.len() {
-                    if let Some(found) = text_lower[pos..].find(&query_lower) {
-                        let m_start = pos + found;
-                        let m_end = m_start + query_lower.len();
-                        let o_pos = lower_to_orig[pos];
-                        let o_start = lower_to_orig[m_start];
-                        let o_end = lower_to_orig[m_end];
-                        if o_start > o_pos {
-                            spans.push((&display_text[o_pos..o_start], false));
-                        }
-                        spans.push((&display_text[o_start..o_end], true));
-                        pos = m_end;
-                    } else {
-                        spans.push((&display_text[lower_to_orig[pos]..], false));
-                        break;
-                    }
-                }
-
-                let mut x = after_prefix;
-                for (span, is_hl) in spans {
-                    let fg = if is_hl { highlight_fg } else { match_fg };
-                    x = self.render_status_text(vertices, span, x, y, max_x, fg, no_bg);
+                if end > start {
+                    x = self.render_status_text(vertices, &display_text[start..end], x, y, max_x, highlight_fg, no_bg);
                 }
+                pos = pos.max(end);
+            }
+            if pos < display_len {
+                self.render_status_text(vertices, &display_text[pos..], x, y, max_x, match_fg, no_bg);
             }
         }
     }
@@ -2345,12 +2334,14 @@ impl Renderer {
                     ("Restore Minimized", kc.restore_minimized.as_str(), ""),
                     ("Rename Pane", kc.rename_pane.as_str(), "sticky title"),
                     ("Repaint Pane", kc.repaint_pane.as_str(), "redraw / fix winsize"),
+                    ("Record Pane", kc.toggle_recording.as_str(), "start/stop .cast recording"),
                     ("Next Waiting", kc.next_attention.as_str(), "waiting pane, else unread"),
                     ("Back / Forward", kc.history_back.as_str(), "panes you visited"),
                 ]),
                 ("EDIT & SEARCH", vec![
                     ("Copy", kc.copy.as_str(), ""),
                     ("Copy Raw", kc.copy_raw.as_str(), ""),
+                    ("Copy Rich", kc.copy_rich.as_str(), "with colors (HTML/RTF)"),
                     ("Paste", kc.paste.as_str(), ""),
                     ("Find", kc.toggle_filter.as_str(), "search in this pane"),
                     ("Global Search", kc.open_search.as_str(), "across all panes"),
@@ -0,0 +1,414 @@
+//! `kova replay`: run a raw PTY capture through the parser headless.
+//!
+//! Same pipeline as the live reader (vte → `VteHandler` → `apply_ops`, fed in
+//! fixed-size chunks), no window, no session, no PTY. Prints the final grid,
+//! the cursor, the alt-screen state and any interior blank band, optionally
+//! the `TermOp` stream, and can bisect the byte offset at which a predicate
+//! ("row 12 blank", "alt-screen", …) first becomes true.
+
+use parking_lot::RwLock;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::terminal::parser::VteHandler;
+use crate::terminal::pty::NullBackend;
+use crate::terminal::{TerminalState, DEFAULT_BG, DEFAULT_FG};
+
+/// Live PTY reader buffer size — the default chunking, so a replay splits
+/// escape sequences where the app did.
+const DEFAULT_CHUNK: usize = 4096;
+/// Minimum height of a blank run reported as a hole.
+const BAND_MIN_ROWS: usize = 3;
+
+pub const USAGE: &str = "\
+USAGE: kova replay <file> --cols N --rows N [OPTIONS]
+  --bytes N          replay only the first N bytes
+  --chunk N          feed N bytes per parser pass (default 4096)
+  --step             print a summary after each chunk (Enter = next, q = quit)
+  --ops              dump the parsed TermOp stream
+  --quiet            do not print the grid
+  --bisect PRED      find the first byte offset where PRED becomes true
+
+PREDICATES:
+  row N blank        row N (0-based) has no content
+  row N contains S   row N contains the text S
+  contains S         some visible row contains S
+  alt-screen         the alternate screen is active
+  band [N]           an interior blank band of at least N rows (default 3)";
+
+#[derive(Debug, PartialEq)]
+struct ReplayArgs {
+    path: PathBuf,
+    cols: u16,
+    rows: u16,
+    bytes: Option<usize>,
+    chunk: usize,
+    step: bool,
+    ops: bool,
+    quiet: bool,
+    bisect: Option<Predicate>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Predicate {
+    RowBlank(usize),
+    RowContains(usize, String),
+    Contains(String),
+    AltScreen,
+    Band(usize),
+}
+
+impl Predicate {
+    fn parse(s: &str) -> Result<Self, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let row = |w: &str| w.parse::<usize>().map_err(|_| format!("invalid row number: {}", w));
+        match words.as_slice() {
+            ["row", n, "blank"] => Ok(Predicate::RowBlank(row(n)?)),
+            ["row", n, "contains", rest @ ..] if !rest.is_empty() => {
+                Ok(Predicate::RowContains(row(n)?, rest.join(" ")))
+            }
+            ["contains", rest @ ..] if !rest.is_empty() => Ok(Predicate::Contains(rest.join(" "))),
+            ["alt-screen"] => Ok(Predicate::AltScreen),
+            ["band"] => Ok(Predicate::Band(BAND_MIN_ROWS)),
+            ["band", n] => match n.parse::<usize>() {
+                Ok(n) if n > 0 => Ok(Predicate::Band(n)),
+                _ => Err(format!("invalid band height: {}", n)),
+            },
+            _ => Err(format!("unknown predicate: {:?}", s)),
+        }
+    }
+
+    fn holds(&self, term: &TerminalState) -> bool {
+        match self {
+            Predicate::RowBlank(n) => row_texts(term).get(*n).is_some_and(|l| l.is_empty()),
+            Predicate::RowContains(n, s) => row_texts(term).get(*n).is_some_and(|l| l.contains(s.as_str())),
+            Predicate::Contains(s) => row_texts(term).iter().any(|l| l.contains(s.as_str())),
+            Predicate::AltScreen => term.in_alt_screen,
+            Predicate::Band(n) => term.interior_blank_band(*n).is_some(),
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<ReplayArgs, String> {
+    let mut path = None;
+    let mut cols = None;
+    let mut rows = None;
+    let mut bytes = None;
+    let mut chunk = DEFAULT_CHUNK;
+    let mut step = false;
+    let mut ops = false;
+    let mut quiet = false;
+    let mut bisect = None;
+
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or_else(|| format!("{} needs a value", name));
+        let number = |name: &str, v: &String| {
+            v.parse::<usize>().map_err(|_| format!("{} expects a number, got {:?}", name, v))
+        };
+        match arg.as_str() {
+            "--cols" => cols = Some(number("--cols", value("--cols")?)?),
+            "--rows" => rows = Some(number("--rows", value("--rows")?)?),
+            "--bytes" => bytes = Some(number("--bytes", value("--bytes")?)?),
+            "--chunk" => chunk = number("--chunk", value("--chunk")?)?,
+            "--bisect" => bisect = Some(Predicate::parse(value("--bisect")?)?),
+            "--step" => step = true,
+            "--ops" => ops = true,
+            "--quiet" => quiet = true,
+            s if s.starts_with("--") => return Err(format!("unknown option: {}", s)),
+            s if path.is_none() => path = Some(expand_tilde(s)),
+            s => return Err(format!("unexpected argument: {}", s)),
+        }
+    }
+
+    let dim = |name: &str, v: Option<usize>| match v {
+        Some(n) if (1..=u16::MAX as usize).contains(&n) => Ok(n as u16),
+        Some(n) => Err(format!("{} out of range: {}", name, n)),
+        None => Err(format!("missing {}", name)),
+    };
+    if chunk == 0 {
+        return Err("--chunk must be at least 1".to_string());
+    }
+    if step && bisect.is_some() {
+        return Err("--step and --bisect are mutually exclusive".to_string());
+    }
+    Ok(ReplayArgs {
+        path: path.ok_or("missing capture file")?,
+        cols: dim("--cols", cols)?,
+        rows: dim("--rows", rows)?,
+        bytes,
+        chunk,
+        step,
+        ops,
+        quiet,
+        bisect,
+    })
+}
+
+fn expand_tilde(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// A headless terminal plus its parser, fed like the live PTY reader.
+struct Replay {
+    term: Arc<RwLock<TerminalState>>,
+    parser: vte::Parser,
+    handler: VteHandler,
+}
+
+impl Replay {
+    fn new(cols: u16, rows: u16, trace_ops: bool) -> Result<Self, String> {
+        let term = new_term(cols, rows);
+        // Replies to queries (DA, DSR, …) have no one to go to.
+        let mut handler = VteHandler::new(term.clone(), Arc::new(NullBackend));
+        if trace_ops {
+            handler.trace_ops();
+        }
+        Ok(Replay { term, parser: vte::Parser::new(), handler })
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        self.parser.advance(&mut self.handler, chunk);
+        self.handler.apply_ops();
+    }
+}
+
+fn new_term(cols: u16, rows: u16) -> Arc<RwLock<TerminalState>> {
+    Arc::new(RwLock::new(TerminalState::new(cols, rows, 10_000, DEFAULT_FG, DEFAULT_BG)))
+}
+
+/// The terminal after `bytes`, fed in `chunk`-sized passes.
+fn replayed(bytes: &[u8], cols: u16, rows: u16, chunk: usize) -> Arc<RwLock<TerminalState>> {
+    let term = new_term(cols, rows);
+    crate::terminal::parser::drive_chunks(&term, bytes.chunks(chunk));
+    term
+}
+
+/// Visible rows as trimmed text (glyph per cell, clusters dropped).
+fn row_texts(term: &TerminalState) -> Vec<String> {
+    term.visible_lines()
+        .iter()
+        .map(|l| l.iter().map(|c| c.c).collect::<String>().trim_end().to_string())
+        .collect()
+}
+
+/// Whether `pred` holds after feeding `bytes` in `chunk`-sized passes.
+fn holds_after(bytes: &[u8], cols: u16, rows: u16, chunk: usize, pred: &Predicate) -> bool {
+    pred.holds(&replayed(bytes, cols, rows, chunk).read())
+}
+
+/// Smallest prefix length after which `pred` holds. Assumes the predicate is
+/// monotonic over prefixes (false … false true … true); a flapping predicate
+/// yields *a* transition, not necessarily the first one.
+fn bisect(bytes: &[u8], cols: u16, rows: u16, chunk: usize, pred: &Predicate) -> Result<usize, String> {
+    if !holds_after(bytes, cols, rows, chunk, pred) {
+        return Err(format!("predicate never holds over the {} bytes replayed", bytes.len()));
+    }
+    if holds_after(&[], cols, rows, chunk, pred) {
+        return Ok(0);
+    }
+    // Invariant: false at `lo`, true at `hi`.
+    let (mut lo, mut hi) = (0, bytes.len());
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if holds_after(&bytes[..mid], cols, rows, chunk, pred) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok(hi)
+}
+
+/// Bytes as a readable escape string: ESC → `\e`, other controls and
+/// non-ASCII → `\xNN`.
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            0x1b => out.push_str("\\e"),
+            b'\r' => out.push_str("\\r"),
+            b'\n' => out.push_str("\\n"),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
+}
+
+fn print_report(term: &TerminalState, quiet: bool) {
+    println!(
+        "cursor: row={} col={} alt_screen={} scrollback={}",
+        term.cursor_y, term.cursor_x, term.in_alt_screen, term.scrollback_len()
+    );
+    if !quiet {
+        for (i, l) in row_texts(term).iter().enumerate() {
+            println!("{:>3} |{}", i, l);
+        }
+    }
+    match term.interior_blank_band(BAND_MIN_ROWS) {
+        Some((start, end)) => {
+            println!("INTERIOR BLANK BAND: rows {}..{} ({} rows)", start, end, end - start + 1)
+        }
+        None => println!("no interior blank band"),
+    }
+}
+
+fn print_ops(ops: &[String], first_index: usize) {
+    for (i, op) in ops.iter().enumerate() {
+        println!("{:>8} {}", first_index + i, op);
+    }
+}
+
+/// Entry point for `kova replay …`; returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let opts = match parse_args(args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("kova replay: {}\n\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let mut bytes = match std::fs::read(&opts.path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("kova replay: cannot read {}: {}", opts.path.display(), e);
+            return 1;
+        }
+    };
+    if let Some(n) = opts.bytes {
+        bytes.truncate(n);
+    }
+    match run_opts(&opts, &bytes) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("kova replay: {}", e);
+            1
+        }
+    }
+}
+
+fn run_opts(opts: &ReplayArgs, bytes: &[u8]) -> Result<(), String> {
+    let (cols, rows, chunk) = (opts.cols, opts.rows, opts.chunk);
+
+    if let Some(pred) = &opts.bisect {
+        let offset = bisect(bytes, cols, rows, chunk, pred)?;
+        println!("predicate {:?} first holds after {} of {} bytes", pred, offset, bytes.len());
+        let before = &bytes[offset.saturating_sub(64)..offset];
+        let after = &bytes[offset..bytes.len().min(offset + 32)];
+        println!("  before: {}", escape_bytes(before));
+        println!("  after:  {}", escape_bytes(after));
+        // Chunking matters: a sequence split across passes can render
+        // differently, so the state shown is the one the bisection saw.
+        print_report(&replayed(&bytes[..offset], cols, rows, chunk).read(), opts.quiet);
+        return Ok(());
+    }
+
+    println!("replayed {} bytes at {}x{} from {}", bytes.len(), cols, rows, opts.path.display());
+    let mut replay = Replay::new(cols, rows, opts.ops)?;
+    let interactive = opts.step && unsafe { libc::isatty(0) } == 1;
+    let stdin = std::io::stdin();
+    let mut op_index = 0;
+    let mut offset = 0;
+    for (i, c) in bytes.chunks(chunk).enumerate() {
+        replay.feed(c);
+        offset += c.len();
+        if !opts.step {
+            continue;
+        }
+        let ops = replay.handler.take_op_trace();
+        print_ops(&ops, op_index);
+        op_index += ops.len();
+        {
+            let term = replay.term.read();
+            let band = term.interior_blank_band(BAND_MIN_ROWS);
+            println!(
+                "-- chunk {} bytes {}..{}: cursor row={} col={} alt_screen={} band={:?}",
+                i, offset - c.len(), offset, term.cursor_y, term.cursor_x, term.in_alt_screen, band
+            );
+        }
+        if interactive {
+            print!("[Enter] next, [q] quit > ");
+            let _ = std::io::stdout().flush();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 || line.trim() == "q" {
+                break;
+            }
+        }
+    }
+    print_ops(&replay.handler.take_op_trace(), op_index);
+    print_report(&replay.term.read(), opts.quiet);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_args_reads_options_and_defaults() {
+        let a = parse_args(&args("cap.raw --cols 89 --rows 65 --bytes 1000 --ops")).unwrap();
+        assert_eq!(a.path, PathBuf::from("cap.raw"));
+        assert_eq!((a.cols, a.rows, a.bytes, a.chunk), (89, 65, Some(1000), DEFAULT_CHUNK));
+        assert!(a.ops && !a.step && !a.quiet && a.bisect.is_none());
+
+        let mut v = args("cap.raw --cols 10 --rows 5 --bisect");
+        v.push("row 3 contains hello world".to_string());
+        let a = parse_args(&v).unwrap();
+        assert_eq!(a.bisect, Some(Predicate::RowContains(3, "hello world".to_string())));
+
+        assert!(parse_args(&args("cap.raw --rows 5")).is_err());
+        assert!(parse_args(&args("cap.raw --cols 10 --rows 5 --chunk 0")).is_err());
+        assert!(parse_args(&args("cap.raw --cols 10 --rows 5 --frobnicate")).is_err());
+    }
+
+    #[test]
+    fn predicate_parse_rejects_garbage() {
+        assert_eq!(Predicate::parse("row 12 blank"), Ok(Predicate::RowBlank(12)));
+        assert_eq!(Predicate::parse("alt-screen"), Ok(Predicate::AltScreen));
+        assert_eq!(Predicate::parse("band"), Ok(Predicate::Band(BAND_MIN_ROWS)));
+        assert_eq!(Predicate::parse("band 8"), Ok(Predicate::Band(8)));
+        assert!(Predicate::parse("band 0").is_err());
+        assert!(Predicate::parse("row x blank").is_err());
+        assert!(Predicate::parse("contains").is_err());
+    }
+
+    #[test]
+    fn bisect_finds_first_offset_where_predicate_holds() {
+        let prefix = b"\x1b[1;1Hhello\r\n".to_vec();
+        let mut stream = prefix.clone();
+        stream.extend_from_slice(b"\x1b[?1049h");
+        stream.extend_from_slice(b"\x1b[3;1Hworld");
+        let alt = Predicate::AltScreen;
+        // The alt screen switches on the final byte of `CSI ? 1049 h`.
+        assert_eq!(bisect(&stream, 20, 5, 4096, &alt), Ok(prefix.len() + 8));
+        let world = Predicate::RowContains(2, "world".to_string());
+        assert_eq!(bisect(&stream, 20, 5, 4096, &world), Ok(stream.len()));
+        // Same answer with tiny chunks (sequences split across passes).
+        assert_eq!(bisect(&stream, 20, 5, 3, &alt), Ok(prefix.len() + 8));
+        assert!(bisect(&stream, 20, 5, 4096, &Predicate::Contains("nope".to_string())).is_err());
+    }
+
+    #[test]
+    fn op_trace_records_applied_ops() {
+        let mut replay = Replay::new(20, 5, true).unwrap();
+        replay.feed(b"ab\r\n");
+        let ops = replay.handler.take_op_trace();
+        assert!(ops.iter().any(|op| op.contains("Print")), "{:?}", ops);
+        assert!(ops.iter().any(|op| op == "CarriageReturn"), "{:?}", ops);
+        assert!(replay.handler.take_op_trace().is_empty());
+    }
+}
@@ -1,10 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::config::Config;
 use crate::pane::{alloc_tab_id, Column, Pane, PaneId, Tab};
 
-const SESSION_VERSION: u32 = 5;
+const SESSION_VERSION: u32 = 6;
 
 /// Multi-window session format (v3 — flat columns).
 #[derive(Serialize, Deserialize)]
@@ -89,6 +90,14 @@ pub struct SavedPane {
     /// stopped. `None` for a pane that was at a shell prompt.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub claude_session: Option<String>,
+    /// Screen and scrollback snapshot (v6, `session.save_scrollback`): a file
+    /// name in `scrollback_dir()`, written next to the session file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scrollback_file: Option<String>,
+    /// The snapshot bytes, taken on the main thread and written to
+    /// `scrollback_file` by `save` (which may run on another thread).
+    #[serde(skip)]
+    pub scrollback: Option<Arc<Vec<u8>>>,
 }
 
 /// Legacy column format (v3) — kept for backward compat reading.
@@ -135,38 +144,73 @@ fn session_path() -> PathBuf {
     PathBuf::from(home).join(".config/kova/session.json")
 }
 
+/// Where pane snapshots (`SavedPane::scrollback_file`) live.
+fn scrollback_dir() -> PathBuf {
+    session_path().with_file_name("scrollback")
+}
+
 // ---------------------------------------------------------------
 // Snapshot (save)
 // ---------------------------------------------------------------
 
-fn snapshot_flat_column(col: &Column) -> SavedFlatColumn {
-    SavedFlatColumn {
-        panes: col.panes.iter().map(|p| SavedPane {
-            cwd: p.cwd(),
-            last_command: p.last_command(),
-            custom_title: p.custom_title.clone(),
-            minimized: p.minimized,
-            title: p.osc_title(),
-            // Must run while the pane's children are alive: Claude Code deletes
-            // its session file on exit, so this is unreadable once the PTYs are
-            // reaped. `will_terminate` saves before `shutdown_all` for this.
-            claude_session: crate::claude_session::for_shell(p.pty.pid()),
+/// `p`'s screen and last `lines` lines of scrollback, or `None` when nothing
+/// changed since the snapshot already written to `path`: the file only keeps
+/// its name then, like a tab whose restore is still deferred.
+fn pane_scrollback(p: &Pane, lines: usize, path: &std::path::Path) -> Option<Arc<Vec<u8>>> {
+    let term = p.terminal.read();
+    let generation = Some((term.content_generation(), lines));
+    if p.saved_generation.get() == generation && path.exists() {
+        return None;
+    }
+    p.saved_generation.set(generation);
+    Some(Arc::new(term.save_contents(lines)))
+}
+
+/// Virtual panes are not saved: what they show comes from an IPC client that
+/// will not be there on the next launch. `None` if nothing in `col` is saved.
+fn snapshot_flat_column(col: &Column, scrollback_lines: Option<usize>) -> Option<SavedFlatColumn> {
+    let kept: Vec<usize> = (0..col.panes.len()).filter(|&i| !col.panes[i].is_virtual()).collect();
+    if kept.is_empty() {
+        return None;
+    }
+    let custom_row_weights: Vec<bool> = kept.iter().map(|&i| col.custom_row_weights[i]).collect();
+    Some(SavedFlatColumn {
+        panes: kept.iter().map(|&i| &col.panes[i]).map(|p| {
+            let scrollback_file = scrollback_lines.map(|_| format!("{}-{}.bin", std::process::id(), p.id));
+            let scrollback = scrollback_lines
+                .zip(scrollback_file.as_deref())
+                .and_then(|(lines, name)| pane_scrollback(p, lines, &scrollback_dir().join(name)));
+            SavedPane {
+                cwd: p.cwd(),
+                last_command: p.last_command(),
+                // A playback pane comes back as a plain shell; its "▶ file"
+                // title would only mislabel it.
+                custom_title: p.custom_title.clone().filter(|_| !p.is_playback()),
+                minimized: p.minimized,
+                title: p.osc_title(),
+                // Must run while the pane's children are alive: Claude Code deletes
+                // its session file on exit, so this is unreadable once the PTYs are
+                // reaped. `will_terminate` saves before `shutdown_all` for this.
+                claude_session: crate::claude_session::for_shell(p.pty.pid()),
+                scrollback_file,
+                scrollback,
+            }
         }).collect(),
-        row_weights: col.row_weights.clone(),
-        custom_row_weights: if col.custom_row_weights.iter().any(|&cw| cw) {
-            Some(col.custom_row_weights.clone())
+        row_weights: kept.iter().map(|&i| col.row_weights[i]).collect(),
+        custom_row_weights: if custom_row_weights.iter().any(|&cw| cw) {
+            Some(custom_row_weights)
         } else {
             None
         },
-    }
+    })
 }
 
-/// Find the flat leaf index of a pane by id across all columns.
+/// Find the flat leaf index of a pane by id across all saved panes.
 fn leaf_index_of_tab(tab: &Tab) -> usize {
     let mut idx = 0;
     let target = tab.focused_pane;
     for col in &tab.columns {
-        for pane in &col.panes {
+        for pane in col.panes.iter().filter(|p| !p.is_virtual()) {
             if pane.id == target {
                 return idx;
             }
@@ -178,12 +222,29 @@ fn leaf_index_of_tab(tab: &Tab) -> usize {
 
 /// Snapshot a single tab into a SavedTab (public for recent_projects).
 pub fn snapshot_tab(tab: &Tab) -> SavedTab {
+    snapshot_tab_with_scrollback(tab, None)
+}
+
+/// `snapshot_tab`, plus each pane's screen and its last `scrollback_lines`
+/// lines when `Some` (the session save with `session.save_scrollback`).
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn snapshot_tab_with_scrollback(tab: &Tab, scrollback_lines: Option<usize>) -> SavedTab {
     let focused_leaf_index = leaf_index_of_tab(tab);
+    let mut flat_columns = Vec::with_capacity(tab.columns.len());
+    let mut column_weights = Vec::with_capacity(tab.columns.len());
+    let mut custom_weights = Vec::with_capacity(tab.columns.len());
+    for (i, col) in tab.columns.iter().enumerate() {
+        if let Some(saved) = snapshot_flat_column(col, scrollback_lines) {
+            flat_columns.push(saved);
+            column_weights.push(tab.column_weights[i]);
+            custom_weights.push(tab.custom_weights[i]);
+        }
+    }
     SavedTab {
-        flat_columns: Some(tab.columns.iter().map(snapshot_flat_column).collect()),
+        flat_columns: Some(flat_columns),
         columns: None,
-        column_weights: Some(tab.column_weights.clone()),
-        custom_weights: if tab.custom_weights.iter().any(|&cw| cw) { Some(tab.custom_weights.clone()) } else { None },
+        column_weights: Some(column_weights),
+        custom_weights: if custom_weights.iter().any(|&cw| cw) { Some(custom_weights) } else { None },
         tree: None,
         focused_leaf_index,
         custom_title: tab.custom_title.clone(),
@@ -199,6 +260,7 @@ const SESSION_HISTORY_COUNT: usize = 10;
 /// Save all windows to a single session file.
 /// Save the current session, rotating backups (`session.1.json` → … → `session.10.json`).
 /// Use this on user-initiated checkpoints — quit, kill, manual save.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn save(windows: &[WindowSession]) {
     save_internal(windows, true);
 }
@@ -206,6 +268,7 @@ pub fn save(windows: &[WindowSession]) {
 /// Save the current session WITHOUT rotating backups. Use this for the periodic
 /// autosave: if Kova ever serializes a corrupted state (failed restore, race),
 /// the rotated history of clean checkpoints remains intact and recoverable.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn save_periodic(windows: &[WindowSession]) {
     save_internal(windows, false);
 }
@@ -223,6 +286,7 @@ fn save_internal(windows: &[WindowSession], rotate: bool) {
             return;
         }
     }
+    write_scrollback_files(&session.windows, &scrollback_dir());
     match serde_json::to_string_pretty(&session) {
         Ok(json) => {
             if rotate {
@@ -252,7 +316,7 @@ fn save_internal(windows: &[WindowSession], rotate: bool) {
 /// them. A macOS home directory is world-readable, so the file has to carry its
 /// own mode. `mode()` only applies when the file is created, hence the explicit
 /// tightening for a file written before this, done before the new bytes land.
-fn write_owner_only(path: &std::path::Path, json: &str) -> std::io::Result<()> {
+fn write_owner_only(path: &std::path::Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
     use std::io::Write;
     #[cfg(unix)]
     use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
@@ -264,7 +328,73 @@ fn write_owner_only(path: &std::path::Path, json: &str) -> std::io::Result<()> {
     let mut file = options.open(path)?;
     #[cfg(unix)]
     file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
-    file.write_all(json.as_bytes())
+    file.write_all(contents.as_ref())
+}
+
+/// Write the pane snapshots carried by `windows` into `dir` (owner-only, like
+/// the session file: it is terminal output), then delete the snapshots no
+/// pane refers to any more — restored panes, closed panes, the option off.
+///
+/// Only `session.json` references them: restoring a rotated backup brings
+/// back the layout without the scrollback.
+fn write_scrollback_files(windows: &[WindowSession], dir: &std::path::Path) {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let panes = windows.iter()
+        .flat_map(|w| &w.tabs)
+        .filter_map(|t| t.flat_columns.as_ref())
+        .flatten()
+        .flat_map(|c| &c.panes);
+    let mut keep = std::collections::HashSet::new();
+    for pane in panes {
+        let Some(name) = &pane.scrollback_file else { continue };
+        keep.insert(name.as_str());
+        let Some(data) = &pane.scrollback else { continue };
+        if let Err(e) = std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir) {
+            log::warn!("Failed to create scrollback dir: {}", e);
+            return;
+        }
+        let path = dir.join(name);
+        // Idle panes produce the same snapshot every autosave
+        if std::fs::read(&path).is_ok_and(|old| old == **data) {
+            continue;
+        }
+        if let Err(e) = write_owner_only(&path, data.as_slice()) {
+            log::warn!("Failed to write scrollback snapshot {}: {}", path.display(), e);
+        }
+    }
+
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        if !name.ends_with(".bin") || keep.contains(name) {
+            continue;
+        }
+        let owner = name.split_once('-').and_then(|(pid, _)| pid.parse::<u32>().ok());
+        if owner.is_some_and(|pid| pid != std::process::id() && crate::instances::alive(pid)) {
+            continue;
+        }
+        let _ = std::fs::remove_file(entry.path());
+    }
+}
+
+/// Load a pane snapshot written by `write_scrollback_files` into `term`.
+fn restore_scrollback(term: &mut crate::terminal::TerminalState, dir: &std::path::Path, name: &str) {
+    // The name comes from a file on disk: never let it leave the directory.
+    if name.contains('/') || name.starts_with('.') {
+        log::warn!("Ignoring scrollback snapshot with a bad name: {:?}", name);
+        return;
+    }
+    let path = dir.join(name);
+    match std::fs::read(&path) {
+        Ok(data) => {
+            if let Err(e) = term.restore_contents(&data) {
+                log::warn!("Scrollback snapshot {} not restored: {}", path.display(), e);
+            }
+        }
+        Err(e) => log::warn!("Scrollback snapshot {} unreadable: {}", path.display(), e),
+    }
 }
 
 /// Rotate session.json -> session.1.json -> session.2.json -> ...
@@ -292,6 +422,7 @@ fn rotate_session_backups(path: &std::path::Path, new_content: &[u8]) {
 }
 
 /// Loaded window data ready for restoration.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub struct RestoredWindow {
     pub tabs: Vec<Tab>,
     pub active_tab: usize,
@@ -332,6 +463,7 @@ fn print_session_entry(path: &std::path::Path, label: &str) {
     println!("  {}  {} {}", label, modified, summary);
 }
 
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn load(backup: Option<usize>) -> Option<Session> {
     let path = match backup {
         Some(n) => {
@@ -346,7 +478,7 @@ pub fn load(backup: Option<usize>) -> Option<Session> {
 
     // Try v4/v3 first, then v2, then v1
     let session: Session = if let Ok(s) = serde_json::from_str::<Session>(&data) {
-        if s.version == SESSION_VERSION || s.version == 4 || s.version == 3 || s.version == 2 {
+        if s.version == SESSION_VERSION || s.version == 5 || s.version == 4 || s.version == 3 || s.version == 2 {
             s
         } else if s.version == 1 {
             log::warn!("Session v1 with windows field, ignoring");
@@ -429,6 +561,9 @@ fn restore_flat_column(saved: &SavedFlatColumn, cols: u16, rows: u16, config: &C
                 term.last_command = Some(cmd);
             }
             term.title = sp.title.clone();
+            if let Some(name) = sp.scrollback_file.as_deref().filter(|_| config.session.save_scrollback) {
+                restore_scrollback(&mut term, &scrollback_dir(), name);
+            }
         }
         pane.custom_title = sp.custom_title.clone();
         pane.minimized = sp.minimized;
@@ -596,6 +731,7 @@ fn count_panes_in_saved_column(col: &SavedColumn) -> usize {
     }
 }
 
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn count_panes_in_saved_tab(tab: &SavedTab) -> usize {
     if let Some(ref flat_cols) = tab.flat_columns {
         flat_cols.iter().map(|c| c.panes.len()).sum()
@@ -607,6 +743,7 @@ pub fn count_panes_in_saved_tab(tab: &SavedTab) -> usize {
 }
 
 /// Restore a single saved tab. Used by recent projects and session restore.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn restore_saved_tab(saved: &SavedTab, cols: u16, rows: u16, config: &Config) -> Option<Tab> {
     let (columns, column_weights, custom_weights, pane_ids) = if let Some(ref flat_cols) = saved.flat_columns {
         // New flat format (v4)
@@ -730,6 +867,7 @@ fn restore_window_tabs(ws: &WindowSession, config: &Config) -> Option<(Vec<Tab>,
 }
 
 /// Restore a multi-window session. Returns a list of windows to create.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn restore_session(session: Session, config: &Config) -> Option<Vec<RestoredWindow>> {
     let mut windows = Vec::new();
 
@@ -763,6 +901,8 @@ mod tests {
             minimized: false,
             title: None,
             claude_session: None,
+            scrollback_file: None,
+            scrollback: None,
         }
     }
 
@@ -783,6 +923,7 @@ mod tests {
         let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
         assert!(!keys.contains(&&"title".to_string()), "unexpected keys: {keys:?}");
         assert!(!keys.contains(&&"claude_session".to_string()), "unexpected keys: {keys:?}");
+        assert!(!keys.contains(&&"scrollback_file".to_string()), "unexpected keys: {keys:?}");
     }
 
     #[test]
@@ -802,6 +943,75 @@ mod tests {
         let _ = std::fs::remove_file(&path);
     }
 
+    #[test]
+    fn scrollback_snapshots_are_written_owner_only_and_pruned_once_unreferenced() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("kova-session-scrollback-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let window = |panes: Vec<SavedPane>| WindowSession {
+            tabs: vec![SavedTab {
+                flat_columns: Some(vec![SavedFlatColumn { panes, row_weights: vec![1.0], custom_row_weights: None }]),
+                columns: None,
+                column_weights: None,
+                custom_weights: None,
+                tree: None,
+                focused_leaf_index: 0,
+                custom_title: None,
+                color: None,
+                virtual_width_override: None,
+                scroll_offset_x: None,
+            }],
+            active_tab: 0,
+            frame: None,
+        };
+        let mine = format!("{}-1.bin", std::process::id());
+        let mut live = pane("/a");
+        live.scrollback_file = Some(mine.clone());
+        live.scrollback = Some(Arc::new(b"snapshot".to_vec()));
+        write_scrollback_files(&[window(vec![live])], &dir);
+        let path = dir.join(&mine);
+        assert_eq!(std::fs::read(&path).unwrap(), b"snapshot");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        // A tab still waiting for its deferred restore only carries the name:
+        // its file must survive the autosave.
+        let mut deferred = pane("/a");
+        deferred.scrollback_file = Some(mine.clone());
+        write_scrollback_files(&[window(vec![deferred])], &dir);
+        assert!(path.exists());
+
+        // Another running instance's file stays; a dead one's goes.
+        let other = dir.join(format!("{}-1.bin", std::os::unix::process::parent_id()));
+        let dead = dir.join(format!("{}-1.bin", u32::MAX));
+        std::fs::write(&other, b"x").unwrap();
+        std::fs::write(&dead, b"x").unwrap();
+        write_scrollback_files(&[window(vec![pane("/a")])], &dir);
+        assert!(!path.exists());
+        assert!(other.exists());
+        assert!(!dead.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_unchanged_pane_is_not_snapshotted_again() {
+        let config = crate::config::Config::default();
+        let (p, _program) = Pane::loopback(20, 5, &config);
+        let path = std::env::temp_dir().join(format!("kova-session-unchanged-{}.bin", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let first = pane_scrollback(&p, 100, &path).expect("first save takes a snapshot");
+        // Its file was never written: take it again rather than leave none.
+        assert!(pane_scrollback(&p, 100, &path).is_some());
+        std::fs::write(&path, first.as_slice()).unwrap();
+        assert!(pane_scrollback(&p, 100, &path).is_none());
+        assert!(pane_scrollback(&p, 50, &path).is_some(), "a new line limit changes the snapshot");
+        assert!(pane_scrollback(&p, 50, &path).is_none());
+        p.terminal.write().clear_scrollback_and_screen();
+        assert!(pane_scrollback(&p, 50, &path).is_some(), "cleared content is saved again");
+        let _ = std::fs::remove_file(&path);
+    }
+
     #[test]
     fn a_pane_that_was_at_a_shell_prompt_keeps_its_last_command() {
         let mut sp = pane("/a");
@@ -843,4 +1053,34 @@ mod tests {
         assert_eq!(restore_command(&sp), None);
     }
 
+    #[test]
+    fn the_last_command_run_in_a_pane_is_retyped_on_restore() {
+        let config = Config::default();
+        let (shell, program) = Pane::loopback(40, 10, &config);
+        program.command_started("cargo build --release");
+        program.command_finished(0);
+        let saved = snapshot_tab(&Tab::with_pane(shell));
+        let sp = &saved.flat_columns.as_ref().unwrap()[0].panes[0];
+        assert_eq!(restore_command(sp).as_deref(), Some("cargo build --release"));
+    }
+
+    #[test]
+    fn virtual_panes_are_left_out_of_the_snapshot() {
+        let config = Config::default();
+        let (shell, program) = Pane::loopback(40, 10, &config);
+        program.set_cwd(Some("/work/kova"));
+        let mut tab = Tab::with_pane(shell);
+        let virtual_id = tab.insert_column_after_focused(Pane::virtual_pane(40, 10, Some("CI".into()), &config));
+        tab.focused_pane = virtual_id;
+
+        let saved = snapshot_tab(&tab);
+        let columns = saved.flat_columns.as_ref().unwrap();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].panes[0].cwd.as_deref(), Some("/work/kova"));
+        assert_eq!(saved.column_weights.as_ref().map(Vec::len), Some(1));
+        assert_eq!(saved.focused_leaf_index, 0);
+
+        let only_virtual = Tab::with_pane(Pane::virtual_pane(40, 10, None, &config));
+        assert_eq!(count_panes_in_saved_tab(&snapshot_tab(&only_virtual)), 0);
+    }
 }
@@ -0,0 +1,651 @@
+//! Styled exports of a pane's content: ANSI, HTML, RTF and JSON cell runs.
+//!
+//! `dump_text` keeps only the characters. The exporters here start from the
+//! same rows, joined into logical lines the same way (soft wraps glued, grid
+//! padding trimmed), but keep each cell's colors, attributes and OSC 8 link as
+//! `StyledRun`s. Colors are the RGB baked into the cells; a color equal to the
+//! terminal's default fg/bg is treated as "default" so the output follows the
+//! reader's own theme where the pane did.
+
+use std::borrow::Cow;
+use std::fmt::{self, Write};
+use std::io;
+
+use super::{Cell, CellAttrs, DumpMode, Row, TerminalState};
+
+/// Output format of `get-pane-content`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Plain text (`dump_text`).
+    Text,
+    /// Text with SGR colors/attributes and OSC 8 links.
+    Ansi,
+    /// A standalone HTML page with inline styles.
+    Html,
+    /// Structured runs (text, fg, bg, attrs, url) per line.
+    Cells,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "text" => Some(ExportFormat::Text),
+            "ansi" => Some(ExportFormat::Ansi),
+            "html" => Some(ExportFormat::Html),
+            "cells" => Some(ExportFormat::Cells),
+            _ => None,
+        }
+    }
+
+    /// The format's name, also the key of the content in an IPC reply.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Text => "text",
+            ExportFormat::Ansi => "ansi",
+            ExportFormat::Html => "html",
+            ExportFormat::Cells => "cells",
+        }
+    }
+}
+
+/// Consecutive cells of one line sharing a style.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyledRun {
+    pub text: String,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    pub attrs: CellAttrs,
+    pub url: Option<String>,
+}
+
+/// A logical line: soft-wrapped rows joined, trailing blank cells dropped.
+pub type StyledLine = Vec<StyledRun>;
+
+/// Styled lines plus the colors a reader should treat as "default".
+pub struct StyledText {
+    pub lines: Vec<StyledLine>,
+    pub default_fg: [u8; 3],
+    pub default_bg: [u8; 3],
+}
+
+impl TerminalState {
+    /// Styled counterpart of `dump_text`: same rows, same line breaks.
+    pub fn styled_text(&self, mode: DumpMode, trim_trailing_blank_lines: bool) -> StyledText {
+        let rows: Box<dyn Iterator<Item = Cow<'_, Row>>> = match mode {
+            DumpMode::Visible => Box::new(self.grid.iter().map(Cow::Borrowed)),
+            DumpMode::Scrollback => Box::new(self.scrollback.iter()),
+            DumpMode::All => Box::new(self.scrollback.iter().chain(self.grid.iter().map(Cow::Borrowed))),
+        };
+        let mut lines = Vec::new();
+        let mut cells: Vec<Cell> = Vec::new();
+        for row in rows {
+            cells.extend(row.cells.iter().cloned());
+            if !row.wrapped {
+                lines.push(self.styled_line(&cells));
+                cells.clear();
+            }
+        }
+        if !cells.is_empty() {
+            lines.push(self.styled_line(&cells));
+        }
+        if trim_trailing_blank_lines {
+            while lines.last().is_some_and(|l| l.iter().all(|r| r.text.trim().is_empty())) {
+                lines.pop();
+            }
+        }
+        self.styled(lines)
+    }
+
+    /// Content in `format`: a string, or the runs array for `Cells`.
+    pub fn export(&self, mode: DumpMode, trim_trailing_blank_lines: bool, format: ExportFormat) -> serde_json::Value {
+        let styled = || self.styled_text(mode, trim_trailing_blank_lines);
+        match format {
+            ExportFormat::Text => self.dump_text(mode, trim_trailing_blank_lines).text.into(),
+            ExportFormat::Ansi => styled().ansi().into(),
+            ExportFormat::Html => styled().html().into(),
+            ExportFormat::Cells => styled().cells_json(),
+        }
+    }
+
+    /// `(chars, bytes)` of what `export` returns (the JSON text for `Cells`).
+    pub fn measure_export(&self, mode: DumpMode, trim_trailing_blank_lines: bool, format: ExportFormat) -> (usize, usize) {
+        let styled = || self.styled_text(mode, trim_trailing_blank_lines);
+        // Written into a counter: the export itself is never held whole.
+        let mut m = Measure::default();
+        match format {
+            ExportFormat::Text => return self.measure_text(mode, trim_trailing_blank_lines),
+            ExportFormat::Ansi => styled().write_ansi(&mut m),
+            ExportFormat::Html => styled().write_html(&mut m),
+            ExportFormat::Cells => {
+                // Line by line, the way `Value::to_string` lays out the array.
+                let styled = styled();
+                m.bytes += 2;
+                m.chars += 2;
+                for (i, line) in styled.lines.iter().enumerate() {
+                    if i > 0 {
+                        m.bytes += 1;
+                        m.chars += 1;
+                    }
+                    let _ = serde_json::to_writer(&mut m, &styled.line_json(line));
+                }
+            }
+        }
+        (m.chars, m.bytes)
+    }
+
+    /// Styled counterpart of `selected_text`, for rich-text copy.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn selected_styled_text(&self) -> StyledText {
+        let Some((start, end)) = self.ordered_selection() else { return self.styled(Vec::new()) };
+        let mut lines = Vec::new();
+        let mut cells: Vec<Cell> = Vec::new();
+        for line_idx in start.line..=end.line {
+            let Some(row) = self.row_at(line_idx) else { continue };
+            if super::paste_block::is_marker_line(&row.cells, self.default_fg) {
+                continue;
+            }
+            let from = if line_idx == start.line { start.col as usize } else { 0 };
+            let to = if line_idx == end.line { end.col as usize + 1 } else { row.cells.len() };
+            if from < to.min(row.cells.len()) {
+                cells.extend(row.cells[from..to.min(row.cells.len())].iter().cloned());
+            }
+            if !row.wrapped || line_idx == end.line {
+                lines.push(self.styled_line(&cells));
+                cells.clear();
+            }
+        }
+        self.styled(lines)
+    }
+
+    fn styled(&self, lines: Vec<StyledLine>) -> StyledText {
+        StyledText { lines, default_fg: self.default_fg, default_bg: self.default_bg }
+    }
+
+    /// Group cells into runs. Trailing cells that would print nothing visible
+    /// (grid padding) are dropped, like `render_row` trims trailing spaces.
+    fn styled_line(&self, cells: &[Cell]) -> StyledLine {
+        let visible = |c: &Cell| {
+            (c.c != ' ' && c.c != '\0')
+                || c.cluster.is_some()
+                || c.bg != self.default_bg
+                || c.attrs.intersects(CellAttrs::UNDERLINE | CellAttrs::STRIKETHROUGH)
+        };
+        let end = cells.iter().rposition(visible).map_or(0, |i| i + 1);
+        let mut runs: StyledLine = Vec::new();
+        for cell in &cells[..end] {
+            if cell.c == '\0' {
+                continue; // wide-char continuation column
+            }
+            let same = runs.last().is_some_and(|r: &StyledRun| {
+                r.fg == cell.fg
+                    && r.bg == cell.bg
+                    && r.attrs == cell.attrs
+                    && r.url.as_deref() == self.hyperlink_url(cell.hyperlink_id)
+            });
+            if !same {
+                runs.push(StyledRun {
+                    text: String::new(),
+                    fg: cell.fg,
+                    bg: cell.bg,
+                    attrs: cell.attrs,
+                    url: self.hyperlink_url(cell.hyperlink_id).map(str::to_string),
+                });
+            }
+            let Some(run) = runs.last_mut() else { continue };
+            match &cell.cluster {
+                Some(cluster) => run.text.push_str(cluster),
+                None => run.text.push(cell.c),
+            }
+        }
+        runs
+    }
+}
+
+impl StyledText {
+    /// Plain characters, one line per logical line.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn plain(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            for run in line {
+                out.push_str(&run.text);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Text with SGR and OSC 8 sequences, emitted only where the style changes.
+    /// Each line ends back in the default state, so lines can be printed alone.
+    pub fn ansi(&self) -> String {
+        let mut out = String::new();
+        self.write_ansi(&mut out);
+        out
+    }
+
+    fn write_ansi(&self, out: &mut impl Write) {
+        for line in &self.lines {
+            let mut cur = Style::default();
+            let mut url: Option<&str> = None;
+            for run in line {
+                let next = self.style(run);
+                if next != cur {
+                    let mut params: Vec<String> = Vec::new();
+                    // Attributes cannot be switched off one by one portably
+                    // enough: start over from a reset when any goes away.
+                    let reset = !next.attrs.contains(cur.attrs)
+                        || (cur.fg.is_some() && next.fg.is_none())
+                        || (cur.bg.is_some() && next.bg.is_none());
+                    let base = if reset {
+                        params.push("0".into());
+                        Style::default()
+                    } else {
+                        cur
+                    };
+                    for (flag, code) in [
+                        (CellAttrs::BOLD, "1"),
+                        (CellAttrs::ITALIC, "3"),
+                        (CellAttrs::UNDERLINE, "4"),
+                        (CellAttrs::STRIKETHROUGH, "9"),
+                    ] {
+                        if next.attrs.contains(flag) && !base.attrs.contains(flag) {
+                            params.push(code.into());
+                        }
+                    }
+                    if let Some(c) = next.fg.filter(|_| next.fg != base.fg) {
+                        params.push(format!("38;2;{};{};{}", c[0], c[1], c[2]));
+                    }
+                    if let Some(c) = next.bg.filter(|_| next.bg != base.bg) {
+                        params.push(format!("48;2;{};{};{}", c[0], c[1], c[2]));
+                    }
+                    let _ = write!(out, "\x1b[{}m", params.join(";"));
+                    cur = next;
+                }
+                if run.url.as_deref() != url {
+                    url = run.url.as_deref();
+                    let _ = write!(out, "\x1b]8;;{}\x1b\\", osc_url(url.unwrap_or("")));
+                }
+                let _ = out.write_str(&run.text);
+            }
+            if url.is_some() {
+                let _ = out.write_str("\x1b]8;;\x1b\\");
+            }
+            if cur != Style::default() {
+                let _ = out.write_str("\x1b[0m");
+            }
+            let _ = out.write_char('\n');
+        }
+    }
+
+    /// A standalone HTML page: one `<pre>` in the pane's colors, a `<span>`
+    /// with inline styles per styled run, `<a>` for links.
+    pub fn html(&self) -> String {
+        let mut out = String::new();
+        self.write_html(&mut out);
+        out
+    }
+
+    fn write_html(&self, out: &mut impl Write) {
+        let _ = write!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Kova</title>\n</head>\n\
+             <body style=\"margin:0;background:{bg}\">\n\
+             <pre style=\"margin:0;padding:8px;color:{fg};background:{bg};font-family:Menlo,Monaco,monospace;font-size:13px\">",
+            fg = hex(self.default_fg),
+            bg = hex(self.default_bg),
+        );
+        for line in &self.lines {
+            for run in line {
+                let style = self.style(run);
+                let mut css = String::new();
+                if let Some(c) = style.fg {
+                    let _ = write!(css, "color:{};", hex(c));
+                }
+                if let Some(c) = style.bg {
+                    let _ = write!(css, "background:{};", hex(c));
+                }
+                if style.attrs.contains(CellAttrs::BOLD) {
+                    css.push_str("font-weight:bold;");
+                }
+                if style.attrs.contains(CellAttrs::ITALIC) {
+                    css.push_str("font-style:italic;");
+                }
+                let decorations: Vec<&str> = [
+                    (CellAttrs::UNDERLINE, "underline"),
+                    (CellAttrs::STRIKETHROUGH, "line-through"),
+                ]
+                .iter()
+                .filter(|(flag, _)| style.attrs.contains(*flag))
+                .map(|(_, name)| *name)
+                .collect();
+                if !decorations.is_empty() {
+                    let _ = write!(css, "text-decoration:{};", decorations.join(" "));
+                }
+                if let Some(url) = &run.url {
+                    let _ = write!(out, "<a href=\"{}\" style=\"color:inherit\">", escape_html(url));
+                }
+                if css.is_empty() {
+                    let _ = out.write_str(&escape_html(&run.text));
+                } else {
+                    let _ = write!(out, "<span style=\"{}\">{}</span>", css, escape_html(&run.text));
+                }
+                if run.url.is_some() {
+                    let _ = out.write_str("</a>");
+                }
+            }
+            let _ = out.write_char('\n');
+        }
+        let _ = out.write_str("</pre>\n</body>\n</html>\n");
+    }
+
+    /// RTF for the pasteboard (Pages, Notes, Mail take it over HTML). Runs
+    /// become `\cf`/`\cb` color switches and `\b`/`\i`/`\ul`/`\strike` groups,
+    /// links become HYPERLINK fields.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn rtf(&self) -> String {
+        let mut colors: Vec<[u8; 3]> = vec![self.default_fg, self.default_bg];
+        let mut color_index = |c: [u8; 3]| match colors.iter().position(|&k| k == c) {
+            Some(i) => i + 1,
+            None => {
+                colors.push(c);
+                colors.len()
+            }
+        };
+        let mut body = String::new();
+        for line in &self.lines {
+            for run in line {
+                let fg = color_index(run.fg);
+                let bg = color_index(run.bg);
+                let mut group = format!("\\cf{}\\cb{}", fg, bg);
+                for (flag, word) in [
+                    (CellAttrs::BOLD, "\\b"),
+                    (CellAttrs::ITALIC, "\\i"),
+                    (CellAttrs::UNDERLINE, "\\ul"),
+                    (CellAttrs::STRIKETHROUGH, "\\strike"),
+                ] {
+                    if run.attrs.contains(flag) {
+                        group.push_str(word);
+                    }
+                }
+                let text = escape_rtf(&run.text);
+                match &run.url {
+                    Some(url) => {
+                        let _ = write!(
+                            body,
+                            "{{\\field{{\\*\\fldinst{{HYPERLINK \"{}\"}}}}{{\\fldrslt{{{} {}}}}}}}",
+                            escape_rtf(url), group, text
+                        );
+                    }
+                    None => {
+                        let _ = write!(body, "{{{} {}}}", group, text);
+                    }
+                }
+            }
+            body.push_str("\\line\n");
+        }
+
+        let mut out = String::from("{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fmodern Menlo;}}\n{\\colortbl;");
+        for c in &colors {
+            let _ = write!(out, "\\red{}\\green{}\\blue{};", c[0], c[1], c[2]);
+        }
+        out.push_str("}\n\\f0\\fs26\\cb2\n");
+        out.push_str(&body);
+        out.push('}');
+        out
+    }
+
+    /// `[[{text, fg, bg, attrs, url?}, …], …]`: one array of runs per line.
+    /// Colors are `#rrggbb`, `null` for the default; attrs are names.
+    pub fn cells_json(&self) -> serde_json::Value {
+        serde_json::Value::Array(self.lines.iter().map(|line| self.line_json(line)).collect())
+    }
+
+    fn line_json(&self, line: &StyledLine) -> serde_json::Value {
+        let runs: Vec<serde_json::Value> = line.iter().map(|run| {
+            let style = self.style(run);
+            let attrs: Vec<&str> = [
+                (CellAttrs::BOLD, "bold"),
+                (CellAttrs::ITALIC, "italic"),
+                (CellAttrs::UNDERLINE, "underline"),
+                (CellAttrs::STRIKETHROUGH, "strikethrough"),
+            ]
+            .iter()
+            .filter(|(flag, _)| run.attrs.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+            let mut v = serde_json::json!({
+                "text": run.text,
+                "fg": style.fg.map(hex),
+                "bg": style.bg.map(hex),
+                "attrs": attrs,
+            });
+            if let Some(url) = &run.url {
+                v["url"] = serde_json::json!(url);
+            }
+            v
+        }).collect();
+        serde_json::Value::Array(runs)
+    }
+
+    fn style(&self, run: &StyledRun) -> Style {
+        Style {
+            fg: Some(run.fg).filter(|&c| c != self.default_fg),
+            bg: Some(run.bg).filter(|&c| c != self.default_bg),
+            attrs: run.attrs,
+        }
+    }
+}
+
+/// A run's look relative to the defaults (`None` = default color).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Style {
+    fg: Option<[u8; 3]>,
+    bg: Option<[u8; 3]>,
+    attrs: CellAttrs,
+}
+
+/// A sink that only counts what is written to it, as chars and bytes.
+#[derive(Default)]
+struct Measure {
+    chars: usize,
+    bytes: usize,
+}
+
+impl Write for Measure {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.chars += s.chars().count();
+        self.bytes += s.len();
+        Ok(())
+    }
+}
+
+impl io::Write for Measure {
+    /// serde_json writes whole UTF-8 sequences: every byte but a
+    /// continuation byte starts a char.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chars += buf.iter().filter(|&&b| b & 0xC0 != 0x80).count();
+        self.bytes += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A URL fit to sit inside an OSC 8 sequence: control characters (C0, DEL,
+/// C1) would end or corrupt the sequence, so they are percent-encoded.
+fn osc_url(url: &str) -> Cow<'_, str> {
+    if !url.chars().any(char::is_control) {
+        return Cow::Borrowed(url);
+    }
+    let mut out = String::with_capacity(url.len() + 8);
+    for c in url.chars() {
+        if c.is_control() {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                let _ = write!(out, "%{:02X}", b);
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Cow::Owned(out)
+}
+
+fn hex(c: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// RTF is 7-bit: escape the control characters, write anything else as
+/// `\uN?` (signed 16-bit units, astral chars as surrogate pairs).
+fn escape_rtf(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    let _ = write!(out, "\\u{}?", *unit as i16);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A terminal that was fed `bytes` through the real parser.
+    fn drive(cols: u16, rows: u16, bytes: &[u8]) -> TerminalState {
+        let term = Arc::new(parking_lot::RwLock::new(TerminalState::new(cols, rows, 100, [200, 200, 200], [0, 0, 0])));
+        let mut handler = crate::terminal::parser::VteHandler::new(term.clone(), Arc::new(crate::terminal::pty::NullBackend));
+        vte::Parser::new().advance(&mut handler, bytes);
+        handler.apply_ops();
+        drop(handler);
+        Arc::try_unwrap(term).ok().unwrap().into_inner()
+    }
+
+    const SAMPLE: &[u8] = b"plain \x1b[1;31mbold red\x1b[0m \x1b]8;;https://x.io/?a=1&b=2\x07link\x1b]8;;\x07 <tag>\r\n";
+
+    #[test]
+    fn styled_text_matches_the_plain_dump() {
+        let t = drive(12, 3, b"abcdefghijklmnop\r\n\xe6\x97\xa5\xe6\x9c\xac end\r\n");
+        let styled = t.styled_text(DumpMode::All, true);
+        assert_eq!(styled.plain(), t.dump_text(DumpMode::All, true).text);
+    }
+
+    #[test]
+    fn runs_carry_colors_attrs_and_links() {
+        let t = drive(60, 3, SAMPLE);
+        let styled = t.styled_text(DumpMode::Visible, true);
+        let line = &styled.lines[0];
+        let texts: Vec<&str> = line.iter().map(|r| r.text.as_str()).collect();
+        assert_eq!(texts, ["plain ", "bold red", " ", "link", " <tag>"]);
+        assert!(line[1].attrs.contains(CellAttrs::BOLD));
+        assert_ne!(line[1].fg, t.default_fg);
+        assert_eq!(line[3].url.as_deref(), Some("https://x.io/?a=1&b=2"));
+
+        let json = styled.cells_json();
+        assert_eq!(json[0][0]["fg"], serde_json::Value::Null);
+        assert_eq!(json[0][1]["attrs"], serde_json::json!(["bold"]));
+        assert_eq!(json[0][3]["url"], "https://x.io/?a=1&b=2");
+    }
+
+    #[test]
+    fn ansi_round_trips_through_the_parser() {
+        let t = drive(60, 3, SAMPLE);
+        let ansi = t.styled_text(DumpMode::Visible, true).ansi();
+        // Default-colored text needs no escape at all
+        assert!(ansi.starts_with("plain \x1b["));
+        // Colors are re-brightened by bold on the way back in: compare the rest
+        let shape = |t: &TerminalState| -> Vec<(String, CellAttrs, Option<String>, bool)> {
+            t.styled_text(DumpMode::Visible, true).lines[0].iter()
+                .map(|r| (r.text.clone(), r.attrs, r.url.clone(), r.fg == t.default_fg))
+                .collect()
+        };
+        let again = drive(60, 3, ansi.replace('\n', "\r\n").as_bytes());
+        assert_eq!(shape(&again), shape(&t));
+    }
+
+    #[test]
+    fn ansi_percent_encodes_control_chars_in_links() {
+        let run = StyledRun {
+            text: "x".into(),
+            fg: [200, 200, 200],
+            bg: [0, 0, 0],
+            attrs: CellAttrs::empty(),
+            url: Some("https://x.io/\x1b]0;pwned\x07\u{9c}".into()),
+        };
+        let styled = StyledText { lines: vec![vec![run]], default_fg: [200, 200, 200], default_bg: [0, 0, 0] };
+        assert_eq!(styled.ansi(), "\x1b]8;;https://x.io/%1B]0;pwned%07%C2%9C\x1b\\x\x1b]8;;\x1b\\\n");
+    }
+
+    #[test]
+    fn measure_matches_the_export() {
+        let t = drive(60, 3, b"caf\xc3\xa9 \x1b[1;31mbold\x1b[0m \x1b]8;;https://x.io/\x07link\x1b]8;;\x07\r\nnext\r\n");
+        for format in [ExportFormat::Text, ExportFormat::Ansi, ExportFormat::Html, ExportFormat::Cells] {
+            let text = match t.export(DumpMode::All, true, format) {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            assert_eq!(t.measure_export(DumpMode::All, true, format), (text.chars().count(), text.len()), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn html_is_escaped_and_styled() {
+        let t = drive(60, 3, SAMPLE);
+        let html = t.styled_text(DumpMode::Visible, true).html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("&lt;tag&gt;"));
+        assert!(html.contains("font-weight:bold;"));
+        assert!(html.contains("<a href=\"https://x.io/?a=1&amp;b=2\""));
+    }
+
+    #[test]
+    fn rtf_escapes_braces_and_non_ascii() {
+        assert_eq!(escape_rtf("{a}\\é😀"), "\\{a\\}\\\\\\u233?\\u-10179?\\u-8704?");
+        let t = drive(60, 3, SAMPLE);
+        let rtf = t.styled_text(DumpMode::Visible, true).rtf();
+        assert!(rtf.starts_with("{\\rtf1"));
+        assert!(rtf.contains("HYPERLINK \"https://x.io/?a=1&b=2\""));
+        assert!(rtf.ends_with('}'));
+    }
+
+    #[test]
+    fn selection_exports_only_the_selected_cells() {
+        let mut t = drive(60, 3, SAMPLE);
+        let line = t.scrollback_len();
+        t.selection = Some(super::super::Selection {
+            anchor: super::super::GridPos { line, col: 6 },
+            end: super::super::GridPos { line, col: 13 },
+            mode: super::super::SelectionMode::Normal,
+        });
+        let styled = t.selected_styled_text();
+        assert_eq!(styled.plain(), "bold red\n");
+        assert!(styled.lines[0][0].attrs.contains(CellAttrs::BOLD));
+    }
+}
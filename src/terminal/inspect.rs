@@ -0,0 +1,296 @@
+//! Escape-sequence inspector: a bounded ring of the last ops the parser
+//! applied to a pane, and counters of the sequences it did not handle.
+//!
+//! The ring is opt-in per pane (IPC `inspect-pane`) — formatting every op has
+//! a cost the live path should not pay by default. The counters are always on:
+//! unhandled sequences are rare, and counting them from everyday use is the
+//! point (compatibility gaps show up without a capture to replay).
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// Ring size when `inspect-pane` does not name one.
+pub const DEFAULT_CAPACITY: usize = 2048;
+/// Upper bound on a ring, so a client cannot make a pane hold megabytes of ops.
+pub const MAX_CAPACITY: usize = 65_536;
+/// Longest op rendering kept. A `Print` of a full screen repaint would
+/// otherwise make a single record as large as the screen.
+const MAX_OP_LEN: usize = 256;
+/// Distinct unhandled-sequence keys kept; later ones are counted under
+/// `"other"`. OSC keys come from the stream, so the map must not grow with it.
+const MAX_UNHANDLED_KEYS: usize = 256;
+
+/// One applied op, or a run of identical consecutive ops folded together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpRecord {
+    /// Position in the pane's op stream (of the first op of a folded run).
+    pub seq: u64,
+    /// Unix time, in ms, of the `apply_ops` pass that applied the op.
+    pub time_ms: u64,
+    /// Parser pass the op came from — one per PTY read. A change of chunk
+    /// between two records is a read boundary.
+    pub chunk: u64,
+    /// `Debug` rendering of the op, truncated to `MAX_OP_LEN`.
+    pub op: String,
+    /// How many identical ops this record stands for (> 1 = coalesced).
+    pub repeat: u32,
+}
+
+pub struct OpRing {
+    capacity: usize,
+    records: VecDeque<OpRecord>,
+    next_seq: u64,
+    chunk: u64,
+    chunk_time_ms: u64,
+    /// Records pushed out of the front of a full ring.
+    evicted: u64,
+}
+
+impl OpRing {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.clamp(1, MAX_CAPACITY);
+        OpRing {
+            capacity,
+            records: VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY)),
+            next_seq: 0,
+            chunk: 0,
+            chunk_time_ms: 0,
+            evicted: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.clamp(1, MAX_CAPACITY);
+        while self.records.len() > self.capacity {
+            self.records.pop_front();
+            self.evicted += 1;
+        }
+    }
+
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Start a parser pass: ops pushed until the next call share its chunk
+    /// number and timestamp.
+    pub fn begin_chunk(&mut self) {
+        self.chunk += 1;
+        self.chunk_time_ms = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+    }
+
+    /// Record an op. An op identical to the previous one within the same
+    /// chunk (a TUI moving the cursor one cell at a time, say) bumps that
+    /// record's `repeat` instead of taking a slot.
+    pub fn push(&mut self, mut op: String) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if op.len() > MAX_OP_LEN {
+            let mut end = MAX_OP_LEN;
+            while !op.is_char_boundary(end) {
+                end -= 1;
+            }
+            op.truncate(end);
+            op.push('…');
+        }
+        match self.records.back_mut() {
+            Some(last) if last.chunk == self.chunk && last.op == op => {
+                last.repeat = last.repeat.saturating_add(1);
+                return;
+            }
+            _ => {}
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+            self.evicted += 1;
+        }
+        self.records.push_back(OpRecord {
+            seq,
+            time_ms: self.chunk_time_ms,
+            chunk: self.chunk,
+            op,
+            repeat: 1,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// The newest `limit` records (all of them for `None`), oldest first.
+    pub fn recent(&self, limit: Option<usize>) -> impl Iterator<Item = &OpRecord> {
+        let skip = limit.map_or(0, |n| self.records.len().saturating_sub(n));
+        self.records.iter().skip(skip)
+    }
+
+    /// `get-pane-ops` rendering of `recent(limit)`. `coalesced` only appears
+    /// on folded records, to keep the common case short.
+    pub fn to_json(&self, limit: Option<usize>) -> serde_json::Value {
+        let ops: Vec<serde_json::Value> = self
+            .recent(limit)
+            .map(|r| {
+                let mut v = serde_json::json!({
+                    "seq": r.seq,
+                    "time_ms": r.time_ms,
+                    "chunk": r.chunk,
+                    "op": r.op,
+                });
+                if r.repeat > 1 {
+                    v["coalesced"] = serde_json::json!(r.repeat);
+                }
+                v
+            })
+            .collect();
+        serde_json::Value::Array(ops)
+    }
+}
+
+/// Family of an unhandled sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SeqKind {
+    Csi,
+    Osc,
+    Dcs,
+    Esc,
+    /// A DEC private (`CSI ? N h/l`) or ANSI (`CSI N h/l`) mode we ignore.
+    Mode,
+}
+
+impl SeqKind {
+    fn name(self) -> &'static str {
+        match self {
+            SeqKind::Csi => "csi",
+            SeqKind::Osc => "osc",
+            SeqKind::Dcs => "dcs",
+            SeqKind::Esc => "esc",
+            SeqKind::Mode => "mode",
+        }
+    }
+}
+
+/// Key of an unhandled CSI/DCS/ESC sequence: its intermediates then its
+/// final byte (`CSI > 4 ; 2 m` → `">m"`), controls shown as `\xNN`.
+pub fn seq_key(intermediates: &[u8], final_byte: char) -> String {
+    let mut key = String::with_capacity(intermediates.len() + 1);
+    for c in intermediates.iter().map(|&b| b as char).chain(std::iter::once(final_byte)) {
+        if c.is_ascii_graphic() {
+            key.push(c);
+        } else {
+            key.push_str(&format!("\\x{:02x}", c as u32));
+        }
+    }
+    key
+}
+
+/// Occurrences of each unhandled sequence, keyed by family and `seq_key`
+/// (the command number for OSC, the mode number for modes).
+#[derive(Default)]
+pub struct UnhandledCounts {
+    counts: BTreeMap<(SeqKind, String), u64>,
+}
+
+impl UnhandledCounts {
+    pub fn add(&mut self, kind: SeqKind, key: String) {
+        let key = if self.counts.len() >= MAX_UNHANDLED_KEYS && !self.counts.contains_key(&(kind, key.clone())) {
+            "other".to_string()
+        } else {
+            key
+        };
+        *self.counts.entry((kind, key)).or_insert(0) += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    /// `{ "csi": { "t": 3 }, "osc": { "52": 1 } }` — families with no miss
+    /// are left out.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut out = serde_json::Map::new();
+        for ((kind, key), n) in &self.counts {
+            let family = out
+                .entry(kind.name())
+                .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+            family[key.as_str()] = serde_json::json!(n);
+        }
+        serde_json::Value::Object(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_evicts_oldest_and_counts_evictions() {
+        let mut ring = OpRing::new(3);
+        ring.begin_chunk();
+        for i in 0..5 {
+            ring.push(format!("CursorUp({})", i));
+        }
+        let ops: Vec<&str> = ring.recent(None).map(|r| r.op.as_str()).collect();
+        assert_eq!(ops, ["CursorUp(2)", "CursorUp(3)", "CursorUp(4)"]);
+        assert_eq!(ring.evicted(), 2);
+        assert_eq!(ring.recent(Some(1)).next().unwrap().seq, 4);
+        ring.set_capacity(1);
+        assert_eq!(ring.recent(None).count(), 1);
+        assert_eq!(ring.evicted(), 4);
+    }
+
+    #[test]
+    fn ring_coalesces_repeats_within_a_chunk_only() {
+        let mut ring = OpRing::new(16);
+        ring.begin_chunk();
+        ring.push("CursorForward(1)".to_string());
+        ring.push("CursorForward(1)".to_string());
+        ring.push("CursorForward(1)".to_string());
+        ring.begin_chunk();
+        ring.push("CursorForward(1)".to_string());
+        let records: Vec<(u64, u64, u32)> = ring.recent(None).map(|r| (r.seq, r.chunk, r.repeat)).collect();
+        assert_eq!(records, [(0, 1, 3), (3, 2, 1)]);
+        let json = ring.to_json(None);
+        assert_eq!(json[0]["coalesced"], 3);
+        assert!(json[1].get("coalesced").is_none());
+    }
+
+    #[test]
+    fn ring_truncates_long_ops_on_a_char_boundary() {
+        let mut ring = OpRing::new(4);
+        ring.begin_chunk();
+        ring.push(format!("Print({:?})", "é".repeat(400)));
+        let op = &ring.recent(None).next().unwrap().op;
+        assert!(op.len() <= MAX_OP_LEN + '…'.len_utf8());
+        assert!(op.ends_with('…'));
+    }
+
+    #[test]
+    fn unhandled_counts_group_by_family() {
+        let mut counts = UnhandledCounts::default();
+        counts.add(SeqKind::Csi, seq_key(b">", 'm'));
+        counts.add(SeqKind::Csi, seq_key(b">", 'm'));
+        counts.add(SeqKind::Osc, "52".to_string());
+        counts.add(SeqKind::Esc, seq_key(b"", '\u{1}'));
+        assert_eq!(
+            counts.to_json(),
+            serde_json::json!({ "csi": { ">m": 2 }, "osc": { "52": 1 }, "esc": { "\\x01": 1 } })
+        );
+    }
+
+    #[test]
+    fn unhandled_counts_cap_distinct_keys() {
+        let mut counts = UnhandledCounts::default();
+        for i in 0..MAX_UNHANDLED_KEYS + 3 {
+            counts.add(SeqKind::Osc, format!("9{}", i));
+        }
+        // Known keys still count under their own name once the map is full.
+        counts.add(SeqKind::Osc, "90".to_string());
+        let json = counts.to_json();
+        assert_eq!(json["osc"]["other"], 3);
+        assert_eq!(json["osc"]["90"], 2);
+    }
+}
@@ -0,0 +1,255 @@
+//! In-memory PTY for tests: a pane with no process and no file descriptor.
+//!
+//! The test plays the program. `LoopbackHandle::output` is what the program
+//! prints — parsed into the pane's terminal right away, so the next line of the
+//! test sees it — and `take_input` is everything Kova sent back: keys,
+//! `send-keys`, and the parser's replies to DA/CPR queries. The process side
+//! (cwd, foreground program, children, exit) is whatever the test sets, and
+//! `command_started`/`command_finished` print what shell integration prints
+//! around a command, exit code included.
+
+use parking_lot::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::output::RawTap;
+use super::parser::VteHandler;
+use super::pipe::PipeSlot;
+use super::pty::{ProcessInfo, Pty, PtyBackend};
+use super::recording::RecorderSlot;
+use super::TerminalState;
+
+#[derive(Default)]
+struct Program {
+    input: Vec<u8>,
+    size: (u16, u16),
+    cwd: Option<String>,
+    foreground: Option<ProcessInfo>,
+    children: Vec<(u32, ProcessInfo)>,
+}
+
+/// The backend half: what the pane's `Pty` talks to.
+#[derive(Default)]
+struct Loopback {
+    program: Mutex<Program>,
+}
+
+impl PtyBackend for Loopback {
+    fn write(&self, data: &[u8]) {
+        self.program.lock().input.extend_from_slice(data);
+    }
+
+    fn resize(&self, cols: u16, rows: u16) {
+        self.program.lock().size = (cols, rows);
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    fn foreground_process(&self) -> Option<ProcessInfo> {
+        self.program.lock().foreground.clone()
+    }
+
+    fn child_processes(&self) -> Vec<(u32, ProcessInfo)> {
+        self.program.lock().children.clone()
+    }
+
+    fn cwd(&self) -> Option<String> {
+        self.program.lock().cwd.clone()
+    }
+}
+
+/// What the reader thread owns for a real shell.
+struct Reader {
+    parser: vte::Parser,
+    handler: VteHandler,
+    recorder: RecorderSlot,
+    pipe: PipeSlot,
+    raw_tap: RawTap,
+    shell_ready: Arc<AtomicBool>,
+    shell_exited: Arc<AtomicBool>,
+}
+
+/// The test's half: the program's end of the loopback.
+#[derive(Clone)]
+pub struct LoopbackHandle {
+    backend: Arc<Loopback>,
+    reader: Arc<Mutex<Reader>>,
+}
+
+impl LoopbackHandle {
+    /// Print `bytes` as the program, the way one PTY read would deliver them.
+    pub fn output(&self, bytes: &[u8]) {
+        let mut reader = self.reader.lock();
+        let Reader { parser, handler, recorder, pipe, raw_tap, shell_ready, .. } = &mut *reader;
+        shell_ready.store(true, Ordering::Relaxed);
+        if let Some(rec) = recorder.lock().as_mut() {
+            rec.output(bytes);
+        }
+        if let Some(sink) = pipe.lock().as_ref() {
+            sink.output(bytes);
+        }
+        raw_tap.output(bytes);
+        parser.advance(handler, bytes);
+        handler.apply_ops();
+    }
+
+    /// Everything written to the program since the last call.
+    pub fn take_input(&self) -> Vec<u8> {
+        std::mem::take(&mut self.backend.program.lock().input)
+    }
+
+    /// The window size the program was last given.
+    pub fn size(&self) -> (u16, u16) {
+        self.backend.program.lock().size
+    }
+
+    pub fn set_cwd(&self, cwd: Option<&str>) {
+        self.backend.program.lock().cwd = cwd.map(String::from);
+    }
+
+    /// Put a program in the foreground (`None`: back to the shell prompt).
+    pub fn set_foreground(&self, name: Option<&str>) {
+        self.backend.program.lock().foreground =
+            name.map(|n| ProcessInfo { name: n.to_string(), version: None });
+    }
+
+    /// Replace the shell's children with `(pid, name)` processes.
+    pub fn set_children(&self, children: &[(u32, &str)]) {
+        self.backend.program.lock().children = children
+            .iter()
+            .map(|&(pid, name)| (pid, ProcessInfo { name: name.to_string(), version: None }))
+            .collect();
+    }
+
+    /// Shell integration announcing `command` (OSC 133;C, then OSC 7777).
+    pub fn command_started(&self, command: &str) {
+        self.output(format!("\x1b]133;C\x07\x1b]7777;{}\x07", command).as_bytes());
+    }
+
+    /// Shell integration reporting the command's exit code (OSC 133;D).
+    pub fn command_finished(&self, exit_code: i32) {
+        self.output(format!("\x1b]133;D;{}\x07", exit_code).as_bytes());
+    }
+
+    /// The shell exits: what EOF on the master fd does for a real one.
+    pub fn exit(&self) {
+        self.reader.lock().shell_exited.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Pty {
+    /// A PTY whose program is the test holding the returned handle.
+    pub fn loopback(
+        pane_id: u32,
+        cols: u16,
+        rows: u16,
+        terminal: Arc<RwLock<TerminalState>>,
+        shell_ready: Arc<AtomicBool>,
+        shell_exited: Arc<AtomicBool>,
+    ) -> (Pty, LoopbackHandle) {
+        let backend = Arc::new(Loopback::default());
+        backend.resize(cols, rows);
+        let pty = Pty::with_backend(backend.clone());
+        let reader = Reader {
+            parser: vte::Parser::new(),
+            handler: VteHandler::new(terminal, backend.clone()),
+            recorder: pty.recorder(),
+            pipe: pty.pipe_slot().clone(),
+            raw_tap: RawTap::new(pane_id),
+            shell_ready,
+            shell_exited,
+        };
+        (pty, LoopbackHandle { backend, reader: Arc::new(Mutex::new(reader)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::pane::Pane;
+    use crate::terminal::DumpMode;
+
+    #[test]
+    fn output_reaches_the_screen_and_replies_go_back_to_the_program() {
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        assert!(!pane.is_ready());
+        // A prompt, then a cursor position query (CPR).
+        program.output(b"$ ls\r\n\x1b[6n");
+        assert!(pane.is_ready());
+        let screen = pane.terminal.read().dump_text(DumpMode::Visible, true).text;
+        assert_eq!(screen.lines().next(), Some("$ ls"));
+        assert_eq!(program.take_input(), b"\x1b[2;1R");
+        assert!(program.take_input().is_empty());
+    }
+
+    #[test]
+    fn what_kova_writes_and_resizes_reaches_the_program() {
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        pane.pty.write(b"echo hi\r");
+        pane.pty.resize(40, 10);
+        assert_eq!(program.take_input(), b"echo hi\r");
+        assert_eq!(program.size(), (40, 10));
+        assert_eq!(pane.pty.input_chars.load(std::sync::atomic::Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn the_process_side_is_what_the_test_says() {
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        assert_eq!(pane.cwd(), None);
+        assert_eq!(pane.foreground_process_name(), None);
+        program.set_cwd(Some("/work/kova"));
+        program.set_foreground(Some("vim"));
+        program.set_children(&[(4242, "vim")]);
+        assert_eq!(pane.cwd().as_deref(), Some("/work/kova"));
+        assert_eq!(pane.foreground_process_name().as_deref(), Some("vim"));
+        let children = pane.pty.child_processes();
+        assert_eq!(children.len(), 1);
+        assert_eq!((children[0].0, children[0].1.name.as_str()), (4242, "vim"));
+    }
+
+    #[test]
+    fn closing_the_pane_ends_its_pipe() {
+        use crate::terminal::pipe::{PipeFormat, PipeTarget};
+        let path = std::env::temp_dir().join(format!("kova-loopback-pipe-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        let command = format!("cat > {}; echo closed >> {}", path.display(), path.display());
+        let state = pane.start_pipe(PipeTarget::Command(command), PipeFormat::Raw).unwrap();
+        assert_eq!(state["dropped"], 0);
+        assert!(pane.start_pipe(PipeTarget::Path(path.clone()), PipeFormat::Text).is_err());
+        program.output(b"\x1b[1mbuilt\x1b[0m\r\n");
+        drop(pane);
+        // `closed` is only written once `cat` saw EOF.
+        let mut text = String::new();
+        for _ in 0..200 {
+            text = std::fs::read_to_string(&path).unwrap_or_default();
+            if text.ends_with("closed\n") {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(text, "\x1b[1mbuilt\x1b[0m\r\nclosed\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_finished_command_leaves_its_exit_code() {
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        program.command_started("make");
+        assert_eq!(pane.terminal.read().completion_reply(1, false), None);
+        program.command_finished(2);
+        let reply = pane.terminal.read().completion_reply(1, false).unwrap();
+        assert_eq!((reply["completed"].as_bool(), reply["exit_code"].as_i64()), (Some(true), Some(2)));
+        assert_eq!(pane.terminal.read().last_command.as_deref(), Some("make"));
+    }
+
+    #[test]
+    fn an_exited_program_leaves_a_dead_pane() {
+        let (pane, program) = Pane::loopback(20, 5, &Config::default());
+        assert!(pane.is_alive());
+        program.exit();
+        assert!(!pane.is_alive());
+    }
+}
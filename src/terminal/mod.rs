@@ -1,9 +1,19 @@
+pub mod export;
+pub mod inspect;
+#[cfg(test)]
+pub mod loopback;
+pub mod output;
 pub mod parser;
 pub mod paste_block;
+pub mod pipe;
+pub mod procinfo;
 pub mod pty;
+pub mod recording;
+pub mod scrollback;
+pub mod search;
+mod snapshot;
 
 use std::borrow::Cow;
-use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::time::Instant;
 
@@ -32,19 +42,28 @@ pub enum DumpMode {
     All,
 }
 
-/// Result of a text dump: the rendered text plus pane geometry / cursor at dump time.
+impl DumpMode {
+    /// Translate an IPC `mode` string. The caller has already validated the
+    /// value, but we map defensively to keep the boundary explicit.
+    pub fn from_ipc(mode: &str) -> Self {
+        match mode {
+            "scrollback" => DumpMode::Scrollback,
+            "all" => DumpMode::All,
+            _ => DumpMode::Visible,
+        }
+    }
+}
+
+/// Result of a text dump.
 pub struct DumpResult {
     pub text: String,
-    pub cols: u16,
-    pub rows: u16,
-    pub cursor_row: u16,
-    pub cursor_col: u16,
 }
 
 /// Convert [u8; 3] color to [f32; 3] for GPU rendering.
 /// Only called at render time — cells store compact [u8; 3] to save RAM.
 /// (Cell is 48→32 bytes, saving ~300MB+ with 10k scrollback × multiple panes)
 #[inline]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn color_to_f32(c: [u8; 3]) -> [f32; 3] {
     [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0]
 }
@@ -64,8 +83,11 @@ pub struct GridPos {
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SelectionMode {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     Normal,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     Word,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     Line,
 }
 
@@ -73,6 +95,7 @@ pub enum SelectionMode {
 pub struct Selection {
     pub anchor: GridPos,
     pub end: GridPos,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub mode: SelectionMode,
 }
 
@@ -200,7 +223,7 @@ pub struct TerminalState {
     pub cols: u16,
     pub rows: u16,
     grid: Vec<Row>,
-    scrollback: VecDeque<Row>,
+    scrollback: scrollback::Scrollback,
     pub scrollback_limit: usize,
     pub cursor_x: u16,
     pub cursor_y: u16,
@@ -281,6 +304,9 @@ pub struct TerminalState {
     pub completion_seen: AtomicBool,
     // Command running (between OSC 133;C and 133;D) — tab running indicator
     pub command_running: AtomicBool,
+    // Exit code of the completed command (OSC 133;D;<code>), for
+    // `wait-for-completion`. Cleared by the next OSC 133;C.
+    pub last_exit_code: Option<i32>,
     // The first OSC 133;D after spawn comes from the shell's startup precmd
     // (no command ran yet) and must not light the completion indicator.
     // Set on the first 133;C or 133;D seen.
@@ -325,13 +351,48 @@ pub struct TerminalState {
     /// them blank — exactly the desync this tracks. Used to decide whether
     /// the post-resize settle nudge is needed (see window.rs).
     rows_touched: Vec<bool>,
+    /// Bumped whenever absolute line numbers stop meaning what they meant
+    /// (reflow, scrollback clear). A resumable search restarts on a change.
+    content_epoch: u64,
+    /// Rows discarded off the front of a full scrollback since creation. Lets
+    /// a resumable search shift the line numbers it already reported.
+    lines_dropped: u64,
+    /// The share of `lines_dropped` taken by the app-wide scrollback budget
+    /// rather than this pane's own limits. Reported in `list-panes`.
+    budget_lines_dropped: u64,
+    /// Bumped each time the parser applies a chunk of output. A parked
+    /// `wait-for-text` only looks at the text again once this moved.
+    output_generation: u64,
+    /// Rung with each `output_generation` bump. Shared with the parked
+    /// `wait-for-text`s, which look at it without taking this lock.
+    pub output_signal: std::sync::Arc<search::OutputSignal>,
+    /// Unix time (ms) the parser last applied output, 0 before the first.
+    /// Unlike `last_activity_secs`, typing into the pane does not move it.
+    last_output_ms: u64,
+    /// Ring of the last parsed ops, while an IPC client inspects the pane
+    /// (`inspect-pane`). Filled by `VteHandler::apply_ops`.
+    pub op_ring: Option<inspect::OpRing>,
+    /// Sequences the parser saw but does not implement, since the pane opened.
+    pub unhandled: inspect::UnhandledCounts,
+    /// The pane's `text` pipe (`pipe-pane`), fed each row that scrolls off.
+    pub pipe: Option<pipe::PipeSlot>,
+    /// The pane's `pane-output` line stream, fed each line the cursor leaves.
+    /// `None` outside a pane (replays, exports, tests).
+    pub line_tap: Option<output::LineTap>,
 }
 
-/// A single line matching a filter query.
+/// A logical line (soft-wrapped rows joined) matching a search query.
 #[derive(Clone, Debug)]
 pub struct FilterMatch {
+    /// Absolute line of the first hit — where "jump to match" should land.
     pub abs_line: usize,
+    /// Text of the whole logical line, trailing blanks trimmed.
     pub text: String,
+    /// Byte ranges of each hit within `text`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub ranges: Vec<std::ops::Range<usize>>,
+    /// Cells covered by the hits, one span per physical row touched.
+    pub spans: Vec<search::MatchSpan>,
 }
 
 impl TerminalState {
@@ -345,7 +406,7 @@ impl TerminalState {
             cols,
             rows,
             grid,
-            scrollback: VecDeque::new(),
+            scrollback: scrollback::Scrollback::new(),
             scrollback_limit,
             cursor_x: 0,
             cursor_y: 0,
@@ -390,6 +451,7 @@ impl TerminalState {
             command_completed: AtomicBool::new(false),
             completion_seen: AtomicBool::new(false),
             command_running: AtomicBool::new(false),
+            last_exit_code: None,
             osc133_primed: false,
             last_command: None,
             last_command_slot_open: false,
@@ -406,6 +468,16 @@ impl TerminalState {
             current_hyperlink: 0,
             hyperlinks: vec![String::new()], // slot 0 = no hyperlink
             rows_touched: vec![false; rows as usize],
+            content_epoch: 0,
+            lines_dropped: 0,
+            budget_lines_dropped: 0,
+            output_generation: 0,
+            output_signal: Default::default(),
+            last_output_ms: 0,
+            op_ring: None,
+            unhandled: inspect::UnhandledCounts::default(),
+            pipe: None,
+            line_tap: None,
         }
     }
 
@@ -420,29 +492,49 @@ impl TerminalState {
             && !self.completion_seen.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// `wait-for-completion`'s reply once a command completed here, with the
+    /// exit code the shell gave (`null` if none); `None` until then. `give_up`
+    /// (the deadline passed) forces a reply, `timed_out` if still running.
+    pub fn completion_reply(&self, pane_id: u32, give_up: bool) -> Option<serde_json::Value> {
+        let completed = self.command_completed.load(std::sync::atomic::Ordering::Relaxed);
+        if !completed && !give_up {
+            return None;
+        }
+        Some(serde_json::json!({
+            "completed": completed,
+            "pane_id": pane_id,
+            "timed_out": !completed,
+            "exit_code": if completed { self.last_exit_code } else { None },
+        }))
+    }
+
     /// Mark the completion as seen (call while the pane is focused). Leaves
     /// `command_completed` alone so IPC `wait-for-completion` still sees it.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn ack_completion(&self) {
         self.completion_seen.store(true, std::sync::atomic::Ordering::Relaxed);
     }
 
     /// Set or clear the active OSC 8 hyperlink.
     pub fn set_hyperlink(&mut self, url: Option<String>) {
-        match url {
-            None => self.current_hyperlink = 0,
-            Some(u) => {
-                // Reuse existing slot if URL already known
-                if let Some(id) = self.hyperlinks.iter().position(|s| s == &u) {
-                    self.current_hyperlink = id as u16;
-                } else if self.hyperlinks.len() < u16::MAX as usize {
-                    self.current_hyperlink = self.hyperlinks.len() as u16;
-                    self.hyperlinks.push(u);
-                } else {
-                    // Table full — degrade to "no link" rather than silently
-                    // attaching the previous link's URL to this text.
-                    self.current_hyperlink = 0;
-                }
-            }
+        self.current_hyperlink = match url {
+            None => 0,
+            Some(u) => self.intern_hyperlink(u),
+        };
+    }
+
+    /// Slot of `url` in the hyperlink table, added if new.
+    fn intern_hyperlink(&mut self, url: String) -> u16 {
+        // Reuse existing slot if URL already known
+        if let Some(id) = self.hyperlinks.iter().position(|s| s == &url) {
+            id as u16
+        } else if self.hyperlinks.len() < u16::MAX as usize {
+            self.hyperlinks.push(url);
+            (self.hyperlinks.len() - 1) as u16
+        } else {
+            // Table full — degrade to "no link" rather than silently
+            // attaching the previous link's URL to this text.
+            0
         }
     }
 
@@ -483,18 +575,18 @@ impl TerminalState {
     /// end of the output are dropped.
     fn build_text(&self, mode: DumpMode, trim_trailing_blank_lines: bool) -> String {
         let mut text = String::new();
-        let push_rows = |rows: &mut dyn Iterator<Item = &Row>, out: &mut String| {
+        let push_rows = |rows: &mut dyn Iterator<Item = Cow<'_, Row>>, out: &mut String| {
             for row in rows {
-                Self::render_row(row, out);
+                Self::render_row(&row, out);
             }
         };
 
         match mode {
-            DumpMode::Visible => push_rows(&mut self.grid.iter(), &mut text),
+            DumpMode::Visible => push_rows(&mut self.grid.iter().map(Cow::Borrowed), &mut text),
             DumpMode::Scrollback => push_rows(&mut self.scrollback.iter(), &mut text),
             DumpMode::All => {
                 push_rows(&mut self.scrollback.iter(), &mut text);
-                push_rows(&mut self.grid.iter(), &mut text);
+                push_rows(&mut self.grid.iter().map(Cow::Borrowed), &mut text);
             }
         }
 
@@ -512,13 +604,7 @@ impl TerminalState {
     /// Build a text dump of this pane's content. See `build_text` for trim semantics.
     pub fn dump_text(&self, mode: DumpMode, trim_trailing_blank_lines: bool) -> DumpResult {
         let text = self.build_text(mode, trim_trailing_blank_lines);
-        DumpResult {
-            text,
-            cols: self.cols,
-            rows: self.rows,
-            cursor_row: self.cursor_y,
-            cursor_col: self.cursor_x,
-        }
+        DumpResult { text }
     }
 
     /// Return `(chars, bytes)` that `dump_text` would produce with the same args.
@@ -538,9 +624,16 @@ impl TerminalState {
             let sb_start = (sb_len - offset) as usize;
             let grid_end = (self.rows as i32 - offset).max(0) as usize;
 
+            // Only the rows on screen: cold rows are decoded, so walking the
+            // whole tail below the viewport would cost O(offset) per frame.
+            let sb_end = (sb_start + self.rows as usize).min(self.scrollback.len());
             let mut lines: Vec<Cow<'_, [Cell]>> = Vec::with_capacity(self.rows as usize);
-            for i in sb_start..self.scrollback.len() {
-                lines.push(Cow::Borrowed(self.scrollback[i].cells.as_slice()));
+            for i in sb_start..sb_end {
+                match self.scrollback.get(i) {
+                    Some(Cow::Borrowed(row)) => lines.push(Cow::Borrowed(row.cells.as_slice())),
+                    Some(Cow::Owned(row)) => lines.push(Cow::Owned(row.cells)),
+                    None => {}
+                }
             }
             for i in 0..grid_end.min(self.grid.len()) {
                 lines.push(Cow::Borrowed(self.grid[i].cells.as_slice()));
@@ -550,6 +643,7 @@ impl TerminalState {
         }
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scroll(&mut self, lines: i32) {
         if self.in_alt_screen {
             return; // No scrollback in alt screen
@@ -563,7 +657,7 @@ impl TerminalState {
         // Log scroll state for cross-terminal debugging
         if self.scroll_offset > 0 && old_offset == 0 {
             // Just started scrolling — log terminal identity + first scrollback line
-            let first_sb_text: String = self.scrollback.front()
+            let first_sb_text: String = self.scrollback.get(0)
                 .map(|r| r.cells.iter().take(60).map(|c| c.c).collect())
                 .unwrap_or_default();
             log::info!("SCROLL-START term_id={} sb_len={} offset={} cwd={:?} first_sb=\"{}\"",
@@ -993,6 +1087,7 @@ impl TerminalState {
     }
 
     fn advance_line(&mut self) {
+        self.leave_cursor_row();
         if self.cursor_y == self.scroll_bottom {
             self.scroll_up(1);
         } else if self.cursor_y < self.rows - 1 {
@@ -1000,20 +1095,33 @@ impl TerminalState {
         }
     }
 
+    /// Hand the row the cursor is moving down from to the `pane-output` tap.
+    fn leave_cursor_row(&mut self) {
+        if self.in_alt_screen {
+            return;
+        }
+        if let (Some(tap), Some(row)) = (self.line_tap.as_mut(), self.grid.get(self.cursor_y as usize)) {
+            tap.leave_row(row);
+        }
+    }
+
     fn push_to_scrollback(&mut self, mut row: Row) {
+        if let Some(slot) = &self.pipe {
+            pipe::scrolled_off(slot, &row);
+        }
         row.trim_trailing_blanks(self.default_fg, self.default_bg);
         self.scrollback.push_back(row);
-        if self.scrollback.len() > self.scrollback_limit {
-            // Buffer at its limit: drop the oldest line. All absolute line
-            // indices shift up by one — move the selection with its content,
+        let dropped = self.trim_scrollback();
+        if dropped > 0 {
+            // Buffer at its limit: the oldest lines are gone. All absolute
+            // line indices shift up — move the selection with its content,
             // or drop it once it reaches the trimmed edge.
-            self.scrollback.pop_front();
             if let Some(sel) = &mut self.selection {
-                if sel.anchor.line == 0 || sel.end.line == 0 {
+                if sel.anchor.line < dropped || sel.end.line < dropped {
                     self.selection = None;
                 } else {
-                    sel.anchor.line -= 1;
-                    sel.end.line -= 1;
+                    sel.anchor.line -= dropped;
+                    sel.end.line -= dropped;
                 }
             }
         }
@@ -1030,6 +1138,67 @@ impl TerminalState {
         }
     }
 
+    /// Apply the scrollback limits (lines, memory budget, spill file size).
+    /// Returns how many of the oldest lines were discarded.
+    fn trim_scrollback(&mut self) -> usize {
+        let dropped = self.scrollback.trim(self.scrollback_limit);
+        self.lines_dropped += dropped as u64;
+        dropped
+    }
+
+    /// Bound the in-memory scrollback to `memory_mb` (0 = lines only) and
+    /// spill what does not fit to a file of up to `disk_mb` (0 = drop it).
+    pub fn set_scrollback_storage(&mut self, memory_mb: usize, disk_mb: usize) {
+        let spill = if disk_mb > 0 {
+            match scrollback::SpillFile::create((disk_mb as u64) << 20) {
+                Ok(spill) => Some(spill),
+                Err(e) => {
+                    log::warn!("scrollback spill file unavailable, history stays in memory: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.scrollback.set_storage(memory_mb << 20, spill);
+        self.trim_scrollback();
+    }
+
+    /// Encode all hot scrollback rows. Lossless. Returns the bytes freed.
+    pub fn compact_scrollback(&mut self) -> usize {
+        self.scrollback.compact()
+    }
+
+    /// Free `bytes` of scrollback memory for the app-wide budget, oldest lines
+    /// first (spilled to disk when possible). Returns the bytes freed.
+    pub fn shed_scrollback(&mut self, bytes: usize) -> usize {
+        let (freed, dropped) = self.scrollback.shed(bytes);
+        if dropped > 0 {
+            self.lines_dropped += dropped as u64;
+            self.budget_lines_dropped += dropped as u64;
+            self.selection = None;
+            self.scroll_offset = self.scroll_offset.min(self.scrollback.len() as i32);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        freed
+    }
+
+    /// Scrollback lines this pane lost to the app-wide budget.
+    pub fn budget_lines_dropped(&self) -> u64 {
+        self.budget_lines_dropped
+    }
+
+    /// Moves whenever the content may have: output was applied, or the
+    /// scrollback was rebuilt or cleared.
+    pub fn content_generation(&self) -> (u64, u64) {
+        (self.content_epoch, self.output_generation)
+    }
+
+    /// Unix time (ms) of the pane's last output, `None` if it printed nothing yet.
+    pub fn last_output_ms(&self) -> Option<u64> {
+        (self.last_output_ms > 0).then_some(self.last_output_ms)
+    }
+
     fn scroll_up(&mut self, n: u16) {
         let top = self.scroll_top as usize;
         let bottom = self.scroll_bottom as usize;
@@ -1190,6 +1359,7 @@ impl TerminalState {
                 // untouched. Claude Code's /clear emits 2J+3J; aliasing 3J to
                 // 2J left stale UI snapshots in the scrollback forever.
                 self.scrollback.clear();
+                self.content_epoch += 1;
                 self.reset_scroll();
                 self.selection = None;
             }
@@ -1234,10 +1404,12 @@ impl TerminalState {
     /// a dead command. The pane falls back to its foreground process or cwd
     /// until something sets a title again. A title the user set by hand
     /// (`custom_title`) is deliberate and survives.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clear_scrollback_and_screen(&mut self) {
         self.dirty.store(true, Ordering::Relaxed);
         self.title = None;
         self.scrollback.clear();
+        self.content_epoch += 1;
         self.reset_scroll();
         self.selection = None;
         for row in &mut self.grid {
@@ -1577,6 +1749,7 @@ impl TerminalState {
         self.cursor_move_epoch.fetch_add(1, Ordering::Relaxed);
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scroll_offset(&self) -> i32 {
         self.scroll_offset
     }
@@ -1603,6 +1776,7 @@ impl TerminalState {
     /// Soft reset: restore rendering-critical state to sane defaults without
     /// clearing grid content or scrollback. Fixes persistent display corruption
     /// (wrong scroll region, hidden cursor, stuck SGR attributes).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn soft_reset(&mut self) {
         self.pending_wrap = false;
         self.g0_dec_graphics = false;
@@ -1638,11 +1812,13 @@ impl TerminalState {
     /// Restart row-coverage tracking. Called whenever the PTY winsize changes
     /// (real resize, repaint nudge, nudge restore) so `row_coverage` measures
     /// how much of the screen the app repainted since that event.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn reset_rows_touched(&mut self) {
         self.rows_touched.iter_mut().for_each(|t| *t = false);
     }
 
     /// Fraction of screen rows the app addressed since the last winsize change.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn row_coverage(&self) -> f32 {
         if self.rows_touched.is_empty() {
             return 0.0;
@@ -1688,6 +1864,7 @@ impl TerminalState {
         // Reflow rebuilds the scrollback/grid — absolute line indices held by
         // the selection no longer point at the same content.
         self.selection = None;
+        self.content_epoch += 1;
         self.rows_touched = vec![false; new_rows as usize];
 
         let old_cols = self.cols;
@@ -1707,12 +1884,12 @@ impl TerminalState {
         // sit at a stale width when the primary screen returns.
         if self.in_alt_screen {
             if new_cols != old_cols && !self.scrollback.is_empty() {
-                let sb: Vec<Row> = self.scrollback.drain(..).collect();
+                let sb: Vec<Row> = self.scrollback.take_rows();
                 let mut reflowed = Self::reflow_rows(sb, old_cols as usize, new_cols as usize, &self.blank);
                 for row in reflowed.iter_mut() {
                     row.trim_trailing_blanks(self.default_fg, self.default_bg);
                 }
-                self.scrollback = reflowed.into();
+                self.scrollback.set_rows(reflowed);
             }
             for row in &mut self.grid {
                 row.cells.resize(new_cols as usize, self.blank.clone());
@@ -1750,9 +1927,7 @@ impl TerminalState {
                         *y = y.saturating_sub(1);
                     }
                 }
-                while self.scrollback.len() > self.scrollback_limit {
-                    self.scrollback.pop_front();
-                }
+                self.lines_dropped += self.scrollback.trim(self.scrollback_limit) as u64;
                 // Growing: pull rows back from the scrollback into the top of
                 // the saved primary grid (mirror of the shrink path)
                 let mut pulled: Vec<Row> = Vec::new();
@@ -1793,7 +1968,7 @@ impl TerminalState {
             let cursor_row = self.cursor_y as usize;
             let cursor_col = self.cursor_x as usize;
 
-            let sb: Vec<Row> = self.scrollback.drain(..).collect();
+            let sb: Vec<Row> = self.scrollback.take_rows();
             let sb_len = sb.len();
             let mut stream: Vec<Row> = sb;
             stream.extend(std::mem::take(&mut self.grid));
@@ -1913,7 +2088,7 @@ impl TerminalState {
             for row in reflowed.iter_mut() {
                 row.trim_trailing_blanks(self.default_fg, self.default_bg);
             }
-            self.scrollback = reflowed.into();
+            self.scrollback.set_rows(reflowed);
             self.grid = grid;
             self.cursor_y = (cursor_in_grid.min(nr.saturating_sub(1))) as u16;
             self.cursor_x = new_cx;
@@ -1979,9 +2154,7 @@ impl TerminalState {
         }
 
         // Trim scrollback
-        while self.scrollback.len() > self.scrollback_limit {
-            self.scrollback.pop_front();
-        }
+        self.trim_scrollback();
 
         self.dirty.store(true, Ordering::Relaxed);
     }
@@ -2138,7 +2311,8 @@ impl TerminalState {
         self.scrollback.len()
     }
 
-    /// Estimated heap bytes used by this terminal (grid + scrollback + alt_grid).
+    /// Estimated heap bytes used by this terminal (grid + both scrollback tiers + alt_grid).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn mem_bytes(&self) -> usize {
         let cell_size = std::mem::size_of::<Cell>();
         let row_overhead = std::mem::size_of::<Row>();
@@ -2146,25 +2320,38 @@ impl TerminalState {
             rows.iter().map(|r| row_overhead + r.cells.capacity() * cell_size).sum::<usize>()
         };
         let grid = row_bytes(&self.grid);
-        let sb: usize = self.scrollback.iter()
-            .map(|r| row_overhead + r.cells.capacity() * cell_size)
-            .sum();
+        let sb = self.scrollback_mem();
         let alt = self.alt_grid.as_ref().map(|g| row_bytes(g)).unwrap_or(0);
-        grid + sb + alt
+        grid + sb.hot_bytes + sb.cold_bytes + alt
+    }
+
+    /// Per-tier scrollback row counts and estimated bytes.
+    pub fn scrollback_mem(&self) -> scrollback::ScrollbackMem {
+        self.scrollback.mem()
     }
 
     pub fn row_at(&self, abs_line: usize) -> Option<Cow<'_, Row>> {
         let sb_len = self.scrollback.len();
         if abs_line < sb_len {
-            Some(Cow::Borrowed(&self.scrollback[abs_line]))
+            self.scrollback.get(abs_line)
         } else {
             self.grid.get(abs_line - sb_len).map(Cow::Borrowed)
         }
     }
 
+    /// Rows from `abs_line` to the bottom of the grid. Cheaper than `row_at`
+    /// per line when scanning: spilled scrollback is read in batches.
+    pub fn rows_from(&self, abs_line: usize) -> impl Iterator<Item = Cow<'_, Row>> + '_ {
+        let sb_len = self.scrollback.len();
+        self.scrollback
+            .iter_from(abs_line)
+            .chain(self.grid.iter().skip(abs_line.saturating_sub(sb_len)).map(Cow::Borrowed))
+    }
+
     /// Returns (start_col, end_col) of the word at the given position.
     /// A "word" is a contiguous run of non-whitespace, non-delimiter characters,
     /// or a single delimiter/whitespace.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn word_bounds_at(&self, pos: GridPos) -> (u16, u16) {
         let Some(row) = self.row_at(pos.line) else { return (pos.col, pos.col) };
         let cells = &row.cells;
@@ -2201,6 +2388,7 @@ impl TerminalState {
         }
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn is_selected(&self, abs_line: usize, col: u16) -> bool {
         let Some((start, end)) = self.ordered_selection() else { return false };
         if abs_line < start.line || abs_line > end.line { return false; }
@@ -2215,6 +2403,7 @@ impl TerminalState {
         }
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn selected_text(&self) -> String {
         let Some((start, end)) = self.ordered_selection() else { return String::new() };
         let mut result = String::new();
@@ -2261,6 +2450,7 @@ impl TerminalState {
 
     /// Like selected_text() but joins consecutive non-empty lines with a space.
     /// Empty lines (paragraph breaks) are preserved as newlines.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn selected_text_joined(&self) -> String {
         let raw = self.selected_text();
         if raw.is_empty() { return raw; }
@@ -2303,6 +2493,7 @@ impl TerminalState {
         result
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn clear_selection(&mut self) {
         if self.selection.is_some() {
             self.selection = None;
@@ -2310,50 +2501,10 @@ impl TerminalState {
         }
     }
 
-    /// Search all lines (scrollback + grid) for a case-insensitive query.
-    /// Returns matching lines as (absolute_line_index, line_text).
-    pub fn search_lines(&self, query: &str) -> Vec<FilterMatch> {
-        if query.is_empty() {
-            return Vec::new();
-        }
-        let query_lower = query.to_lowercase();
-        let mut results = Vec::new();
-        let sb_len = self.scrollback.len();
-
-        let row_text = |row: &Row| -> String {
-            row.cells.iter().map(|c| {
-                if let Some(ref cluster) = c.cluster {
-                    cluster.to_string()
-                } else if c.c == '\0' {
-                    String::new()
-                } else {
-                    c.c.to_string()
-                }
-            }).collect::<String>().trim_end().to_string()
-        };
-
-        // Search scrollback
-        for (i, row) in self.scrollback.iter().enumerate() {
-            let text = row_text(row);
-            if text.to_lowercase().contains(&query_lower) {
-                results.push(FilterMatch { abs_line: i, text });
-            }
-        }
-
-        // Search grid
-        for (i, row) in self.grid.iter().enumerate() {
-            let text = row_text(row);
-            if text.to_lowercase().contains(&query_lower) {
-                results.push(FilterMatch { abs_line: sb_len + i, text });
-            }
-        }
-
-        results
-    }
-
     /// Set scroll_offset to center a given absolute line in the viewport.
     /// If the line is near the edges, it will be as close to center as possible
     /// while staying within valid scroll bounds.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn scroll_to_abs_line(&mut self, abs_line: usize) {
         let sb_len = self.scrollback.len();
         if abs_line >= sb_len {
@@ -2379,7 +2530,7 @@ impl TerminalState {
             let sb_start = (sb_len - offset) as usize;
             let sb_visible = self.scrollback.len() - sb_start;
             if visible_row < sb_visible {
-                self.scrollback.get(sb_start + visible_row).map_or(false, |r| r.wrapped)
+                self.scrollback.get(sb_start + visible_row).is_some_and(|r| r.wrapped)
             } else {
                 let grid_idx = visible_row - sb_visible;
                 self.grid.get(grid_idx).map_or(false, |r| r.wrapped)
@@ -2390,6 +2541,7 @@ impl TerminalState {
     /// Detect a URL at a given visible row/col position.
     /// Returns per-row highlight segments and the full URL string.
     /// Checks OSC 8 hyperlinks first, then falls back to auto-detection.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn url_at(&self, visible_row: usize, col: u16) -> Option<(Vec<(usize, u16, u16)>, String)> {
         let display = self.visible_lines();
         let cells = display.get(visible_row)?;
@@ -2526,6 +2678,7 @@ impl TerminalState {
     /// Disabled in alt screen (TUI apps) and when the cursor is parked above the
     /// content (explicit cursor positioning via escape sequences). Leading blank
     /// rows are fine — they're the common "clear screen + print a newline" flow.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn y_offset_rows(&self) -> usize {
         if self.in_alt_screen || self.scroll_offset != 0 {
             return 0;
@@ -3268,11 +3421,219 @@ mod tests {
     fn search_finds_text_with_wide_chars() {
         let mut t = term(20, 5);
         put_str(&mut t, "前 hello");
-        let res = t.search_lines("前 hello");
+        let res = search(&t, "前 hello", false, search::CaseMode::Insensitive);
         assert_eq!(res.len(), 1, "wide-char continuation cells must not break matching");
         assert_eq!(res[0].text, "前 hello");
     }
 
+    // --- Search engine (search.rs) ---
+
+    fn search(t: &TerminalState, pattern: &str, regex: bool, case: search::CaseMode) -> Vec<FilterMatch> {
+        let query = search::SearchQuery { pattern: pattern.into(), regex, case, accents: search::AccentMode::Sensitive };
+        let matcher = query.compile().unwrap();
+        let mut job = search::SearchJob::new(matcher, usize::MAX);
+        job.step(t, usize::MAX);
+        job.results().to_vec()
+    }
+
+    fn span(line: usize, start_col: u16, end_col: u16) -> search::MatchSpan {
+        search::MatchSpan { line, start_col, end_col }
+    }
+
+    #[test]
+    fn search_finds_match_across_soft_wrap() {
+        let mut t = term(8, 4);
+        put_str(&mut t, "xx needle yy");
+        let res = search(&t, "needle", false, search::CaseMode::Smart);
+        assert_eq!(res.len(), 1, "a hit straddling the wrap must be found");
+        assert_eq!(res[0].text, "xx needle yy");
+        assert_eq!(res[0].spans, vec![span(0, 3, 8), span(1, 0, 1)]);
+        assert_eq!(res[0].abs_line, 0);
+    }
+
+    #[test]
+    fn search_across_wrap_in_trimmed_scrollback_row() {
+        // A wrapped row pushed to scrollback loses its trailing blanks; the
+        // join must pad it back or the next row's text shifts left.
+        let mut t = term(8, 2);
+        put_str(&mut t, "ab      cd");
+        t.carriage_return();
+        t.newline();
+        t.carriage_return();
+        t.newline();
+        assert_eq!(t.scrollback_len(), 2);
+        let res = search(&t, "ab      cd", false, search::CaseMode::Smart);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].spans, vec![span(0, 0, 8), span(1, 0, 2)]);
+    }
+
+    #[test]
+    fn search_case_modes() {
+        let mut t = term(20, 3);
+        put_str(&mut t, "Error: error");
+        let pos = |res: Vec<FilterMatch>| -> Vec<u16> { res[0].spans.iter().map(|s| s.start_col).collect() };
+        assert_eq!(pos(search(&t, "error", false, search::CaseMode::Smart)), vec![0, 7]);
+        assert_eq!(pos(search(&t, "Error", false, search::CaseMode::Smart)), vec![0], "uppercase makes smart-case sensitive");
+        assert_eq!(pos(search(&t, "ERROR", false, search::CaseMode::Insensitive)), vec![0, 7]);
+        assert_eq!(pos(search(&t, "error", false, search::CaseMode::Sensitive)), vec![7]);
+    }
+
+    #[test]
+    fn search_regex_and_smart_case_ignores_escapes() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "PID 4242 EXIT 1");
+        let res = search(&t, r"pid \d+", true, search::CaseMode::Smart);
+        assert_eq!(res[0].spans, vec![span(0, 0, 8)], "\\d is syntax, not an uppercase letter");
+        let res = search(&t, r"\S+T", true, search::CaseMode::Smart);
+        assert_eq!(res[0].spans, vec![span(0, 9, 13)]);
+        // Literal mode escapes metacharacters.
+        assert!(search(&t, r"\d+", false, search::CaseMode::Smart).is_empty());
+    }
+
+    #[test]
+    fn search_invalid_regex_is_an_error() {
+        let q = search::SearchQuery { pattern: "(".into(), regex: true, ..Default::default() };
+        assert_eq!(q.compile().unwrap_err(), "invalid regex: unclosed group");
+    }
+
+    #[test]
+    fn search_spans_cover_wide_chars() {
+        let mut t = term(20, 3);
+        put_str(&mut t, "a前b");
+        let res = search(&t, "前b", false, search::CaseMode::Smart);
+        assert_eq!(res[0].spans, vec![span(0, 1, 4)], "the wide char covers two columns");
+        assert_eq!(&res[0].text[res[0].ranges[0].clone()], "前b");
+    }
+
+    fn search_folded(t: &TerminalState, pattern: &str, regex: bool) -> Vec<FilterMatch> {
+        let query = search::SearchQuery { pattern: pattern.into(), regex, ..Default::default() };
+        let mut job = search::SearchJob::new(query.compile().unwrap(), usize::MAX);
+        job.step(t, usize::MAX);
+        job.results().to_vec()
+    }
+
+    #[test]
+    fn search_folds_accents_unless_the_pattern_has_one() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "état de l'etat");
+        let res = search_folded(&t, "etat", false);
+        assert_eq!(res[0].spans, vec![span(0, 0, 4), span(0, 10, 14)]);
+        assert_eq!(&res[0].text[res[0].ranges[0].clone()], "état");
+        // An accent in the pattern asks for exactly that spelling.
+        let res = search_folded(&t, "état", false);
+        assert_eq!(res[0].spans, vec![span(0, 0, 4)]);
+    }
+
+    #[test]
+    fn search_matches_nfd_text_with_nfc_pattern() {
+        // macOS apps paste decomposed text: 'e' + U+0301 lands in one cluster cell.
+        let mut t = term(30, 3);
+        put_str(&mut t, "re\u{301}sume\u{301}");
+        assert_eq!(t.visible_lines()[0][1].cluster.as_deref(), Some("e\u{301}"));
+        let res = search_folded(&t, "r\u{e9}sum\u{e9}", false);
+        assert_eq!(res.len(), 1, "NFC pattern must match NFD text");
+        assert_eq!(res[0].spans, vec![span(0, 0, 6)]);
+        assert_eq!(res[0].ranges[0], 0..res[0].text.len());
+    }
+
+    #[test]
+    fn search_uses_full_case_folding() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "Straße ﬁle");
+        let res = search_folded(&t, "strasse", false);
+        assert_eq!(res[0].spans, vec![span(0, 0, 6)], "ß folds to ss and highlights its one cell");
+        let res = search_folded(&t, "fil", false);
+        assert_eq!(res[0].spans, vec![span(0, 7, 9)], "the ﬁ ligature folds to fi");
+    }
+
+    #[test]
+    fn search_folding_keeps_wide_char_spans_exact() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "日本 ÉTAT");
+        let res = search_folded(&t, "本 etat", false);
+        assert_eq!(res[0].spans, vec![span(0, 2, 9)]);
+        assert_eq!(&res[0].text[res[0].ranges[0].clone()], "本 ÉTAT");
+    }
+
+    #[test]
+    fn search_regex_folds_text_but_not_escapes() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "12 ÉTAT x");
+        // `\D` would become `\d` if lowercased with the text.
+        let res = search_folded(&t, r"\d+ etat\D", true);
+        assert_eq!(res[0].spans, vec![span(0, 0, 8)]);
+    }
+
+    #[test]
+    fn search_regex_accent_folds_literal_runs_but_not_classes() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "B ete");
+        let query = search::SearchQuery {
+            pattern: "[À-Ö]|été".into(),
+            regex: true,
+            accents: search::AccentMode::Insensitive,
+            ..Default::default()
+        };
+        let mut job = search::SearchJob::new(query.compile().unwrap(), usize::MAX);
+        job.step(&t, usize::MAX);
+        // Folded, the class would be `[A-O]` and take the "B".
+        assert_eq!(job.results()[0].spans, vec![span(0, 2, 5)]);
+    }
+
+    #[test]
+    fn search_regex_leaves_its_operators_to_the_letters_they_follow() {
+        let mut t = term(30, 3);
+        put_str(&mut t, "Straße ss ẞ");
+        // Folded, `ß+` would be `ss+` and find the plain "ss" too.
+        let res = search_folded(&t, "ß+", true);
+        assert_eq!(res[0].spans, vec![span(0, 4, 5), span(0, 10, 11)]);
+        // Decomposed text would shrink once normalized, moving the `?`.
+        let q = search::SearchQuery { pattern: "e\u{301}?".into(), regex: true, ..Default::default() };
+        assert!(q.compile().unwrap_err().contains("changes length when normalized"));
+    }
+
+    #[test]
+    fn search_job_follows_lines_trimmed_between_steps() {
+        let mut t = term(10, 2);
+        t.scrollback_limit = 5;
+        for i in 0..6 {
+            put_str(&mut t, &format!("L{}", i));
+            t.carriage_return();
+            t.newline();
+        }
+        let matcher = search::SearchQuery::literal("L", search::CaseMode::Smart).compile().unwrap();
+        let mut job = search::SearchJob::new(matcher, usize::MAX);
+        assert!(!job.step(&t, 3));
+        let first: Vec<String> = job.results().iter().map(|m| m.text.clone()).collect();
+        // Two more lines arrive: the two oldest rows fall off the scrollback.
+        for i in 6..8 {
+            put_str(&mut t, &format!("L{}", i));
+            t.carriage_return();
+            t.newline();
+        }
+        while !job.step(&t, 3) {}
+        let res = job.results();
+        for m in res {
+            assert_eq!(t.row_at(m.abs_line).map(|r| r.cells[1].c), m.text.chars().nth(1), "stale line number for {}", m.text);
+        }
+        let texts: Vec<&str> = res.iter().map(|m| m.text.as_str()).collect();
+        assert!(texts.ends_with(&["L6", "L7"]), "new output must be scanned, got {:?} (first pass {:?})", texts, first);
+        assert!(!texts.contains(&"L0"), "trimmed lines must be dropped from the results");
+    }
+
+    #[test]
+    fn search_job_restarts_after_reflow() {
+        let mut t = term(10, 3);
+        put_str(&mut t, "one two three");
+        let matcher = search::SearchQuery::literal("three", search::CaseMode::Smart).compile().unwrap();
+        let mut job = search::SearchJob::new(matcher, usize::MAX);
+        job.step(&t, 1);
+        t.resize(20, 3);
+        while !job.step(&t, 1) {}
+        assert_eq!(job.results().len(), 1);
+        assert_eq!(job.results()[0].spans, vec![span(0, 8, 13)]);
+    }
+
     #[test]
     fn url_detection_spans_wide_chars() {
         let mut t = term(30, 5);
@@ -3318,10 +3679,275 @@ mod tests {
         assert_eq!(t.current_bg, [10, 255, 20]);
     }
 
+    // --- wait-for-text (search::TextWait) ---
+
+    fn text_wait(t: &TerminalState, pattern: &str, regex: bool, scope: search::WaitScope) -> search::TextWait {
+        let query = search::SearchQuery { pattern: pattern.into(), regex, ..Default::default() };
+        search::TextWait::new(query.compile().unwrap(), scope, t)
+    }
+
+    /// What `apply_ops` does after each chunk: the wait only looks again then.
+    fn print(t: &mut TerminalState, s: &str) {
+        put_str(t, s);
+        t.output_generation += 1;
+    }
+
+    #[test]
+    fn text_wait_on_the_screen_reports_the_line_and_the_groups_as_displayed() {
+        let mut t = term(12, 3);
+        print(&mut t, "Listening on PORT 3000");
+        let mut wait = text_wait(&t, r"port (\d+)|(?<never>x)", true, search::WaitScope::Screen);
+        let hit = wait.check(&t).expect("already on screen");
+        // The row the hit starts on, as in `search-pane`: past the wrap.
+        assert_eq!(hit["line"], 1);
+        assert_eq!(hit["text"], "Listening on PORT 3000");
+        // Smart case folded the line to match; the groups still read as shown.
+        assert_eq!(hit["match"], "PORT 3000");
+        assert_eq!(hit["groups"], serde_json::json!(["3000", null]));
+        assert_eq!(hit["named_groups"], serde_json::json!({ "never": null }));
+    }
+
+    #[test]
+    fn text_wait_on_new_output_ignores_what_was_there() {
+        let mut t = term(20, 3);
+        print(&mut t, "$ ready? ");
+        let mut wait = text_wait(&t, "ready", false, search::WaitScope::NewOutput);
+        assert!(wait.check(&t).is_none(), "the prompt predates the wait");
+        // Same line, after the cursor: that is new output.
+        print(&mut t, "ready");
+        let hit = wait.check(&t).unwrap();
+        assert_eq!(hit["text"], "$ ready? ready");
+        assert_eq!(hit["match"], "ready");
+    }
+
+    #[test]
+    fn text_wait_follows_its_lines_off_the_screen() {
+        let mut t = TerminalState::new(20, 2, 3, FG, BG); // 3 lines of scrollback
+        let mut wait = text_wait(&t, "needle", false, search::WaitScope::NewOutput);
+        for line in ["needle", "a", "b", "c", "d"] {
+            put_str(&mut t, line);
+            t.carriage_return();
+            t.newline();
+        }
+        // Printed after the wait, then scrolled off and trimmed away: gone.
+        assert!(t.lines_dropped > 0);
+        t.output_generation += 1;
+        assert!(wait.check(&t).is_none());
+        print(&mut t, "needle");
+        let hit = wait.check(&t).unwrap();
+        assert_eq!(hit["line_id"], t.lines_dropped + hit["line"].as_u64().unwrap());
+        assert_eq!(hit["line_id"], 5);
+    }
+
+    // --- Cold scrollback tier (scrollback.rs) ---
+
+    /// Same content in two terminals: one keeps every scrollback row hot, the
+    /// other encodes all of them. Anything observable must be identical.
+    fn hot_and_cold(cols: u16, rows: u16, lines: usize) -> (TerminalState, TerminalState) {
+        let mut hot = TerminalState::new(cols, rows, 1000, FG, BG);
+        let mut cold = TerminalState::new(cols, rows, 1000, FG, BG);
+        hot.scrollback = scrollback::Scrollback::with_hot_limit(usize::MAX);
+        cold.scrollback = scrollback::Scrollback::with_hot_limit(0);
+        for t in [&mut hot, &mut cold] {
+            for i in 0..lines {
+                t.set_sgr(&[31]);
+                put_str(t, &format!("{:03}", i));
+                t.set_sgr(&[0]);
+                t.current_hyperlink = (i % 3) as u16;
+                put_str(t, &format!(" café 前 {}", "word ".repeat(i % 7)));
+                t.put_char('🇫');
+                t.put_char('🇷');
+                t.newline();
+                t.carriage_return();
+            }
+        }
+        (hot, cold)
+    }
+
+    fn screen(t: &TerminalState) -> Vec<String> {
+        t.visible_lines().iter().map(|l| {
+            l.iter().map(|c| format!("{}{:?}{:?}{}", c.cluster.as_deref().unwrap_or(""), c.c, c.fg, c.hyperlink_id)).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn cold_rows_scroll_dump_and_search_like_hot_rows() {
+        let (mut hot, mut cold) = hot_and_cold(16, 4, 30);
+        assert!(cold.scrollback_mem().cold_rows > 0);
+        assert_eq!(cold.scrollback_mem().hot_rows, 0);
+        assert_eq!(hot.scrollback_len(), cold.scrollback_len());
+        for _ in 0..=hot.scrollback_len() {
+            assert_eq!(screen(&hot), screen(&cold));
+            hot.scroll(1);
+            cold.scroll(1);
+        }
+        assert_eq!(hot.dump_text(DumpMode::All, true).text, cold.dump_text(DumpMode::All, true).text);
+        let found = |t: &TerminalState| {
+            search(t, "前 word word", false, search::CaseMode::Smart).iter()
+                .map(|m| (m.abs_line, m.spans.clone())).collect::<Vec<_>>()
+        };
+        assert!(!found(&hot).is_empty());
+        assert_eq!(found(&hot), found(&cold));
+        assert!(cold.mem_bytes() < hot.mem_bytes());
+    }
+
+    #[test]
+    fn reflow_through_cold_tier_matches_hot() {
+        let (mut hot, mut cold) = hot_and_cold(16, 4, 30);
+        for (cols, rows) in [(9, 4), (23, 6), (16, 4)] {
+            hot.resize(cols, rows);
+            cold.resize(cols, rows);
+            assert_eq!(hot.dump_text(DumpMode::All, true).text, cold.dump_text(DumpMode::All, true).text);
+            assert_eq!((hot.cursor_x, hot.cursor_y), (cold.cursor_x, cold.cursor_y));
+        }
+        // Growing the grid pulls cold rows back into it.
+        hot.resize(16, 20);
+        cold.resize(16, 20);
+        assert_eq!(screen(&hot), screen(&cold));
+    }
+
+    #[test]
+    fn spilled_history_scrolls_searches_and_dumps() {
+        let mut t = TerminalState::new(20, 3, 4, FG, BG); // 4 lines in memory
+        let dir = std::env::temp_dir().join("kova-spill-tests");
+        t.scrollback.set_storage(0, Some(scrollback::SpillFile::create_in(&dir, 1 << 20).unwrap()));
+        for i in 0..40 {
+            put_str(&mut t, &format!("line {}", i));
+            t.newline();
+            t.carriage_return();
+        }
+        let mem = t.scrollback_mem();
+        assert_eq!(mem.hot_rows + mem.cold_rows, 4);
+        assert_eq!(t.scrollback_len(), 38, "nothing is lost to the line limit");
+        assert_eq!(t.lines_dropped, 0);
+
+        t.scroll(1000);
+        assert_eq!(row_text(&t, 0), "line 0");
+        let text = t.dump_text(DumpMode::Scrollback, true).text;
+        assert!(text.starts_with("line 0\nline 1\n"), "got {:?}", &text[..20]);
+        let hits = search(&t, "line 3$", true, search::CaseMode::Smart);
+        assert_eq!(hits.iter().map(|m| m.abs_line).collect::<Vec<_>>(), vec![3]);
+    }
+
+    fn budget_pane(lines: usize, last_activity: u64, protected: bool) -> scrollback::BudgetPane {
+        let mut t = TerminalState::new(40, 2, 10_000, FG, BG);
+        for i in 0..lines {
+            put_str(&mut t, &format!("{} some output", i));
+            t.newline();
+            t.carriage_return();
+        }
+        t.last_activity_secs.store(last_activity, Ordering::Relaxed);
+        scrollback::BudgetPane { terminal: std::sync::Arc::new(parking_lot::RwLock::new(t)), protected }
+    }
+
+    fn sb_bytes(p: &scrollback::BudgetPane) -> usize {
+        let mem = p.terminal.read().scrollback_mem();
+        mem.hot_bytes + mem.cold_bytes
+    }
+
+    #[test]
+    fn global_budget_compresses_idle_panes_before_dropping_lines() {
+        let panes = [budget_pane(200, 100, false), budget_pane(200, 50, false), budget_pane(200, 10, true)];
+        let total: usize = panes.iter().map(sb_bytes).sum();
+        // Compressing one idle pane is enough: the one idle longest goes first.
+        scrollback::enforce_budget(&panes, total - sb_bytes(&panes[0]) / 2);
+        assert_eq!(panes[1].terminal.read().scrollback_mem().hot_rows, 0);
+        assert_eq!(panes[0].terminal.read().scrollback_mem().cold_rows, 0);
+        assert!(panes.iter().all(|p| p.terminal.read().budget_lines_dropped() == 0));
+
+        // A budget below what the protected pane alone uses empties the others.
+        scrollback::enforce_budget(&panes, 1);
+        assert_eq!(panes[0].terminal.read().budget_lines_dropped(), 199);
+        assert_eq!(panes[1].terminal.read().budget_lines_dropped(), 199);
+        assert_eq!(panes[2].terminal.read().scrollback_len(), 199, "protected pane untouched");
+        assert_eq!(panes[2].terminal.read().scrollback_mem().cold_rows, 0);
+    }
+
+    #[test]
+    fn global_budget_skips_panes_with_a_selection() {
+        let panes = [budget_pane(50, 0, false)];
+        panes[0].terminal.write().selection = Some(Selection {
+            anchor: GridPos { line: 3, col: 0 },
+            end: GridPos { line: 3, col: 2 },
+            mode: SelectionMode::Normal,
+        });
+        assert_eq!(scrollback::enforce_budget(&panes, 1), 0);
+        assert_eq!(panes[0].terminal.read().scrollback_len(), 49);
+    }
+
+    /// 100k lines of styled output, all hot vs. default tiering vs. mostly
+    /// spilled to disk. Run with
+    /// `cargo test --release -- --ignored bench_ --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_cold_scrollback_scroll_and_search() {
+        use std::time::Instant;
+        let build = |hot_limit: usize, spill: bool| {
+            let mut t = TerminalState::new(120, 50, 100_000, FG, BG);
+            t.scrollback = scrollback::Scrollback::with_hot_limit(hot_limit);
+            if spill {
+                let dir = std::env::temp_dir().join("kova-spill-tests");
+                t.scrollback.set_storage(1 << 20, Some(scrollback::SpillFile::create_in(&dir, 64 << 20).unwrap()));
+            }
+            for i in 0..100_050 {
+                t.set_sgr(&[32]);
+                put_str(&mut t, &format!("{:6} ", i));
+                t.set_sgr(&[0]);
+                put_str(&mut t, "src/terminal/mod.rs:2170: warning: unused variable `x` ");
+                t.set_sgr(&[1, 33]);
+                put_str(&mut t, "note");
+                t.set_sgr(&[0]);
+                t.newline();
+                t.carriage_return();
+            }
+            t
+        };
+        const SEARCH_SLOWDOWN_MAX: u32 = 3;
+        let mut hot_search = None;
+        for (name, hot_limit, spill) in
+            [("all hot", usize::MAX, false), ("tiered", scrollback::HOT_ROWS, false), ("spilled", scrollback::HOT_ROWS, true)]
+        {
+            let mut t = build(hot_limit, spill);
+            let mem = t.scrollback_mem();
+
+            // One frame per page, from the bottom to the top.
+            let start = Instant::now();
+            let mut frames = 0u32;
+            while t.scroll_offset < t.scrollback_len() as i32 {
+                t.scroll(t.rows as i32);
+                std::hint::black_box(t.visible_lines());
+                frames += 1;
+            }
+            let per_frame = start.elapsed() / frames;
+
+            let start = Instant::now();
+            let hits = search(&t, "variable `y`", false, search::CaseMode::Smart);
+            let search_time = start.elapsed();
+            assert!(hits.is_empty());
+
+            println!(
+                "{:8}: scrollback {:.1} MB hot + {:.1} MB cold + {:.1} MB disk, {:?}/frame, search {:?}",
+                name,
+                mem.hot_bytes as f64 / (1024.0 * 1024.0),
+                mem.cold_bytes as f64 / (1024.0 * 1024.0),
+                mem.disk_bytes as f64 / (1024.0 * 1024.0),
+                per_frame,
+                search_time,
+            );
+            // A frame at 120 Hz is ~8 ms; decoding a page must be a small part of it.
+            assert!(per_frame.as_micros() < 1000, "{}: {:?} per frame", name, per_frame);
+            // Decoding (and reading back) costs something, but a search of
+            // the whole history must stay within a small multiple of the
+            // all-hot scan.
+            let hot = *hot_search.get_or_insert(search_time);
+            assert!(search_time < hot * SEARCH_SLOWDOWN_MAX, "{}: search {:?} vs {:?} all hot", name, search_time, hot);
+        }
+    }
+
     // --- Cmd+R rows-nudge round-trip (probe) ---
     fn sb_dump(t: &TerminalState) -> Vec<String> {
         (0..t.scrollback_len()).map(|i| {
-            t.scrollback[i].cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
+            t.scrollback.get(i).unwrap().cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
         }).collect()
     }
 
@@ -3407,7 +4033,7 @@ mod tests {
         eprintln!("BEFORE {:?} sb={}", before, sb_before);
         eprintln!("AFTER  {:?} sb={}", after, t.scrollback_len());
         eprintln!("SCROLLBACK {:?}", (0..t.scrollback_len()).map(|i| {
-            t.scrollback[i].cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
+            t.scrollback.get(i).unwrap().cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
         }).collect::<Vec<_>>());
     }
 
@@ -3422,13 +4048,13 @@ mod tests {
         // Now scrollback has older lines; grid bottom row is the blank line after L11.
         let before: Vec<String> = (0..5).map(|r| row_text(&t, r)).collect();
         let sb_before: Vec<String> = (0..t.scrollback_len()).map(|i| {
-            t.scrollback[i].cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
+            t.scrollback.get(i).unwrap().cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
         }).collect();
         t.resize(10, 4);
         t.resize(10, 5);
         let after: Vec<String> = (0..5).map(|r| row_text(&t, r)).collect();
         let sb_after: Vec<String> = (0..t.scrollback_len()).map(|i| {
-            t.scrollback[i].cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
+            t.scrollback.get(i).unwrap().cells.iter().filter(|c| c.c != '\0').map(|c| c.c).collect::<String>().trim_end().to_string()
         }).collect();
         eprintln!("GRID BEFORE {:?}", before);
         eprintln!("GRID AFTER  {:?}", after);
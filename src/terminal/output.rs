@@ -0,0 +1,253 @@
+//! `pane-output`: a pane's output, published to IPC subscribers as it arrives.
+//!
+//! Two shapes. `lines` (`LineTap`) publishes a logical line — soft wraps
+//! joined — when the cursor leaves it for the next one, as plain text; it
+//! lives in the `TerminalState`, which is the only place that knows when that
+//! happens. `raw` (`RawTap`) publishes every chunk as it was read from the
+//! PTY, escape sequences included; it lives with whoever feeds the parser.
+//!
+//! Both number what they publish so a client can spot a hole — typically
+//! after Kova dropped it for falling behind and it reconnected. `seq` counts
+//! the pane's lines, `offset` its bytes, from the moment it opened. The
+//! counters run whether anybody listens or not; the text is only built when
+//! somebody does. A full-screen app on the alternate screen redraws rather
+//! than prints lines, so nothing is published from there.
+//!
+//! The other side of output is its absence: the parser stamps each chunk in
+//! milliseconds (`TerminalState::last_output_ms`), which `wait-for-idle`
+//! (`IdleWait`) and the `pane-quiet` events measure silence against.
+
+use crate::ipc::topic;
+
+use super::{Row, TerminalState};
+
+/// The `lines` half, held by the pane's terminal.
+pub struct LineTap {
+    pane_id: u32,
+    /// Number of the next line to end.
+    seq: u64,
+    /// Rows of the current line that already wrapped away from the cursor.
+    partial: String,
+}
+
+impl LineTap {
+    pub fn new(pane_id: u32) -> Self {
+        LineTap { pane_id, seq: 0, partial: String::new() }
+    }
+
+    /// The cursor is moving down off `row`. A wrapped row continues on the
+    /// next one; any other row ends the line.
+    pub fn leave_row(&mut self, row: &Row) {
+        if !crate::ipc::has_subscribers(topic::PANE_OUTPUT) {
+            self.partial.clear();
+            if !row.wrapped {
+                self.seq += 1;
+            }
+            return;
+        }
+        TerminalState::render_row(row, &mut self.partial);
+        if row.wrapped {
+            return;
+        }
+        let mut text = std::mem::take(&mut self.partial);
+        text.pop(); // render_row's '\n'
+        crate::ipc::publish_pane(
+            topic::PANE_OUTPUT,
+            self.pane_id,
+            serde_json::json!({
+                "event": "pane-output",
+                "pane_id": self.pane_id,
+                "seq": self.seq,
+                "text": text,
+            }),
+        );
+        self.seq += 1;
+    }
+}
+
+/// The `raw` half, held next to the parser it is fed alongside.
+pub struct RawTap {
+    pane_id: u32,
+    /// Bytes of output seen so far.
+    offset: u64,
+    /// A UTF-8 sequence cut by the end of the last chunk, held back so it
+    /// goes out whole with the next one instead of as two replacement chars.
+    tail: Vec<u8>,
+}
+
+impl RawTap {
+    pub fn new(pane_id: u32) -> Self {
+        RawTap { pane_id, offset: 0, tail: Vec::new() }
+    }
+
+    /// A chunk of output, as read from the PTY.
+    pub fn output(&mut self, bytes: &[u8]) {
+        let offset = self.offset;
+        self.offset += bytes.len() as u64;
+        if !crate::ipc::has_subscribers(topic::PANE_OUTPUT_RAW) {
+            self.tail.clear();
+            return;
+        }
+        let start = offset - self.tail.len() as u64;
+        let mut chunk = std::mem::take(&mut self.tail);
+        chunk.extend_from_slice(bytes);
+        let keep = incomplete_suffix(&chunk);
+        self.tail = chunk.split_off(chunk.len() - keep);
+        if chunk.is_empty() {
+            return;
+        }
+        crate::ipc::publish_pane(topic::PANE_OUTPUT_RAW, self.pane_id, raw_event(self.pane_id, start, &chunk));
+    }
+}
+
+/// A raw chunk as text in `data`, or, when it is not UTF-8, byte for byte
+/// in `data_base64`: `bytes` always adds up to the next `offset`.
+fn raw_event(pane_id: u32, offset: u64, chunk: &[u8]) -> serde_json::Value {
+    let mut event = serde_json::json!({
+        "event": "pane-output",
+        "pane_id": pane_id,
+        "offset": offset,
+        "bytes": chunk.len(),
+    });
+    match std::str::from_utf8(chunk) {
+        Ok(text) => event["data"] = text.into(),
+        Err(_) => event["data_base64"] = base64(chunk).into(),
+    }
+    event
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// `wait-for-idle`: the pane has printed nothing for `quiet_ms`.
+///
+/// Silence is counted from the later of the last output and the request
+/// itself, so the wait always lasts at least `quiet_ms`. A client that just
+/// sent keys must not be told the pane is idle before the program even had
+/// time to answer them.
+pub struct IdleWait {
+    quiet_ms: u64,
+    since_ms: u64,
+}
+
+impl IdleWait {
+    pub fn new(quiet_ms: u64) -> Self {
+        IdleWait { quiet_ms, since_ms: now_ms() }
+    }
+
+    /// The reply once the pane has been quiet long enough, `None` until then.
+    /// `give_up` (the deadline passed) forces a reply, `timed_out` if still busy.
+    pub fn check(&self, pane_id: u32, term: &TerminalState, give_up: bool) -> Option<serde_json::Value> {
+        self.check_at(pane_id, term, give_up, now_ms())
+    }
+
+    fn check_at(&self, pane_id: u32, term: &TerminalState, give_up: bool, now: u64) -> Option<serde_json::Value> {
+        let silent_ms = now.saturating_sub(term.last_output_ms.max(self.since_ms));
+        let idle = silent_ms >= self.quiet_ms;
+        if !idle && !give_up {
+            return None;
+        }
+        Some(serde_json::json!({
+            "pane_id": pane_id,
+            "idle": idle,
+            "timed_out": !idle,
+            "silent_ms": silent_ms,
+            "last_output_ms": term.last_output_ms(),
+        }))
+    }
+}
+
+/// Unix time in milliseconds, the clock output is stamped with.
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Length of the UTF-8 sequence the chunk ends in the middle of, 0 if none.
+fn incomplete_suffix(bytes: &[u8]) -> usize {
+    // A sequence is at most 4 bytes: only its lead can be 1 to 3 bytes back.
+    for back in 1..=bytes.len().min(3) {
+        let b = bytes[bytes.len() - back];
+        if b & 0xC0 == 0x80 {
+            continue; // continuation byte, keep looking for the lead
+        }
+        let len = match b {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return 0,
+        };
+        return if len > back { back } else { 0 };
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::{DEFAULT_BG, DEFAULT_FG};
+
+    #[test]
+    fn a_cut_utf8_sequence_is_held_back() {
+        let e_acute = "é".as_bytes();
+        assert_eq!(incomplete_suffix(b"abc"), 0);
+        assert_eq!(incomplete_suffix(&[b'a', e_acute[0]]), 1);
+        assert_eq!(incomplete_suffix(&[b'a', e_acute[0], e_acute[1]]), 0);
+        let crab = "🦀".as_bytes();
+        assert_eq!(incomplete_suffix(&crab[..3]), 3);
+        assert_eq!(incomplete_suffix(crab), 0);
+        // Not UTF-8 at all: nothing to wait for.
+        assert_eq!(incomplete_suffix(&[0xFF]), 0);
+    }
+
+    #[test]
+    fn a_chunk_that_is_not_utf8_goes_out_as_base64() {
+        let event = raw_event(3, 10, b"ok\r\n");
+        assert_eq!(event, serde_json::json!({
+            "event": "pane-output", "pane_id": 3, "offset": 10, "bytes": 4, "data": "ok\r\n",
+        }));
+        let event = raw_event(3, 14, b"a\xFFb");
+        assert_eq!(event["bytes"], 3);
+        assert_eq!(event["data_base64"], "Yf9i");
+        assert!(event.get("data").is_none());
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+    }
+
+    #[test]
+    fn idle_counts_silence_from_the_later_of_the_output_and_the_request() {
+        let mut term = TerminalState::new(20, 5, 100, DEFAULT_FG, DEFAULT_BG);
+        let wait = IdleWait { quiet_ms: 500, since_ms: 10_000 };
+        // Silent for ages before the request: it still has to wait its turn.
+        assert!(wait.check_at(7, &term, false, 10_400).is_none());
+        let reply = wait.check_at(7, &term, false, 10_500).unwrap();
+        assert_eq!(reply["idle"], true);
+        assert_eq!(reply["last_output_ms"], serde_json::Value::Null);
+        // Output after the request restarts the count.
+        term.last_output_ms = 10_300;
+        assert!(wait.check_at(7, &term, false, 10_700).is_none());
+        let reply = wait.check_at(7, &term, false, 10_800).unwrap();
+        assert_eq!(reply["silent_ms"], 500);
+        assert_eq!(reply["last_output_ms"], 10_300);
+        // The deadline answers anyway, as a timeout.
+        let reply = wait.check_at(7, &term, true, 10_400).unwrap();
+        assert_eq!(reply["idle"], false);
+        assert_eq!(reply["timed_out"], true);
+    }
+}
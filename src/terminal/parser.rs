@@ -1,10 +1,22 @@
 use parking_lot::RwLock;
-use std::os::fd::OwnedFd;
 use std::sync::Arc;
 use vte::{Params, Perform};
 
+use super::inspect::{seq_key, SeqKind};
+use super::pty::{NullBackend, PtyBackend};
 use super::{CursorShape, TerminalState};
 
+/// Feed `chunks` into `term` the way the PTY reader does: one parser across
+/// all of them, the ops applied after each. Queries (DA, DSR, …) go unanswered.
+pub fn drive_chunks<'a>(term: &Arc<RwLock<TerminalState>>, chunks: impl IntoIterator<Item = &'a [u8]>) {
+    let mut parser = vte::Parser::new();
+    let mut handler = VteHandler::new(term.clone(), Arc::new(NullBackend));
+    for chunk in chunks {
+        parser.advance(&mut handler, chunk);
+        handler.apply_ops();
+    }
+}
+
 /// Walk up from `path` to find `.git` and extract the branch name.
 /// Supports both regular repos (`.git/HEAD`) and worktrees (`.git` file pointing to gitdir).
 /// Returns `None` if not in a git repo.
@@ -48,6 +60,7 @@ fn parse_head(content: &str) -> Option<String> {
 
 /// Buffered terminal operation. Accumulated during VTE parsing (no lock held),
 /// then replayed in a single write lock acquisition.
+#[derive(Debug)]
 enum TermOp {
     /// Text to display (grapheme clusters from print buffer)
     Print(String),
@@ -109,8 +122,8 @@ enum TermOp {
     SetLastCommand(String),
     /// OSC 133;C — command started
     CommandStarted,
-    /// OSC 133;D — command completed
-    SetCommandCompleted,
+    /// OSC 133;D — command completed, with its exit code when the shell says
+    SetCommandCompleted(Option<i32>),
     // Kitty keyboard protocol
     KittyKeyboardPush(u8),
     KittyKeyboardPop(u16),
@@ -139,7 +152,8 @@ enum TermOp {
 
 pub struct VteHandler {
     terminal: Arc<RwLock<TerminalState>>,
-    pty_writer: Arc<OwnedFd>,
+    /// Where replies to the program's queries go: the pane's PTY backend.
+    pty_writer: Arc<dyn PtyBackend>,
     /// Buffer for consecutive print() calls. Flushed as a Print op
     /// before any non-print event.
     print_buf: String,
@@ -149,19 +163,37 @@ pub struct VteHandler {
     /// Bounds the holdback to one chunk: if the stream goes quiet, the next
     /// flush shows the fragment instead of withholding it forever.
     held_tail: bool,
+    /// Debug rendering of every op applied, in order, while tracing is on
+    /// (`kova replay --ops`). `None` = off, the live path pays nothing.
+    op_trace: Option<Vec<String>>,
+    /// Unhandled sequences seen while parsing (no lock held), folded into
+    /// `TerminalState::unhandled` by the next `apply_ops`.
+    unhandled: Vec<(SeqKind, String)>,
 }
 
 impl VteHandler {
-    pub fn new(terminal: Arc<RwLock<TerminalState>>, pty_writer: Arc<OwnedFd>) -> Self {
+    pub fn new(terminal: Arc<RwLock<TerminalState>>, pty_writer: Arc<dyn PtyBackend>) -> Self {
         VteHandler {
             terminal,
             pty_writer,
             print_buf: String::new(),
             ops: Vec::with_capacity(256),
             held_tail: false,
+            op_trace: None,
+            unhandled: Vec::new(),
         }
     }
 
+    /// Start recording the ops applied from now on (see `take_op_trace`).
+    pub fn trace_ops(&mut self) {
+        self.op_trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Ops applied since tracing started or since the last call, oldest first.
+    pub fn take_op_trace(&mut self) -> Vec<String> {
+        self.op_trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
     /// Flush the print buffer into a Print op.
     fn flush_print_buf(&mut self) {
         self.held_tail = false;
@@ -224,29 +256,52 @@ impl VteHandler {
         }
     }
 
+    /// Count an OSC we do not implement, keyed by its command number.
+    fn unhandled_osc(&mut self, params: &[&[u8]]) {
+        let cmd = params.first().map(|c| String::from_utf8_lossy(c)).unwrap_or_default();
+        log::debug!("unhandled OSC: cmd={}, params_count={}", cmd, params.len());
+        // Bounded: a hostile stream could otherwise mint a key per sequence.
+        let key: String = cmd.chars().take(16).collect();
+        self.unhandled.push((SeqKind::Osc, key));
+    }
+
     fn write_to_pty(&self, data: &[u8]) {
-        let _ = rustix::io::write(&*self.pty_writer, data);
+        self.pty_writer.write(data);
     }
 
     /// Take the write lock once, replay all buffered ops, release the lock,
     /// then write any PTY responses.
     pub fn apply_ops(&mut self) {
         self.flush_print_buf_chunk_end();
-        if self.ops.is_empty() {
+        if self.ops.is_empty() && self.unhandled.is_empty() {
             return;
         }
 
         // Collect PTY responses to write after releasing the lock
         let mut pty_responses: Vec<Vec<u8>> = Vec::new();
 
-        {
+        let output_signal = {
             let mut term = self.terminal.write();
-            let now_secs = std::time::SystemTime::now()
-                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0);
-            term.last_activity_secs.store(now_secs, std::sync::atomic::Ordering::Relaxed);
+            let now_ms = super::output::now_ms();
+            term.last_activity_secs.store(now_ms / 1000, std::sync::atomic::Ordering::Relaxed);
+            term.last_output_ms = now_ms;
+            term.output_generation += 1;
+            for (kind, key) in self.unhandled.drain(..) {
+                term.unhandled.add(kind, key);
+            }
+            if let Some(ring) = term.op_ring.as_mut() {
+                ring.begin_chunk();
+            }
             for op in self.ops.drain(..) {
+                if self.op_trace.is_some() || term.op_ring.is_some() {
+                    let rendered = format!("{:?}", op);
+                    if let Some(ring) = term.op_ring.as_mut() {
+                        ring.push(rendered.clone());
+                    }
+                    if let Some(trace) = self.op_trace.as_mut() {
+                        trace.push(rendered);
+                    }
+                }
                 match op {
                     TermOp::Print(buf) => {
                         use unicode_segmentation::UnicodeSegmentation;
@@ -351,13 +406,19 @@ impl VteHandler {
                                     term.dirty.store(true, std::sync::atomic::Ordering::Relaxed);
                                 }
                             }
-                            _ => log::debug!("unhandled DEC mode: {}", mode),
+                            _ => {
+                                log::debug!("unhandled DEC mode: {}", mode);
+                                term.unhandled.add(SeqKind::Mode, format!("?{}", mode));
+                            }
                         }
                     }
                     TermOp::SetMode(mode, on) => {
                         match mode {
                             4 => term.insert_mode = on,
-                            _ => log::debug!("unhandled SM/RM mode: {}", mode),
+                            _ => {
+                                log::debug!("unhandled SM/RM mode: {}", mode);
+                                term.unhandled.add(SeqKind::Mode, mode.to_string());
+                            }
                         }
                     }
                     TermOp::SetSgr(params) => term.set_sgr(&params),
@@ -379,8 +440,22 @@ impl VteHandler {
                         let fg = term.default_fg;
                         let bg = term.default_bg;
                         let last_activity = term.last_activity_secs.clone();
+                        let last_output_ms = term.last_output_ms;
+                        let output_signal = term.output_signal.clone();
+                        // RIS resets what the app sees, not the inspector or
+                        // what the pane streams (`pipe-pane`, `pane-output`).
+                        let op_ring = term.op_ring.take();
+                        let unhandled = std::mem::take(&mut term.unhandled);
+                        let pipe = term.pipe.take();
+                        let line_tap = term.line_tap.take();
                         *term = TerminalState::new(cols, rows, scrollback_limit, fg, bg);
                         term.last_activity_secs = last_activity;
+                        term.last_output_ms = last_output_ms;
+                        term.output_signal = output_signal;
+                        term.op_ring = op_ring;
+                        term.unhandled = unhandled;
+                        term.pipe = pipe;
+                        term.line_tap = line_tap;
                     }
                     TermOp::SetTitle(title) => {
                         term.title = Some(title);
@@ -420,14 +495,16 @@ impl VteHandler {
                         term.last_command_slot_open = true;
                         term.command_completed.store(false, std::sync::atomic::Ordering::Relaxed);
                         term.command_running.store(true, std::sync::atomic::Ordering::Relaxed);
+                        term.last_exit_code = None;
                     }
-                    TermOp::SetCommandCompleted => {
+                    TermOp::SetCommandCompleted(exit_code) => {
                         log::debug!("OSC 133;D command completed (terminal {})", term.terminal_id);
                         // The first D with no prior C is the shell's startup
                         // precmd — swallow it (no command actually completed).
                         // Later D-without-C (e.g. Claude Code's Stop hook)
                         // must still fire: the startup D already primed us.
                         if term.osc133_primed {
+                            term.last_exit_code = exit_code;
                             term.command_completed.store(true, std::sync::atomic::Ordering::Relaxed);
                             // Fresh completion — unread until the pane is looked at.
                             term.completion_seen.store(false, std::sync::atomic::Ordering::Relaxed);
@@ -490,8 +567,11 @@ impl VteHandler {
                     }
                 }
             }
-        }
-        // Write lock released — now send PTY responses without holding any lock
+            term.output_signal.clone()
+        };
+        // Write lock released: a `wait-for-text` that sees the count can read.
+        output_signal.ring();
+        // Now send PTY responses without holding any lock
         for response in &pty_responses {
             self.write_to_pty(response);
         }
@@ -530,6 +610,7 @@ impl Perform for VteHandler {
             params,
             intermediates
         );
+        self.unhandled.push((SeqKind::Dcs, seq_key(intermediates, action)));
     }
     fn put(&mut self, _byte: u8) { self.flush_print_buf(); }
     fn unhook(&mut self) { self.flush_print_buf(); }
@@ -573,7 +654,11 @@ impl Perform for VteHandler {
                     // C/D are logged with the terminal id when the op is applied.
                     match sub.first() {
                         Some(b'C') => self.ops.push(TermOp::CommandStarted),
-                        Some(b'D') => self.ops.push(TermOp::SetCommandCompleted),
+                        Some(b'D') => {
+                            // `133;D;<exit code>`, the code optional.
+                            let code = params.get(2).and_then(|c| std::str::from_utf8(c).ok()?.parse().ok());
+                            self.ops.push(TermOp::SetCommandCompleted(code));
+                        }
                         _ => {}
                     }
                 }
@@ -609,11 +694,10 @@ impl Perform for VteHandler {
                         self.ops.push(TermOp::SetLastCommand(command));
                     }
                 }
-                _ => {
-                    let cmd = String::from_utf8_lossy(params[0]);
-                    log::debug!("unhandled OSC: cmd={}, params_count={}", cmd, params.len());
-                }
+                _ => self.unhandled_osc(params),
             }
+        } else {
+            self.unhandled_osc(params);
         }
     }
 
@@ -828,6 +912,7 @@ impl Perform for VteHandler {
                     params,
                     intermediates
                 );
+                self.unhandled.push((SeqKind::Csi, seq_key(intermediates, action)));
             }
         }
     }
@@ -860,6 +945,7 @@ impl Perform for VteHandler {
                     byte,
                     intermediates
                 );
+                self.unhandled.push((SeqKind::Esc, seq_key(intermediates, byte as char)));
             }
         }
     }
@@ -953,6 +1039,7 @@ impl AnsiColor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terminal::pty::NullBackend;
     use crate::terminal::{DEFAULT_BG, DEFAULT_FG};
 
     /// Feed raw bytes through the real vte parser into a TerminalState.
@@ -960,32 +1047,14 @@ mod tests {
         let term = Arc::new(RwLock::new(TerminalState::new(
             cols, rows, 100, DEFAULT_FG, DEFAULT_BG,
         )));
-        let devnull = std::fs::OpenOptions::new()
-            .write(true)
-            .open("/dev/null")
-            .unwrap();
-        let writer: Arc<OwnedFd> = Arc::new(devnull.into());
-        let mut parser = vte::Parser::new();
-        let mut handler = VteHandler::new(term.clone(), writer);
-        for chunk in chunks {
-            parser.advance(&mut handler, chunk);
-            handler.apply_ops();
-        }
+        drive_chunks(&term, chunks.iter().copied());
         term
     }
 
     /// Feed more bytes into an existing terminal (all parser state that matters
     /// across chunks lives on `TerminalState`, so a fresh handler is fine).
     fn feed(term: &Arc<RwLock<TerminalState>>, bytes: &[u8]) {
-        let devnull = std::fs::OpenOptions::new()
-            .write(true)
-            .open("/dev/null")
-            .unwrap();
-        let writer: Arc<OwnedFd> = Arc::new(devnull.into());
-        let mut parser = vte::Parser::new();
-        let mut handler = VteHandler::new(term.clone(), writer);
-        parser.advance(&mut handler, bytes);
-        handler.apply_ops();
+        drive_chunks(term, [bytes]);
     }
 
     #[test]
@@ -1113,6 +1182,16 @@ mod tests {
         assert!(!t.read().command_running.load(std::sync::atomic::Ordering::Relaxed));
     }
 
+    #[test]
+    fn osc133_d_carries_the_exit_code_until_the_next_command() {
+        let t = drive(20, 5, &[b"\x1b]133;C\x07\x1b]133;D;2\x07"]);
+        assert_eq!(t.read().last_exit_code, Some(2));
+        feed(&t, b"\x1b]133;C\x07");
+        assert_eq!(t.read().last_exit_code, None);
+        feed(&t, b"\x1b]133;D\x07");
+        assert_eq!(t.read().last_exit_code, None, "a shell that does not say leaves it unknown");
+    }
+
     #[test]
     fn ack_hides_the_dot_but_keeps_the_ipc_flag() {
         // A completed command is unread until the pane is looked at.
@@ -1228,6 +1307,43 @@ mod tests {
         assert_eq!(cell(&t, 2, 9).c, 'X');
     }
 
+    #[test]
+    fn unhandled_sequences_are_counted_by_final_byte() {
+        // CSI > 4;2 m (modifyOtherKeys), OSC 52 (clipboard), ESC # 8
+        // (DECALN), DEC mode 1005 — none implemented.
+        let t = drive(20, 5, &[b"\x1b[>4;2m\x1b]52;c;aGk=\x07\x1b#8\x1b[?1005h\x1b[>4;0m"]);
+        let json = t.read().unhandled.to_json();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "csi": { ">m": 2 },
+                "osc": { "52": 1 },
+                "esc": { "#8": 1 },
+                "mode": { "?1005": 1 },
+            })
+        );
+    }
+
+    #[test]
+    fn op_ring_records_chunks_and_survives_full_reset() {
+        let t = drive(20, 5, &[]);
+        t.write().op_ring = Some(crate::terminal::inspect::OpRing::new(64));
+        feed(&t, b"ab\x1b[C\x1b[C");
+        feed(&t, b"\x1bc\x1b[2J");
+        let term = t.read();
+        let ring = term.op_ring.as_ref().expect("RIS must keep the inspector");
+        let ops: Vec<(u64, &str, u32)> = ring.recent(None).map(|r| (r.chunk, r.op.as_str(), r.repeat)).collect();
+        assert_eq!(
+            ops,
+            [
+                (1, "Print(\"ab\")", 1),
+                (1, "CursorForward(1)", 2),
+                (2, "FullReset", 1),
+                (2, "EraseInDisplay(2)", 1),
+            ]
+        );
+    }
+
     /// Deterministic fuzz: pseudo-random byte streams (biased toward VT
     /// introducers) plus mid-stream resizes must never panic, and the
     /// terminal invariants must hold after every chunk.
@@ -1261,10 +1377,8 @@ mod tests {
             let term = Arc::new(RwLock::new(TerminalState::new(
                 cols, rows, 50, DEFAULT_FG, DEFAULT_BG,
             )));
-            let devnull = std::fs::OpenOptions::new().write(true).open("/dev/null").unwrap();
-            let writer: Arc<OwnedFd> = Arc::new(devnull.into());
             let mut parser = vte::Parser::new();
-            let mut handler = VteHandler::new(term.clone(), writer);
+            let mut handler = VteHandler::new(term.clone(), Arc::new(NullBackend));
 
             for _chunk_idx in 0..8 {
                 let mut chunk: Vec<u8> = Vec::with_capacity(512);
@@ -1340,8 +1454,10 @@ mod tests {
 
     /// Prints the final grid with row numbers, the cursor position, and any
     /// interior blank band (the "hole" signature). Bytes are fed in 4096-byte
-    /// chunks, like the live PTY reader. For a rotated capture, replay the
-    /// ".raw.1" chunk and the ".raw" file concatenated (cat a.1 a > full.raw).
+    /// chunks, like the live PTY reader. `kova replay` (replay.rs) is the
+    /// first-class version, with op dumps, stepping and bisection. For a
+    /// rotated capture, replay the ".raw.1" chunk and the ".raw" file
+    /// concatenated (cat a.1 a > full.raw).
     #[test]
     #[ignore]
     fn replay_capture_file() {
@@ -64,6 +64,7 @@ const TAGS: [&str; 5] = ["slack", "mail", "linkedin", "sms", "paste"];
 
 /// What the renderer does with a row.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub enum RowPaint {
     /// Draw it as the terminal wrote it.
     Normal,
@@ -74,6 +75,7 @@ pub enum RowPaint {
 }
 
 /// The foreground SGR 2 leaves behind: `effective_colors` halves each component.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 fn dim(fg: [u8; 3]) -> [u8; 3] {
     [fg[0] / 2, fg[1] / 2, fg[2] / 2]
 }
@@ -170,6 +172,7 @@ pub fn is_marker_line(line: &[Cell], default_fg: [u8; 3]) -> bool {
 /// The dim opening label stays [RowPaint::Normal]: Claude Code already prints it in grey,
 /// and grey above a coloured block reads as its label. Everything else that delimits — the
 /// dim closing line, and all four lines of the unrendered form — is taken away.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn paste_block_rows(lines: &[&[Cell]], default_fg: [u8; 3]) -> Vec<RowPaint> {
     let dim_fg = dim(default_fg);
     let mut paint = vec![RowPaint::Normal; lines.len()];
@@ -0,0 +1,302 @@
+//! `pipe-pane`: a copy of a pane's output, streamed to a command or a file.
+//!
+//! Two formats. `raw` is every chunk the reader thread reads from the PTY,
+//! escape sequences included, tapped next to the recorder. `text` is plain
+//! text, one line at a time as lines scroll off the top of the grid into the
+//! scrollback — what the user would see, without the redraws that produced it.
+//! Lines still on screen, and anything a full-screen app draws on the
+//! alternate screen, never scroll off and are not piped.
+//!
+//! The producer side runs on the `pty-reader` thread (or the main thread for
+//! a virtual pane's `write-output`) and must never wait on the consumer: it
+//! `try_send`s into a bounded queue and counts what did not fit. A writer
+//! thread per pipe does the blocking I/O. Dropping the `PaneSink` closes the
+//! queue; the writer then closes the command's stdin (or the file) and reaps
+//! the command, killing it if it outlives `REAP_GRACE`.
+
+use parking_lot::Mutex;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use super::{Row, TerminalState};
+
+/// Chunks that may wait for the writer before new ones are dropped. A raw
+/// chunk is at most one 4 KB PTY read, so a full queue holds about 4 MB.
+const QUEUE_CAP: usize = 1024;
+
+/// How long a command gets to exit after its stdin closes before it is
+/// killed. A command that ignores EOF would otherwise pin the writer thread.
+const REAP_GRACE: Duration = Duration::from_secs(2);
+
+/// Shared between a `Pty`, its reader thread and, for `text`, its terminal:
+/// `Some` while the pane is piped.
+pub type PipeSlot = Arc<Mutex<Option<PaneSink>>>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PipeFormat {
+    Raw,
+    Text,
+}
+
+impl PipeFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(PipeFormat::Raw),
+            "text" => Some(PipeFormat::Text),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PipeFormat::Raw => "raw",
+            PipeFormat::Text => "text",
+        }
+    }
+}
+
+/// Where the output goes.
+#[derive(Clone)]
+pub enum PipeTarget {
+    /// A shell command (`sh -c`), fed on its stdin.
+    Command(String),
+    /// A file, appended to.
+    Path(PathBuf),
+}
+
+/// The producer half of a pipe.
+pub struct PaneSink {
+    tx: mpsc::SyncSender<Vec<u8>>,
+    format: PipeFormat,
+    /// What `pipe-pane` was given, for `list-panes`.
+    target: serde_json::Value,
+    dropped: Arc<AtomicU64>,
+    /// Set by the writer when the command exited or the file stopped taking
+    /// writes. Everything sent after that is counted as dropped.
+    closed: Arc<AtomicBool>,
+    /// Text format: a soft-wrapped line scrolls off one row at a time; its
+    /// rows are joined here until the one that ends it.
+    partial: String,
+}
+
+impl PaneSink {
+    /// Open `target` and start its writer thread.
+    pub fn start(target: PipeTarget, format: PipeFormat, pane_id: u32) -> Result<Self, String> {
+        let (out, child, target_json): (Box<dyn Write + Send>, _, _) = match target {
+            PipeTarget::Command(command) => {
+                let mut child = std::process::Command::new("/bin/sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("cannot run {:?}: {}", command, e))?;
+                let stdin = child.stdin.take().ok_or("command has no stdin")?;
+                (Box::new(stdin), Some(child), serde_json::json!({ "command": command }))
+            }
+            PipeTarget::Path(path) => {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .mode(0o600)
+                    .open(&path)
+                    .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+                (Box::new(file), None, serde_json::json!({ "path": path.display().to_string() }))
+            }
+        };
+
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(QUEUE_CAP);
+        let closed = Arc::new(AtomicBool::new(false));
+        let writer_closed = closed.clone();
+        std::thread::Builder::new()
+            .name(format!("pipe-pane-{}", pane_id))
+            .spawn(move || write_all(rx, out, child, writer_closed))
+            .map_err(|e| format!("cannot start the pipe writer: {}", e))?;
+        log::info!("Pane {} piped ({}) to {}", pane_id, format.name(), target_json);
+
+        Ok(PaneSink {
+            tx,
+            format,
+            target: target_json,
+            dropped: Arc::new(AtomicU64::new(0)),
+            closed,
+            partial: String::new(),
+        })
+    }
+
+    /// A chunk of PTY output. Ignored by a `text` pipe.
+    pub fn output(&self, bytes: &[u8]) {
+        if self.format == PipeFormat::Raw {
+            self.send(bytes.to_vec());
+        }
+    }
+
+    /// A row that just scrolled off the grid. Ignored by a `raw` pipe.
+    pub fn scrolled_off(&mut self, row: &Row) {
+        if self.format != PipeFormat::Text {
+            return;
+        }
+        TerminalState::render_row(row, &mut self.partial);
+        if !row.wrapped {
+            let line = std::mem::take(&mut self.partial);
+            self.send(line.into_bytes());
+        }
+    }
+
+    fn send(&self, chunk: Vec<u8>) {
+        if self.closed.load(Ordering::Relaxed) || self.tx.try_send(chunk).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// State of the pipe, as `pipe-pane` and `list-panes` report it.
+    pub fn json(&self) -> serde_json::Value {
+        let mut v = self.target.clone();
+        v["format"] = self.format.name().into();
+        v["dropped"] = self.dropped.load(Ordering::Relaxed).into();
+        v["closed"] = self.closed.load(Ordering::Relaxed).into();
+        v
+    }
+}
+
+/// Feed a row that scrolled off the grid to the pipe in `slot`, if any.
+pub fn scrolled_off(slot: &PipeSlot, row: &Row) {
+    if let Some(sink) = slot.lock().as_mut() {
+        sink.scrolled_off(row);
+    }
+}
+
+/// The writer thread: drain the queue into `out` until the sink is dropped
+/// or a write fails, then close `out` and reap the command.
+fn write_all(
+    rx: mpsc::Receiver<Vec<u8>>,
+    mut out: Box<dyn Write + Send>,
+    child: Option<Child>,
+    closed: Arc<AtomicBool>,
+) {
+    for chunk in rx.iter() {
+        if let Err(e) = out.write_all(&chunk).and_then(|_| out.flush()) {
+            log::info!("pipe-pane: output closed: {}", e);
+            closed.store(true, Ordering::Relaxed);
+            break;
+        }
+    }
+    // EOF on the command's stdin: that is what ends a `cat > file`.
+    drop(out);
+    if let Some(mut child) = child {
+        let _ = reap(&mut child, REAP_GRACE);
+    }
+}
+
+/// Wait up to `grace` for `child` to exit, then kill it and wait for that.
+fn reap(child: &mut Child, grace: Duration) -> std::io::Result<ExitStatus> {
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    log::info!("pipe-pane: command {} still running after its input closed, killing it", child.id());
+    let _ = child.kill();
+    child.wait()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("kova-pipe-{}-{}", std::process::id(), name))
+    }
+
+    /// The writer runs on its own thread: wait for it to catch up.
+    fn read_when(path: &std::path::Path, done: impl Fn(&str) -> bool) -> String {
+        for _ in 0..200 {
+            let text = std::fs::read_to_string(path).unwrap_or_default();
+            if done(&text) {
+                return text;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        std::fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn raw_pipes_every_chunk_to_a_command() {
+        let path = temp_path("raw");
+        let command = format!("cat > {}", path.display());
+        let sink = PaneSink::start(PipeTarget::Command(command), PipeFormat::Raw, 1).unwrap();
+        sink.output(b"\x1b[1mbold\x1b[0m ");
+        sink.output(b"plain\r\n");
+        drop(sink);
+        let text = read_when(&path, |t| t.ends_with("\r\n"));
+        assert_eq!(text, "\x1b[1mbold\x1b[0m plain\r\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn text_pipes_lines_as_they_scroll_off() {
+        let path = temp_path("text");
+        let _ = std::fs::remove_file(&path);
+        let slot: PipeSlot = Arc::new(Mutex::new(Some(
+            PaneSink::start(PipeTarget::Path(path.clone()), PipeFormat::Text, 2).unwrap(),
+        )));
+        let mut term = TerminalState::new(10, 3, 100, [255, 255, 255], [0, 0, 0]);
+        term.pipe = Some(slot.clone());
+        let term = Arc::new(parking_lot::RwLock::new(term));
+        let mut parser = vte::Parser::new();
+        let mut handler = super::super::parser::VteHandler::new(term.clone(), Arc::new(super::super::pty::NullBackend));
+        // A line too long for 10 columns wraps; it must come out whole.
+        parser.advance(&mut handler, b"\x1b[31mone\x1b[0m\r\nwrapped across two\r\nthree\r\nfour\r\nfive");
+        handler.apply_ops();
+        // Lines still on the grid are not piped yet.
+        slot.lock().take();
+        let text = read_when(&path, |t| t.contains("two"));
+        assert_eq!(text, "one\nwrapped across two\n");
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_full_queue_drops_and_counts_instead_of_blocking() {
+        let sink = PaneSink::start(PipeTarget::Command("sleep 5".into()), PipeFormat::Raw, 3).unwrap();
+        // `sleep` never reads: the pipe buffer fills, the writer blocks, the
+        // queue fills behind it. None of this may block the caller.
+        let chunk = vec![b'x'; 4096];
+        for _ in 0..(QUEUE_CAP + 200) {
+            sink.output(&chunk);
+        }
+        assert!(sink.json()["dropped"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn a_command_that_ignores_eof_is_killed_after_the_grace() {
+        let mut child = std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg("sleep 30")
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        drop(child.stdin.take());
+        let started = Instant::now();
+        let status = reap(&mut child, Duration::from_millis(50)).unwrap();
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn format_names_round_trip() {
+        for format in [PipeFormat::Raw, PipeFormat::Text] {
+            assert_eq!(PipeFormat::from_name(format.name()), Some(format));
+        }
+        assert_eq!(PipeFormat::from_name("html"), None);
+    }
+}
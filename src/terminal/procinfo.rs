@@ -0,0 +1,419 @@
+//! What the OS can tell us about a process: how it was invoked, where it
+//! runs, who its parent and children are.
+//!
+//! Pane titles, `list-panes` (`cwd`, `child_processes`, `is_idle`) and the
+//! Claude session lookup all ask the same few questions. Each platform answers
+//! them its own way — `proc_pidinfo`/`KERN_PROCARGS2` on macOS, `/proc` on
+//! Linux — behind `ProcessInspector`; callers go through `system()`.
+//!
+//! Every method is a best-effort probe of a process that may exit at any
+//! moment (or belong to another user): a miss is `None`/empty, never an error.
+
+/// One platform's way of inspecting a live process.
+pub trait ProcessInspector: Sync {
+    /// The executable path and argv[0] of `pid`. The path may be empty when
+    /// the OS will not resolve it (another user's process on Linux).
+    fn args(&self, pid: u32) -> Option<(String, String)>;
+    /// The kernel's short name for `pid` (`p_comm`/`comm`): the basename of
+    /// the executed file, truncated. The fallback when argv is unreadable.
+    fn comm(&self, pid: u32) -> Option<String>;
+    /// The current working directory of `pid`.
+    fn cwd(&self, pid: u32) -> Option<String>;
+    /// Direct children of `pid`, in no particular order.
+    fn children(&self, pid: u32) -> Vec<u32>;
+    /// Parent PID and start time (epoch seconds) of `pid`.
+    fn parent_and_start(&self, pid: u32) -> Option<(u32, u64)>;
+}
+
+/// The inspector for the platform Kova was built for.
+pub fn system() -> &'static dyn ProcessInspector {
+    #[cfg(target_os = "macos")]
+    {
+        &Darwin
+    }
+    #[cfg(target_os = "linux")]
+    {
+        &Procfs
+    }
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    {
+        &Unsupported
+    }
+}
+
+/// macOS: `libproc` and the `KERN_PROCARGS2` sysctl.
+#[cfg(target_os = "macos")]
+pub struct Darwin;
+
+#[cfg(target_os = "macos")]
+impl ProcessInspector for Darwin {
+    /// Via `KERN_PROCARGS2`. Only the first two fields are wanted and they sit
+    /// at the front, so a small buffer is enough — the kernel copies out what
+    /// fits instead of failing, which avoids allocating `KERN_ARGMAX` (1 MiB)
+    /// per probe.
+    fn args(&self, pid: u32) -> Option<(String, String)> {
+        let mut buf = vec![0u8; 4096];
+        let mut len = buf.len();
+        let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid as libc::c_int];
+        let rc = unsafe {
+            libc::sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as libc::c_uint,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if rc != 0 || len <= std::mem::size_of::<libc::c_int>() {
+            return None;
+        }
+        parse_proc_args(&buf[..len])
+    }
+
+    fn comm(&self, pid: u32) -> Option<String> {
+        let mut name_buf = [0u8; 256];
+        let len = unsafe {
+            libc::proc_name(pid as i32, name_buf.as_mut_ptr() as *mut libc::c_void, 256)
+        };
+        (len > 0).then(|| String::from_utf8_lossy(&name_buf[..len as usize]).into_owned())
+    }
+
+    /// Via `proc_pidinfo` with `PROC_PIDVNODEPATHINFO`.
+    fn cwd(&self, pid: u32) -> Option<String> {
+        unsafe {
+            let mut vpi: libc::proc_vnodepathinfo = std::mem::zeroed();
+            let ret = libc::proc_pidinfo(
+                pid as i32,
+                libc::PROC_PIDVNODEPATHINFO,
+                0,
+                &mut vpi as *mut _ as *mut libc::c_void,
+                std::mem::size_of::<libc::proc_vnodepathinfo>() as i32,
+            );
+            if ret <= 0 {
+                return None;
+            }
+            let path = std::ffi::CStr::from_ptr(vpi.pvi_cdir.vip_path.as_ptr() as *const i8);
+            path.to_str().ok().map(String::from)
+        }
+    }
+
+    fn children(&self, pid: u32) -> Vec<u32> {
+        let pid = pid as i32;
+        // First call to get count
+        let count = unsafe { libc::proc_listchildpids(pid, std::ptr::null_mut(), 0) };
+        if count <= 0 {
+            return Vec::new();
+        }
+        let mut pids = vec![0i32; count as usize];
+        let actual = unsafe {
+            libc::proc_listchildpids(
+                pid,
+                pids.as_mut_ptr() as *mut libc::c_void,
+                (pids.len() * std::mem::size_of::<i32>()) as i32,
+            )
+        };
+        if actual <= 0 {
+            return Vec::new();
+        }
+        // proc_listchildpids returns a *count* of PIDs written, not a byte
+        // length — dividing by size_of::<i32>() truncated every shell with
+        // fewer than four children down to zero.
+        pids.truncate(actual as usize);
+        pids.into_iter().map(|p| p as u32).collect()
+    }
+
+    fn parent_and_start(&self, pid: u32) -> Option<(u32, u64)> {
+        unsafe {
+            let mut info: libc::proc_bsdinfo = std::mem::zeroed();
+            let ret = libc::proc_pidinfo(
+                pid as i32,
+                libc::PROC_PIDTBSDINFO,
+                0,
+                &mut info as *mut _ as *mut libc::c_void,
+                std::mem::size_of::<libc::proc_bsdinfo>() as i32,
+            );
+            if ret <= 0 {
+                return None;
+            }
+            Some((info.pbi_ppid, info.pbi_start_tvsec))
+        }
+    }
+}
+
+/// Split a `KERN_PROCARGS2` buffer into (executable path, argv[0]).
+///
+/// The area is laid out as: argc (i32), the executable path, NUL padding, then
+/// argv[0], argv[1]…
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_proc_args(buf: &[u8]) -> Option<(String, String)> {
+    let body = buf.get(std::mem::size_of::<libc::c_int>()..)?; // skip argc
+    let end = body.iter().position(|&b| b == 0)?;
+    let exe_path = String::from_utf8_lossy(&body[..end]).into_owned();
+    // The path is followed by one or more NULs before argv[0] starts.
+    let rest = &body[end..];
+    let start = rest.iter().position(|&b| b != 0)?;
+    let rest = &rest[start..];
+    let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    Some((exe_path, String::from_utf8_lossy(&rest[..end]).into_owned()))
+}
+
+/// Linux: `/proc/<pid>/{cmdline,exe,comm,cwd,stat}`.
+#[cfg(target_os = "linux")]
+pub struct Procfs;
+
+#[cfg(target_os = "linux")]
+impl ProcessInspector for Procfs {
+    fn args(&self, pid: u32) -> Option<(String, String)> {
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        // Kernel threads and zombies have an empty cmdline: nothing to name.
+        let argv0 = cmdline.split(|&b| b == 0).next().filter(|a| !a.is_empty())?;
+        let exe_path = std::fs::read_link(format!("/proc/{}/exe", pid))
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Some((exe_path, String::from_utf8_lossy(argv0).into_owned()))
+    }
+
+    fn comm(&self, pid: u32) -> Option<String> {
+        let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some(comm.trim_end_matches('\n').to_string()).filter(|c| !c.is_empty())
+    }
+
+    fn cwd(&self, pid: u32) -> Option<String> {
+        let path = std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()?;
+        path.to_str().map(String::from)
+    }
+
+    /// Via `/proc/<pid>/task/*/children`, which each thread keeps for the
+    /// children it forked. Kernels built without `CONFIG_PROC_CHILDREN` lack
+    /// those files; there the whole of `/proc` is scanned for the parent link.
+    fn children(&self, pid: u32) -> Vec<u32> {
+        let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+            return Vec::new();
+        };
+        let mut children = Vec::new();
+        for task in tasks.flatten() {
+            match std::fs::read_to_string(task.path().join("children")) {
+                Ok(list) => children.extend(list.split_whitespace().filter_map(|p| p.parse::<u32>().ok())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return scan_children(pid),
+                Err(_) => {}
+            }
+        }
+        children
+    }
+
+    fn parent_and_start(&self, pid: u32) -> Option<(u32, u64)> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let (ppid, start_ticks) = parse_stat(&stat)?;
+        let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks_per_sec <= 0 {
+            return None;
+        }
+        Some((ppid, boot_time()? + start_ticks / ticks_per_sec as u64))
+    }
+}
+
+/// Children of `pid` found the slow way: every process's `stat`.
+#[cfg(target_os = "linux")]
+fn scan_children(pid: u32) -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&child| {
+            std::fs::read_to_string(format!("/proc/{}/stat", child))
+                .ok()
+                .and_then(|stat| parse_stat(&stat))
+                .is_some_and(|(ppid, _)| ppid == pid)
+        })
+        .collect()
+}
+
+/// Boot time (epoch seconds), the `btime` line of `/proc/stat`. Process start
+/// times in `/proc/<pid>/stat` count clock ticks from there.
+#[cfg(target_os = "linux")]
+fn boot_time() -> Option<u64> {
+    static BOOT_TIME: std::sync::OnceLock<Option<u64>> = std::sync::OnceLock::new();
+    *BOOT_TIME.get_or_init(|| {
+        let stat = std::fs::read_to_string("/proc/stat").ok()?;
+        stat.lines().find_map(|l| l.strip_prefix("btime ")?.trim().parse().ok())
+    })
+}
+
+/// Parent PID and start time (clock ticks since boot) from a
+/// `/proc/<pid>/stat` line. The command name sits in parentheses and may hold
+/// spaces or parentheses itself, so fields are counted from the last `)`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str) -> Option<(u32, u64)> {
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 1..)?.split_whitespace().collect();
+    // After the name: state (field 3), ppid (4), … starttime (22).
+    let ppid = fields.get(1)?.parse().ok()?;
+    let start_ticks = fields.get(19)?.parse().ok()?;
+    Some((ppid, start_ticks))
+}
+
+/// Anywhere else: no answers, so pane titles fall back to the shell's own.
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub struct Unsupported;
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+impl ProcessInspector for Unsupported {
+    fn args(&self, _pid: u32) -> Option<(String, String)> {
+        None
+    }
+    fn comm(&self, _pid: u32) -> Option<String> {
+        None
+    }
+    fn cwd(&self, _pid: u32) -> Option<String> {
+        None
+    }
+    fn children(&self, _pid: u32) -> Vec<u32> {
+        Vec::new()
+    }
+    fn parent_and_start(&self, _pid: u32) -> Option<(u32, u64)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Child, Command};
+
+    /// Build a KERN_PROCARGS2-shaped buffer: argc, exec path, padding, argv.
+    fn procargs_buffer(exe_path: &str, argv: &[&str]) -> Vec<u8> {
+        let mut buf = (argv.len() as libc::c_int).to_ne_bytes().to_vec();
+        buf.extend_from_slice(exe_path.as_bytes());
+        buf.extend_from_slice(&[0, 0, 0]); // the kernel pads with NULs
+        for arg in argv {
+            buf.extend_from_slice(arg.as_bytes());
+            buf.push(0);
+        }
+        buf
+    }
+
+    #[test]
+    fn proc_args_reads_the_exe_path_and_argv0() {
+        let buf = procargs_buffer("/usr/bin/vim", &["vim", "notes.md"]);
+        assert_eq!(
+            parse_proc_args(&buf),
+            Some(("/usr/bin/vim".to_string(), "vim".to_string()))
+        );
+    }
+
+    #[test]
+    fn proc_args_survives_a_truncated_buffer() {
+        // Only argv[0] is needed, so a buffer cut short mid-argv still answers.
+        let buf = procargs_buffer("/usr/bin/vim", &["vim", "notes.md"]);
+        let cut = buf.len() - 4;
+        assert_eq!(
+            parse_proc_args(&buf[..cut]),
+            Some(("/usr/bin/vim".to_string(), "vim".to_string()))
+        );
+    }
+
+    #[test]
+    fn proc_args_rejects_a_buffer_with_nothing_in_it() {
+        assert_eq!(parse_proc_args(&[]), None);
+        assert_eq!(parse_proc_args(&[1, 2]), None);
+    }
+
+    #[test]
+    fn stat_fields_are_counted_past_a_tricky_name() {
+        let stat = "4242 (a) b (c) S 17 4242 4242 0 -1 4194560 95 0 0 0 0 0 0 0 20 0 1 0 123456 2260992 100";
+        assert_eq!(parse_stat(stat), Some((17, 123456)));
+        assert_eq!(parse_stat("4242 (sleep) S 17"), None);
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    /// A `sleep` child, killed and reaped on drop so a failed assert does not
+    /// leave it behind.
+    struct Sleeper(Child);
+
+    impl Sleeper {
+        fn spawn_in(dir: &std::path::Path) -> Self {
+            Sleeper(Command::new("sleep").arg("30").current_dir(dir).spawn().expect("spawn sleep"))
+        }
+        fn pid(&self) -> u32 {
+            self.0.id()
+        }
+    }
+
+    impl Drop for Sleeper {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kova-procinfo-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // The kernel reports the resolved path (/private/tmp on macOS).
+        dir.canonicalize().unwrap()
+    }
+
+    /// Give a just-spawned child time to get through exec: until then it still
+    /// carries the test binary's name and arguments. Polls argv rather than the
+    /// name — Linux renames the process partway through execve, before the new
+    /// argv is readable.
+    fn wait_for_exec(pid: u32, argv0: &str) {
+        for _ in 0..200 {
+            if system().args(pid).is_some_and(|(_, a)| a == argv0) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        panic!("pid {} never exec'd {:?}", pid, argv0);
+    }
+
+    #[test]
+    fn a_spawned_child_is_named_and_placed() {
+        let dir = scratch_dir("named");
+        let child = Sleeper::spawn_in(&dir);
+        wait_for_exec(child.pid(), "sleep");
+        let inspector = system();
+
+        let (exe_path, _) = inspector.args(child.pid()).expect("args of a live child");
+        assert!(exe_path.starts_with('/'), "exe path not absolute: {:?}", exe_path);
+        assert_eq!(inspector.comm(child.pid()).as_deref(), Some("sleep"));
+        assert_eq!(inspector.cwd(child.pid()).as_deref(), dir.to_str());
+        drop(child);
+        let _ = std::fs::remove_dir(&dir);
+    }
+
+    #[test]
+    fn a_spawned_child_knows_its_parent_and_start() {
+        let dir = scratch_dir("family");
+        let child = Sleeper::spawn_in(&dir);
+        let inspector = system();
+
+        assert!(inspector.children(std::process::id()).contains(&child.pid()));
+        let (ppid, start) = inspector.parent_and_start(child.pid()).expect("stat of a live child");
+        assert_eq!(ppid, std::process::id());
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        // Second-granularity clocks on both sides, plus scheduling slack.
+        assert!(start.abs_diff(now) <= 5, "start {} vs now {}", start, now);
+        drop(child);
+        let _ = std::fs::remove_dir(&dir);
+    }
+
+    #[test]
+    fn a_reaped_child_answers_nothing() {
+        let dir = scratch_dir("reaped");
+        let child = Sleeper::spawn_in(&dir);
+        let pid = child.pid();
+        drop(child);
+        let inspector = system();
+        assert_eq!(inspector.args(pid), None);
+        assert_eq!(inspector.cwd(pid), None);
+        assert_eq!(inspector.parent_and_start(pid), None);
+        assert!(!inspector.children(std::process::id()).contains(&pid));
+        let _ = std::fs::remove_dir(&dir);
+    }
+}
@@ -7,14 +7,18 @@ use std::os::unix::process::CommandExt;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
+use super::output::RawTap;
 use super::parser::VteHandler;
+use super::pipe::PipeSlot;
+use super::procinfo;
+use super::recording::{Cast, CastWriter, PlaybackControl, RecorderSlot};
 use super::TerminalState;
 
 /// Entry in the global PTY registry.
 struct PtyEntry {
     child_pid: u32,
     /// Raw fd of the master PTY. Valid as long as this entry is in the registry.
-    /// SAFETY: `Pty::drop` removes the entry *before* `OwnedFd` is dropped,
+    /// SAFETY: `ShellBackend::drop` removes the entry *before* `OwnedFd` is dropped,
     /// so the fd is always valid while the entry exists.
     master_fd: i32,
     shutdown: Arc<AtomicBool>,
@@ -58,6 +62,7 @@ pub struct ProcessInfo {
 impl ProcessInfo {
     /// One string for display: "claude 2.1.226", or just "vim" when the
     /// version is unknown. Empty when the name could not be resolved.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn label(&self) -> String {
         match &self.version {
             Some(v) if !self.name.is_empty() => format!("{} {}", self.name, v),
@@ -68,7 +73,7 @@ impl ProcessInfo {
 
 /// The name and version of the program running as `pid`.
 ///
-/// The obvious source, `proc_name`, returns the kernel's `p_comm`: the basename
+/// The obvious source, the kernel's `p_comm` (`proc_name`), is the basename
 /// of the *file* that was executed, truncated to 16 characters. Claude Code
 /// installs its binary as `~/.local/share/claude/versions/2.1.226`, so `p_comm`
 /// there is a version number and never the word "claude" — every caller reading
@@ -80,68 +85,18 @@ impl ProcessInfo {
 /// exactly the case that broke the name, and it answers "which version of
 /// Claude is this pane running".
 pub fn process_info(pid: u32) -> ProcessInfo {
-    let args = proc_args(pid);
+    let inspector = procinfo::system();
+    let args = inspector.args(pid);
     let name = args
         .as_ref()
         .map(|(_, argv0)| program_name(argv0))
         .filter(|name| !name.is_empty())
-        .unwrap_or_else(|| proc_comm(pid));
+        .or_else(|| inspector.comm(pid))
+        .unwrap_or_default();
     let version = args.as_ref().and_then(|(exe_path, _)| version_from_exe_path(exe_path));
     ProcessInfo { name, version }
 }
 
-/// The executable path and argv[0] of `pid`, via `KERN_PROCARGS2`.
-///
-/// The area is laid out as: argc (i32), the executable path, NUL padding, then
-/// argv[0], argv[1]… Only the first two fields are wanted and they sit at the
-/// front, so a small buffer is enough — the kernel copies out what fits instead
-/// of failing, which avoids allocating `KERN_ARGMAX` (1 MiB) per probe.
-fn proc_args(pid: u32) -> Option<(String, String)> {
-    let mut buf = vec![0u8; 4096];
-    let mut len = buf.len();
-    let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid as libc::c_int];
-    let rc = unsafe {
-        libc::sysctl(
-            mib.as_mut_ptr(),
-            mib.len() as libc::c_uint,
-            buf.as_mut_ptr() as *mut libc::c_void,
-            &mut len,
-            std::ptr::null_mut(),
-            0,
-        )
-    };
-    if rc != 0 || len <= std::mem::size_of::<libc::c_int>() {
-        return None;
-    }
-    parse_proc_args(&buf[..len])
-}
-
-/// Split a `KERN_PROCARGS2` buffer into (executable path, argv[0]).
-fn parse_proc_args(buf: &[u8]) -> Option<(String, String)> {
-    let body = buf.get(std::mem::size_of::<libc::c_int>()..)?; // skip argc
-    let end = body.iter().position(|&b| b == 0)?;
-    let exe_path = String::from_utf8_lossy(&body[..end]).into_owned();
-    // The path is followed by one or more NULs before argv[0] starts.
-    let rest = &body[end..];
-    let start = rest.iter().position(|&b| b != 0)?;
-    let rest = &rest[start..];
-    let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
-    Some((exe_path, String::from_utf8_lossy(&rest[..end]).into_owned()))
-}
-
-/// The kernel's `p_comm` for `pid` — the fallback when argv[0] is unreadable.
-fn proc_comm(pid: u32) -> String {
-    let mut name_buf = [0u8; 256];
-    let len = unsafe {
-        libc::proc_name(pid as i32, name_buf.as_mut_ptr() as *mut libc::c_void, 256)
-    };
-    if len > 0 {
-        String::from_utf8_lossy(&name_buf[..len as usize]).into_owned()
-    } else {
-        String::new()
-    }
-}
-
 /// The program name inside an argv[0]: its last path component, without the
 /// leading dash a login shell is exec'd with.
 fn program_name(argv0: &str) -> String {
@@ -161,17 +116,176 @@ fn version_from_exe_path(exe_path: &str) -> Option<String> {
     numeric.then(|| base.to_string())
 }
 
-pub struct Pty {
+/// The far side of a pane's PTY: where input goes, and what runs there.
+///
+/// A login shell on a real PTY in the app; nothing at all for placeholder and
+/// playback panes; an in-memory loopback in tests (see `loopback`). The
+/// process queries default to "no program", which is what a backend without
+/// one should answer.
+pub trait PtyBackend: Send + Sync {
+    /// Deliver bytes to the program: keys, pastes, and the parser's replies
+    /// to its queries (DA, CPR, DECRQM…).
+    fn write(&self, data: &[u8]);
+    fn resize(&self, cols: u16, rows: u16);
+    /// PID of the shell; 0 when there is none.
+    fn pid(&self) -> u32 {
+        0
+    }
+    /// True if a program runs behind this PTY.
+    fn is_live(&self) -> bool {
+        false
+    }
+    /// See `Pty::foreground_process`.
+    fn foreground_process(&self) -> Option<ProcessInfo> {
+        None
+    }
+    fn child_processes(&self) -> Vec<(u32, ProcessInfo)> {
+        Vec::new()
+    }
+    fn cwd(&self) -> Option<String> {
+        None
+    }
+    /// Ask the program to exit, so whatever reads its output sees EOF.
+    /// Called once, when the pane goes away.
+    fn hang_up(&self) {}
+}
+
+/// No program: input goes nowhere. Placeholder and playback panes, and the
+/// parser runs that have no PTY at all (`kova replay`, tests).
+pub struct NullBackend;
+
+impl PtyBackend for NullBackend {
+    fn write(&self, _data: &[u8]) {}
+    fn resize(&self, _cols: u16, _rows: u16) {}
+}
+
+/// The program is an IPC client (a virtual pane): what Kova would send a
+/// program — keys, pastes, `send-keys`, the parser's replies — goes out to it
+/// as `pane-input` events, to the subscriber that owns the pane, and its
+/// output comes back through `write-output`.
+struct ClientBackend {
+    pane_id: u32,
+}
+
+impl PtyBackend for ClientBackend {
+    fn write(&self, data: &[u8]) {
+        crate::ipc::publish_pane_input(
+            self.pane_id,
+            serde_json::json!({
+                "event": "pane-input",
+                "pane_id": self.pane_id,
+                "data": String::from_utf8_lossy(data),
+            }),
+        );
+    }
+
+    fn resize(&self, _cols: u16, _rows: u16) {}
+
+    fn hang_up(&self) {
+        crate::ipc::virtual_pane_closed(self.pane_id);
+    }
+}
+
+/// A login shell on the slave side of `master_fd`.
+struct ShellBackend {
     master_fd: OwnedFd,
     child_pid: u32,
+}
+
+impl PtyBackend for ShellBackend {
+    fn write(&self, data: &[u8]) {
+        let _ = rustix::io::write(&self.master_fd, data);
+    }
+
+    fn resize(&self, cols: u16, rows: u16) {
+        let winsize = Winsize {
+            ws_row: rows,
+            ws_col: cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let _ = termios::tcsetwinsize(self.master_fd.as_fd(), winsize);
+        // TIOCSWINSZ (via tcsetwinsize) automatically sends SIGWINCH to the
+        // foreground process group when the controlling terminal is properly
+        // established (setsid + TIOCSCTTY in pre_exec).
+    }
+
+    fn pid(&self) -> u32 {
+        self.child_pid
+    }
+
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    fn foreground_process(&self) -> Option<ProcessInfo> {
+        let fg_pgid = foreground_pgid(self.master_fd.as_raw_fd(), self.child_pid)?;
+        Some(process_info(fg_pgid as u32))
+    }
+
+    fn child_processes(&self) -> Vec<(u32, ProcessInfo)> {
+        procinfo::system()
+            .children(self.child_pid)
+            .into_iter()
+            .map(|cpid| (cpid, process_info(cpid)))
+            .collect()
+    }
+
+    fn cwd(&self) -> Option<String> {
+        procinfo::system().cwd(self.child_pid)
+    }
+
+    /// SIGHUP, so the shell exits and the reader sees EOF. Without this, the
+    /// reader thread could block indefinitely on read().
+    fn hang_up(&self) {
+        let kill_rc = unsafe { libc::kill(self.child_pid as i32, libc::SIGHUP) };
+        if kill_rc != 0 {
+            let err = std::io::Error::last_os_error();
+            // ESRCH is expected if the child already exited — don't log noise.
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                log::warn!("PTY: kill(SIGHUP, {}) failed: {}", self.child_pid, err);
+            }
+        }
+    }
+}
+
+impl Drop for ShellBackend {
+    /// Runs once the reader thread is gone (it holds a reference for the
+    /// parser's replies), so nothing reads the master fd any more.
+    fn drop(&mut self) {
+        let pid = self.child_pid;
+        PTY_REGISTRY.lock().retain(|e| e.child_pid != pid);
+        let result = std::thread::Builder::new()
+            .name(format!("pty-reaper-{}", pid))
+            .spawn(move || reap_child(pid as i32, 50));
+        if let Err(e) = result {
+            log::warn!("Failed to spawn reaper for pid {}: {}, reaping synchronously", pid, e);
+            reap_child(pid as i32, 50);
+        }
+        log::info!("PTY child {} cleanup delegated to reaper thread", pid);
+    }
+}
+
+pub struct Pty {
+    backend: Arc<dyn PtyBackend>,
     shutdown: Arc<AtomicBool>,
     pub input_chars: Arc<AtomicU64>,
     /// Shared with TerminalState — updated on every write (input) and by the
     /// parser (output) so the status bar can show time since last interaction.
     pub last_activity_secs: Arc<AtomicU64>,
     reader_thread: Option<std::thread::JoinHandle<()>>,
-    /// True for placeholder PTYs that have no child process.
-    is_dummy: bool,
+    /// asciicast recording in progress, fed by the reader thread (output),
+    /// `write` (input) and `resize`.
+    recorder: RecorderSlot,
+    /// `pipe-pane` in progress: the reader thread feeds a `raw` pipe, the
+    /// terminal a `text` one.
+    pipe: PipeSlot,
+    /// Set for playback panes: the reader thread is a `.cast` player and
+    /// input drives it instead of a shell.
+    playback: Option<Arc<PlaybackControl>>,
+    /// Set for virtual panes: there is no reader thread, the output an IPC
+    /// client writes is parsed here, on the main thread.
+    output: Option<parking_lot::Mutex<(vte::Parser, VteHandler, RawTap)>>,
 }
 
 impl Pty {
@@ -228,6 +342,7 @@ impl Pty {
                 .env("TERM_PROGRAM", "Kova")
                 .env("KOVA_SHELL_INTEGRATION", "1")
                 .env("KOVA_SOCKET", crate::ipc::socket_path())
+                .env("KOVA_TOKEN", crate::ipc_auth::launch_token())
                 .env("KOVA_PANE_ID", pane_id.to_string())
                 .current_dir(&start_dir)
                 .pre_exec(move || {
@@ -266,29 +381,31 @@ impl Pty {
         PTY_REGISTRY.lock().push(PtyEntry { child_pid, master_fd: master_fd.as_raw_fd(), shutdown: shutdown.clone() });
 
         let dup_fd = unsafe { libc::dup(master_fd.as_raw_fd()) };
+        // From here on, an early return reaps the child through the backend.
+        let backend: Arc<dyn PtyBackend> = Arc::new(ShellBackend { master_fd, child_pid });
         if dup_fd < 0 {
             return Err("dup() failed".into());
         }
         let reader_fd = unsafe { OwnedFd::from_raw_fd(dup_fd) };
-
-        // Dup for VteHandler write-back (CSI responses)
-        let writer_dup = unsafe { libc::dup(master_fd.as_raw_fd()) };
-        if writer_dup < 0 {
-            return Err("dup() failed for writer".into());
-        }
-        let writer_fd = Arc::new(unsafe { OwnedFd::from_raw_fd(writer_dup) });
+        // The parser answers the shell's queries (CSI responses) through it.
+        let reply_backend = backend.clone();
 
         let input_chars = Arc::new(AtomicU64::new(0));
         let last_activity_secs = terminal.read().last_activity_secs.clone();
+        let recorder: RecorderSlot = Arc::new(parking_lot::Mutex::new(None));
+        let pipe: PipeSlot = Arc::new(parking_lot::Mutex::new(None));
 
         let reader_shutdown = shutdown.clone();
+        let reader_recorder = recorder.clone();
+        let reader_pipe = pipe.clone();
+        let mut raw_tap = RawTap::new(pane_id);
         let reader_handle = std::thread::Builder::new()
             .name("pty-reader".into())
             .spawn(move || {
                 let mut file = unsafe { std::fs::File::from_raw_fd(reader_fd.into_raw_fd()) };
                 let raw_fd = file.as_raw_fd();
                 let mut parser = vte::Parser::new();
-                let mut handler = VteHandler::new(terminal, writer_fd);
+                let mut handler = VteHandler::new(terminal, reply_backend);
                 let mut buf = [0u8; 4096];
                 let mut eof = false;
 
@@ -385,6 +502,13 @@ impl Pty {
                                     capture_written += n as u64;
                                 }
                             }
+                            if let Some(rec) = reader_recorder.lock().as_mut() {
+                                rec.output(&buf[..n]);
+                            }
+                            if let Some(sink) = reader_pipe.lock().as_ref() {
+                                sink.output(&buf[..n]);
+                            }
+                            raw_tap.output(&buf[..n]);
                             parser.advance(&mut handler, &buf[..n]);
                             handler.apply_ops();
                         }
@@ -399,40 +523,163 @@ impl Pty {
             })?;
 
         Ok(Pty {
-            master_fd,
-            child_pid,
+            backend,
             shutdown,
             input_chars,
             last_activity_secs,
             reader_thread: Some(reader_handle),
-            is_dummy: false,
+            recorder,
+            pipe,
+            playback: None,
+            output: None,
         })
     }
 
-    /// Create a lightweight dummy PTY with no child process.
-    /// Used for placeholder tabs during deferred restore — avoids spawning
-    /// a shell process that would compete with the active tab's shell for
-    /// zshrc/plugin loading time.
-    pub fn dummy() -> Result<Self, Box<dyn std::error::Error>> {
-        let pty_pair = openpty(None, None)?;
-        Ok(Pty {
-            master_fd: pty_pair.controller,
-            child_pid: 0,
+    /// A PTY over `backend`, with no reader thread: whoever owns the backend
+    /// feeds the terminal itself.
+    pub(super) fn with_backend(backend: Arc<dyn PtyBackend>) -> Self {
+        Pty {
+            backend,
             shutdown: Arc::new(AtomicBool::new(false)),
             input_chars: Arc::new(AtomicU64::new(0)),
             last_activity_secs: Arc::new(AtomicU64::new(0)),
             reader_thread: None,
-            is_dummy: true,
-        })
+            recorder: Arc::new(parking_lot::Mutex::new(None)),
+            pipe: Arc::new(parking_lot::Mutex::new(None)),
+            playback: None,
+            output: None,
+        }
+    }
+
+    /// Create a lightweight dummy PTY with no child process.
+    /// Used for placeholder tabs during deferred restore — avoids spawning
+    /// a shell process that would compete with the active tab's shell for
+    /// zshrc/plugin loading time.
+    pub fn dummy() -> Self {
+        Self::with_backend(Arc::new(NullBackend))
+    }
+
+    /// A read-only PTY that replays `cast` into `terminal` instead of running
+    /// a shell. Like `dummy`, there is no child process; the player thread
+    /// stands in for the reader and is stopped on drop.
+    pub fn playback(
+        pane_id: u32,
+        cast: Cast,
+        terminal: Arc<RwLock<TerminalState>>,
+        speed: f32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut pty = Self::dummy();
+        let control = Arc::new(PlaybackControl::new(speed));
+        let player = super::recording::spawn_player(
+            cast,
+            terminal,
+            control.clone(),
+            pty.shutdown.clone(),
+            RawTap::new(pane_id),
+        )?;
+        pty.reader_thread = Some(player);
+        pty.playback = Some(control);
+        Ok(pty)
+    }
+
+    /// A PTY for a virtual pane: no process, an IPC client in its place. Input
+    /// is published as `pane-input` events for pane `pane_id`; output arrives
+    /// through `write_output`.
+    pub fn virtual_pane(pane_id: u32, terminal: Arc<RwLock<TerminalState>>) -> Self {
+        crate::ipc::virtual_pane_opened(pane_id);
+        let backend = Arc::new(ClientBackend { pane_id });
+        let mut pty = Self::with_backend(backend.clone());
+        pty.last_activity_secs = terminal.read().last_activity_secs.clone();
+        pty.output = Some(parking_lot::Mutex::new((
+            vte::Parser::new(),
+            VteHandler::new(terminal, backend),
+            RawTap::new(pane_id),
+        )));
+        pty
+    }
+
+    /// True for the PTY of a virtual pane.
+    pub fn is_virtual(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Feed `data` to a virtual pane's terminal, as if its program had printed
+    /// it. Errors on any other pane: their output belongs to a process.
+    pub fn write_output(&self, data: &[u8]) -> Result<(), String> {
+        let output = self.output.as_ref().ok_or("not a virtual pane")?;
+        if let Some(rec) = self.recorder.lock().as_mut() {
+            rec.output(data);
+        }
+        if let Some(sink) = self.pipe.lock().as_ref() {
+            sink.output(data);
+        }
+        let mut output = output.lock();
+        let (parser, handler, raw_tap) = &mut *output;
+        raw_tap.output(data);
+        parser.advance(handler, data);
+        handler.apply_ops();
+        Ok(())
+    }
+
+    /// Speed and pause controls, for playback PTYs.
+    pub fn playback_control(&self) -> Option<&Arc<PlaybackControl>> {
+        self.playback.as_ref()
+    }
+
+    /// Start recording this pane to `path` (asciicast v2). `cols`/`rows` is the
+    /// current terminal size, written to the header.
+    pub fn start_recording(&self, path: &std::path::Path, cols: u16, rows: u16, record_input: bool) -> Result<(), String> {
+        if !self.is_live() && !self.is_virtual() {
+            return Err("pane has no shell to record".to_string());
+        }
+        let mut slot = self.recorder.lock();
+        if let Some(rec) = slot.as_ref() {
+            return Err(format!("already recording to {}", rec.path().display()));
+        }
+        *slot = Some(CastWriter::create(path, cols, rows, record_input)?);
+        log::info!("Recording pid={} to {}", self.pid(), path.display());
+        Ok(())
+    }
+
+    /// Stop recording. Returns the file written, `None` if not recording.
+    pub fn stop_recording(&self) -> Option<std::path::PathBuf> {
+        let rec = self.recorder.lock().take()?;
+        let path = rec.finish();
+        log::info!("Recording pid={} saved to {}", self.pid(), path.display());
+        Some(path)
+    }
+
+    /// File being recorded to, if any.
+    pub fn recording_path(&self) -> Option<std::path::PathBuf> {
+        self.recorder.lock().as_ref().map(|rec| rec.path().to_path_buf())
+    }
+
+    /// The `pipe-pane` slot. See `Pane::start_pipe`.
+    pub fn pipe_slot(&self) -> &PipeSlot {
+        &self.pipe
     }
 
     /// Returns true if this PTY has a real child process (not a dummy).
     pub fn is_live(&self) -> bool {
-        !self.is_dummy
+        self.backend.is_live()
+    }
+
+    /// The recorder slot, for a backend that feeds output without the reader
+    /// thread.
+    #[cfg(test)]
+    pub(super) fn recorder(&self) -> RecorderSlot {
+        self.recorder.clone()
     }
 
     pub fn write(&self, data: &[u8]) {
-        let _ = rustix::io::write(&self.master_fd, data);
+        if let Some(control) = &self.playback {
+            control.handle_input(data);
+            return;
+        }
+        self.backend.write(data);
+        if let Some(rec) = self.recorder.lock().as_mut() {
+            rec.input(data);
+        }
         // Count UTF-8 characters (non-continuation bytes)
         let n = data.iter().filter(|b| (*b & 0xC0) != 0x80).count() as u64;
         self.input_chars.fetch_add(n, Ordering::Relaxed);
@@ -445,17 +692,11 @@ impl Pty {
     }
 
     pub fn resize(&self, cols: u16, rows: u16) {
-        log::trace!("PTY resize: pid={}, cols={}, rows={}", self.child_pid, cols, rows);
-        let winsize = Winsize {
-            ws_row: rows,
-            ws_col: cols,
-            ws_xpixel: 0,
-            ws_ypixel: 0,
-        };
-        let _ = termios::tcsetwinsize(self.master_fd.as_fd(), winsize);
-        // TIOCSWINSZ (via tcsetwinsize) automatically sends SIGWINCH to the
-        // foreground process group when the controlling terminal is properly
-        // established (setsid + TIOCSCTTY in pre_exec).
+        log::trace!("PTY resize: pid={}, cols={}, rows={}", self.pid(), cols, rows);
+        self.backend.resize(cols, rows);
+        if let Some(rec) = self.recorder.lock().as_mut() {
+            rec.resize(cols, rows);
+        }
     }
 
     /// Foreground process *and* its name in a single tcgetpgrp probe, for
@@ -466,69 +707,29 @@ impl Pty {
     /// process does; `info.name` is empty when the name could not be resolved,
     /// so an empty name must never be read as "no foreground process".
     pub fn foreground_process(&self) -> Option<ProcessInfo> {
-        if self.is_dummy {
-            return None;
-        }
-        let fg_pgid = foreground_pgid(self.master_fd.as_raw_fd(), self.child_pid)?;
-        Some(process_info(fg_pgid as u32))
+        self.backend.foreground_process()
     }
 
     /// Returns the name of the foreground process if it differs from the shell
     /// (i.e. a command like vim, cargo, etc. is running).
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     pub fn foreground_process_name(&self) -> Option<String> {
         self.foreground_process().map(|p| p.name).filter(|name| !name.is_empty())
     }
 
     /// Returns the PID of the child shell process.
     pub fn pid(&self) -> u32 {
-        self.child_pid
+        self.backend.pid()
     }
 
     /// Returns the list of child processes of the shell (pid, name + version).
-    /// Uses macOS `proc_listchildpids`, then `process_info` for each child.
     pub fn child_processes(&self) -> Vec<(u32, ProcessInfo)> {
-        let pid = self.child_pid as i32;
-        // First call to get count
-        let count = unsafe { libc::proc_listchildpids(pid, std::ptr::null_mut(), 0) };
-        if count <= 0 {
-            return Vec::new();
-        }
-        let mut pids = vec![0i32; count as usize];
-        let actual = unsafe {
-            libc::proc_listchildpids(
-                pid,
-                pids.as_mut_ptr() as *mut libc::c_void,
-                (pids.len() * std::mem::size_of::<i32>()) as i32,
-            )
-        };
-        if actual <= 0 {
-            return Vec::new();
-        }
-        // proc_listchildpids returns a *count* of PIDs written, not a byte
-        // length — dividing by size_of::<i32>() truncated every shell with
-        // fewer than four children down to zero.
-        pids.truncate(actual as usize);
-        pids.iter().map(|&cpid| (cpid as u32, process_info(cpid as u32))).collect()
+        self.backend.child_processes()
     }
 
     /// Returns the current working directory of the child shell process.
-    /// Uses macOS `proc_pidinfo` with `PROC_PIDVNODEPATHINFO`.
     pub fn cwd(&self) -> Option<String> {
-        unsafe {
-            let mut vpi: libc::proc_vnodepathinfo = std::mem::zeroed();
-            let ret = libc::proc_pidinfo(
-                self.child_pid as i32,
-                libc::PROC_PIDVNODEPATHINFO,
-                0,
-                &mut vpi as *mut _ as *mut libc::c_void,
-                std::mem::size_of::<libc::proc_vnodepathinfo>() as i32,
-            );
-            if ret <= 0 {
-                return None;
-            }
-            let path = std::ffi::CStr::from_ptr(vpi.pvi_cdir.vip_path.as_ptr() as *const i8);
-            path.to_str().ok().map(String::from)
-        }
+        self.backend.cwd()
     }
 }
 
@@ -560,6 +761,7 @@ fn reap_child(pid: i32, step_ms: u64) {
 
 /// Count how many PTYs have a foreground process that differs from the shell.
 /// This is the global equivalent of `Pane::foreground_process_name().is_some()`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 pub fn foreground_process_count() -> u32 {
     let registry = PTY_REGISTRY.lock();
     registry.iter().filter(|e| foreground_pgid(e.master_fd, e.child_pid).is_some()).count() as u32
@@ -589,41 +791,25 @@ pub fn shutdown_all() {
 
 impl Drop for Pty {
     fn drop(&mut self) {
-        if self.is_dummy {
-            return;
-        }
+        self.stop_recording();
+        // Closes the pipe's queue: its writer ends the command's input.
+        self.pipe.lock().take();
         self.shutdown.store(true, Ordering::Relaxed);
-        // Send SIGHUP to the child so it exits, which causes EOF on the reader fd.
-        // Without this, the reader thread could block indefinitely on read().
-        let kill_rc = unsafe { libc::kill(self.child_pid as i32, libc::SIGHUP) };
-        if kill_rc != 0 {
-            let err = std::io::Error::last_os_error();
-            // ESRCH is expected if the child already exited — don't log noise.
-            if err.raw_os_error() != Some(libc::ESRCH) {
-                log::warn!("PTY: kill(SIGHUP, {}) failed: {}", self.child_pid, err);
-            }
-        }
+        self.backend.hang_up();
         if let Some(handle) = self.reader_thread.take() {
             // With the polling reader this join is bounded (~100ms + parse
             // time); anything slower means the shutdown path regressed and
-            // we're back to freezing the main thread on pane close.
+            // we're back to freezing the main thread on pane close. A
+            // playback PTY's player thread sleeps in short slices and
+            // re-checks the flag, so its join is bounded the same way.
             let t0 = std::time::Instant::now();
             let _ = handle.join();
             let elapsed = t0.elapsed();
             if elapsed.as_millis() > 500 {
-                log::warn!("PTY: reader join for child {} took {:?}", self.child_pid, elapsed);
+                log::warn!("PTY: reader join for child {} took {:?}", self.pid(), elapsed);
             }
         }
-        let pid = self.child_pid;
-        PTY_REGISTRY.lock().retain(|e| e.child_pid != pid);
-        let result = std::thread::Builder::new()
-            .name(format!("pty-reaper-{}", pid))
-            .spawn(move || reap_child(pid as i32, 50));
-        if let Err(e) = result {
-            log::warn!("Failed to spawn reaper for pid {}: {}, reaping synchronously", pid, e);
-            reap_child(pid as i32, 50);
-        }
-        log::info!("PTY child {} cleanup delegated to reaper thread", pid);
+        // The backend goes with the last reference, now that the reader's is gone.
     }
 }
 
@@ -631,44 +817,6 @@ impl Drop for Pty {
 mod tests {
     use super::*;
 
-    /// Build a KERN_PROCARGS2-shaped buffer: argc, exec path, padding, argv.
-    fn procargs_buffer(exe_path: &str, argv: &[&str]) -> Vec<u8> {
-        let mut buf = (argv.len() as libc::c_int).to_ne_bytes().to_vec();
-        buf.extend_from_slice(exe_path.as_bytes());
-        buf.extend_from_slice(&[0, 0, 0]); // the kernel pads with NULs
-        for arg in argv {
-            buf.extend_from_slice(arg.as_bytes());
-            buf.push(0);
-        }
-        buf
-    }
-
-    #[test]
-    fn proc_args_reads_the_exe_path_and_argv0() {
-        let buf = procargs_buffer("/usr/bin/vim", &["vim", "notes.md"]);
-        assert_eq!(
-            parse_proc_args(&buf),
-            Some(("/usr/bin/vim".to_string(), "vim".to_string()))
-        );
-    }
-
-    #[test]
-    fn proc_args_survives_a_truncated_buffer() {
-        // Only argv[0] is needed, so a buffer cut short mid-argv still answers.
-        let buf = procargs_buffer("/usr/bin/vim", &["vim", "notes.md"]);
-        let cut = buf.len() - 4;
-        assert_eq!(
-            parse_proc_args(&buf[..cut]),
-            Some(("/usr/bin/vim".to_string(), "vim".to_string()))
-        );
-    }
-
-    #[test]
-    fn proc_args_rejects_a_buffer_with_nothing_in_it() {
-        assert_eq!(parse_proc_args(&[]), None);
-        assert_eq!(parse_proc_args(&[1, 2]), None);
-    }
-
     #[test]
     fn program_name_is_the_last_path_component() {
         assert_eq!(program_name("/usr/bin/vim"), "vim");
@@ -707,7 +855,7 @@ mod tests {
         assert_eq!(vim.label(), "vim");
     }
 
-    /// Guards the sysctl call itself, which no synthetic buffer can cover.
+    /// Guards the platform probe itself, which no synthetic buffer can cover.
     #[test]
     fn process_info_names_the_running_test_binary() {
         let info = process_info(std::process::id());
@@ -0,0 +1,495 @@
+//! Pane recording and playback in asciicast v2 format.
+//!
+//! A `.cast` file is one JSON header line (`version`, `width`, `height`, …)
+//! followed by one JSON array per event: `[seconds, code, data]`, where code is
+//! `"o"` (output), `"i"` (input) or `"r"` (resize, data `"COLSxROWS"`). This is
+//! what asciinema writes and plays, so a recording made here opens there too.
+//!
+//! Recording hooks into `Pty`: the reader thread feeds output, `Pty::write`
+//! feeds input, `Pty::resize` feeds resizes. Playback runs a `.cast` through
+//! the regular `VteHandler` on its own thread, in place of a PTY reader.
+
+use parking_lot::{Mutex, RwLock};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::output::RawTap;
+use super::parser::VteHandler;
+use super::pty::NullBackend;
+use super::TerminalState;
+
+/// Slowest and fastest playback speed (multiplier of recorded time).
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 16.0;
+
+/// Shared between a `Pty` and its reader thread: `Some` while recording.
+pub type RecorderSlot = Arc<Mutex<Option<CastWriter>>>;
+
+/// Writes one pane's activity to a `.cast` file as it happens.
+pub struct CastWriter {
+    out: std::io::BufWriter<std::fs::File>,
+    path: PathBuf,
+    start: Instant,
+    /// Whether keystrokes are recorded as `"i"` events. Off by default: input
+    /// holds whatever the user typed, passwords at a silent prompt included.
+    record_input: bool,
+    /// Tail of a UTF-8 sequence split across two PTY reads, held back until
+    /// the rest arrives (event data must be a valid JSON string).
+    pending: Vec<u8>,
+    /// A failed write is logged once, not on every read.
+    failed: bool,
+}
+
+impl CastWriter {
+    /// Create `path` (and its directory) and write the header. An existing
+    /// file is never overwritten: the path may come from an IPC client. The
+    /// file is owner-only, like the directory if it is created here — with
+    /// input on, it holds what was typed.
+    pub fn create(path: &Path, cols: u16, rows: u16, record_input: bool) -> Result<Self, String> {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        if let Some(dir) = path.parent() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = serde_json::json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "env": {
+                "TERM": "xterm-256color",
+                "SHELL": std::env::var("SHELL").unwrap_or_default(),
+            },
+        });
+        let mut out = std::io::BufWriter::new(file);
+        writeln!(out, "{}", header).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+        Ok(CastWriter {
+            out,
+            path: path.to_path_buf(),
+            start: Instant::now(),
+            record_input,
+            pending: Vec::new(),
+            failed: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Bytes the shell wrote to the terminal.
+    pub fn output(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        let text = take_utf8(&mut self.pending);
+        if !text.is_empty() {
+            let t = self.elapsed();
+            self.event(t, "o", &text);
+        }
+    }
+
+    /// Bytes sent to the shell (keystrokes, pastes), if input recording is on.
+    pub fn input(&mut self, bytes: &[u8]) {
+        if self.record_input {
+            let t = self.elapsed();
+            self.event(t, "i", &String::from_utf8_lossy(bytes));
+        }
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        let t = self.elapsed();
+        self.event(t, "r", &format!("{}x{}", cols, rows));
+    }
+
+    /// Flush what is buffered and close the file. Returns its path.
+    pub fn finish(mut self) -> PathBuf {
+        if !self.pending.is_empty() {
+            let text = String::from_utf8_lossy(&self.pending).into_owned();
+            let t = self.elapsed();
+            self.event(t, "o", &text);
+        }
+        if let Err(e) = self.out.flush() {
+            log::warn!("Recording: flush of {} failed: {}", self.path.display(), e);
+        }
+        self.path
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    fn event(&mut self, t: f64, code: &str, data: &str) {
+        let data = serde_json::Value::String(data.to_string());
+        match writeln!(self.out, "[{:.6}, \"{}\", {}]", t, code, data) {
+            Err(e) if !self.failed => {
+                log::warn!("Recording: write to {} failed: {}", self.path.display(), e);
+                self.failed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Drain the longest valid UTF-8 prefix of `buf`. An incomplete sequence at the
+/// very end stays in `buf` for the next call; invalid bytes become U+FFFD.
+fn take_utf8(buf: &mut Vec<u8>) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    loop {
+        match std::str::from_utf8(&buf[pos..]) {
+            Ok(s) => {
+                out.push_str(s);
+                pos = buf.len();
+                break;
+            }
+            Err(e) => {
+                let valid = pos + e.valid_up_to();
+                out.push_str(std::str::from_utf8(&buf[pos..valid]).unwrap_or_default());
+                match e.error_len() {
+                    Some(n) => {
+                        out.push(char::REPLACEMENT_CHARACTER);
+                        pos = valid + n;
+                    }
+                    None => {
+                        pos = valid;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    buf.drain(..pos);
+    out
+}
+
+/// A parsed `.cast` file.
+#[derive(Debug)]
+pub struct Cast {
+    pub cols: u16,
+    pub rows: u16,
+    /// Longest pause kept during playback (`idle_time_limit` in the header).
+    pub idle_time_limit: Option<f64>,
+    pub events: Vec<CastEvent>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CastEvent {
+    pub time: f64,
+    pub kind: CastEventKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CastEventKind {
+    Output(String),
+    Input(String),
+    Resize(u16, u16),
+}
+
+impl Cast {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    /// Parse asciicast v2. Event codes other than o/i/r (markers) are skipped.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        let (_, header) = lines.next().ok_or("empty recording")?;
+        let header: serde_json::Value =
+            serde_json::from_str(header).map_err(|e| format!("line 1: invalid header: {}", e))?;
+        if header.get("version").and_then(|v| v.as_u64()) != Some(2) {
+            return Err("not an asciicast v2 recording (\"version\" must be 2)".to_string());
+        }
+        let dim = |key: &str| {
+            header
+                .get(key)
+                .and_then(|v| v.as_u64())
+                .filter(|&n| n > 0 && n <= u16::MAX as u64)
+                .map(|n| n as u16)
+                .ok_or_else(|| format!("header: missing or invalid \"{}\"", key))
+        };
+        let cols = dim("width")?;
+        let rows = dim("height")?;
+        let idle_time_limit = header.get("idle_time_limit").and_then(|v| v.as_f64()).filter(|&l| l > 0.0);
+
+        let mut events = Vec::new();
+        for (i, line) in lines {
+            let bad = |what: &str| format!("line {}: {}", i + 1, what);
+            let v: serde_json::Value = serde_json::from_str(line).map_err(|e| bad(&e.to_string()))?;
+            let (time, code, data) = match v.as_array().map(Vec::as_slice) {
+                Some([t, c, d]) => match (t.as_f64(), c.as_str(), d.as_str()) {
+                    (Some(t), Some(c), Some(d)) if t >= 0.0 => (t, c, d),
+                    _ => return Err(bad("expected [seconds, code, data]")),
+                },
+                _ => return Err(bad("expected [seconds, code, data]")),
+            };
+            let kind = match code {
+                "o" => CastEventKind::Output(data.to_string()),
+                "i" => CastEventKind::Input(data.to_string()),
+                "r" => {
+                    let size = data.split_once('x').and_then(|(c, r)| {
+                        Some((c.parse::<u16>().ok()?, r.parse::<u16>().ok()?))
+                    });
+                    match size {
+                        Some((c, r)) if c > 0 && r > 0 => CastEventKind::Resize(c, r),
+                        _ => return Err(bad("resize data must be COLSxROWS")),
+                    }
+                }
+                _ => continue,
+            };
+            events.push(CastEvent { time, kind });
+        }
+        Ok(Cast { cols, rows, idle_time_limit, events })
+    }
+}
+
+/// Speed and pause state of a playback, shared with its player thread.
+pub struct PlaybackControl {
+    speed_bits: AtomicU32,
+    paused: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl PlaybackControl {
+    pub fn new(speed: f32) -> Self {
+        PlaybackControl {
+            speed_bits: AtomicU32::new(speed.clamp(MIN_SPEED, MAX_SPEED).to_bits()),
+            paused: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        f32::from_bits(self.speed_bits.load(Ordering::Relaxed))
+    }
+
+    /// Clamped to `MIN_SPEED..=MAX_SPEED`; takes effect mid-pause too.
+    pub fn set_speed(&self, speed: f32) {
+        self.speed_bits.store(speed.clamp(MIN_SPEED, MAX_SPEED).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Keys typed into a playback pane drive the player instead of a shell:
+    /// space pauses/resumes, `+`/`-` double/halve the speed, `1` resets it.
+    pub fn handle_input(&self, data: &[u8]) {
+        for &b in data {
+            match b {
+                b' ' => self.set_paused(!self.is_paused()),
+                b'+' | b'=' => self.set_speed(self.speed() * 2.0),
+                b'-' | b'_' => self.set_speed(self.speed() / 2.0),
+                b'1' => self.set_speed(1.0),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sleep for `recorded` seconds of recording time, following speed changes
+    /// and pauses as they happen. Returns false if `shutdown` was raised.
+    fn wait(&self, recorded: f64, shutdown: &AtomicBool) -> bool {
+        const SLICE: f64 = 0.05;
+        let mut remaining = recorded;
+        loop {
+            if shutdown.load(Ordering::Relaxed) {
+                return false;
+            }
+            if self.is_paused() {
+                std::thread::sleep(Duration::from_secs_f64(SLICE));
+                continue;
+            }
+            if remaining <= 0.0 {
+                return true;
+            }
+            let speed = self.speed() as f64;
+            let step = (remaining / speed).min(SLICE);
+            std::thread::sleep(Duration::from_secs_f64(step));
+            remaining -= step * speed;
+        }
+    }
+}
+
+/// Replay `cast` into `terminal` on a new thread. Recorded resizes resize the
+/// terminal, input events are skipped (their effect is already in the output).
+/// The thread stops at the end of the recording or when `shutdown` is raised.
+/// The output also goes to `raw_tap`, as a PTY read would.
+pub fn spawn_player(
+    cast: Cast,
+    terminal: Arc<RwLock<TerminalState>>,
+    control: Arc<PlaybackControl>,
+    shutdown: Arc<AtomicBool>,
+    mut raw_tap: RawTap,
+) -> Result<std::thread::JoinHandle<()>, String> {
+    std::thread::Builder::new()
+        .name("cast-player".into())
+        .spawn(move || {
+            let mut parser = vte::Parser::new();
+            // Replies to terminal queries (DA, DSR…) have nobody to go to.
+            let mut handler = VteHandler::new(terminal.clone(), Arc::new(NullBackend));
+            let mut last = 0.0;
+            for event in &cast.events {
+                let mut gap = (event.time - last).max(0.0);
+                if let Some(limit) = cast.idle_time_limit {
+                    gap = gap.min(limit);
+                }
+                last = event.time;
+                if !control.wait(gap, &shutdown) {
+                    return;
+                }
+                match &event.kind {
+                    CastEventKind::Output(data) => {
+                        raw_tap.output(data.as_bytes());
+                        parser.advance(&mut handler, data.as_bytes());
+                        handler.apply_ops();
+                    }
+                    CastEventKind::Resize(cols, rows) => terminal.write().resize(*cols, *rows),
+                    CastEventKind::Input(_) => {}
+                }
+            }
+            control.finished.store(true, Ordering::Relaxed);
+            log::info!("Playback finished ({} events)", cast.events.len());
+        })
+        .map_err(|e| format!("cannot spawn player thread: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::{DumpMode, DEFAULT_BG, DEFAULT_FG};
+
+    fn temp_cast(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("kova-rec-{}-{}.cast", std::process::id(), name))
+    }
+
+    #[test]
+    fn utf8_split_across_reads_is_held_back_until_complete() {
+        let mut buf = "é".as_bytes()[..1].to_vec();
+        assert_eq!(take_utf8(&mut buf), "");
+        buf.extend_from_slice(&"é".as_bytes()[1..]);
+        buf.extend_from_slice(b"a\xffb");
+        assert_eq!(take_utf8(&mut buf), "éa\u{FFFD}b");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn recording_round_trips_through_the_parser() {
+        let path = temp_cast("roundtrip");
+        let mut rec = CastWriter::create(&path, 80, 24, false).unwrap();
+        rec.output(b"hello \xe2\x94");
+        rec.output(b"\x80\r\n");
+        rec.input(b"secret");
+        rec.resize(100, 30);
+        let path = rec.finish();
+
+        let cast = Cast::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!((cast.cols, cast.rows), (80, 24));
+        let kinds: Vec<&CastEventKind> = cast.events.iter().map(|e| &e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &CastEventKind::Output("hello ".into()),
+                &CastEventKind::Output("─\r\n".into()),
+                &CastEventKind::Resize(100, 30),
+            ],
+            "input is left out unless asked for"
+        );
+        assert!(cast.events.windows(2).all(|w| w[0].time <= w[1].time));
+    }
+
+    #[test]
+    fn input_events_are_recorded_when_enabled() {
+        let path = temp_cast("input");
+        let mut rec = CastWriter::create(&path, 80, 24, true).unwrap();
+        rec.input(b"ls\r");
+        let path = rec.finish();
+        let cast = Cast::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(cast.events[0].kind, CastEventKind::Input("ls\r".into()));
+    }
+
+    #[test]
+    fn a_recording_is_owner_only_and_never_overwrites_a_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_cast("exclusive");
+        std::fs::write(&path, "keep me").unwrap();
+        assert!(CastWriter::create(&path, 80, 24, false).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+        std::fs::remove_file(&path).unwrap();
+
+        let path = CastWriter::create(&path, 80, 24, true).unwrap().finish();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn parse_rejects_what_is_not_asciicast_v2() {
+        assert!(Cast::parse("").is_err());
+        assert!(Cast::parse(r#"{"version": 1, "width": 80, "height": 24}"#).is_err());
+        assert!(Cast::parse(r#"{"version": 2, "height": 24}"#).is_err());
+        let err = Cast::parse("{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.5, \"r\", \"80\"]")
+            .unwrap_err();
+        assert!(err.starts_with("line 2"), "{}", err);
+        // Markers and unknown codes are not errors, just not played.
+        let cast = Cast::parse("{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.5, \"m\", \"\"]").unwrap();
+        assert!(cast.events.is_empty());
+    }
+
+    #[test]
+    fn playback_drives_the_terminal_and_follows_resizes() {
+        let cast = Cast::parse(concat!(
+            "{\"version\": 2, \"width\": 20, \"height\": 5, \"idle_time_limit\": 0.01}\n",
+            "[0.0, \"o\", \"first\\r\\n\"]\n",
+            "[5.0, \"r\", \"30x6\"]\n",
+            "[9.0, \"o\", \"second\"]\n",
+        ))
+        .unwrap();
+        let term = Arc::new(RwLock::new(TerminalState::new(20, 5, 100, DEFAULT_FG, DEFAULT_BG)));
+        let control = Arc::new(PlaybackControl::new(MAX_SPEED));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        spawn_player(cast, term.clone(), control.clone(), shutdown, RawTap::new(0)).unwrap().join().unwrap();
+
+        assert!(control.is_finished());
+        let term = term.read();
+        assert_eq!((term.cols, term.rows), (30, 6));
+        let text = term.dump_text(DumpMode::Visible, true).text;
+        assert_eq!(text.trim_end(), "first\nsecond");
+    }
+
+    #[test]
+    fn playback_keys_pause_and_change_speed_within_bounds() {
+        let control = PlaybackControl::new(1.0);
+        control.handle_input(b"++");
+        assert_eq!(control.speed(), 4.0);
+        control.handle_input(b"-------");
+        assert_eq!(control.speed(), MIN_SPEED);
+        control.handle_input(b"1 ");
+        assert_eq!(control.speed(), 1.0);
+        assert!(control.is_paused());
+    }
+}
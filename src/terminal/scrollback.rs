@@ -0,0 +1,1164 @@
+//! Tiered scrollback storage: plain rows, encoded rows, and an optional spill file.
+//!
+//! A `Row` costs 32 bytes per cell, and most of those bytes repeat: a line of
+//! shell output is one or two colors and a handful of attributes across 80+
+//! cells. With dozens of panes at 10k lines each, the scrollback dominated RSS.
+//!
+//! The newest `HOT_ROWS` rows stay as plain `Row`s — that is where the user
+//! scrolls and selects most, and where resize pulls rows back into the grid.
+//! Older rows move to the cold tier as a `ColdRow`: one byte buffer holding
+//! runs of identical style (fg, bg, attrs, hyperlink id), interned grapheme
+//! clusters, and the cell characters as UTF-8. A typical 80-column line drops
+//! from ~2.5 KB to ~100 bytes.
+//!
+//! Cold rows are decoded on demand (`get`, `iter`) into an owned `Row`, which
+//! is why every accessor hands out `Cow<Row>`. Decoding a screenful costs a
+//! few microseconds, so scrolling and search do not notice the difference
+//! (see the `bench_` tests in `terminal/mod.rs`).
+//!
+//! Past the in-memory limits (`scrollback` lines, `scrollback_memory_mb`),
+//! the oldest rows are either dropped or, with `scrollback_disk_mb` set,
+//! written to a per-pane `SpillFile` in the same encoding, each record with
+//! its own clusters: the pane's cluster table only serves cold rows, and a
+//! cluster is freed once no cold row uses it. Spilled rows are read back on
+//! demand like cold rows; only reflow leaves them alone — they keep the width
+//! they were written at.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use parking_lot::RwLock;
+
+use super::{Cell, CellAttrs, Row, TerminalState};
+
+/// Rows kept uncompressed at the bottom of the scrollback.
+pub const HOT_ROWS: usize = 1024;
+
+/// Spilled records are buffered and written in chunks of this size, so a
+/// line of output does not cost a syscall.
+const SPILL_WRITE_CHUNK: usize = 64 * 1024;
+
+/// Spilled rows read back per `pread` when scanning (`iter`, `iter_from`),
+/// instead of one read per row.
+const SPILL_READ_BATCH: usize = 256;
+
+/// Byte layout of `ColdRow::data` (all integers are LEB128 varints):
+///
+/// ```text
+/// cells  runs  { len fg[3] bg[3] attrs hyperlink }*runs
+/// clusters { cell_index cluster_id }*clusters
+/// UTF-8 of each cell's `c` ('\0' for wide-char continuations)
+/// ```
+///
+/// A row with no cells has an empty buffer (no allocation).
+#[derive(Clone, Debug)]
+struct ColdRow {
+    data: Box<[u8]>,
+    wrapped: bool,
+}
+
+/// Memory used by each tier, for `mem_bytes` and the Cmd+Shift+I report.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScrollbackMem {
+    pub hot_rows: usize,
+    pub hot_bytes: usize,
+    pub cold_rows: usize,
+    /// Encoded rows, the cluster table and the spill file's row index.
+    pub cold_bytes: usize,
+    pub disk_rows: usize,
+    /// Bytes of live rows in the spill file (not RAM).
+    pub disk_bytes: usize,
+}
+
+pub struct Scrollback {
+    /// Oldest rows, on disk. Absolute line 0 when present.
+    spill: Option<SpillFile>,
+    /// Encoded rows. Follow the spilled rows in line order.
+    cold: VecDeque<ColdRow>,
+    /// Newest rows, as-is. Follow `cold` in line order.
+    hot: VecDeque<Row>,
+    hot_limit: usize,
+    /// Cap on `hot_bytes + cold_bytes` (0 = lines only).
+    memory_budget: usize,
+    hot_bytes: usize,
+    cold_bytes: usize,
+    /// Interned grapheme clusters referenced by cold rows, counted per row.
+    clusters: ClusterTable,
+}
+
+impl Scrollback {
+    pub fn new() -> Self {
+        Self::with_hot_limit(HOT_ROWS)
+    }
+
+    pub fn with_hot_limit(hot_limit: usize) -> Self {
+        Scrollback {
+            spill: None,
+            cold: VecDeque::new(),
+            hot: VecDeque::new(),
+            hot_limit,
+            memory_budget: 0,
+            hot_bytes: 0,
+            cold_bytes: 0,
+            clusters: ClusterTable::default(),
+        }
+    }
+
+    /// Set the memory budget in bytes (0 = none) and where evicted rows go
+    /// (`None` = dropped). Takes effect at the next `trim`.
+    pub fn set_storage(&mut self, memory_budget: usize, spill: Option<SpillFile>) {
+        self.memory_budget = memory_budget;
+        self.spill = spill;
+    }
+
+    pub fn len(&self) -> usize {
+        self.disk_len() + self.cold.len() + self.hot.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn disk_len(&self) -> usize {
+        self.spill.as_ref().map_or(0, |s| s.len())
+    }
+
+    fn memory_len(&self) -> usize {
+        self.cold.len() + self.hot.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.cold.clear();
+        self.hot.clear();
+        self.hot_bytes = 0;
+        self.cold_bytes = 0;
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+        self.reset_clusters();
+    }
+
+    /// Append the newest row, encoding the oldest hot row if the hot tier is full.
+    pub fn push_back(&mut self, row: Row) {
+        self.hot_bytes += hot_row_bytes(&row);
+        self.hot.push_back(row);
+        while self.hot.len() > self.hot_limit {
+            let Some(old) = self.hot.pop_front() else { break };
+            self.hot_bytes -= hot_row_bytes(&old);
+            let cold = self.encode(&old);
+            self.cold_bytes += cold_row_bytes(&cold);
+            self.cold.push_back(cold);
+        }
+    }
+
+    /// Enforce the in-memory limits: at most `max_lines` rows and the memory
+    /// budget. The oldest rows over either limit move to the spill file, or
+    /// are dropped without one. Returns how many rows were dropped for good
+    /// (a full spill file drops its own oldest rows too).
+    pub fn trim(&mut self, max_lines: usize) -> usize {
+        let mut dropped = 0;
+        while self.memory_len() > max_lines
+            || (self.memory_budget > 0 && self.memory_len() > 0 && self.memory_bytes() > self.memory_budget)
+        {
+            let Some(n) = self.evict_oldest() else { break };
+            dropped += n;
+        }
+        dropped
+    }
+
+    /// Encode every hot row (the global budget's first, lossless resort).
+    /// Returns the bytes freed.
+    pub fn compact(&mut self) -> usize {
+        let before = self.memory_bytes();
+        while let Some(row) = self.hot.pop_front() {
+            self.hot_bytes -= hot_row_bytes(&row);
+            let cold = self.encode(&row);
+            self.cold_bytes += cold_row_bytes(&cold);
+            self.cold.push_back(cold);
+        }
+        self.hot.shrink_to_fit();
+        before.saturating_sub(self.memory_bytes())
+    }
+
+    /// Evict the oldest in-memory rows until `bytes` are freed (or memory is
+    /// empty). Returns `(bytes freed, rows dropped for good)`.
+    pub fn shed(&mut self, bytes: usize) -> (usize, usize) {
+        let before = self.memory_bytes();
+        let mut dropped = 0;
+        while before - self.memory_bytes() < bytes {
+            let Some(n) = self.evict_oldest() else { break };
+            dropped += n;
+        }
+        (before - self.memory_bytes(), dropped)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.hot_bytes + self.cold_bytes
+    }
+
+    /// Move the oldest in-memory row to the spill file, or drop it without
+    /// one. Returns how many rows were lost, `None` when memory is empty.
+    fn evict_oldest(&mut self) -> Option<usize> {
+        let spilled = match self.cold.pop_front() {
+            Some(cold) => {
+                self.cold_bytes -= cold_row_bytes(&cold);
+                let ids = cluster_ids(&cold.data);
+                if ids.is_empty() {
+                    // Spill encoding with no clusters of its own.
+                    let data = std::iter::once(0).chain(cold.data.iter().copied()).collect();
+                    self.spill.is_some().then_some(ColdRow { data, wrapped: cold.wrapped })
+                } else {
+                    let row = self.spill.is_some().then(|| self.decode(&cold));
+                    self.clusters.release(&ids);
+                    row.map(|row| encode_spilled(&row))
+                }
+            }
+            None => {
+                let row = self.hot.pop_front()?;
+                self.hot_bytes -= hot_row_bytes(&row);
+                self.spill.is_some().then(|| encode_spilled(&row))
+            }
+        };
+        Some(match (&mut self.spill, spilled) {
+            (Some(spill), Some(row)) => spill.push(&row),
+            _ => 1,
+        })
+    }
+
+    /// Remove and return the newest row (resize pulls it back into the grid).
+    pub fn pop_back(&mut self) -> Option<Row> {
+        if let Some(row) = self.hot.pop_back() {
+            self.hot_bytes -= hot_row_bytes(&row);
+            return Some(row);
+        }
+        let Some(cold) = self.cold.pop_back() else {
+            return self.spill.as_mut()?.pop_back().map(|cold| decode_spilled(&cold));
+        };
+        self.cold_bytes -= cold_row_bytes(&cold);
+        let row = self.decode(&cold);
+        self.clusters.release(&cluster_ids(&cold.data));
+        Some(row)
+    }
+
+    pub fn get(&self, index: usize) -> Option<Cow<'_, Row>> {
+        let disk = self.disk_len();
+        if index < disk {
+            return self.spilled(index).map(Cow::Owned);
+        }
+        match self.cold.get(index - disk) {
+            Some(cold) => Some(Cow::Owned(self.decode(cold))),
+            None => self.hot.get(index - disk - self.cold.len()).map(Cow::Borrowed),
+        }
+    }
+
+
+    /// All rows, oldest first. Cold and spilled rows are decoded one at a time.
+    pub fn iter(&self) -> impl Iterator<Item = Cow<'_, Row>> + '_ {
+        self.iter_from(0)
+    }
+
+    /// Rows from `start` on. Spilled rows are read `SPILL_READ_BATCH` at a
+    /// time, as they are reached.
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = Cow<'_, Row>> + '_ {
+        let disk = self.disk_len();
+        let cold_start = start.saturating_sub(disk);
+        let hot_start = cold_start.saturating_sub(self.cold.len());
+        (start.min(disk)..disk)
+            .step_by(SPILL_READ_BATCH)
+            .flat_map(move |i| self.spilled_run(i, SPILL_READ_BATCH.min(disk - i)))
+            .map(Cow::Owned)
+            .chain(self.cold.iter().skip(cold_start).map(|c| Cow::Owned(self.decode(c))))
+            .chain(self.hot.iter().skip(hot_start).map(Cow::Borrowed))
+    }
+
+    /// A spilled row. An unreadable one comes back blank rather than missing,
+    /// so absolute line numbers stay put.
+    fn spilled(&self, index: usize) -> Option<Row> {
+        self.spilled_run(index, 1).pop()
+    }
+
+    /// Up to `count` spilled rows from `start`, blank where unreadable.
+    fn spilled_run(&self, start: usize, count: usize) -> Vec<Row> {
+        let Some(spill) = &self.spill else { return Vec::new() };
+        spill
+            .read_run(start, count)
+            .into_iter()
+            .map(|cold| match cold {
+                Some(cold) => decode_spilled(&cold),
+                None => Row { cells: Vec::new(), wrapped: false },
+            })
+            .collect()
+    }
+
+    /// Take the in-memory rows out, decoded (reflow rebuilds them). Spilled
+    /// rows stay on disk.
+    pub fn take_rows(&mut self) -> Vec<Row> {
+        let mut rows = Vec::with_capacity(self.memory_len());
+        for cold in std::mem::take(&mut self.cold) {
+            rows.push(self.decode(&cold));
+        }
+        rows.extend(self.hot.drain(..));
+        self.hot_bytes = 0;
+        self.cold_bytes = 0;
+        self.reset_clusters();
+        rows
+    }
+
+    /// Put `rows` back after the spilled rows, oldest first (the inverse of
+    /// `take_rows`).
+    pub fn set_rows(&mut self, rows: Vec<Row>) {
+        let cold_count = rows.len().saturating_sub(self.hot_limit);
+        let mut rows = rows.into_iter();
+        for row in rows.by_ref().take(cold_count) {
+            let cold = self.encode(&row);
+            self.cold_bytes += cold_row_bytes(&cold);
+            self.cold.push_back(cold);
+        }
+        for row in rows {
+            self.hot_bytes += hot_row_bytes(&row);
+            self.hot.push_back(row);
+        }
+    }
+
+    pub fn mem(&self) -> ScrollbackMem {
+        let cluster_bytes = self.clusters.bytes();
+        let (disk_rows, disk_bytes, index_bytes) = match &self.spill {
+            Some(spill) => (spill.len(), spill.live_bytes() as usize, spill.index_bytes()),
+            None => (0, 0, 0),
+        };
+        ScrollbackMem {
+            hot_rows: self.hot.len(),
+            hot_bytes: self.hot_bytes,
+            cold_rows: self.cold.len(),
+            cold_bytes: self.cold_bytes + cluster_bytes + index_bytes,
+            disk_rows,
+            disk_bytes,
+        }
+    }
+
+    fn reset_clusters(&mut self) {
+        self.clusters = ClusterTable::default();
+    }
+
+    fn encode(&mut self, row: &Row) -> ColdRow {
+        ColdRow { data: encode_row(row, &mut self.clusters), wrapped: row.wrapped }
+    }
+
+    fn decode(&self, cold: &ColdRow) -> Row {
+        decode_row(&cold.data, cold.wrapped, &self.clusters, usize::MAX).unwrap_or_else(|e| {
+            log::warn!("Scrollback: {}", e);
+            Row { cells: Vec::new(), wrapped: cold.wrapped }
+        })
+    }
+}
+
+/// Encode a row for the spill file: `clusters { len utf8 }*` ahead of the
+/// `ColdRow` encoding, whose cluster ids index that list.
+fn encode_spilled(row: &Row) -> ColdRow {
+    let mut clusters = ClusterTable::default();
+    let body = encode_row(row, &mut clusters);
+    let mut data = Vec::with_capacity(1 + body.len());
+    put_varint(&mut data, clusters.list.len() as u32);
+    for cluster in &clusters.list {
+        put_varint(&mut data, cluster.len() as u32);
+        data.extend_from_slice(cluster.as_bytes());
+    }
+    data.extend_from_slice(&body);
+    ColdRow { data: data.into_boxed_slice(), wrapped: row.wrapped }
+}
+
+/// Inverse of `encode_spilled`. A row that does not decode (a spill file
+/// damaged on disk) comes back blank rather than failing the read.
+fn decode_spilled(cold: &ColdRow) -> Row {
+    let decode = || -> Result<Row, String> {
+        let data = &cold.data[..];
+        let mut pos = 0;
+        let mut clusters = ClusterTable::default();
+        let count = if data.is_empty() { 0 } else { get_varint(data, &mut pos) as usize };
+        for _ in 0..count {
+            let len = if pos < data.len() { get_varint(data, &mut pos) as usize } else { usize::MAX };
+            let bytes = data.get(pos..).and_then(|d| d.get(..len)).ok_or("corrupt row")?;
+            let cluster = std::str::from_utf8(bytes).map_err(|_| "corrupt row")?;
+            clusters.list.push(cluster.into());
+            pos += len;
+        }
+        decode_row(data.get(pos..).unwrap_or_default(), cold.wrapped, &clusters, usize::MAX)
+    };
+    decode().unwrap_or_else(|e| {
+        log::warn!("Scrollback: spilled {}", e);
+        Row { cells: Vec::new(), wrapped: cold.wrapped }
+    })
+}
+
+/// The cluster ids an encoded row refers to (one per clustered cell).
+fn cluster_ids(data: &[u8]) -> Vec<u32> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut pos = 0;
+    get_varint(data, &mut pos);
+    let runs = get_varint(data, &mut pos);
+    for _ in 0..runs {
+        get_varint(data, &mut pos);
+        pos += 7;
+        get_varint(data, &mut pos);
+    }
+    let count = get_varint(data, &mut pos);
+    (0..count).map(|_| {
+        get_varint(data, &mut pos);
+        get_varint(data, &mut pos)
+    }).collect()
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Grapheme clusters interned by `encode_row`; encoded rows refer to them by
+/// id. Each intern counts a use; `release` gives it back, and an id no row
+/// uses is reused for the next new cluster.
+#[derive(Default)]
+pub(super) struct ClusterTable {
+    /// Clusters in id order.
+    pub(super) list: Vec<Box<str>>,
+    refs: Vec<u32>,
+    ids: HashMap<Box<str>, u32>,
+    free: Vec<u32>,
+}
+
+impl ClusterTable {
+    pub(super) fn intern(&mut self, cluster: &str) -> u32 {
+        if let Some(&id) = self.ids.get(cluster) {
+            self.refs[id as usize] += 1;
+            return id;
+        }
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.list[id as usize] = cluster.into();
+                self.refs[id as usize] = 1;
+                id
+            }
+            None => {
+                self.list.push(cluster.into());
+                self.refs.push(1);
+                self.list.len() as u32 - 1
+            }
+        };
+        self.ids.insert(cluster.into(), id);
+        id
+    }
+
+    /// Drop one use of each id in `ids`, freeing clusters left unused.
+    fn release(&mut self, ids: &[u32]) {
+        for &id in ids {
+            let Some(refs) = self.refs.get_mut(id as usize).filter(|r| **r > 0) else { continue };
+            *refs -= 1;
+            if *refs == 0 {
+                let cluster = std::mem::take(&mut self.list[id as usize]);
+                self.ids.remove(&cluster);
+                self.free.push(id);
+            }
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        // Each cluster is stored twice: in the table and as the map key.
+        self.list.iter()
+            .map(|s| 2 * (std::mem::size_of::<Box<str>>() + s.len()) + 3 * std::mem::size_of::<u32>())
+            .sum()
+    }
+}
+
+/// Encode a row's cells (layout documented on `ColdRow`). The wrap flag is
+/// the caller's to store.
+pub(super) fn encode_row(row: &Row, clusters: &mut ClusterTable) -> Box<[u8]> {
+    if row.cells.is_empty() {
+        return Box::default();
+    }
+    let cells = &row.cells;
+    let mut runs: Vec<(usize, &Cell)> = Vec::new();
+    for cell in cells {
+        match runs.last_mut() {
+            Some((len, first)) if same_style(first, cell) => *len += 1,
+            _ => runs.push((1, cell)),
+        }
+    }
+
+    let mut data = Vec::with_capacity(8 + runs.len() * 10 + cells.len());
+    put_varint(&mut data, cells.len() as u32);
+    put_varint(&mut data, runs.len() as u32);
+    for (len, cell) in &runs {
+        put_varint(&mut data, *len as u32);
+        data.extend_from_slice(&cell.fg);
+        data.extend_from_slice(&cell.bg);
+        data.push(cell.attrs.bits());
+        put_varint(&mut data, cell.hyperlink_id as u32);
+    }
+
+    let clustered: Vec<(usize, &str)> = cells.iter().enumerate()
+        .filter_map(|(i, c)| c.cluster.as_deref().map(|s| (i, s)))
+        .collect();
+    put_varint(&mut data, clustered.len() as u32);
+    for (i, cluster) in clustered {
+        let id = clusters.intern(cluster);
+        put_varint(&mut data, i as u32);
+        put_varint(&mut data, id);
+    }
+
+    let mut buf = [0u8; 4];
+    for cell in cells {
+        data.extend_from_slice(cell.c.encode_utf8(&mut buf).as_bytes());
+    }
+    data.into_boxed_slice()
+}
+
+/// Inverse of `encode_row`. Input that is truncated, corrupt, or holds more
+/// than `max_cells` cells is refused (snapshots and spilled rows come back
+/// from disk): nothing is sized from a count before it is checked against
+/// the bytes that are actually there.
+pub(super) fn decode_row(data: &[u8], wrapped: bool, clusters: &ClusterTable, max_cells: usize) -> Result<Row, String> {
+    if data.is_empty() {
+        return Ok(Row { cells: Vec::new(), wrapped });
+    }
+    const CORRUPT: &str = "corrupt row";
+    let mut pos = 0;
+    let varint = |pos: &mut usize| -> Result<usize, String> {
+        if *pos >= data.len() {
+            return Err(CORRUPT.to_string());
+        }
+        Ok(get_varint(data, pos) as usize)
+    };
+    let count = varint(&mut pos)?;
+    // Every cell takes at least one byte of text at the end.
+    if count > max_cells || count > data.len() {
+        return Err(format!("row of {} cells, at most {} expected", count, max_cells.min(data.len())));
+    }
+    let run_count = varint(&mut pos)?;
+    if run_count > count {
+        return Err(CORRUPT.to_string());
+    }
+    let mut cells: Vec<Cell> = Vec::with_capacity(count);
+    for _ in 0..run_count {
+        let len = varint(&mut pos)?;
+        let style = data.get(pos..pos + 7).ok_or(CORRUPT)?;
+        let fg = [style[0], style[1], style[2]];
+        let bg = [style[3], style[4], style[5]];
+        let attrs = CellAttrs::from_bits_truncate(style[6]);
+        pos += 7;
+        let hyperlink_id = varint(&mut pos)? as u16;
+        if len > count - cells.len() {
+            return Err(CORRUPT.to_string());
+        }
+        let style = Cell { c: ' ', cluster: None, fg, bg, hyperlink_id, attrs };
+        cells.extend(std::iter::repeat_n(style, len));
+    }
+    if cells.len() != count {
+        return Err(CORRUPT.to_string());
+    }
+
+    let cluster_count = varint(&mut pos)?;
+    if cluster_count > count {
+        return Err(CORRUPT.to_string());
+    }
+    for _ in 0..cluster_count {
+        let i = varint(&mut pos)?;
+        let id = varint(&mut pos)?;
+        if let (Some(cell), Some(s)) = (cells.get_mut(i), clusters.list.get(id)) {
+            cell.cluster = Some(s.clone());
+        }
+    }
+
+    // Written from `char`s by `encode_row`, so always valid UTF-8 unless corrupt.
+    let text = std::str::from_utf8(data.get(pos..).unwrap_or_default()).map_err(|_| CORRUPT)?;
+    let mut chars = text.chars();
+    for cell in cells.iter_mut() {
+        cell.c = chars.next().ok_or(CORRUPT)?;
+    }
+    Ok(Row { cells, wrapped })
+}
+
+/// One pane as the app-wide scrollback budget sees it.
+pub struct BudgetPane {
+    pub terminal: Arc<RwLock<TerminalState>>,
+    /// Focused, listed by a search, or watched by a parked IPC wait or
+    /// `search-pane` scan: never compressed or trimmed.
+    pub protected: bool,
+}
+
+/// Bring the scrollback memory of all panes under `budget` bytes.
+///
+/// Panes idle longest (`last_activity_secs`) pay first: all of their hot rows
+/// are encoded before any pane loses a line, then the oldest lines go —
+/// spilled when the pane has a spill file, dropped otherwise. Protected panes
+/// and panes with a selection count toward the total but are never touched.
+/// Returns the bytes freed.
+pub fn enforce_budget(panes: &[BudgetPane], budget: usize) -> usize {
+    let mut total: usize = panes.iter()
+        .map(|p| {
+            let mem = p.terminal.read().scrollback_mem();
+            mem.hot_bytes + mem.cold_bytes
+        })
+        .sum();
+    if total <= budget {
+        return 0;
+    }
+    let start_total = total;
+    let mut order: Vec<(u64, &BudgetPane)> = panes.iter()
+        .filter(|p| !p.protected)
+        .map(|p| (p.terminal.read().last_activity_secs.load(Ordering::Relaxed), p))
+        .collect();
+    order.sort_by_key(|&(last_activity, _)| last_activity);
+
+    'shrink: {
+        for (_, pane) in &order {
+            let mut term = pane.terminal.write();
+            if term.selection.is_some() {
+                continue;
+            }
+            total = total.saturating_sub(term.compact_scrollback());
+            if total <= budget {
+                break 'shrink;
+            }
+        }
+        for (_, pane) in &order {
+            let mut term = pane.terminal.write();
+            if term.selection.is_some() {
+                continue;
+            }
+            total = total.saturating_sub(term.shed_scrollback(total - budget));
+            if total <= budget {
+                break 'shrink;
+            }
+        }
+    }
+    let freed = start_total - total;
+    if freed == 0 {
+        return 0;
+    }
+    log::info!(
+        "scrollback budget: freed {:.1} MB, {:.1} / {:.1} MB in use",
+        freed as f64 / (1024.0 * 1024.0),
+        total as f64 / (1024.0 * 1024.0),
+        budget as f64 / (1024.0 * 1024.0),
+    );
+    freed
+}
+
+static SPILL_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Where spill files are created: `~/Library/Caches/Kova/scrollback` on
+/// macOS, `$XDG_CACHE_HOME/kova/scrollback` (default `~/.cache`) elsewhere.
+fn spill_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".into());
+    spill_dir_in(Path::new(&home), std::env::var_os("XDG_CACHE_HOME").as_deref().map(Path::new))
+}
+
+fn spill_dir_in(home: &Path, xdg_cache: Option<&Path>) -> PathBuf {
+    if cfg!(target_os = "macos") {
+        return home.join("Library/Caches/Kova/scrollback");
+    }
+    // The spec says to ignore a relative path.
+    match xdg_cache.filter(|d| d.is_absolute()) {
+        Some(cache) => cache.join("kova/scrollback"),
+        None => home.join(".cache/kova/scrollback"),
+    }
+}
+
+/// Per-pane on-disk scrollback: a ring of encoded rows inside a file of fixed
+/// capacity. When a write reaches the end it wraps to the start, dropping the
+/// oldest rows it overwrites.
+///
+/// Each record is `len: u32 LE, wrapped: u8, data` (`encode_spilled`). The
+/// file is unlinked right after it is created, so it is private to the
+/// process and its blocks are freed when the pane closes — or when Kova
+/// crashes. The byte offset of every live row is kept in memory (8 bytes/row).
+pub struct SpillFile {
+    file: File,
+    capacity: u64,
+    /// File offset of each live row, oldest first.
+    offsets: VecDeque<u64>,
+    /// Where the next record goes.
+    head: u64,
+    /// End of the previous lap, once the ring has wrapped.
+    lap_end: u64,
+    /// Records not written yet. They start at `pending_at` and end at `head`.
+    pending: Vec<u8>,
+    pending_at: u64,
+}
+
+impl SpillFile {
+    /// Create a spill file of `capacity` bytes in the cache directory.
+    pub fn create(capacity: u64) -> io::Result<Self> {
+        Self::create_in(&spill_dir(), capacity)
+    }
+
+    pub fn create_in(dir: &Path, capacity: u64) -> io::Result<Self> {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        let name = format!(
+            "{}-{}.spill",
+            std::process::id(),
+            SPILL_COUNTER.fetch_add(1, Ordering::Relaxed),
+        );
+        let path = dir.join(name);
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+        log::debug!("scrollback spill file created ({} MB)", capacity >> 20);
+        Ok(SpillFile {
+            file,
+            capacity,
+            offsets: VecDeque::new(),
+            head: 0,
+            lap_end: 0,
+            pending: Vec::new(),
+            pending_at: 0,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Append a row. Returns how many rows were dropped to make room (1 for
+    /// the row itself if it can never fit).
+    fn push(&mut self, row: &ColdRow) -> usize {
+        let size = 5 + row.data.len() as u64;
+        if size > self.capacity {
+            return 1;
+        }
+        let mut dropped = 0;
+        if self.head + size > self.capacity {
+            self.flush();
+            // Rows of the previous lap still past the head are the oldest:
+            // with the head back at 0 they would be out of order. Drop them.
+            while self.offsets.front().is_some_and(|&o| o >= self.head) {
+                self.offsets.pop_front();
+                dropped += 1;
+            }
+            self.lap_end = self.head;
+            self.head = 0;
+        }
+        let end = self.head + size;
+        while self.offsets.front().is_some_and(|&o| o >= self.head && o < end) {
+            self.offsets.pop_front();
+            dropped += 1;
+        }
+        if self.pending.is_empty() {
+            self.pending_at = self.head;
+        }
+        self.pending.extend_from_slice(&(row.data.len() as u32).to_le_bytes());
+        self.pending.push(row.wrapped as u8);
+        self.pending.extend_from_slice(&row.data);
+        self.offsets.push_back(self.head);
+        self.head = end;
+        if self.pending.len() >= SPILL_WRITE_CHUNK {
+            self.flush();
+        }
+        dropped
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if let Err(e) = self.file.write_all_at(&self.pending, self.pending_at) {
+            log::warn!("scrollback spill write failed: {}", e);
+        }
+        self.pending.clear();
+    }
+
+    fn in_pending(&self, offset: u64) -> bool {
+        (self.pending_at..self.pending_at + self.pending.len() as u64).contains(&offset)
+    }
+
+    /// Where the record at `index` ends: the next record, or the head (the
+    /// end of the previous lap for the last row before a wrap).
+    fn record_end(&self, index: usize) -> u64 {
+        let offset = self.offsets[index];
+        match self.offsets.get(index + 1) {
+            Some(&next) if next > offset => next,
+            Some(_) => self.lap_end,
+            None if offset < self.head => self.head,
+            None => self.lap_end,
+        }
+    }
+
+    fn read(&self, index: usize) -> Option<ColdRow> {
+        self.read_run(index, 1).pop().flatten()
+    }
+
+    /// Rows `start..start + count` (fewer past the end). Records that sit
+    /// back to back in the file come in with one read.
+    fn read_run(&self, start: usize, count: usize) -> Vec<Option<ColdRow>> {
+        let end = start.saturating_add(count).min(self.len());
+        let mut rows = Vec::with_capacity(end.saturating_sub(start));
+        let mut i = start;
+        while i < end {
+            let first = self.offsets[i];
+            if self.in_pending(first) {
+                let at = (first - self.pending_at) as usize;
+                let slot = self.pending.get(at..at + (self.record_end(i) - first) as usize);
+                rows.push(slot.and_then(parse_record));
+                i += 1;
+                continue;
+            }
+            let mut j = i + 1;
+            while j < end && self.offsets[j] > self.offsets[j - 1] && !self.in_pending(self.offsets[j]) {
+                j += 1;
+            }
+            let mut buf = vec![0u8; (self.record_end(j - 1) - first) as usize];
+            match self.file.read_exact_at(&mut buf, first) {
+                Ok(()) => rows.extend((i..j).map(|k| {
+                    let at = (self.offsets[k] - first) as usize;
+                    parse_record(&buf[at..(self.record_end(k) - first) as usize])
+                })),
+                Err(e) => {
+                    log::warn!("scrollback spill read failed at {}: {}", first, e);
+                    rows.extend((i..j).map(|_| None));
+                }
+            }
+            i = j;
+        }
+        rows
+    }
+
+    fn pop_back(&mut self) -> Option<ColdRow> {
+        let row = self.read(self.offsets.len().checked_sub(1)?);
+        let offset = self.offsets.pop_back()?;
+        if self.in_pending(offset) {
+            self.pending.truncate((offset - self.pending_at) as usize);
+        }
+        if offset >= self.head {
+            // The last row of the previous lap: the ring is back to one lap,
+            // ending where that row started.
+            self.lap_end = 0;
+        }
+        self.head = offset;
+        row
+    }
+
+    fn clear(&mut self) {
+        self.offsets.clear();
+        self.pending.clear();
+        self.head = 0;
+        self.lap_end = 0;
+        if let Err(e) = self.file.set_len(0) {
+            log::warn!("scrollback spill truncate failed: {}", e);
+        }
+    }
+
+    /// Bytes between the oldest live row and the head.
+    fn live_bytes(&self) -> u64 {
+        match self.offsets.front() {
+            None => 0,
+            Some(&front) if front < self.head => self.head - front,
+            Some(&front) => self.lap_end - front + self.head,
+        }
+    }
+
+    fn index_bytes(&self) -> usize {
+        self.offsets.capacity() * std::mem::size_of::<u64>()
+    }
+}
+
+/// One record, `len wrapped data`, from the bytes of its slot. A record
+/// fills its slot exactly: a length that says otherwise is a damaged file.
+fn parse_record(slot: &[u8]) -> Option<ColdRow> {
+    let (header, data) = slot.split_at_checked(5)?;
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if len != data.len() {
+        log::warn!("scrollback spill record of {} bytes in a {} byte slot", len, data.len());
+        return None;
+    }
+    Some(ColdRow { data: data.into(), wrapped: header[4] != 0 })
+}
+
+fn hot_row_bytes(row: &Row) -> usize {
+    std::mem::size_of::<Row>() + row.cells.capacity() * std::mem::size_of::<Cell>()
+}
+
+fn cold_row_bytes(row: &ColdRow) -> usize {
+    std::mem::size_of::<ColdRow>() + row.data.len()
+}
+
+fn same_style(a: &Cell, b: &Cell) -> bool {
+    a.fg == b.fg && a.bg == b.bg && a.attrs == b.attrs && a.hyperlink_id == b.hyperlink_id
+}
+
+pub(super) fn put_varint(out: &mut Vec<u8>, mut v: u32) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+pub(super) fn get_varint(data: &[u8], pos: &mut usize) -> u32 {
+    let mut v = 0u32;
+    let mut shift = 0;
+    while let Some(&b) = data.get(*pos) {
+        *pos += 1;
+        v |= ((b & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+        if b & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(c: char) -> Cell {
+        Cell { c, ..Cell::default() }
+    }
+
+    fn text(row: &Row) -> String {
+        row.cells.iter().map(|c| c.c).collect()
+    }
+
+    fn row(s: &str) -> Row {
+        Row { cells: s.chars().map(cell).collect(), wrapped: false }
+    }
+
+    #[test]
+    fn cold_rows_round_trip_every_cell_field() {
+        let mut sb = Scrollback::with_hot_limit(0);
+        let mut r = row("ab  cd");
+        r.wrapped = true;
+        r.cells[1].fg = [200, 10, 10];
+        r.cells[1].attrs = CellAttrs::BOLD | CellAttrs::UNDERLINE;
+        r.cells[2].bg = [1, 2, 3];
+        r.cells[4].hyperlink_id = 300;
+        r.cells[4].c = '前';
+        r.cells[5].c = '\0';
+        r.cells[0].cluster = Some("🇫🇷".into());
+        r.cells[0].c = '🇫';
+        sb.push_back(r.clone());
+        sb.push_back(row(""));
+        assert_eq!(sb.mem().cold_rows, 2);
+
+        let back = sb.get(0).unwrap();
+        assert!(back.wrapped);
+        assert_eq!(back.cells.len(), r.cells.len());
+        for (a, b) in back.cells.iter().zip(&r.cells) {
+            assert_eq!(a.c, b.c);
+            assert_eq!(a.cluster, b.cluster);
+            assert_eq!(a.fg, b.fg);
+            assert_eq!(a.bg, b.bg);
+            assert_eq!(a.attrs, b.attrs);
+            assert_eq!(a.hyperlink_id, b.hyperlink_id);
+        }
+        assert!(sb.get(1).unwrap().cells.is_empty());
+        assert!(!sb.get(1).unwrap().wrapped);
+    }
+
+    #[test]
+    fn tiers_keep_line_order() {
+        let mut sb = Scrollback::with_hot_limit(3);
+        for i in 0..10 {
+            sb.push_back(row(&format!("line {}", i)));
+        }
+        assert_eq!(sb.mem().hot_rows, 3);
+        assert_eq!(sb.mem().cold_rows, 7);
+        let all: Vec<String> = sb.iter().map(|r| text(&r)).collect();
+        assert_eq!(all, (0..10).map(|i| format!("line {}", i)).collect::<Vec<_>>());
+
+        assert_eq!(sb.trim(9), 1);
+        assert_eq!(text(&sb.get(0).unwrap()), "line 1");
+        // Popping past the hot tier decodes cold rows back.
+        for i in (6..10).rev() {
+            assert_eq!(text(&sb.pop_back().unwrap()), format!("line {}", i));
+        }
+        assert_eq!(sb.len(), 5);
+
+        let rows = sb.take_rows();
+        assert!(sb.is_empty());
+        sb.set_rows(rows);
+        assert_eq!(sb.mem().cold_rows, 2);
+        assert_eq!(text(&sb.get(4).unwrap()), "line 5");
+    }
+
+    #[test]
+    fn cold_tier_is_much_smaller() {
+        let mut styled = row(&"x".repeat(80));
+        for c in &mut styled.cells[10..20] {
+            c.fg = [255, 0, 0];
+        }
+        let mut hot = Scrollback::with_hot_limit(usize::MAX);
+        let mut cold = Scrollback::with_hot_limit(0);
+        for _ in 0..100 {
+            hot.push_back(styled.clone());
+            cold.push_back(styled.clone());
+        }
+        let (h, c) = (hot.mem().hot_bytes, cold.mem().cold_bytes);
+        assert!(c * 10 < h, "cold {} bytes vs hot {} bytes", c, h);
+    }
+
+    fn spill(capacity: u64) -> SpillFile {
+        SpillFile::create_in(&std::env::temp_dir().join("kova-spill-tests"), capacity).unwrap()
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn spill_files_go_to_the_xdg_cache_dir() {
+        let home = Path::new("/home/u");
+        assert_eq!(spill_dir_in(home, Some(Path::new("/c"))), Path::new("/c/kova/scrollback"));
+        assert_eq!(spill_dir_in(home, None), Path::new("/home/u/.cache/kova/scrollback"));
+        assert_eq!(spill_dir_in(home, Some(Path::new("rel"))), Path::new("/home/u/.cache/kova/scrollback"));
+    }
+
+    #[test]
+    fn rows_past_the_line_limit_spill_to_disk_and_read_back() {
+        let mut sb = Scrollback::with_hot_limit(2);
+        sb.set_storage(0, Some(spill(1 << 20)));
+        for i in 0..10 {
+            sb.push_back(row(&format!("line {}", i)));
+            assert_eq!(sb.trim(4), 0);
+        }
+        let mem = sb.mem();
+        assert_eq!((mem.disk_rows, mem.cold_rows, mem.hot_rows), (6, 2, 2));
+        assert!(mem.disk_bytes > 0);
+        let all: Vec<String> = sb.iter().map(|r| text(&r)).collect();
+        assert_eq!(all, (0..10).map(|i| format!("line {}", i)).collect::<Vec<_>>());
+        assert_eq!(sb.get(0).map(|r| r.wrapped), Some(false));
+        let tail: Vec<String> = sb.iter_from(3).map(|r| text(&r)).collect();
+        assert_eq!(tail, (3..10).map(|i| format!("line {}", i)).collect::<Vec<_>>());
+
+        // Pulling rows back into the grid reaches into the file.
+        for i in (3..10).rev() {
+            assert_eq!(text(&sb.pop_back().unwrap()), format!("line {}", i));
+        }
+        assert_eq!(sb.len(), 3);
+        sb.clear();
+        assert!(sb.is_empty());
+        assert_eq!(sb.mem().disk_bytes, 0);
+    }
+
+    #[test]
+    fn full_spill_file_wraps_and_drops_its_oldest_rows() {
+        let mut sb = Scrollback::with_hot_limit(0);
+        // Each record is 5 + 15 bytes ("row NNNN": 8 cells, 1 run): 5 fit.
+        sb.set_storage(0, Some(spill(105)));
+        let mut dropped = 0;
+        for i in 0..23 {
+            sb.push_back(row(&format!("row {:04}", i)));
+            dropped += sb.trim(0);
+        }
+        assert_eq!(dropped + sb.len(), 23, "every row is either kept or counted as dropped");
+        let kept: Vec<String> = sb.iter().map(|r| text(&r)).collect();
+        let expected: Vec<String> = (23 - sb.len()..23).map(|i| format!("row {:04}", i)).collect();
+        assert_eq!(kept, expected, "the newest rows survive, in order");
+        assert!(sb.len() >= 4);
+        assert!(sb.mem().disk_bytes <= 105);
+    }
+
+    #[test]
+    fn popping_back_across_a_lap_keeps_rows_and_sizes_right() {
+        let mut sb = Scrollback::with_hot_limit(0);
+        let size = 5 + encode_spilled(&row("row 0000")).data.len();
+        // Four records fit: the fifth wraps to offset 0, the seventh leaves
+        // one row of the first lap.
+        sb.set_storage(0, Some(spill(4 * size as u64 + 1)));
+        for i in 0..7 {
+            sb.push_back(row(&format!("row {:04}", i)));
+            sb.trim(0);
+        }
+        let kept = sb.len();
+        assert!(sb.spill.as_ref().unwrap().lap_end > 0, "the ring wrapped");
+        for n in (0..kept).rev() {
+            assert_eq!(sb.mem().disk_bytes, size * (n + 1));
+            assert_eq!(text(&sb.pop_back().unwrap()), format!("row {:04}", 7 - kept + n));
+        }
+        assert_eq!(sb.mem().disk_bytes, 0);
+        assert!(sb.pop_back().is_none());
+
+        // The file takes rows again from where the pops left the head.
+        sb.push_back(row("row 9999"));
+        sb.trim(0);
+        assert_eq!(sb.iter().map(|r| text(&r)).collect::<Vec<_>>(), ["row 9999"]);
+    }
+
+    #[test]
+    fn a_damaged_record_length_reads_back_blank() {
+        let mut file = spill(1 << 20);
+        for i in 0..3 {
+            file.push(&encode_spilled(&row(&format!("row {}", i))));
+        }
+        file.flush();
+        file.file.write_all_at(&u32::MAX.to_le_bytes(), file.offsets[1]).unwrap();
+        assert!(file.read(0).is_some());
+        assert!(file.read(1).is_none(), "a length past the next record is refused");
+        assert!(file.read(2).is_some());
+    }
+
+    #[test]
+    fn clusters_are_freed_with_the_rows_that_used_them() {
+        let clustered = |i: u32| {
+            let mut r = row("e ");
+            r.cells[0].cluster = Some(format!("e\u{301}{}", char::from_u32(0x1F600 + i % 64).unwrap()).into());
+            r
+        };
+        let mut sb = Scrollback::with_hot_limit(0);
+        for i in 0..1000 {
+            sb.push_back(clustered(i));
+            sb.trim(10);
+        }
+        assert!(sb.clusters.ids.len() <= 10);
+        assert!(sb.clusters.list.len() <= 11);
+
+        // Spilled rows carry their clusters: the table only serves cold rows.
+        let mut sb = Scrollback::with_hot_limit(0);
+        sb.set_storage(0, Some(spill(1 << 20)));
+        for i in 0..100 {
+            sb.push_back(clustered(i));
+            assert_eq!(sb.trim(2), 0);
+        }
+        assert_eq!(sb.clusters.ids.len(), 2);
+        for i in 0..100 {
+            assert_eq!(sb.get(i as usize).unwrap().cells[0].cluster, clustered(i).cells[0].cluster);
+        }
+        let last = sb.pop_back().unwrap();
+        assert_eq!(last.cells[0].cluster, clustered(99).cells[0].cluster);
+        assert_eq!(sb.clusters.ids.len(), 1);
+    }
+
+    #[test]
+    fn memory_budget_evicts_oldest_rows_first() {
+        let mut sb = Scrollback::with_hot_limit(usize::MAX);
+        let budget = 3 * hot_row_bytes(&row("0123456789"));
+        sb.set_storage(budget, Some(spill(1 << 20)));
+        for i in 0..10 {
+            sb.push_back(row(&format!("{:010}", i)));
+            sb.trim(usize::MAX);
+        }
+        let mem = sb.mem();
+        assert!(mem.hot_bytes <= budget);
+        assert_eq!(mem.hot_rows, 3);
+        assert_eq!(mem.disk_rows, 7);
+        assert_eq!(text(&sb.get(0).unwrap()), "0000000000");
+
+        // Without a spill file the same rows are dropped.
+        let mut sb = Scrollback::with_hot_limit(usize::MAX);
+        sb.set_storage(budget, None);
+        let dropped: usize = (0..10).map(|i| {
+            sb.push_back(row(&format!("{:010}", i)));
+            sb.trim(usize::MAX)
+        }).sum();
+        assert_eq!((dropped, sb.len()), (7, 3));
+    }
+}
@@ -0,0 +1,788 @@
+//! Scrollback search engine.
+//!
+//! Patterns are matched against *logical* lines: consecutive soft-wrapped rows
+//! (`Row.wrapped`) are joined the way `rows_to_logical_lines` joins them for
+//! reflow, so a hit that straddles a wrap is found like any other. Every hit
+//! is mapped back to the cells it covers and reported as per-row column spans,
+//! which is what the overlays highlight and what `search-pane` returns.
+//!
+//! A 100k-line scrollback takes a while to scan, and the PTY reader needs the
+//! terminal's write lock to make progress. `SearchJob` therefore scans a
+//! bounded number of rows per call; callers release the read lock between
+//! calls (the Cmd+F palette's worker) or spread the calls over render ticks
+//! (the Cmd+F overlay, `search-pane`).
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use caseless::Caseless;
+use regex::{Regex, RegexBuilder};
+use unicode_normalization::UnicodeNormalization;
+
+use super::{FilterMatch, Row, TerminalState};
+
+/// Rows scanned per read-lock acquisition. At this size a step takes well
+/// under a millisecond, so the PTY reader never waits long for its write lock.
+pub const ROWS_PER_LOCK: usize = 4096;
+
+/// How letter case is compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseMode {
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the pattern contains an uppercase letter (the
+    /// vim/ripgrep convention).
+    #[default]
+    Smart,
+}
+
+impl CaseMode {
+    /// Wire names, as accepted by the `search-pane` IPC command.
+    pub const NAMES: [&str; 3] = ["sensitive", "insensitive", "smart"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sensitive" => Some(CaseMode::Sensitive),
+            "insensitive" => Some(CaseMode::Insensitive),
+            "smart" => Some(CaseMode::Smart),
+            _ => None,
+        }
+    }
+
+    /// Next mode in the Cmd+F overlay's toggle cycle.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn next(self) -> Self {
+        match self {
+            CaseMode::Smart => CaseMode::Sensitive,
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+        }
+    }
+}
+
+/// Whether diacritics must match: `etat` vs `état`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccentMode {
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the pattern itself carries a diacritic — typing
+    /// `etat` finds `état`, typing `état` asks for exactly that.
+    #[default]
+    Smart,
+}
+
+impl AccentMode {
+    /// Wire names, as accepted by the `search-pane` IPC command.
+    pub const NAMES: [&str; 3] = ["sensitive", "insensitive", "smart"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sensitive" => Some(AccentMode::Sensitive),
+            "insensitive" => Some(AccentMode::Insensitive),
+            "smart" => Some(AccentMode::Smart),
+            _ => None,
+        }
+    }
+}
+
+/// What to look for, before compilation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub pattern: String,
+    /// Interpret `pattern` as a regular expression (`regex` crate syntax)
+    /// instead of a literal string.
+    pub regex: bool,
+    pub case: CaseMode,
+    pub accents: AccentMode,
+}
+
+impl SearchQuery {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn literal(pattern: &str, case: CaseMode) -> Self {
+        SearchQuery { pattern: pattern.to_string(), case, ..Default::default() }
+    }
+
+    /// Compile into a `Matcher`. Fails on an empty pattern or invalid regex,
+    /// with a message fit to show the user as-is.
+    pub fn compile(&self) -> Result<Matcher, String> {
+        if self.pattern.is_empty() {
+            return Err("empty search pattern".to_string());
+        }
+        let case = match self.case {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !self.text_pieces().any(|t| t.chars().any(char::is_uppercase)),
+        };
+        let fold = Fold {
+            case,
+            accents: match self.accents {
+                AccentMode::Sensitive => false,
+                AccentMode::Insensitive => true,
+                AccentMode::Smart => !self.text_pieces().any(|t| t.nfd().any(is_diacritic)),
+            },
+        };
+        // The haystack is folded cell by cell; a literal pattern gets the
+        // same treatment so both sides meet in one canonical form. A regex
+        // is not case-folded (`ß+` would become `ss+`, `[ß-ſ]` a bad range):
+        // the regex engine compares case itself, and only its text is
+        // normalized, refused where that changes its length. Only literal
+        // runs are accent-folded; a class is left to say what it means.
+        let (source, fold) = if self.regex {
+            let fold = Fold { case: false, ..fold };
+            let mut source = String::with_capacity(self.pattern.len());
+            for piece in pattern_pieces(&self.pattern) {
+                match piece {
+                    Piece::Syntax(s) => source.push_str(s),
+                    Piece::Text(t) | Piece::Class(t) => {
+                        let fold = match piece {
+                            Piece::Class(_) => Fold { accents: false, ..fold },
+                            _ => fold,
+                        };
+                        let start = source.len();
+                        fold.apply(t, &mut source);
+                        if source[start..].chars().count() != t.chars().count() {
+                            return Err(format!(
+                                "invalid regex: \"{}\" changes length when normalized \
+                                 (spell accents precomposed, or search for it literally)",
+                                t,
+                            ));
+                        }
+                    }
+                }
+            }
+            (source, fold)
+        } else {
+            let mut folded = String::new();
+            fold.apply(&self.pattern, &mut folded);
+            (regex::escape(&folded), fold)
+        };
+        let re = RegexBuilder::new(&source)
+            // Redundant for a literal the fold already lowered; still needed
+            // for letters spelled as escapes (`\x41`), and for any regex.
+            .case_insensitive(case)
+            .build()
+            .map_err(|e| match e {
+                // The syntax error is a multi-line caret diagram; its last
+                // line ("error: unclosed group") is the part worth showing.
+                regex::Error::Syntax(msg) => {
+                    let reason = msg.lines().rev().find_map(|l| l.strip_prefix("error: "));
+                    format!("invalid regex: {}", reason.unwrap_or(msg.trim()))
+                }
+                other => format!("invalid regex: {}", other),
+            })?;
+        Ok(Matcher { re, fold })
+    }
+
+    /// The parts of the pattern that are text to find, as opposed to regex
+    /// syntax — what the smart modes look at.
+    fn text_pieces(&self) -> impl Iterator<Item = &str> {
+        let pieces = if self.regex {
+            pattern_pieces(&self.pattern)
+        } else {
+            vec![Piece::Text(&self.pattern)]
+        };
+        pieces.into_iter().filter_map(|p| match p {
+            Piece::Text(t) | Piece::Class(t) => Some(t),
+            Piece::Syntax(_) => None,
+        })
+    }
+}
+
+enum Piece<'a> {
+    /// An escape sequence: `\S`, `\.`, `\p{Lu}`, `\x{e9}`.
+    Syntax(&'a str),
+    /// A bracketed class or the part of one between escapes, brackets
+    /// included: `[À-Ö]`, `[^a-z`.
+    Class(&'a str),
+    /// Everything else. Operators like `*` or `(` are in here too, but
+    /// folding leaves ASCII punctuation alone.
+    Text(&'a str),
+}
+
+/// Split a regex into escape sequences, character classes and the text
+/// between them. Escapes are syntax, not text: `\S` or the `\p{Lu}` class
+/// name must neither flip smart case nor be case-folded. Classes are kept
+/// apart so they can be normalized without being accent-folded, which would
+/// turn `[À-Ö]` into `[A-O]`.
+fn pattern_pieces(pattern: &str) -> Vec<Piece<'_>> {
+    fn push<'a>(pieces: &mut Vec<Piece<'a>>, depth: usize, run: &'a str) {
+        if !run.is_empty() {
+            pieces.push(if depth > 0 { Piece::Class(run) } else { Piece::Text(run) });
+        }
+    }
+    let mut pieces = Vec::new();
+    let mut run_start = 0;
+    // Nesting depth of `[...]`, and where the innermost class's body starts:
+    // a `]` right there is a literal, not the close.
+    let mut depth = 0;
+    let mut body = 0;
+    let mut iter = pattern.char_indices().peekable();
+    while let Some((i, c)) = iter.next() {
+        match c {
+            '[' => {
+                if depth == 0 {
+                    push(&mut pieces, 0, &pattern[run_start..i]);
+                    run_start = i;
+                }
+                depth += 1;
+                body = i + 1;
+                if iter.next_if(|&(_, c)| c == '^').is_some() {
+                    body += 1;
+                }
+                continue;
+            }
+            ']' if depth > 0 && i != body => {
+                depth -= 1;
+                if depth == 0 {
+                    push(&mut pieces, 1, &pattern[run_start..=i]);
+                    run_start = i + 1;
+                }
+                continue;
+            }
+            '\\' => {}
+            _ => continue,
+        }
+        push(&mut pieces, depth, &pattern[run_start..i]);
+        let mut end = pattern.len();
+        if let Some((_, e)) = iter.next() {
+            end = iter.peek().map_or(pattern.len(), |&(j, _)| j);
+            // `\p{...}`, `\x{...}`, `\u{...}`: the braces belong to the escape.
+            if matches!(e, 'p' | 'P' | 'x' | 'u' | 'U') && iter.peek().map(|&(_, c)| c) == Some('{') {
+                for (j, c) in iter.by_ref() {
+                    end = j + c.len_utf8();
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+        }
+        pieces.push(Piece::Syntax(&pattern[i..end]));
+        run_start = end;
+    }
+    push(&mut pieces, depth, &pattern[run_start..]);
+    pieces
+}
+
+/// Combining diacritics — the marks `é`, `ñ` or `ö` decompose into. Limited
+/// to the diacritical-mark blocks on purpose: spacing marks and the vowel
+/// signs of Indic scripts are letters of their own, and stripping the kana
+/// voicing marks would make か match が.
+fn is_diacritic(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// The canonical form text and pattern are both brought to before matching.
+/// Always canonically normalized (NFC), so NFD text pasted from macOS apps
+/// matches NFC text typed in the shell; optionally case-folded with full
+/// Unicode rules (`ß` = `ss`, `ﬁ` = `fi`) and stripped of diacritics.
+#[derive(Clone, Copy, Debug, Default)]
+struct Fold {
+    case: bool,
+    accents: bool,
+}
+
+impl Fold {
+    /// Append the folded form of `s` to `out`.
+    fn apply(self, s: &str, out: &mut String) {
+        if s.is_ascii() {
+            // Nothing to normalize, and ASCII case folding is lowercasing.
+            if self.case {
+                out.extend(s.chars().map(|c| c.to_ascii_lowercase()));
+            } else {
+                out.push_str(s);
+            }
+            return;
+        }
+        // Unicode canonical caseless matching (D145):
+        // NFD(casefold(NFD(s))), then recomposed.
+        let mut decomposed: String = if self.case {
+            s.nfd().default_case_fold().nfd().collect()
+        } else {
+            s.nfd().collect()
+        };
+        if self.accents {
+            decomposed.retain(|c| !is_diacritic(c));
+        }
+        out.extend(decomposed.nfc());
+    }
+}
+
+/// A compiled search pattern.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    /// Runs over folded text, never over raw cell text.
+    re: Regex,
+    fold: Fold,
+}
+
+impl Matcher {
+    /// Whether `text` (e.g. a pane or tab title) contains a match.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn is_match(&self, text: &str) -> bool {
+        let mut folded = String::with_capacity(text.len());
+        self.fold.apply(text, &mut folded);
+        self.find_ranges(&folded).next().is_some()
+    }
+
+    /// Byte ranges of the non-empty matches in an already-folded haystack.
+    /// Empty matches (`a*`, `^`) have nothing to highlight and are skipped.
+    fn find_ranges<'a>(&'a self, folded: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        self.re.find_iter(folded).filter(|m| !m.is_empty()).map(|m| m.range())
+    }
+}
+
+/// One highlighted stretch of a physical row: columns `start_col..end_col`
+/// (end exclusive) of absolute line `line` (scrollback + grid indexing, like
+/// `row_at`). A hit that crosses a soft wrap yields one span per row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchSpan {
+    pub line: usize,
+    pub start_col: u16,
+    pub end_col: u16,
+}
+
+/// Where one unit of logical-line text came from on screen.
+#[derive(Clone, Copy, Debug)]
+struct CellPos {
+    line: usize,
+    col: u16,
+    /// 2 for a wide char (its '\0' continuation is folded in), else 1.
+    width: u16,
+}
+
+/// One logical line, twice: `text` as displayed and `folded` as matched,
+/// each with the byte offset where every cell's contribution starts
+/// (`starts[i]` / `folded_starts[i]` for `cells[i]`). Folding changes byte
+/// lengths (`é` NFD is 3 bytes, `ß` folds to `ss`, a lone diacritic folds
+/// to nothing), so a hit is mapped through the cells it covers, never
+/// through raw offsets — which keeps highlights exact across wide chars
+/// and clusters.
+#[derive(Default)]
+struct LineText {
+    text: String,
+    starts: Vec<usize>,
+    folded: String,
+    folded_starts: Vec<usize>,
+    cells: Vec<CellPos>,
+}
+
+impl LineText {
+    fn push_row(&mut self, abs_line: usize, row: &Row, cols: usize, fold: Fold) {
+        let mut buf = [0u8; 4];
+        for (col, cell) in row.cells.iter().enumerate() {
+            if cell.c == '\0' {
+                // Continuation of a wide char: widen its base instead of
+                // contributing text.
+                match self.cells.last_mut() {
+                    Some(last) if last.line == abs_line => last.width += 1,
+                    _ => {}
+                }
+                continue;
+            }
+            let piece = match cell.cluster {
+                Some(ref cluster) => cluster,
+                None => &*cell.c.encode_utf8(&mut buf),
+            };
+            self.push_cell(CellPos { line: abs_line, col: col as u16, width: 1 }, piece, fold);
+        }
+        if row.wrapped {
+            // Trimmed scrollback rows lost their trailing blanks; pad them
+            // back so the next row's text lands at the right offset (same
+            // reasoning as rows_to_logical_lines).
+            for col in row.cells.len()..cols {
+                self.push_cell(CellPos { line: abs_line, col: col as u16, width: 1 }, " ", fold);
+            }
+        }
+    }
+
+    fn push_cell(&mut self, pos: CellPos, piece: &str, fold: Fold) {
+        self.starts.push(self.text.len());
+        self.folded_starts.push(self.folded.len());
+        self.cells.push(pos);
+        self.text.push_str(piece);
+        fold.apply(piece, &mut self.folded);
+    }
+
+    fn clear(&mut self) {
+        self.text.clear();
+        self.starts.clear();
+        self.folded.clear();
+        self.folded_starts.clear();
+        self.cells.clear();
+    }
+
+    /// Drop trailing blanks, like `render_row` does for an unwrapped row.
+    fn trim_end(&mut self) {
+        let len = self.text.trim_end_matches(' ').len();
+        let keep = self.starts.partition_point(|&s| s < len);
+        if keep < self.cells.len() {
+            self.text.truncate(len);
+            self.folded.truncate(self.folded_starts[keep]);
+            self.starts.truncate(keep);
+            self.folded_starts.truncate(keep);
+            self.cells.truncate(keep);
+        }
+    }
+
+    /// Index of the cell whose folded text contains byte `offset`. Cells that
+    /// folded to nothing share their start with the next cell, which wins.
+    fn cell_at(&self, offset: usize) -> usize {
+        self.folded_starts.partition_point(|&s| s <= offset).saturating_sub(1)
+    }
+
+    /// Map a hit in `folded` to the cells it covers: appends their per-row
+    /// column spans to `spans` and returns the matching byte range of `text`.
+    fn locate(&self, hit: &Range<usize>, spans: &mut Vec<MatchSpan>) -> Range<usize> {
+        let first = self.cell_at(hit.start);
+        let last = self.cell_at(hit.end - 1);
+        for pos in &self.cells[first..=last] {
+            let end_col = pos.col + pos.width;
+            match spans.last_mut() {
+                Some(span) if span.line == pos.line && span.end_col == pos.col => {
+                    span.end_col = end_col;
+                }
+                _ => spans.push(MatchSpan { line: pos.line, start_col: pos.col, end_col }),
+            }
+        }
+        let text_end = self.starts.get(last + 1).copied().unwrap_or(self.text.len());
+        self.starts[first]..text_end
+    }
+}
+
+/// An in-progress scan of one terminal, resumable across lock releases.
+///
+/// Between two `step` calls the terminal may have moved on: new output is
+/// simply scanned when reached; lines trimmed off the front of a full
+/// scrollback shift the stored results (like the selection in
+/// `push_to_scrollback`); a reflow, clear or full reset renumbers every line,
+/// so the scan starts over.
+pub struct SearchJob {
+    matcher: Matcher,
+    /// Stop after this many matching lines.
+    limit: usize,
+    /// Next absolute row to scan — always the first row of a logical line.
+    next_row: usize,
+    /// `(terminal_id, content_epoch, lines_dropped)` as of the last step.
+    seen: (u32, u64, u64),
+    results: Vec<FilterMatch>,
+    done: bool,
+}
+
+impl SearchJob {
+    pub fn new(matcher: Matcher, limit: usize) -> Self {
+        SearchJob {
+            matcher,
+            limit,
+            next_row: 0,
+            seen: (0, 0, 0),
+            results: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Matching lines found so far, in buffer order.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn results(&self) -> &[FilterMatch] {
+        &self.results
+    }
+
+    /// `search-pane` reply: the matches with their highlight spans, and
+    /// whether `limit` cut the scan short.
+    pub fn ipc_json(&self, pane_id: u32) -> serde_json::Value {
+        let matches: Vec<serde_json::Value> = self
+            .results
+            .iter()
+            .map(|m| {
+                let spans: Vec<serde_json::Value> = m
+                    .spans
+                    .iter()
+                    .map(|s| serde_json::json!({
+                        "line": s.line,
+                        "start_col": s.start_col,
+                        "end_col": s.end_col,
+                    }))
+                    .collect();
+                serde_json::json!({ "line": m.abs_line, "text": m.text, "spans": spans })
+            })
+            .collect();
+        serde_json::json!({
+            "id": pane_id,
+            "matches": matches,
+            "truncated": self.truncated(),
+        })
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// True when the scan stopped at `limit` rather than at the end of the buffer.
+    pub fn truncated(&self) -> bool {
+        self.results.len() >= self.limit
+    }
+
+    /// Scan at least `budget` rows (finishing the logical line in progress).
+    /// Returns true once the whole buffer has been searched.
+    pub fn step(&mut self, term: &TerminalState, budget: usize) -> bool {
+        if self.done {
+            return true;
+        }
+        self.resync(term);
+
+        let cols = term.cols as usize;
+        let total = term.scrollback_len() + term.grid.len();
+        let mut scanned = 0usize;
+        let mut line = LineText::default();
+        let mut rows = term.rows_from(self.next_row);
+        while self.next_row < total {
+            let abs = self.next_row;
+            let Some(row) = rows.next() else { break };
+            line.push_row(abs, &row, cols, self.matcher.fold);
+            self.next_row += 1;
+            scanned += 1;
+            if row.wrapped && self.next_row < total {
+                continue;
+            }
+            line.trim_end();
+            let mut spans = Vec::new();
+            let ranges: Vec<Range<usize>> = self
+                .matcher
+                .find_ranges(&line.folded)
+                .map(|hit| line.locate(&hit, &mut spans))
+                .collect();
+            if ranges.is_empty() {
+                line.clear();
+            } else {
+                let text = std::mem::take(&mut line).text;
+                self.results.push(FilterMatch { abs_line: spans[0].line, text, ranges, spans });
+                if self.results.len() >= self.limit {
+                    self.done = true;
+                    return true;
+                }
+            }
+            if scanned >= budget {
+                return false;
+            }
+        }
+        self.done = true;
+        true
+    }
+
+    /// Re-anchor the stored position and results to the terminal's current
+    /// line numbering.
+    fn resync(&mut self, term: &TerminalState) {
+        let (id, epoch, dropped) = (term.terminal_id, term.content_epoch, term.lines_dropped);
+        if self.next_row == 0 && self.results.is_empty() {
+            self.seen = (id, epoch, dropped);
+            return;
+        }
+        let (seen_id, seen_epoch, seen_dropped) = self.seen;
+        self.seen = (id, epoch, dropped);
+        if id != seen_id || epoch != seen_epoch || dropped < seen_dropped {
+            self.next_row = 0;
+            self.results.clear();
+            return;
+        }
+        let shift = (dropped - seen_dropped) as usize;
+        if shift == 0 {
+            return;
+        }
+        self.next_row = self.next_row.saturating_sub(shift);
+        self.results.retain_mut(|m| {
+            if m.abs_line < shift {
+                return false;
+            }
+            m.abs_line -= shift;
+            for span in &mut m.spans {
+                span.line = span.line.saturating_sub(shift);
+            }
+            true
+        });
+    }
+}
+
+/// Where a `wait-for-text` looks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitScope {
+    /// The visible screen, what is already on it included.
+    Screen,
+    /// Only what is printed from the cursor onward once the wait starts,
+    /// wherever it scrolls to.
+    NewOutput,
+}
+
+impl WaitScope {
+    /// Wire names, as accepted by the `wait-for-text` IPC command.
+    pub const NAMES: [&str; 2] = ["screen", "new-output"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "screen" => Some(WaitScope::Screen),
+            "new-output" => Some(WaitScope::NewOutput),
+            _ => None,
+        }
+    }
+}
+
+/// Counts the chunks of output the parser applied to a pane. A parked
+/// `wait-for-text` keeps it, and reads the pane's text again only once the
+/// count moved: a quiet pane costs one atomic load per tick, and no lock.
+#[derive(Default)]
+pub struct OutputSignal(AtomicU64);
+
+impl OutputSignal {
+    pub fn ring(&self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn rung_count(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// A parked `wait-for-text`.
+///
+/// The main thread (or the headless loop) polls it on its tick, but only
+/// once the pane's `OutputSignal` moved, and `check` only reads text when the
+/// parser applied output since the previous look.
+pub struct TextWait {
+    matcher: Matcher,
+    scope: WaitScope,
+    /// `NewOutput`: the cursor when the wait started, as (line id, column).
+    /// A hit that starts before it was already there.
+    anchor: (u64, u16),
+    /// `NewOutput`: first line not yet known to be scanned for good. Rows
+    /// that scrolled off the screen no longer change, so they are not read
+    /// twice.
+    next_line: u64,
+    /// `(terminal_id, content_epoch, output_generation)` as of the last check.
+    seen: Option<(u32, u64, u64)>,
+}
+
+impl TextWait {
+    pub fn new(matcher: Matcher, scope: WaitScope, term: &TerminalState) -> Self {
+        let mut wait = TextWait { matcher, scope, anchor: (0, 0), next_line: 0, seen: None };
+        wait.anchor_at_cursor(term);
+        wait
+    }
+
+    fn anchor_at_cursor(&mut self, term: &TerminalState) {
+        let line = term.lines_dropped + (term.scrollback_len() + term.cursor_y as usize) as u64;
+        self.anchor = (line, term.cursor_x);
+        self.next_line = line;
+    }
+
+    /// The `wait-for-text` reply: the hit `check` returned, or a timeout.
+    pub fn reply(pane_id: u32, hit: Option<serde_json::Value>) -> serde_json::Value {
+        let found = hit.is_some();
+        let mut data = hit.unwrap_or_else(|| serde_json::json!({}));
+        data["pane_id"] = pane_id.into();
+        data["found"] = found.into();
+        data["timed_out"] = (!found).into();
+        data
+    }
+
+    /// The first hit: `None` until there is one.
+    pub fn check(&mut self, term: &TerminalState) -> Option<serde_json::Value> {
+        let now = (term.terminal_id, term.content_epoch, term.output_generation);
+        let Some(seen) = self.seen.replace(now) else {
+            return self.scan(term);
+        };
+        if seen == now {
+            return None;
+        }
+        if self.scope == WaitScope::NewOutput && (seen.0, seen.1) != (now.0, now.1) {
+            // A reflow, a cleared scrollback or a reset renumbered the lines:
+            // the anchor means nothing any more. What is there now counts as
+            // old, rather than risk reporting text that predates the wait.
+            self.anchor_at_cursor(term);
+        }
+        self.scan(term)
+    }
+
+    fn scan(&mut self, term: &TerminalState) -> Option<serde_json::Value> {
+        let screen_top = term.scrollback_len();
+        let (from, after) = match self.scope {
+            WaitScope::Screen => (screen_top, None),
+            WaitScope::NewOutput => {
+                let from = self.next_line.saturating_sub(term.lines_dropped) as usize;
+                let anchor = self.anchor.0.saturating_sub(term.lines_dropped) as usize;
+                (from, Some((anchor, self.anchor.1)))
+            }
+        };
+        let hit = self.first_hit(term, from, after);
+        if hit.is_none() {
+            let settled = term.lines_dropped + screen_top as u64;
+            self.next_line = self.next_line.max(settled);
+        }
+        hit
+    }
+
+    /// Scan the logical lines from the one holding absolute row `from`, for
+    /// a hit starting at or after `after` (absolute line, column).
+    fn first_hit(
+        &self,
+        term: &TerminalState,
+        from: usize,
+        after: Option<(usize, u16)>,
+    ) -> Option<serde_json::Value> {
+        let cols = term.cols as usize;
+        let total = term.scrollback_len() + term.grid.len();
+        let mut next_row = from.min(total);
+        while next_row > 0 && term.row_at(next_row - 1).is_some_and(|row| row.wrapped) {
+            next_row -= 1;
+        }
+        let mut line = LineText::default();
+        while next_row < total {
+            let abs = next_row;
+            let row = term.row_at(abs)?;
+            line.push_row(abs, &row, cols, self.matcher.fold);
+            next_row += 1;
+            if row.wrapped && next_row < total {
+                continue;
+            }
+            line.trim_end();
+            for caps in self.matcher.re.captures_iter(&line.folded) {
+                let Some(whole) = caps.get(0).filter(|m| !m.is_empty()) else { continue };
+                let mut spans = Vec::new();
+                let matched = line.locate(&whole.range(), &mut spans);
+                let start = (spans[0].line, spans[0].start_col);
+                if after.is_some_and(|after| start < after) {
+                    continue;
+                }
+                // Groups are found in the folded text; report them as displayed.
+                let group_text = |group: Option<regex::Match>| match group {
+                    Some(m) if !m.is_empty() => {
+                        serde_json::Value::from(&line.text[line.locate(&m.range(), &mut Vec::new())])
+                    }
+                    Some(_) => serde_json::Value::from(""),
+                    None => serde_json::Value::Null,
+                };
+                let groups: Vec<serde_json::Value> = caps.iter().skip(1).map(group_text).collect();
+                let named: serde_json::Map<String, serde_json::Value> = self
+                    .matcher
+                    .re
+                    .capture_names()
+                    .flatten()
+                    .map(|name| (name.to_string(), group_text(caps.name(name))))
+                    .collect();
+                return Some(serde_json::json!({
+                    "line": start.0,
+                    "line_id": term.lines_dropped + start.0 as u64,
+                    "text": line.text,
+                    "match": line.text[matched],
+                    "groups": groups,
+                    "named_groups": named,
+                }));
+            }
+            line.clear();
+        }
+        None
+    }
+}
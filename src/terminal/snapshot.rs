@@ -0,0 +1,377 @@
+//! Pane contents saved with the session (`session.save_scrollback`).
+//!
+//! A snapshot holds the newest scrollback lines and the primary screen, in
+//! the scrollback's row encoding (colors, attrs, clusters, wrap flags), plus
+//! the cluster and hyperlink tables the rows refer to:
+//!
+//! ```text
+//! "KVSB" version:u32le cols
+//! clusters  { len utf8 }*
+//! links     { len utf8 }*      (hyperlink ids 1.., slot 0 is "no link")
+//! rows      { wrapped:u8 len data }*
+//! ```
+//!
+//! Integers are LEB128 varints unless noted. A snapshot of another version is
+//! refused rather than guessed at — the pane then just starts empty.
+
+use super::scrollback::{decode_row, encode_row, get_varint, put_varint, ClusterTable};
+use super::{Cell, CellAttrs, Row, TerminalState};
+
+const MAGIC: &[u8; 4] = b"KVSB";
+
+/// Bump on any change to the layout above.
+const SNAPSHOT_VERSION: u32 = 1;
+
+const SEPARATOR_LABEL: &str = " restored from previous session ";
+
+impl TerminalState {
+    /// Serialize the primary screen (down to its last non-blank row) and the
+    /// newest `max_lines` scrollback lines above it.
+    pub fn save_contents(&self, max_lines: usize) -> Vec<u8> {
+        // A full-screen app's screen means nothing once it is gone: save the
+        // shell screen underneath it instead.
+        let screen = match &self.alt_grid {
+            Some(primary) if self.in_alt_screen => primary,
+            _ => &self.grid,
+        };
+        let used = screen.iter()
+            .rposition(|r| row_has_content(r, self.default_fg, self.default_bg))
+            .map_or(0, |i| i + 1);
+
+        let sb_len = self.scrollback.len();
+        let history = (sb_len.saturating_sub(max_lines)..sb_len).filter_map(|i| self.scrollback.get(i));
+        let mut clusters = ClusterTable::default();
+        let mut rows = Vec::new();
+        let mut count = 0u32;
+        let cols = self.cols as usize;
+        for row in history {
+            count += put_records(&mut rows, &row, cols, &mut clusters);
+        }
+        for row in &screen[..used] {
+            let mut row = row.clone();
+            row.trim_trailing_blanks(self.default_fg, self.default_bg);
+            count += put_records(&mut rows, &row, cols, &mut clusters);
+        }
+
+        let mut out = Vec::with_capacity(rows.len() + 64);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        put_varint(&mut out, self.cols as u32);
+        put_varint(&mut out, clusters.list.len() as u32);
+        for cluster in &clusters.list {
+            put_str(&mut out, cluster);
+        }
+        put_varint(&mut out, (self.hyperlinks.len() - 1) as u32);
+        for url in &self.hyperlinks[1..] {
+            put_str(&mut out, url);
+        }
+        put_varint(&mut out, count);
+        out.extend_from_slice(&rows);
+        out
+    }
+
+    /// Put a `save_contents` snapshot back above the current contents,
+    /// reflowed to the current width, followed by a separator row. Whatever
+    /// the new shell has printed so far stays below the separator, with the
+    /// cursor.
+    pub fn restore_contents(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4)? != MAGIC {
+            return Err("not a scrollback snapshot".into());
+        }
+        let version = u32::from_le_bytes(r.bytes(4)?.try_into().unwrap_or_default());
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("unsupported snapshot version {}", version));
+        }
+        let old_cols = (r.varint()? as usize).max(1);
+        let mut clusters = ClusterTable::default();
+        for _ in 0..r.varint()? {
+            clusters.intern(r.str()?);
+        }
+        // Saved hyperlink id → id in this terminal's table
+        let mut links = vec![0u16];
+        for _ in 0..r.varint()? {
+            let url = r.str()?.to_string();
+            links.push(self.intern_hyperlink(url));
+        }
+        let count = r.varint()? as usize;
+        let mut saved = Vec::with_capacity(count.min(data.len()));
+        for _ in 0..count {
+            let wrapped = r.bytes(1)?[0] != 0;
+            let len = r.varint()? as usize;
+            let mut row = decode_row(r.bytes(len)?, wrapped, &clusters, old_cols)?;
+            for cell in &mut row.cells {
+                cell.hyperlink_id = links.get(cell.hyperlink_id as usize).copied().unwrap_or(0);
+            }
+            saved.push(row);
+        }
+
+        let cols = self.cols as usize;
+        let mut restored = Self::reflow_rows(saved, old_cols, cols, &self.blank);
+        restored.push(self.separator_row());
+
+        self.selection = None;
+        self.content_epoch += 1;
+        self.reset_scroll();
+        self.dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+
+        if self.in_alt_screen {
+            // The screen belongs to a running app: history only.
+            let mut rows = restored;
+            rows.extend(self.scrollback.take_rows());
+            self.set_scrollback_rows(rows);
+            return Ok(());
+        }
+
+        // The new shell's output so far, down to the cursor
+        let cursor_y = self.cursor_y as usize;
+        let used = self.grid.iter()
+            .rposition(|r| row_has_content(r, self.default_fg, self.default_bg))
+            .map_or(0, |i| i + 1)
+            .max(cursor_y + 1);
+        let mut stream = restored;
+        stream.extend(self.scrollback.take_rows());
+        stream.extend(self.grid.drain(..used));
+
+        let height = self.rows as usize;
+        let on_screen = stream.len().min(height);
+        let mut grid = stream.split_off(stream.len() - on_screen);
+        for row in &mut grid {
+            row.cells.resize(cols, self.blank.clone());
+        }
+        grid.resize(height, Row::new(cols, &self.blank));
+        self.grid = grid;
+        self.cursor_y = (on_screen - used + cursor_y) as u16;
+        self.set_scrollback_rows(stream);
+        Ok(())
+    }
+
+    /// Replace the in-memory scrollback with `rows` (oldest first), within limits.
+    fn set_scrollback_rows(&mut self, mut rows: Vec<Row>) {
+        for row in &mut rows {
+            row.trim_trailing_blanks(self.default_fg, self.default_bg);
+        }
+        self.scrollback.set_rows(rows);
+        self.lines_dropped += self.scrollback.trim(self.scrollback_limit) as u64;
+    }
+
+    /// `──── restored from previous session ────`, dimmed, across the width.
+    fn separator_row(&self) -> Row {
+        let cols = self.cols as usize;
+        let fg = self.default_fg;
+        let style = Cell {
+            c: '─',
+            fg: [fg[0] / 2, fg[1] / 2, fg[2] / 2],
+            attrs: CellAttrs::empty(),
+            ..self.blank.clone()
+        };
+        let mut row = Row::new(cols, &style);
+        let label: Vec<char> = SEPARATOR_LABEL.chars().collect();
+        if label.len() + 4 <= cols {
+            let start = (cols - label.len()) / 2;
+            for (cell, c) in row.cells[start..].iter_mut().zip(label) {
+                cell.c = c;
+            }
+        }
+        row
+    }
+}
+
+/// Same test as `Row::trim_trailing_blanks`: anything it would keep.
+fn row_has_content(row: &Row, default_fg: [u8; 3], default_bg: [u8; 3]) -> bool {
+    row.cells.iter().any(|c| c.c != ' ' || c.cluster.is_some() || c.fg != default_fg || c.bg != default_bg)
+}
+
+/// Write `row` as records of at most `cols` cells, the most a restore reads
+/// back. A spilled row kept at an older, wider width is split into wrapped
+/// pieces, which the restore's reflow joins again. Returns the records written.
+fn put_records(out: &mut Vec<u8>, row: &Row, cols: usize, clusters: &mut ClusterTable) -> u32 {
+    if row.cells.len() <= cols {
+        put_record(out, row, clusters);
+        return 1;
+    }
+    let pieces = row.cells.chunks(cols).count();
+    for (i, cells) in row.cells.chunks(cols).enumerate() {
+        let wrapped = i + 1 < pieces || row.wrapped;
+        put_record(out, &Row { cells: cells.to_vec(), wrapped }, clusters);
+    }
+    pieces as u32
+}
+
+fn put_record(out: &mut Vec<u8>, row: &Row, clusters: &mut ClusterTable) {
+    let data = encode_row(row, clusters);
+    out.push(row.wrapped as u8);
+    put_varint(out, data.len() as u32);
+    out.extend_from_slice(&data);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_varint(out, s.len() as u32);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Bounds-checked cursor over a snapshot read back from disk.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.data.len())
+            .ok_or("truncated snapshot")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u32, String> {
+        if self.pos >= self.data.len() {
+            return Err("truncated snapshot".into());
+        }
+        Ok(get_varint(self.data, &mut self.pos))
+    }
+
+    fn str(&mut self) -> Result<&'a str, String> {
+        let len = self.varint()? as usize;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| "corrupt snapshot".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A terminal that was fed `bytes` through the real parser.
+    fn drive(cols: u16, rows: u16, bytes: &[u8]) -> TerminalState {
+        use std::sync::Arc;
+        let term = Arc::new(parking_lot::RwLock::new(TerminalState::new(cols, rows, 1000, [200, 200, 200], [0, 0, 0])));
+        let mut handler = crate::terminal::parser::VteHandler::new(term.clone(), Arc::new(crate::terminal::pty::NullBackend));
+        vte::Parser::new().advance(&mut handler, bytes);
+        handler.apply_ops();
+        drop(handler);
+        Arc::try_unwrap(term).ok().unwrap().into_inner()
+    }
+
+    fn text(row: &Row) -> String {
+        row.cells.iter().map(|c| c.c).collect::<String>().trim_end().to_string()
+    }
+
+    fn lines(t: &TerminalState) -> Vec<String> {
+        let sb = t.scrollback.iter().map(|r| text(&r));
+        sb.chain(t.grid.iter().map(text)).collect()
+    }
+
+    #[test]
+    fn snapshot_round_trips_styles_links_and_wraps() {
+        let mut bytes = Vec::new();
+        for i in 0..8 {
+            bytes.extend_from_slice(format!("line {}\r\n", i).as_bytes());
+        }
+        bytes.extend_from_slice(b"\x1b[1;31mred\x1b[0m \x1b]8;;https://example.com\x07link\x1b]8;;\x07 \xF0\x9F\x87\xAB\xF0\x9F\x87\xB7\r\n");
+        bytes.extend_from_slice(b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEF\r\n");
+        let t = drive(40, 5, &bytes);
+        let data = t.save_contents(1000);
+
+        let mut fresh = drive(40, 5, b"");
+        fresh.restore_contents(&data).unwrap();
+        let all = lines(&fresh);
+        let sep = all.iter().position(|l| l.contains("restored from previous session")).unwrap();
+        assert_eq!(&all[..sep], &lines(&t)[..sep]);
+        assert_eq!(all[0], "line 0");
+
+        let red = all.iter().position(|l| l.starts_with("red")).unwrap();
+        let styled = fresh.row_at(red).unwrap().into_owned();
+        assert!(styled.cells[0].attrs.contains(CellAttrs::BOLD));
+        assert_ne!(styled.cells[0].fg, fresh.default_fg);
+        assert_eq!(fresh.hyperlink_url(styled.cells[4].hyperlink_id), Some("https://example.com"));
+        assert_eq!(styled.cells[9].cluster.as_deref(), Some("🇫🇷"));
+
+        let wrapped = all.iter().position(|l| l == "abcdefghijklmnopqrstuvwxyz0123456789ABCD").unwrap();
+        assert_eq!(all[wrapped + 1], "EF");
+        // The cursor waits on the row below the separator
+        assert_eq!(fresh.scrollback.len() + fresh.cursor_y as usize, sep + 1);
+    }
+
+    #[test]
+    fn restore_reflows_and_keeps_the_new_shell_output_below() {
+        let t = drive(10, 4, b"0123456789abcde\r\nold\r\n");
+        let data = t.save_contents(1000);
+
+        let mut fresh = drive(40, 4, b"$ ");
+        fresh.restore_contents(&data).unwrap();
+        let all = lines(&fresh);
+        assert_eq!(all[0], "0123456789abcde");
+        assert_eq!(all[1], "old");
+        assert!(all[2].contains("restored"));
+        assert_eq!(all[3], "$");
+        assert_eq!(fresh.scrollback.len() + fresh.cursor_y as usize, 3);
+        assert_eq!(fresh.cursor_x, 2);
+    }
+
+    #[test]
+    fn restore_refuses_other_versions_and_truncated_data() {
+        let mut data = drive(10, 4, b"hello\r\n").save_contents(1000);
+        let mut fresh = drive(10, 4, b"");
+        assert!(fresh.restore_contents(&data[..data.len() - 1]).is_err());
+        data[4] = 99;
+        assert!(fresh.restore_contents(&data).unwrap_err().contains("version"));
+        assert!(fresh.restore_contents(b"nope").is_err());
+        assert!(lines(&fresh).iter().all(|l| l.is_empty()));
+    }
+
+    #[test]
+    fn restore_refuses_rows_that_claim_more_cells_than_are_there() {
+        let header = |out: &mut Vec<u8>| {
+            out.extend_from_slice(MAGIC);
+            out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+            put_varint(out, 10); // cols
+            put_varint(out, 0); // clusters
+            put_varint(out, 0); // links
+            put_varint(out, 1); // rows
+        };
+        let mut fresh = drive(10, 4, b"");
+        // A row of four billion cells, in one run, in a few bytes.
+        let mut row = Vec::new();
+        put_varint(&mut row, u32::MAX);
+        put_varint(&mut row, 1);
+        put_varint(&mut row, u32::MAX);
+        row.extend_from_slice(&[0; 7]);
+        row.push(0);
+        let mut data = Vec::new();
+        header(&mut data);
+        data.push(0);
+        put_varint(&mut data, row.len() as u32);
+        data.extend_from_slice(&row);
+        assert!(fresh.restore_contents(&data).is_err());
+
+        // Three cells claimed, wider than nothing but with no text behind them.
+        let mut row = Vec::new();
+        put_varint(&mut row, 3);
+        put_varint(&mut row, 1);
+        put_varint(&mut row, 3);
+        row.extend_from_slice(&[0; 7]);
+        row.push(0);
+        put_varint(&mut row, u32::MAX); // clusters, then EOF
+        let mut data = Vec::new();
+        header(&mut data);
+        data.push(0);
+        put_varint(&mut data, row.len() as u32);
+        data.extend_from_slice(&row);
+        assert!(fresh.restore_contents(&data).is_err());
+        assert!(lines(&fresh).iter().all(|l| l.is_empty()));
+    }
+
+    #[test]
+    fn rows_wider_than_the_pane_are_saved_as_wrapped_pieces() {
+        let mut t = drive(10, 4, b"");
+        let wide: String = ('a'..='z').collect();
+        let row = Row { cells: wide.chars().map(|c| Cell { c, ..t.blank.clone() }).collect(), wrapped: false };
+        t.scrollback.push_back(row);
+        let data = t.save_contents(1000);
+
+        let mut fresh = drive(10, 4, b"");
+        fresh.restore_contents(&data).unwrap();
+        assert_eq!(&lines(&fresh)[..3], ["abcdefghij", "klmnopqrst", "uvwxyz"]);
+    }
+}
@@ -11,9 +11,11 @@ use std::sync::Arc;
 use crate::config::{Config, TerminalConfig};
 use crate::input;
 use crate::keybindings::{Action, Keybindings, KeyCombo};
-use crate::pane::{alloc_tab_id, NavDirection, Pane, PaneId, SplitDirection, Tab, TabId};
+use crate::ipc_host::{IpcCloseTabResult, IpcMergeTabResult, IpcSwapPaneResult};
+use crate::pane::{alloc_tab_id, pane_json, start_pane_recording, NavDirection, Pane, PaneId, SplitDirection, Tab, TabId};
 use crate::renderer::{FilterRenderData, PaneViewport, Renderer};
 use crate::terminal::pty::ProcessInfo;
+use crate::terminal::search::{AccentMode, CaseMode, ROWS_PER_LOCK, SearchJob, SearchQuery};
 use crate::terminal::{FilterMatch, GridPos, Selection, SelectionMode};
 
 #[derive(Clone, Copy)]
@@ -149,6 +151,11 @@ pub struct KovaViewIvars {
     /// snapshot the placeholder's *original* data rather than its empty live
     /// state — otherwise periodic autosave silently overwrites the user's tab.
     tab_backup: RefCell<std::collections::HashMap<TabId, crate::session::SavedTab>>,
+    /// Set while an IPC `batch` runs: `resize_all_panes` only notes in
+    /// `layout_owed` that the panes need sizing, and `ipc_release_layout`
+    /// sizes them once, so the steps do not reflow the neighbors one by one.
+    layout_held: Cell<bool>,
+    layout_owed: Cell<bool>,
 }
 
 #[derive(Clone, Copy)]
@@ -175,9 +182,57 @@ struct BoundaryFlash {
     remaining_frames: u32,
 }
 
+/// Cap on the lines the Cmd+F overlay collects — far more than fit on screen.
+const FILTER_MAX_MATCHES: usize = 10_000;
+
+/// Matches handed to the renderer each frame — more than any pane shows.
+const FILTER_RENDER_ROWS: usize = 256;
+
+/// Main-thread time the render tick may spend advancing a Cmd+F scan.
+const FILTER_STEP_BUDGET: std::time::Duration = std::time::Duration::from_millis(4);
+
 struct FilterState {
     query: String,
-    matches: Vec<FilterMatch>,
+    /// Tab toggles between literal and regex matching.
+    regex: bool,
+    /// Shift+Tab cycles smart → sensitive → insensitive.
+    case: CaseMode,
+    /// Scan of the focused pane for the current query. Advanced a few
+    /// thousand rows per tick (`poll_filter_search`) so a huge scrollback
+    /// never holds the terminal lock long enough to stall the PTY reader.
+    job: Option<SearchJob>,
+    /// Why the query can't run (invalid regex) — shown instead of a count.
+    error: Option<String>,
+}
+
+impl FilterState {
+    fn matches(&self) -> &[FilterMatch] {
+        self.job.as_ref().map_or(&[], |j| j.results())
+    }
+
+    /// Recompile the query and start a fresh scan of `term`. The first step
+    /// runs right away so short buffers show their results on the keystroke.
+    fn restart(&mut self, term: &crate::terminal::TerminalState) {
+        self.job = None;
+        self.error = None;
+        if self.query.is_empty() {
+            return;
+        }
+        let query = SearchQuery {
+            pattern: self.query.clone(),
+            regex: self.regex,
+            case: self.case,
+            accents: AccentMode::Smart,
+        };
+        match query.compile() {
+            Ok(matcher) => {
+                let mut job = SearchJob::new(matcher, FILTER_MAX_MATCHES);
+                job.step(term, ROWS_PER_LOCK);
+                self.job = Some(job);
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
 }
 
 /// One hit returned by the search worker. Stable across window/tab reordering
@@ -569,31 +624,6 @@ struct PaneSwitcherState {
     scroll_acc: f64,
 }
 
-/// Outcome of `KovaView::ipc_close_tab`. Lets the caller distinguish "not in this window"
-/// (keep scanning) from "last tab — refuse" (final answer).
-pub enum IpcCloseTabResult {
-    Closed,
-    WouldTerminate,
-    NotFound,
-}
-
-/// Outcome of `KovaView::ipc_merge_tab`. `SourceMissing` lets the caller keep scanning
-/// across windows; `TargetMissing` is a final answer because we found the source here.
-pub enum IpcMergeTabResult {
-    Merged,
-    SourceMissing,
-    TargetMissing,
-}
-
-/// Outcome of `KovaView::ipc_swap_pane`. Same pattern as merge: only `AMissing` keeps
-/// scanning across windows.
-pub enum IpcSwapPaneResult {
-    Swapped,
-    AMissing,
-    BMissing,
-    Failed,
-}
-
 fn build_items(entries: Vec<crate::recent_projects::RecentProject>) -> Vec<RecentProjectItem> {
     entries.into_iter().map(|e| {
         let render = crate::renderer::RecentProjectEntry {
@@ -1586,6 +1616,20 @@ fn copy_to_pasteboard(text: &str) {
     }
 }
 
+/// Copy styled text as HTML and RTF, with the plain text for apps that take
+/// neither. Rich editors pick the flavor they prefer.
+fn copy_rich_to_pasteboard(styled: &crate::terminal::export::StyledText) {
+    let pasteboard = NSPasteboard::generalPasteboard();
+    pasteboard.clearContents();
+    let plain = styled.plain();
+    let rtf = objc2_foundation::NSData::with_bytes(styled.rtf().as_bytes());
+    unsafe {
+        pasteboard.setString_forType(&NSString::from_str(&styled.html()), objc2_app_kit::NSPasteboardTypeHTML);
+        pasteboard.setData_forType(Some(&rtf), objc2_app_kit::NSPasteboardTypeRTF);
+        pasteboard.setString_forType(&NSString::from_str(plain.trim_end_matches('\n')), objc2_app_kit::NSPasteboardTypeString);
+    }
+}
+
 impl KovaView {
     fn new(mtm: MainThreadMarker, frame: CGRect) -> Retained<Self> {
         let this = mtm.alloc::<Self>().set_ivars(KovaViewIvars {
@@ -1638,6 +1682,8 @@ impl KovaView {
             post_restore_checks: RefCell::new(Vec::new()),
             band_repair_attempts: RefCell::new(std::collections::HashMap::new()),
             tab_backup: RefCell::new(std::collections::HashMap::new()),
+            layout_held: Cell::new(false),
+            layout_owed: Cell::new(false),
         });
         unsafe { msg_send![super(this), initWithFrame: frame] }
     }
@@ -1649,9 +1695,15 @@ impl KovaView {
         // Per-pane stats across ALL windows
         let mut total_panes = 0usize;
         let mut total_grid_bytes = 0usize;
-        let mut total_sb_lines = 0usize;
-        let mut total_sb_bytes = 0usize;
+        let mut total_sb_hot_lines = 0usize;
+        let mut total_sb_hot_bytes = 0usize;
+        let mut total_sb_cold_lines = 0usize;
+        let mut total_sb_cold_bytes = 0usize;
+        let mut total_sb_disk_lines = 0usize;
+        let mut total_sb_disk_bytes = 0usize;
         let mut total_alt_bytes = 0usize;
+        let mut total_terminal = 0usize;
+        let mut total_budget_lost = 0u64;
         let mut total_renderer_bytes = 0usize;
         let mut pane_details = Vec::new();
         let mut renderer_details = Vec::new();
@@ -1666,8 +1718,7 @@ impl KovaView {
                 for (ti, tab) in tabs.iter().enumerate() {
                     tab.for_each_pane(&mut |pane| {
                         let term = pane.terminal.read();
-                        let mem = term.mem_bytes();
-                        let sb_len = term.scrollback_len();
+                        let sb = term.scrollback_mem();
                         let cols = term.cols;
                         let rows = term.rows;
 
@@ -1675,18 +1726,28 @@ impl KovaView {
                         let row_oh = std::mem::size_of::<crate::terminal::Row>();
                         let grid_b = rows as usize * (row_oh + cols as usize * cell_size);
                         let alt_b = if term.in_alt_screen { grid_b } else { 0 };
-                        let sb_b = mem - grid_b - alt_b;
 
                         total_panes += 1;
+                        total_terminal += term.mem_bytes();
                         total_grid_bytes += grid_b;
-                        total_sb_lines += sb_len;
-                        total_sb_bytes += sb_b;
+                        total_sb_hot_lines += sb.hot_rows;
+                        total_sb_hot_bytes += sb.hot_bytes;
+                        total_sb_cold_lines += sb.cold_rows;
+                        total_sb_cold_bytes += sb.cold_bytes;
+                        total_sb_disk_lines += sb.disk_rows;
+                        total_sb_disk_bytes += sb.disk_bytes;
                         total_alt_bytes += alt_b;
 
+                        let lost = term.budget_lines_dropped();
+                        total_budget_lost += lost;
                         pane_details.push(format!(
-                            "  w{}t{} pane{}: {}x{}, sb={} lines ({:.1} KB), grid={:.1} KB",
-                            wi, ti, pane.id, cols, rows, sb_len,
-                            sb_b as f64 / 1024.0, grid_b as f64 / 1024.0,
+                            "  w{}t{} pane{}: {}x{}, sb={} hot ({:.1} KB) + {} cold ({:.1} KB) + {} disk ({:.1} KB), grid={:.1} KB{}",
+                            wi, ti, pane.id, cols, rows,
+                            sb.hot_rows, sb.hot_bytes as f64 / 1024.0,
+                            sb.cold_rows, sb.cold_bytes as f64 / 1024.0,
+                            sb.disk_rows, sb.disk_bytes as f64 / 1024.0,
+                            grid_b as f64 / 1024.0,
+                            if lost > 0 { format!(", lost {} lines to budget", lost) } else { String::new() },
                         ));
                     });
                 }
@@ -1707,19 +1768,36 @@ impl KovaView {
         }
         drop(all_windows);
 
-        let total_terminal = total_grid_bytes + total_sb_bytes + total_alt_bytes;
 
         // Build report lines (plain text, no ANSI — rendered by overlay)
         let mut report = Vec::new();
         report.push(format!("RSS: {:.1} MB  |  Panes: {}", rss_mb, total_panes));
         report.push(format!(
-            "~Terminal: {:.1} MB (grid {:.1} KB, scrollback {:.1} MB [{} lines], alt {:.1} KB)",
+            "~Terminal: {:.1} MB (grid {:.1} KB, scrollback hot {:.1} MB [{} lines] + cold {:.1} MB [{} lines], alt {:.1} KB)",
             total_terminal as f64 / (1024.0 * 1024.0),
             total_grid_bytes as f64 / 1024.0,
-            total_sb_bytes as f64 / (1024.0 * 1024.0),
-            total_sb_lines,
+            total_sb_hot_bytes as f64 / (1024.0 * 1024.0),
+            total_sb_hot_lines,
+            total_sb_cold_bytes as f64 / (1024.0 * 1024.0),
+            total_sb_cold_lines,
             total_alt_bytes as f64 / 1024.0,
         ));
+        let budget_mb = self.ivars().config.get().map_or(0, |c| c.terminal.scrollback_total_mb);
+        if budget_mb > 0 {
+            report.push(format!(
+                "Scrollback budget: {:.1} / {} MB in use, {} lines lost",
+                (total_sb_hot_bytes + total_sb_cold_bytes) as f64 / (1024.0 * 1024.0),
+                budget_mb,
+                total_budget_lost,
+            ));
+        }
+        if total_sb_disk_lines > 0 {
+            report.push(format!(
+                "Scrollback on disk: {:.1} MB [{} lines] (not in RSS)",
+                total_sb_disk_bytes as f64 / (1024.0 * 1024.0),
+                total_sb_disk_lines,
+            ));
+        }
         report.push(format!(
             "~Renderer: {:.1} MB total",
             total_renderer_bytes as f64 / (1024.0 * 1024.0),
@@ -1795,6 +1873,21 @@ impl KovaView {
         self.set_pane_flash(pane_id, 20, None);
     }
 
+    /// Start recording the focused pane, or stop if it is already recording.
+    /// The status bar says where the file went.
+    fn do_toggle_recording(&self) {
+        let Some(pane) = self.focused_pane() else { return };
+        if let Some(path) = pane.pty.stop_recording() {
+            self.set_transient_status(&format!("Recording saved: {}", path.display()));
+            return;
+        }
+        let Some(config) = self.ivars().config.get() else { return };
+        match start_pane_recording(pane, &config.recording, None, None) {
+            Ok(path) => self.set_transient_status(&format!("Recording to {}", path.display())),
+            Err(e) => self.set_transient_status(&format!("Recording failed: {}", e)),
+        }
+    }
+
     fn focused_pane(&self) -> Option<&Pane> {
         let tabs = self.ivars().tabs.borrow();
         let idx = self.ivars().active_tab.get();
@@ -3266,8 +3359,9 @@ struct SearchPaneSnapshot {
     terminal: Arc<parking_lot::RwLock<crate::terminal::TerminalState>>,
 }
 
-/// Worker-thread search. Uses ASCII case-insensitive `contains` — good enough
-/// for terminal text, which is overwhelmingly ASCII.
+/// Worker-thread search, on the same engine as Cmd+F: literal, smart-case,
+/// wrap-aware. Each pane's scan stops at its first hit and releases the
+/// terminal lock every `ROWS_PER_LOCK` rows.
 ///
 /// Produces a two-section row list:
 ///   1. Panes whose title OR content matches, grouped under a per-tab header
@@ -3280,18 +3374,19 @@ fn run_search_worker(
     tabs: &[SearchTabSnapshot],
     panes: &[SearchPaneSnapshot],
 ) -> Vec<SearchRow> {
-    let needle = query.to_ascii_lowercase();
+    let Ok(matcher) = SearchQuery::literal(query, CaseMode::Smart).compile() else {
+        return Vec::new();
+    };
+    let title_matches = |title: &str| matcher.is_match(title);
     let mut rows: Vec<SearchRow> = Vec::new();
 
     // Section 1: matching panes, grouped by tab.
     let mut current_tab: Option<TabId> = None;
     for p in panes {
-        let matches = p.pane_title.to_ascii_lowercase().contains(&needle) || {
-            let term = p.terminal.read();
-            term.dump_text(crate::terminal::DumpMode::All, true)
-                .text
-                .to_ascii_lowercase()
-                .contains(&needle)
+        let matches = title_matches(&p.pane_title) || {
+            let mut job = SearchJob::new(matcher.clone(), 1);
+            while !job.step(&p.terminal.read(), ROWS_PER_LOCK) {}
+            !job.results().is_empty()
         };
         if !matches {
             continue;
@@ -3310,7 +3405,7 @@ fn run_search_worker(
     // Section 2: tabs whose title matches.
     let mut tab_section_open = false;
     for tab in tabs {
-        if tab.title.to_ascii_lowercase().contains(&needle) {
+        if title_matches(&tab.title) {
             if !tab_section_open {
                 rows.push(SearchRow::Header("Tabs".to_string()));
                 tab_section_open = true;
@@ -3888,6 +3983,7 @@ impl KovaView {
             Action::BreakPane => self.do_break_pane(),
             Action::MergeTab => self.do_merge_tab(),
             Action::MergeWindow => self.do_merge_window(),
+            Action::ToggleRecording => self.do_toggle_recording(),
 
             Action::SwitchTab(idx) => self.do_switch_tab(*idx),
             Action::MinimizePane => self.do_minimize_pane(),
@@ -3951,9 +4047,9 @@ impl KovaView {
                 // If filter is active, copy all filtered lines
                 let filter = self.ivars().filter.borrow();
                 if let Some(state) = filter.as_ref() {
-                    if !state.matches.is_empty() {
+                    if !state.matches().is_empty() {
                         let mut text = String::new();
-                        for (i, m) in state.matches.iter().enumerate() {
+                        for (i, m) in state.matches().iter().enumerate() {
                             if i > 0 { text.push('\n'); }
                             text.push_str(&m.text);
                         }
@@ -3982,6 +4078,15 @@ impl KovaView {
                     }
                 }
             }
+            Action::CopyRich => {
+                let Some(pane) = self.focused_pane() else { return false };
+                let styled = pane.terminal.read().selected_styled_text();
+                if styled.lines.is_empty() {
+                    return false;
+                }
+                copy_rich_to_pasteboard(&styled);
+                pane.terminal.write().clear_selection();
+            }
             Action::Paste => {
                 if let Some(pane) = self.focused_pane() {
                     let pasteboard = NSPasteboard::generalPasteboard();
@@ -4348,32 +4453,7 @@ impl KovaView {
 
         // Adjust target index after removal
         let target = if target_idx > active { target_idx - 1 } else { target_idx };
-
-        // The leftmost pane in the source becomes the new focus
-        let new_focus = source.columns.first()
-            .and_then(|col| col.panes.first())
-            .map(|p| p.id)
-            .unwrap_or(source.focused_pane);
-
-        // Append source columns to target tab, normalizing weights
-        let target_avg: f32 = tabs[target].column_weights.iter().sum::<f32>()
-            / tabs[target].columns.len() as f32;
-        let source_avg: f32 = source.column_weights.iter().sum::<f32>()
-            / source.columns.len().max(1) as f32;
-        let scale = if source_avg > 0.0 { target_avg / source_avg } else { 1.0 };
-        for (i, (col, weight)) in source.columns.into_iter().zip(source.column_weights.into_iter()).enumerate() {
-            tabs[target].columns.push(col);
-            tabs[target].column_weights.push(weight * scale);
-            tabs[target].custom_weights.push(
-                source.custom_weights.get(i).copied().unwrap_or(false)
-            );
-        }
-
-        // Merge minimized stacks
-        tabs[target].minimized_stack.extend(source.minimized_stack);
-
-        // Focus the leftmost pane from the merged columns
-        tabs[target].focused_pane = new_focus;
+        tabs[target].merge_from(source);
 
         // Switch to the target tab
         self.ivars().active_tab.set(target);
@@ -4402,21 +4482,60 @@ impl KovaView {
     // IPC methods (called from app.rs IPC command handlers)
     // ---------------------------------------------------------------
 
-    /// IPC: create a split in the active tab's focused pane.
-    /// Returns the new pane's ID on success.
-    pub fn ipc_split(
+    /// IPC: hold pane resizes until `ipc_release_layout` (see `layout_held`).
+    pub fn ipc_hold_layout(&self) {
+        self.ivars().layout_held.set(true);
+    }
+
+    /// IPC: end `ipc_hold_layout`, sizing the panes once if a step moved them.
+    pub fn ipc_release_layout(&self) {
+        self.ivars().layout_held.set(false);
+        if self.ivars().layout_owed.take() {
+            self.resize_all_panes();
+            self.mark_dirty();
+        }
+    }
+
+    /// IPC: run `f` on a pane of this window. `None` if the pane is not here.
+    pub fn ipc_with_pane<R>(&self, pane_id: PaneId, f: impl FnOnce(&Pane) -> R) -> Option<R> {
+        let tabs = self.ivars().tabs.borrow();
+        tabs.iter().find_map(|tab| tab.pane(pane_id)).map(f)
+    }
+
+    /// IPC: run `f` on the tab holding `pane_id`, then redraw — nothing else
+    /// observes a tab's title or color, or a pane's waiting flag. `None` if
+    /// the pane is not in this window.
+    pub fn ipc_with_tab_of<R>(&self, pane_id: PaneId, f: impl FnOnce(&mut Tab) -> R) -> Option<R> {
+        let mut tabs = self.ivars().tabs.borrow_mut();
+        let result = tabs.iter_mut().find(|tab| tab.contains(pane_id)).map(f);
+        drop(tabs);
+        if result.is_some() {
+            self.mark_dirty();
+        }
+        result
+    }
+
+    /// IPC: insert `tab` after the active tab and switch to it.
+    pub fn ipc_open_tab(&self, tab: Tab) {
+        let new_idx = self.ivars().active_tab.get() + 1;
+        self.ivars().tabs.borrow_mut().insert(new_idx, tab);
+        self.ivars().active_tab.set(new_idx);
+        self.resize_all_panes();
+    }
+
+    /// IPC: build a pane sized for half of the focused pane and insert it as
+    /// a split next to it, focused. `make_pane` gets the grid size of the slot.
+    pub fn ipc_split_focused(
         &self,
-        config: &crate::config::Config,
         direction: SplitDirection,
-        cwd: Option<&str>,
-        command: Option<String>,
-    ) -> Option<PaneId> {
+        make_pane: impl FnOnce(u16, u16) -> Result<Pane, String>,
+    ) -> Result<PaneId, String> {
         let (focused_id, current_vp) = {
             let tabs = self.ivars().tabs.borrow();
             let idx = self.ivars().active_tab.get();
-            let tab = tabs.get(idx)?;
+            let tab = tabs.get(idx).ok_or("no active tab")?;
             let fid = tab.focused_pane;
-            let vp = tab.viewport_for_pane(fid, self.panes_viewport_for_tab(tab))?;
+            let vp = tab.viewport_for_pane(fid, self.panes_viewport_for_tab(tab)).ok_or("no focused pane")?;
             (fid, vp)
         };
 
@@ -4435,19 +4554,7 @@ impl KovaView {
             },
         };
         let (cols, rows) = self.viewport_to_grid(&half_vp);
-
-        let new_pane = match Pane::spawn(cols, rows, config, cwd) {
-            Ok(p) => p,
-            Err(e) => {
-                log::error!("IPC split: failed to spawn pane: {}", e);
-                return None;
-            }
-        };
-
-        // If a command was provided, set it as pending (will be injected once shell is ready)
-        if let Some(cmd) = command {
-            new_pane.pending_command.set(Some(cmd));
-        }
+        let new_pane = make_pane(cols, rows)?;
 
         let new_id = new_pane.id;
         let open_timer = new_pane.open_timer.clone();
@@ -4479,8 +4586,7 @@ impl KovaView {
 
         open_timer.mark_inserted(new_id);
         self.resize_all_panes();
-        log::info!("IPC: split created pane {}", new_id);
-        Some(new_id)
+        Ok(new_id)
     }
 
     /// IPC: close a specific pane by ID. Returns true if found and closed.
@@ -4575,6 +4681,39 @@ impl KovaView {
         }
     }
 
+    /// Every pane of this window for the app-wide scrollback budget. The
+    /// focused pane of the active tab is protected — it is on screen, and an
+    /// open Cmd+F search is scanning it — and so are the hits listed by the
+    /// search palette and the panes in `watched` (parked IPC waits and
+    /// `search-pane` scans).
+    pub fn collect_budget_panes(
+        &self,
+        watched: &[PaneId],
+        out: &mut Vec<crate::terminal::scrollback::BudgetPane>,
+    ) {
+        let listed: Vec<PaneId> = self.ivars().search_palette.borrow().as_ref().map_or(Vec::new(), |state| {
+            state.rows.iter()
+                .filter_map(|row| match row {
+                    SearchRow::Hit(hit) => hit.pane_id,
+                    SearchRow::Header(_) => None,
+                })
+                .collect()
+        });
+        let tabs = self.ivars().tabs.borrow();
+        let active_tab = self.ivars().active_tab.get();
+        for (tab_idx, tab) in tabs.iter().enumerate() {
+            let focused_id = tab.focused_pane;
+            tab.for_each_pane(&mut |pane| {
+                out.push(crate::terminal::scrollback::BudgetPane {
+                    terminal: pane.terminal.clone(),
+                    protected: (tab_idx == active_tab && pane.id == focused_id)
+                        || watched.contains(&pane.id)
+                        || listed.contains(&pane.id),
+                });
+            });
+        }
+    }
+
     /// The pane this window would hand the keyboard to: the focused pane of the
     /// active tab, its tab index, and the Claude conversation running in it.
     ///
@@ -4639,26 +4778,13 @@ impl KovaView {
                         working: pane.is_working(),
                         awaiting: pane.is_awaiting(),
                         awaiting_since: pane.awaiting_since(),
+                        last_output_ms: pane.terminal.read().last_output_ms(),
                     },
                 );
             });
         }
     }
 
-    /// IPC: write text to a pane's PTY. Returns true if the pane was found.
-    pub fn ipc_send_keys(&self, pane_id: PaneId, text: &str) -> bool {
-        let tabs = self.ivars().tabs.borrow();
-        for tab in tabs.iter() {
-            if let Some(pane) = tab.pane(pane_id) {
-                // Same rule as a keystroke: someone answered this pane.
-                pane.clear_awaiting();
-                pane.pty.write(text.as_bytes());
-                return true;
-            }
-        }
-        false
-    }
-
     /// IPC: focus a pane by ID (switch tab if needed). Returns true if found.
     pub fn ipc_focus_pane(&self, pane_id: PaneId) -> bool {
         let mut tabs = self.ivars().tabs.borrow_mut();
@@ -4695,134 +4821,6 @@ impl KovaView {
         }
     }
 
-    /// IPC: build a JSON entry with the rendered text of a pane.
-    /// Returns `None` if the pane is not in this window.
-    pub fn ipc_dump_pane_text(
-        &self,
-        pane_id: PaneId,
-        mode: crate::terminal::DumpMode,
-        trim: bool,
-    ) -> Option<serde_json::Value> {
-        let tabs = self.ivars().tabs.borrow();
-        for tab in tabs.iter() {
-            if let Some(pane) = tab.pane(pane_id) {
-                let term = pane.terminal.read();
-                let dump = term.dump_text(mode, trim);
-                return Some(serde_json::json!({
-                    "id": pane_id,
-                    "text": dump.text,
-                    "cols": dump.cols,
-                    "rows": dump.rows,
-                    "cursor": { "row": dump.cursor_row, "col": dump.cursor_col },
-                }));
-            }
-        }
-        None
-    }
-
-    /// IPC: report whether the OSC 133;D flag is set for a pane.
-    /// `None` means the pane is not in this window; `Some(b)` is the flag's value.
-    pub fn ipc_check_completion(&self, pane_id: PaneId) -> Option<bool> {
-        let tabs = self.ivars().tabs.borrow();
-        for tab in tabs.iter() {
-            if let Some(pane) = tab.pane(pane_id) {
-                let flag = pane
-                    .terminal
-                    .read()
-                    .command_completed
-                    .load(std::sync::atomic::Ordering::Relaxed);
-                return Some(flag);
-            }
-        }
-        None
-    }
-
-    /// IPC: measure how big the rendered text of a pane would be.
-    /// Returns `(chars, bytes)`, or `None` if the pane is not in this window.
-    pub fn ipc_measure_pane_text(
-        &self,
-        pane_id: PaneId,
-        mode: crate::terminal::DumpMode,
-        trim: bool,
-    ) -> Option<(usize, usize)> {
-        let tabs = self.ivars().tabs.borrow();
-        for tab in tabs.iter() {
-            if let Some(pane) = tab.pane(pane_id) {
-                let term = pane.terminal.read();
-                return Some(term.measure_text(mode, trim));
-            }
-        }
-        None
-    }
-
-    /// IPC: set the custom title of the tab containing `pane_id`.
-    /// `title: None` clears the custom title (tab falls back to auto-derived title).
-    /// Returns true if the pane was found in this window.
-    pub fn ipc_set_tab_title(&self, pane_id: PaneId, title: Option<String>) -> bool {
-        let mut tabs = self.ivars().tabs.borrow_mut();
-        let tab_idx = match tabs.iter().position(|tab| tab.contains(pane_id)) {
-            Some(i) => i,
-            None => return false,
-        };
-        tabs[tab_idx].custom_title = title;
-        log::info!("IPC: set tab title for pane {}", pane_id);
-        true
-    }
-
-    /// IPC: color the tab holding `pane_id`, or clear its color with `None`.
-    ///
-    /// Same palette as the tab bar's right-click menu, and the redraw is asked for the
-    /// same way: nothing else observes this field, so without it the tab keeps its old
-    /// color until something unrelated makes the window dirty.
-    pub fn ipc_set_tab_color(&self, pane_id: PaneId, color: Option<usize>) -> bool {
-        let mut tabs = self.ivars().tabs.borrow_mut();
-        let tab_idx = match tabs.iter().position(|tab| tab.contains(pane_id)) {
-            Some(i) => i,
-            None => return false,
-        };
-        tabs[tab_idx].color = color;
-        drop(tabs);
-        self.mark_dirty();
-        log::info!("IPC: set tab color for pane {}", pane_id);
-        true
-    }
-
-    /// IPC: create a new tab. Returns (tab_id, pane_id) on success.
-    pub fn ipc_new_tab(
-        &self,
-        config: &crate::config::Config,
-        cwd: Option<&str>,
-        command: Option<String>,
-    ) -> Option<(u32, u32)> {
-        // Determine CWD: explicit param > focused pane's CWD
-        let effective_cwd: Option<String> = cwd.map(String::from).or_else(|| self.ipc_focused_cwd());
-
-        let tab = match Tab::new_with_cwd(config, effective_cwd.as_deref()) {
-            Ok(t) => t,
-            Err(e) => {
-                log::error!("IPC new-tab: failed to create tab: {}", e);
-                return None;
-            }
-        };
-
-        let tab_id = tab.id;
-        let pane_id = tab.first_pane().id;
-
-        // If a command was provided, set it as pending
-        if let Some(cmd) = command {
-            tab.first_pane().pending_command.set(Some(cmd));
-        }
-
-        let mut tabs = self.ivars().tabs.borrow_mut();
-        let new_idx = self.ivars().active_tab.get() + 1;
-        tabs.insert(new_idx, tab);
-        drop(tabs);
-        self.ivars().active_tab.set(new_idx);
-        self.resize_all_panes();
-        log::info!("IPC: new tab created: tab_id={}, pane_id={}", tab_id, pane_id);
-        Some((tab_id, pane_id))
-    }
-
     /// IPC: collect this window's tabs as JSON entries.
     pub fn ipc_collect_tabs(&self, win_idx: usize, is_key_window: bool, out: &mut Vec<serde_json::Value>) {
         let tabs = self.ivars().tabs.borrow();
@@ -4902,28 +4900,9 @@ impl KovaView {
         if !tabs[tab_idx].contains(pane_id_b) {
             return IpcSwapPaneResult::BMissing;
         }
-        // Pick a synthetic direction based on layout: same column → Up (in-column),
-        // different columns → Right (swap whole columns). This reuses the existing
-        // direction-aware logic without forcing the caller to know layout details.
-        let tab = &mut tabs[tab_idx];
-        let col_a = tab.column_index_of(pane_id_a);
-        let col_b = tab.column_index_of(pane_id_b);
-        let dir = match (col_a, col_b) {
-            (Some(a), Some(b)) if a == b => crate::pane::NavDirection::Up,
-            (Some(_), Some(_)) => crate::pane::NavDirection::Right,
-            _ => return IpcSwapPaneResult::Failed,
-        };
-        let ok = tab.swap_panes(pane_id_a, pane_id_b, dir);
-        if !ok {
+        if !tabs[tab_idx].swap_panes_auto(pane_id_a, pane_id_b) {
             return IpcSwapPaneResult::Failed;
         }
-        // Mark both panes dirty so they redraw in their new positions.
-        if let Some(p) = tab.pane(pane_id_a) {
-            p.terminal.read().dirty.store(true, std::sync::atomic::Ordering::Relaxed);
-        }
-        if let Some(p) = tab.pane(pane_id_b) {
-            p.terminal.read().dirty.store(true, std::sync::atomic::Ordering::Relaxed);
-        }
         drop(tabs);
         self.resize_all_panes();
         log::info!("IPC: swapped panes {} and {}", pane_id_a, pane_id_b);
@@ -4942,42 +4921,10 @@ impl KovaView {
         grow: bool,
         amount_pct: f32,
     ) -> Option<bool> {
-        use crate::pane::SplitAxis;
         let mut tabs = self.ivars().tabs.borrow_mut();
         let tab_idx = tabs.iter().position(|t| t.contains(pane_id))?;
         let tab = &mut tabs[tab_idx];
-
-        // Translate grow/shrink into the signed delta the internal API expects.
-        // For non-last position, delta>0 = grow. For last position, delta<0 = grow.
-        // (Mirrors adjust_column_weight_directional / adjust_row_weight_directional.)
-        let is_last = match axis {
-            SplitAxis::Horizontal => {
-                let col_idx = tab.column_index_of(pane_id);
-                if tab.columns.len() < 2 { return Some(false); }
-                col_idx.map(|i| i == tab.columns.len() - 1).unwrap_or(false)
-            }
-            SplitAxis::Vertical => {
-                let col_idx = match tab.column_index_of(pane_id) {
-                    Some(i) => i,
-                    None => return Some(false),
-                };
-                let col = &tab.columns[col_idx];
-                if col.panes.len() < 2 { return Some(false); }
-                col.panes.iter().position(|p| p.id == pane_id)
-                    .map(|i| i == col.panes.len() - 1)
-                    .unwrap_or(false)
-            }
-        };
-        let mag = amount_pct / 100.0;
-        let delta = match (grow, is_last) {
-            (true, false) => mag,
-            (true, true) => -mag,
-            (false, false) => -mag,
-            (false, true) => mag,
-        };
-
-        let changed = tab.adjust_ratio_directional(pane_id, delta, axis);
-        if !changed {
+        if !tab.resize_split(pane_id, axis, grow, amount_pct) {
             return Some(false);
         }
         let full = self.drawable_viewport();
@@ -4991,50 +4938,6 @@ impl KovaView {
         Some(true)
     }
 
-    /// IPC: set/clear a pane's sticky custom title (equivalent to OSC 1 / Cmd-Option-R).
-    /// Returns true if the pane was found.
-    pub fn ipc_rename_pane(&self, pane_id: PaneId, title: Option<String>) -> bool {
-        let mut tabs = self.ivars().tabs.borrow_mut();
-        for tab in tabs.iter_mut() {
-            // for_each_pane is read-only; we need mutable access to set custom_title.
-            // Walk columns directly for that.
-            for col in tab.columns.iter_mut() {
-                for pane in col.panes.iter_mut() {
-                    if pane.id == pane_id {
-                        pane.custom_title = title.clone();
-                        // Also clear any pending OSC 1 sticky from the terminal so a stale
-                        // OSC 1 doesn't immediately overwrite the IPC title on next frame.
-                        pane.terminal.write().osc1_title = None;
-                        pane.terminal.read().dirty.store(true, std::sync::atomic::Ordering::Relaxed);
-                        log::info!("IPC: renamed pane {}", pane_id);
-                        return true;
-                    }
-                }
-            }
-        }
-        false
-    }
-
-    /// IPC: set/clear a pane's "waiting for the user" flag. Returns true if the
-    /// pane was found. The flag is stored as-is; whether it is *shown* on the
-    /// pane the user is currently sitting on is a display decision, made where
-    /// focus is known (mirroring how bell/completion are suppressed).
-    pub fn ipc_set_pane_status(&self, pane_id: PaneId, waiting: bool) -> bool {
-        let tabs = self.ivars().tabs.borrow();
-        for tab in tabs.iter() {
-            let Some(pane) = tab.pane(pane_id) else { continue };
-            if waiting {
-                pane.set_awaiting();
-            } else {
-                pane.clear_awaiting();
-            }
-            log::info!("IPC: pane {} waiting={}", pane_id, waiting);
-            self.mark_dirty();
-            return true;
-        }
-        false
-    }
-
     /// Minimize the focused pane.
     fn do_minimize_pane(&self) {
         let mut tabs = self.ivars().tabs.borrow_mut();
@@ -5333,6 +5236,10 @@ impl KovaView {
 
     /// Resize all panes in the active tab to match their current viewports.
     fn resize_all_panes(&self) {
+        if self.ivars().layout_held.get() {
+            self.ivars().layout_owed.set(true);
+            return;
+        }
         let renderer = match self.ivars().renderer.get() {
             Some(r) => r,
             None => return,
@@ -5354,7 +5261,8 @@ impl KovaView {
             tab.cell_h.set(cell_h);
             tab.for_each_pane_with_viewport(panes_vp, &mut |pane, vp| {
                 // Skip PTY resize for minimized panes (keep old dimensions)
-                if pane.minimized {
+                // and playback panes (the recording sets their size).
+                if pane.minimized || pane.is_playback() {
                     return;
                 }
                 let cols = ((vp.width - 2.0 * crate::renderer::PANE_H_PADDING) / cell_w).floor().max(1.0) as u16;
@@ -5549,7 +5457,10 @@ impl KovaView {
         } else {
             *filter = Some(FilterState {
                 query: String::new(),
-                matches: Vec::new(),
+                regex: false,
+                case: CaseMode::default(),
+                job: None,
+                error: None,
             });
         }
         drop(filter);
@@ -5582,7 +5493,7 @@ impl KovaView {
             }
             '\r' => {
                 // Enter → close filter and scroll to first match
-                let first_match = state.matches.first().map(|m| m.abs_line);
+                let first_match = state.matches().first().map(|m| m.abs_line);
                 *filter = None;
                 drop(filter);
                 if let Some(abs_line) = first_match {
@@ -5597,6 +5508,9 @@ impl KovaView {
                 // Backspace
                 state.query.pop();
             }
+            '\t' => state.regex = !state.regex,
+            // Shift+Tab (backtab)
+            '\u{19}' => state.case = state.case.next(),
             c if c >= ' ' && !c.is_control() => {
                 state.query.push(c);
             }
@@ -5606,11 +5520,30 @@ impl KovaView {
         // Re-run search
         if let Some(pane) = self.focused_pane() {
             let term = pane.terminal.read();
-            state.matches = term.search_lines(&state.query);
+            state.restart(&term);
             term.dirty.store(true, std::sync::atomic::Ordering::Relaxed);
         }
     }
 
+    /// Advance an unfinished Cmd+F scan. Called by the app delegate's tick;
+    /// the read lock is released between steps so the PTY reader can apply
+    /// output while a large scrollback is being searched.
+    pub fn poll_filter_search(&self) {
+        let mut filter = self.ivars().filter.borrow_mut();
+        let Some(job) = filter.as_mut().and_then(|f| f.job.as_mut()) else { return };
+        if job.is_done() {
+            return;
+        }
+        let Some(pane) = self.focused_pane() else { return };
+        let deadline = std::time::Instant::now() + FILTER_STEP_BUDGET;
+        while !job.step(&pane.terminal.read(), ROWS_PER_LOCK) {
+            if std::time::Instant::now() >= deadline {
+                break;
+            }
+        }
+        pane.terminal.read().dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
     fn start_rename_tab(&self) {
         // Pre-fill with current tab title
         let current_title = {
@@ -5813,7 +5746,7 @@ impl KovaView {
         let abs_line = match filter.as_ref() {
             Some(state) => {
                 let idx = click_row as usize;
-                state.matches.get(idx).map(|m| m.abs_line)
+                state.matches().get(idx).map(|m| m.abs_line)
             }
             None => return,
         };
@@ -5892,13 +5825,27 @@ impl KovaView {
             let f = win.frame();
             (f.origin.x, f.origin.y, f.size.width, f.size.height)
         });
+        let scrollback_lines = self.ivars().config.get()
+            .filter(|c| c.session.save_scrollback)
+            .map(|c| c.session.scrollback_lines);
         let backup = self.ivars().tab_backup.borrow();
-        let saved_tabs: Vec<crate::session::SavedTab> = tabs.iter().map(|t| {
-            backup.get(&t.id).cloned().unwrap_or_else(|| crate::session::snapshot_tab(t))
-        }).collect();
+        let mut saved_tabs: Vec<crate::session::SavedTab> = Vec::with_capacity(tabs.len());
+        let mut saved_active = 0;
+        for (idx, t) in tabs.iter().enumerate() {
+            let saved = backup.get(&t.id).cloned()
+                .unwrap_or_else(|| crate::session::snapshot_tab_with_scrollback(t, scrollback_lines));
+            // A tab of nothing but virtual panes has nothing to save.
+            if crate::session::count_panes_in_saved_tab(&saved) == 0 {
+                continue;
+            }
+            if idx <= active_tab {
+                saved_active = saved_tabs.len();
+            }
+            saved_tabs.push(saved);
+        }
         out.push(crate::session::WindowSession {
             tabs: saved_tabs,
-            active_tab,
+            active_tab: saved_active,
             frame,
         });
     }
@@ -6460,7 +6407,12 @@ impl KovaView {
             let filter = ivars.filter.borrow();
             filter.as_ref().map(|f| FilterRenderData {
                 query: f.query.clone(),
-                matches: f.matches.clone(),
+                regex: f.regex,
+                case: f.case,
+                matches: f.matches().iter().take(FILTER_RENDER_ROWS).cloned().collect(),
+                total: f.matches().len(),
+                searching: f.job.as_ref().is_some_and(|j| !j.is_done()),
+                error: f.error.clone(),
             })
         };
 
@@ -6873,56 +6825,6 @@ fn should_skip_settle_nudge(row_coverage: f32, has_interior_band: bool) -> bool
     !has_interior_band && row_coverage >= SETTLE_SKIP_COVERAGE
 }
 
-/// The JSON shape of one pane, shared by `list-panes`, the `subscribe` snapshot
-/// and the event payloads — a client parses the same object everywhere.
-///
-/// Costs a `proc_pidinfo` (the CWD) and a process-table walk (the children), so
-/// callers on a polling path must build it only for panes they actually report.
-pub fn pane_json(
-    pane: &Pane,
-    win_idx: usize,
-    tab_idx: usize,
-    focused: bool,
-) -> serde_json::Value {
-    let pid = pane.pty.pid();
-    let children = pane.pty.child_processes();
-    let is_idle = children.is_empty();
-    let child_json: Vec<serde_json::Value> = children
-        .into_iter()
-        .map(|(cpid, info)| {
-            serde_json::json!({
-                "pid": cpid,
-                "name": info.name,
-                "version": info.version,
-            })
-        })
-        .collect();
-    serde_json::json!({
-        "id": pane.id,
-        "window": win_idx,
-        "tab": tab_idx,
-        "cwd": pane.cwd().unwrap_or_default(),
-        "title": pane.display_title("shell"),
-        "focused": focused,
-        "pid": pid,
-        "child_processes": child_json,
-        "is_idle": is_idle,
-        "working": pane.is_working(),
-        "awaiting": pane.is_awaiting(),
-        "awaiting_since": pane.awaiting_since(),
-        // Whether the waiting pane has been looked at here since it started waiting. This is
-        // the halt Cmd+J obeys and the status bar ignores: the `?` marker stays up until the
-        // question is answered, while a pane that has been read stops pulling the jump back to
-        // itself. A remote client walking waiting panes needs the same halt, or it hands back
-        // panes already read on this Mac. False on a pane that is not waiting at all, where the
-        // bit means nothing.
-        "awaiting_seen": pane.is_awaiting() && !pane.is_awaiting_unseen(),
-        "minimized": pane.minimized,
-        "claude_session_id": pane.claude_session_id(),
-        "claude_session_name": pane.claude_session_name(),
-    })
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;